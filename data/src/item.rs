@@ -0,0 +1,492 @@
+//! Item templates, what every copy of an item has in common, keyed by item
+//! id. Read from client_items.xml and compiled into a compact binary form
+
+use std::collections::HashMap;
+
+use quick_xml::de::from_str;
+use serde::Deserialize;
+
+use crate::{write_string, Error, Reader, Result};
+
+/// Every class, restrictions are indexed by class id
+pub const CLASSES: usize = 12;
+
+/// Start of the binary form, bumped whenever the layout changes
+const MAGIC: [u8; 4] = *b"ITM2";
+/// Stands in for a missing damage range or class in the binary form
+const NONE: u16 = u16::MAX;
+
+/// Where equipment can be worn, the client names the pairs of slots
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum EquipmentSlots {
+    Main,
+    Sub,
+    MainOrSub,
+    Head,
+    Torso,
+    Glove,
+    Foot,
+    RightOrLeftEar,
+    RightOrLeftFinger,
+    Neck,
+    Shoulder,
+    Leg,
+    RightOrLeftBattery,
+    Wing,
+    Waist,
+}
+
+impl EquipmentSlots {
+    /// A bit for each gear slot it fits, in the order the client numbers
+    /// them
+    fn mask(self) -> u32 {
+        match self {
+            Self::Main => 0x1,
+            Self::Sub => 0x2,
+            Self::MainOrSub => 0x3,
+            Self::Head => 0x4,
+            Self::Torso => 0x8,
+            Self::Glove => 0x10,
+            Self::Foot => 0x20,
+            Self::RightOrLeftEar => 0xC0,
+            Self::RightOrLeftFinger => 0x300,
+            Self::Neck => 0x400,
+            Self::Shoulder => 0x800,
+            Self::Leg => 0x1000,
+            Self::RightOrLeftBattery => 0x6000,
+            Self::Wing => 0x8000,
+            Self::Waist => 0x10000,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename = "client_items")]
+struct ClientItems {
+    #[serde(rename = "client_item")]
+    items: Vec<ClientItem>,
+}
+
+/// Classes missing from an item can't use it, unless none are listed at all.
+/// The client calls priests clerics and clerics priests
+#[derive(Debug, Deserialize)]
+struct ClientItem {
+    id: u32,
+    name: String,
+    #[serde(default)]
+    name_id: u32,
+    #[serde(default)]
+    price: u32,
+    equipment_slots: Option<EquipmentSlots>,
+    min_damage: Option<u16>,
+    max_damage: Option<u16>,
+    #[serde(default)]
+    attack_delay: u16,
+    #[serde(default)]
+    attack_range: f32,
+    #[serde(default)]
+    str: u16,
+    #[serde(default)]
+    agi: u16,
+    #[serde(default)]
+    kno: u16,
+    #[serde(default)]
+    hit_accuracy: u16,
+    #[serde(default)]
+    critical: u16,
+    #[serde(default)]
+    parry: u16,
+    #[serde(default)]
+    magical_skill_boost: u16,
+    #[serde(default)]
+    magical_hit_accuracy: u16,
+    #[serde(default)]
+    physical_defend: u16,
+    #[serde(default)]
+    magical_resist: u16,
+    #[serde(default)]
+    dodge: u16,
+    #[serde(default)]
+    block: u16,
+    warrior: Option<u16>,
+    fighter: Option<u16>,
+    knight: Option<u16>,
+    scout: Option<u16>,
+    assassin: Option<u16>,
+    ranger: Option<u16>,
+    mage: Option<u16>,
+    wizard: Option<u16>,
+    elementalist: Option<u16>,
+    cleric: Option<u16>,
+    priest: Option<u16>,
+    chanter: Option<u16>,
+}
+
+/// Damage range of a weapon
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Damage {
+    pub min: u16,
+    pub max: u16,
+}
+
+/// Stats an item adds while it is equipped
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Bonuses {
+    pub power: u16,
+    pub agility: u16,
+    pub knowledge: u16,
+    pub accuracy: u16,
+    pub crit: u16,
+    pub parry: u16,
+    pub magic_boost: u16,
+    pub magic_accuracy: u16,
+    pub physical_defence: u16,
+    pub magic_resist: u16,
+    pub evasion: u16,
+    pub block: u16,
+}
+
+impl Bonuses {
+    pub fn add(&mut self, other: &Bonuses) {
+        for (stat, other) in self.stats_mut().into_iter().zip(other.stats()) {
+            *stat += other;
+        }
+    }
+
+    fn stats(&self) -> [u16; 12] {
+        [
+            self.power,
+            self.agility,
+            self.knowledge,
+            self.accuracy,
+            self.crit,
+            self.parry,
+            self.magic_boost,
+            self.magic_accuracy,
+            self.physical_defence,
+            self.magic_resist,
+            self.evasion,
+            self.block,
+        ]
+    }
+
+    fn stats_mut(&mut self) -> [&mut u16; 12] {
+        [
+            &mut self.power,
+            &mut self.agility,
+            &mut self.knowledge,
+            &mut self.accuracy,
+            &mut self.crit,
+            &mut self.parry,
+            &mut self.magic_boost,
+            &mut self.magic_accuracy,
+            &mut self.physical_defence,
+            &mut self.magic_resist,
+            &mut self.evasion,
+            &mut self.block,
+        ]
+    }
+}
+
+/// Everything every copy of an item has in common
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    pub id: u32,
+    pub name: String,
+    /// The client looks the displayed name up from this
+    pub name_id: u32,
+    /// Kinah merchants charge for one, 0 for items they won't take
+    pub price: u32,
+    /// Every slot it can be worn in, 0 for items that can't be equipped
+    pub slots: u32,
+    /// Only weapons have one
+    pub damage: Option<Damage>,
+    /// Milliseconds between swings
+    pub attack_delay: u16,
+    pub attack_range: f32,
+    pub bonuses: Bonuses,
+    /// Level each class needs to use it, None for classes that can't
+    levels: [Option<u16>; CLASSES],
+}
+
+impl Template {
+    /// Level a class needs to use the item, None if it can't use it at all
+    pub fn required_level(&self, class: u8) -> Option<u16> {
+        *self.levels.get(usize::from(class))?
+    }
+
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.extend(self.id.to_le_bytes());
+        write_string(buf, &self.name);
+        buf.extend(self.name_id.to_le_bytes());
+        buf.extend(self.price.to_le_bytes());
+        buf.extend(self.slots.to_le_bytes());
+        let (min, max) = self
+            .damage
+            .map_or((NONE, NONE), |damage| (damage.min, damage.max));
+        buf.extend(min.to_le_bytes());
+        buf.extend(max.to_le_bytes());
+        buf.extend(self.attack_delay.to_le_bytes());
+        buf.extend(self.attack_range.to_le_bytes());
+        for stat in self.bonuses.stats() {
+            buf.extend(stat.to_le_bytes());
+        }
+        for level in self.levels {
+            buf.extend(level.unwrap_or(NONE).to_le_bytes());
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self> {
+        let id = reader.u32()?;
+        let name = reader.string()?;
+        let name_id = reader.u32()?;
+        let price = reader.u32()?;
+        let slots = reader.u32()?;
+        let (min, max) = (reader.u16()?, reader.u16()?);
+        if min != NONE && min > max {
+            return Err(Error::Binary("damage range inverted"));
+        }
+        let damage = (min != NONE).then_some(Damage { min, max });
+        let attack_delay = reader.u16()?;
+        let attack_range = reader.f32()?;
+        let mut bonuses = Bonuses::default();
+        for stat in bonuses.stats_mut() {
+            *stat = reader.u16()?;
+        }
+        let mut levels = [None; CLASSES];
+        for level in &mut levels {
+            *level = Some(reader.u16()?).filter(|level| *level != NONE);
+        }
+
+        Ok(Self {
+            id,
+            name,
+            name_id,
+            price,
+            slots,
+            damage,
+            attack_delay,
+            attack_range,
+            bonuses,
+            levels,
+        })
+    }
+}
+
+impl TryFrom<ClientItem> for Template {
+    type Error = Error;
+
+    fn try_from(item: ClientItem) -> Result<Self> {
+        // In the same order as the class ids
+        let mut levels = [
+            item.warrior,
+            item.fighter,
+            item.knight,
+            item.scout,
+            item.assassin,
+            item.ranger,
+            item.mage,
+            item.wizard,
+            item.elementalist,
+            item.cleric,
+            item.priest,
+            item.chanter,
+        ];
+        if levels.iter().all(Option::is_none) {
+            levels = [Some(0); CLASSES];
+        }
+        let damage = match (item.min_damage, item.max_damage) {
+            (Some(min), Some(max)) if min > max => {
+                return Err(Error::Template {
+                    id: item.id,
+                    reason: "min_damage is over max_damage",
+                })
+            }
+            (Some(NONE), _) | (_, Some(NONE)) => {
+                return Err(Error::Template {
+                    id: item.id,
+                    reason: "damage can't be stored in the binary form",
+                })
+            }
+            (Some(min), Some(max)) => Some(Damage { min, max }),
+            _ => None,
+        };
+
+        Ok(Self {
+            id: item.id,
+            name: item.name,
+            name_id: item.name_id,
+            price: item.price,
+            slots: item.equipment_slots.map_or(0, EquipmentSlots::mask),
+            damage,
+            attack_delay: item.attack_delay,
+            attack_range: item.attack_range,
+            bonuses: Bonuses {
+                power: item.str,
+                agility: item.agi,
+                knowledge: item.kno,
+                accuracy: item.hit_accuracy,
+                crit: item.critical,
+                parry: item.parry,
+                magic_boost: item.magical_skill_boost,
+                magic_accuracy: item.magical_hit_accuracy,
+                physical_defence: item.physical_defend,
+                magic_resist: item.magical_resist,
+                evasion: item.dodge,
+                block: item.block,
+            },
+            levels,
+        })
+    }
+}
+
+/// Every item template keyed by item id
+#[derive(Debug, Default)]
+pub struct Templates {
+    templates: HashMap<u32, Template>,
+}
+
+impl Templates {
+    /// Read client_items.xml, only needed when compiling the binary form.
+    /// Fails on the first item that can't be used
+    pub fn parse(xml: &str) -> Result<Self> {
+        let items: ClientItems = from_str(xml).map_err(Error::Xml)?;
+        let templates = items
+            .items
+            .into_iter()
+            .map(|item| Ok((item.id, Template::try_from(item)?)))
+            .collect::<Result<_>>()?;
+        Ok(Self { templates })
+    }
+
+    /// The compact binary form, sorted by id so it always comes out the same
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut templates: Vec<&Template> = self.templates.values().collect();
+        templates.sort_unstable_by_key(|template| template.id);
+
+        let mut buf = MAGIC.to_vec();
+        buf.extend((templates.len() as u32).to_le_bytes());
+        for template in templates {
+            template.write(&mut buf);
+        }
+        buf
+    }
+
+    pub fn from_bytes(buf: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(buf);
+        if reader.take::<4>()? != MAGIC {
+            return Err(Error::Binary("not item templates"));
+        }
+        let len = reader.u32()?;
+        let templates = (0..len)
+            .map(|_| {
+                let template = Template::read(&mut reader)?;
+                Ok((template.id, template))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { templates })
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Template> {
+        self.templates.get(&id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Template> {
+        self.templates.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.templates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_round_trip() {
+        let templates = Templates::parse(
+            "<client_items>
+                <client_item>
+                    <id>100000640</id>
+                    <name>sword_n_c1_10a</name>
+                    <name_id>1000001</name_id>
+                    <price>1200</price>
+                    <equipment_slots>main_or_sub</equipment_slots>
+                    <min_damage>20</min_damage>
+                    <max_damage>30</max_damage>
+                    <attack_delay>1500</attack_delay>
+                    <attack_range>1.5</attack_range>
+                    <hit_accuracy>12</hit_accuracy>
+                    <warrior>10</warrior>
+                    <assassin>10</assassin>
+                </client_item>
+                <client_item>
+                    <id>182400001</id>
+                    <name>black_aion_toll_01</name>
+                </client_item>
+            </client_items>",
+        )
+        .unwrap();
+        assert_eq!(templates.len(), 2);
+
+        let sword = templates.get(100000640).unwrap();
+        assert_eq!((sword.name_id, sword.price), (1000001, 1200));
+        assert_eq!(sword.slots, 0x3);
+        assert_eq!(sword.damage, Some(Damage { min: 20, max: 30 }));
+        assert_eq!(sword.bonuses.accuracy, 12);
+        assert_eq!(sword.required_level(0), Some(10));
+        assert_eq!(sword.required_level(6), None);
+
+        let kinah = templates.get(182400001).unwrap();
+        assert_eq!(kinah.slots, 0);
+        assert_eq!(kinah.damage, None);
+        assert_eq!(kinah.price, 0);
+        assert_eq!(kinah.required_level(6), Some(0));
+
+        let bytes = templates.to_bytes();
+        let loaded = Templates::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.get(100000640), Some(sword));
+        assert_eq!(loaded.get(182400001), Some(kinah));
+        assert!(Templates::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn rejects_unusable_damage() {
+        let templates = Templates::parse(
+            "<client_items>
+                <client_item>
+                    <id>100000640</id>
+                    <name>sword_n_c1_10a</name>
+                    <min_damage>30</min_damage>
+                    <max_damage>20</max_damage>
+                </client_item>
+            </client_items>",
+        );
+        assert!(matches!(
+            templates,
+            Err(Error::Template { id: 100000640, .. })
+        ));
+
+        let templates = Templates::parse(
+            "<client_items>
+                <client_item>
+                    <id>100000641</id>
+                    <name>sword_n_c1_10b</name>
+                    <min_damage>20</min_damage>
+                    <max_damage>65535</max_damage>
+                </client_item>
+            </client_items>",
+        );
+        assert!(matches!(
+            templates,
+            Err(Error::Template { id: 100000641, .. })
+        ));
+    }
+}
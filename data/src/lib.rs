@@ -0,0 +1,66 @@
+//! Game data read from the XML `bxml` decodes out of the client's pak files.
+//! Parsing the XML is slow so it is compiled into a compact binary form
+//! ahead of time, the servers only ever load that
+
+pub mod item;
+
+#[derive(Debug)]
+pub enum Error {
+    Xml(quick_xml::DeError),
+    /// The binary form is truncated or from a different version
+    Binary(&'static str),
+    /// An item in the XML that the server can't use as it is
+    Template {
+        id: u32,
+        reason: &'static str,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Reads the little endian values the binary forms are made of
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let Some((bytes, rest)) = self.buf.split_first_chunk::<N>() else {
+            return Err(Error::Binary("truncated"));
+        };
+        self.buf = rest;
+        Ok(*bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.take()?))
+    }
+
+    /// Length prefixed UTF-8
+    fn string(&mut self) -> Result<String> {
+        let len = usize::from(self.u16()?);
+        if self.buf.len() < len {
+            return Err(Error::Binary("truncated"));
+        }
+        let (bytes, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        String::from_utf8(bytes.to_vec()).map_err(|_| Error::Binary("bad name"))
+    }
+}
+
+fn write_string(buf: &mut Vec<u8>, value: &str) {
+    buf.extend((value.len() as u16).to_le_bytes());
+    buf.extend(value.as_bytes());
+}
@@ -0,0 +1,44 @@
+//! Compiles the item templates into the binary form the server embeds, so
+//! the XML is only parsed when it changes rather than every start up. An item
+//! the server can't use fails the build
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where the bxml tool's client_items.xml goes, every item in the client
+const CLIENT_ITEMS_PATH: &str = "../bxml/items.xml";
+/// Trimmed down copy the repo ships for when the client's isn't there
+const SHIPPED_ITEMS_PATH: &str = "data/client_items.xml";
+/// Set to build from an item file anywhere else
+const ITEMS_PATH_VAR: &str = "ITEM_TEMPLATES_XML";
+
+fn main() {
+    println!("cargo:rerun-if-env-changed={ITEMS_PATH_VAR}");
+    println!("cargo:rerun-if-changed={CLIENT_ITEMS_PATH}");
+    println!("cargo:rerun-if-changed={SHIPPED_ITEMS_PATH}");
+
+    let path = match env::var(ITEMS_PATH_VAR) {
+        Ok(path) => {
+            println!("cargo:rerun-if-changed={path}");
+            PathBuf::from(path)
+        }
+        Err(_) if Path::new(CLIENT_ITEMS_PATH).exists() => {
+            PathBuf::from(CLIENT_ITEMS_PATH)
+        }
+        Err(_) => {
+            println!(
+                "cargo:warning=No client items at {CLIENT_ITEMS_PATH}, only \
+                 the few in {SHIPPED_ITEMS_PATH} will exist"
+            );
+            PathBuf::from(SHIPPED_ITEMS_PATH)
+        }
+    };
+
+    let xml = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("Can't read {}: {e}", path.display()));
+    let templates = data::item::Templates::parse(&xml)
+        .unwrap_or_else(|e| panic!("Can't compile {}: {e:?}", path.display()));
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("client_items.bin");
+    fs::write(out, templates.to_bytes()).unwrap();
+}
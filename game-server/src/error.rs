@@ -1,7 +1,54 @@
+use std::fmt;
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug)]
 pub enum Error {
-    SetStreamTimeout(std::io::Error),
-    PeerAddr(std::io::Error),
+    Network(std::io::Error),
+    Database(rusqlite::Error),
+    /// The database was written by a newer server
+    SchemaVersion(usize),
+    IdsExhausted(crate::game::ObjectKind),
+    PacketLength(usize),
+    /// The client filled its read buffer without sending a whole message
+    ReadBufferFull(usize),
+    PacketChecksum {
+        opcode: u16,
+        checksum: u16,
+    },
+    UnknownOpcode(u16),
+    DataFile(std::io::Error),
+    DataParse(quick_xml::DeError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Network(err) => write!(f, "network error: {err}"),
+            Self::Database(err) => write!(f, "database error: {err}"),
+            Self::SchemaVersion(version) => write!(
+                f,
+                "database is on schema version {version}, newer than this \
+                 server"
+            ),
+            Self::IdsExhausted(kind) => write!(f, "ran out of {kind:?} ids"),
+            Self::PacketLength(len) => {
+                write!(f, "message too short at {len} bytes")
+            }
+            Self::ReadBufferFull(len) => {
+                write!(f, "{len} bytes read without a whole message")
+            }
+            Self::PacketChecksum { opcode, checksum } => write!(
+                f,
+                "bad checksum {checksum:#06X} on opcode {opcode:#06X}"
+            ),
+            Self::UnknownOpcode(opcode) => {
+                write!(f, "unknown opcode {opcode:#06X}")
+            }
+            Self::DataFile(err) => write!(f, "can't read data file: {err}"),
+            Self::DataParse(err) => write!(f, "can't parse data file: {err}"),
+        }
+    }
 }
+
+impl std::error::Error for Error {}
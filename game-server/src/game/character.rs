@@ -1,13 +1,22 @@
-use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
-use crate::to_le_bytes;
+use crate::{copy_bytes, to_le_bytes};
 
-use super::data::gear::RawItem;
+use super::data::gear::Weapon;
+use super::data::item::{
+    Bonuses, Equipment, EquipmentKind, InventoryItem,
+    Templates as ItemTemplates, NO_SLOT,
+};
 use super::data::skill::{Skill, SkillType};
-use super::engine::damage::{Hit, Type};
+use super::engine::combat::{self, Fighter, Hand};
+use super::engine::damage::Hit;
+use super::engine::effect::Effects;
+use super::engine::inventory::{Change, Inventory};
+use super::engine::level;
+use super::engine::regen::{self, Pace, Regenerated};
 use super::entity::Entity;
 use super::{
-    data::gear::{Gear, Item, Slot, SlotType},
+    data::gear::{Gear, Slot},
     engine::Coord,
 };
 
@@ -25,14 +34,124 @@ pub struct Stats {
     pub dp: Dp,
     /// Attacks per second in millis
     pub attack_speed: u16,
+    /// How far the main hand reaches
+    pub attack_range: f32,
     pub move_speed: f32,
     pub cast_speed: f32,
+    /// Added by whatever is equipped
+    pub gear: Bonuses,
 }
 
 impl Stats {
     pub fn level(&self) -> u16 {
         self.level
     }
+
+    /// Dying costs a slice of the current level's experience but never a
+    /// level, returns the experience lost
+    pub fn death_penalty(&mut self) -> u64 {
+        let lost = (self.exp_to_level * DEATH_PENALTY / 100).min(self.exp);
+        self.exp -= lost;
+        lost
+    }
+
+    /// Add experience and level up as many times as it covers, returns the
+    /// number of levels gained
+    pub fn gain_exp(&mut self, class: Class, exp: u64) -> u16 {
+        let start = self.level;
+        self.exp += exp;
+        while self.level < level::MAX_LEVEL && self.exp >= self.exp_to_level {
+            self.exp -= self.exp_to_level;
+            self.set_level(class, self.level + 1);
+        }
+        if self.level == level::MAX_LEVEL {
+            self.exp = self.exp.min(self.exp_to_level);
+        }
+        self.level - start
+    }
+
+    /// Recompute everything that grows with level from the class's growth
+    /// table, this also fully heals
+    pub fn set_level(&mut self, class: Class, level: u16) {
+        self.level = level;
+        self.exp_to_level = level::exp_to_level(level);
+        self.recompute(class);
+        self.hp.current = self.hp.max;
+        self.mp.current = self.mp.max;
+    }
+
+    /// Change what equipped items add, HP and MP are kept as long as they
+    /// still fit
+    pub fn set_gear(&mut self, class: Class, gear: Bonuses) {
+        self.gear = gear;
+        self.recompute(class);
+    }
+
+    /// Everything that comes from the level and the gear
+    fn recompute(&mut self, class: Class) {
+        let growth = level::growth(class);
+        let level = self.level;
+        let gained = level.saturating_sub(1);
+        let gear = self.gear;
+        let [power, health, agility, accuracy, knowledge, will] =
+            growth.primary;
+        let (power, agility, knowledge) = (
+            power + gear.power,
+            agility + gear.agility,
+            knowledge + gear.knowledge,
+        );
+
+        self.primary = Primary {
+            power,
+            health,
+            agility,
+            accuracy,
+            knowledge,
+            will,
+        };
+
+        let hp = (growth.hp.0 + growth.hp.1 * i32::from(gained))
+            * i32::from(health)
+            / 100;
+        self.hp = Hp {
+            max: hp,
+            base: hp,
+            current: self.hp.current.min(hp),
+        };
+        let mp = (growth.mp.0 + growth.mp.1 * u32::from(gained))
+            * u32::from(will)
+            / 100;
+        self.mp = Mp {
+            max: mp,
+            base: mp,
+            current: self.mp.current.min(mp),
+        };
+
+        let secondary = &mut self.secondary;
+        secondary.main_hand_attack = power / 5 + level * 2;
+        secondary.off_hand_attack = secondary.main_hand_attack * 85 / 100;
+        secondary.main_hand_accuracy = accuracy * 2 + level * 16;
+        secondary.off_hand_accuracy = secondary.main_hand_accuracy;
+        secondary.evasion = agility * 3 + level * 12;
+        secondary.parry = power * 3 + level * 16;
+        secondary.block = health + level * 12;
+        secondary.main_hand_crit = agility / 2;
+        secondary.off_hand_crit = agility / 2;
+        secondary.magic_accuracy = knowledge + level * 8;
+        secondary.magic_resist = will * 2;
+
+        secondary.main_hand_accuracy += gear.accuracy;
+        secondary.off_hand_accuracy += gear.accuracy;
+        secondary.main_hand_crit += gear.crit;
+        secondary.off_hand_crit += gear.crit;
+        secondary.parry += gear.parry;
+        secondary.evasion += gear.evasion;
+        secondary.block += gear.block;
+        secondary.magic_accuracy += gear.magic_accuracy;
+        secondary.magic_resist += gear.magic_resist;
+        secondary.magic_boost = gear.magic_boost;
+        secondary.physical_defence = gear.physical_defence;
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -114,8 +233,18 @@ impl Hp {
     pub fn percent(&self) -> u8 {
         ((self.current as f32 / self.max as f32) * 100.) as u8
     }
+    /// Never goes over max
     pub fn update(&mut self, x: i32) {
-        self.current += x;
+        self.current = (self.current + x).min(self.max);
+    }
+    /// Never goes over max
+    pub fn set_current(&mut self, current: i32) {
+        self.current = current.min(self.max);
+    }
+    /// Restore a saved value, used when loading from the database. It is
+    /// only held to max once the gear is worn, which can raise it
+    pub fn restore(&mut self, current: i32) {
+        self.current = current;
     }
 }
 
@@ -135,6 +264,24 @@ impl Mp {
     pub fn current(&self) -> u32 {
         self.current
     }
+    /// Restore a saved value the same way as [Hp::restore]
+    pub fn restore(&mut self, current: u32) {
+        self.current = current;
+    }
+    /// Takes the MP if there is enough of it
+    pub fn spend(&mut self, mp: u32) -> bool {
+        let Some(current) = self.current.checked_sub(mp) else {
+            return false;
+        };
+        self.current = current;
+        true
+    }
+    /// Never goes over max, returns how much was actually gained
+    pub fn gain(&mut self, mp: u32) -> u32 {
+        let gained = mp.min(self.max.saturating_sub(self.current));
+        self.current += gained;
+        gained
+    }
 }
 #[derive(Debug, Clone)]
 pub struct Dp {
@@ -149,42 +296,214 @@ impl Dp {
     pub fn current(&self) -> u16 {
         self.current
     }
+    /// Takes the DP if there is enough of it
+    pub fn spend(&mut self, dp: u16) -> bool {
+        let Some(current) = self.current.checked_sub(dp) else {
+            return false;
+        };
+        self.current = current;
+        true
+    }
+    /// Never goes over max, returns how much was actually gained
+    pub fn gain(&mut self, dp: u16) -> u16 {
+        let gained = dp.min(self.max.saturating_sub(self.current));
+        self.current += gained;
+        gained
+    }
+}
+
+/// Percent of the level's experience lost on death
+const DEATH_PENALTY: u64 = 3;
+/// Percent of HP and MP a character comes back with
+const RESURRECT_PERCENT: i32 = 25;
+/// How long NPCs ignore a character after it resurrects
+const RESURRECT_PROTECTION: Duration = Duration::from_secs(60);
+/// A character is out of combat this long after the last blow
+const COMBAT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Shouts reach the whole world so each character only gets one this often
+const SHOUT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Milliseconds between swings without a weapon
+const UNARMED_ATTACK_SPEED: u16 = 1750;
+/// Reach without a weapon
+const UNARMED_ATTACK_RANGE: f32 = 1.5;
+/// Added to the reach of every swing, positions lag behind and the target
+/// has a body rather than being a point
+const REACH_SLACK: f32 = 3.;
+/// Fraction of the attack speed that has to pass between swings, the rest
+/// is left for latency jitter
+const SWING_TOLERANCE: f32 = 0.8;
+
+/// New characters skip the tutorial levels
+const STARTING_LEVEL: u16 = 14;
+
+/// Length of the appearance block the client sends on character creation,
+/// everything from skin colour up to and including height
+pub const APPEARANCE_LEN: usize = 72;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum Gender {
+    Male = 0,
+    Female = 1,
+}
+impl TryFrom<u32> for Gender {
+    type Error = u32;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Self::Male,
+            1 => Self::Female,
+            gender => return Err(gender),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum Race {
+    Elyos = 0,
+    Asmodian = 1,
+}
+impl TryFrom<u32> for Race {
+    type Error = u32;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Self::Elyos,
+            1 => Self::Asmodian,
+            race => return Err(race),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum Class {
+    Warrior = 0,
+    Gladiator = 1,
+    Templar = 2,
+    Scout = 3,
+    Assassin = 4,
+    Ranger = 5,
+    Mage = 6,
+    Sorcerer = 7,
+    SpiritMaster = 8,
+    Priest = 9,
+    Cleric = 10,
+    Chanter = 11,
+}
+impl TryFrom<u32> for Class {
+    type Error = u32;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Self::Warrior,
+            1 => Self::Gladiator,
+            2 => Self::Templar,
+            3 => Self::Scout,
+            4 => Self::Assassin,
+            5 => Self::Ranger,
+            6 => Self::Mage,
+            7 => Self::Sorcerer,
+            8 => Self::SpiritMaster,
+            9 => Self::Priest,
+            10 => Self::Cleric,
+            11 => Self::Chanter,
+            class => return Err(class),
+        })
+    }
+}
+
+/// Everything picked on the character creation screen
+#[derive(Debug, Clone)]
+pub struct Appearance {
+    pub gender: Gender,
+    pub race: Race,
+    pub class: Class,
+    pub voice: u32,
+    /// Colours, face and body sliders, kept exactly as the client sent them
+    pub raw: [u8; APPEARANCE_LEN],
+}
+
+impl Appearance {
+    /// The appearance of our captured character, Azphelumbra
+    #[cfg(test)]
+    pub fn starter() -> Self {
+        Self {
+            gender: Gender::Female,
+            race: Race::Asmodian,
+            class: Class::Assassin,
+            voice: 2,
+            raw: [
+                0xAB, 0xAB, 0xBC, 0x00, 0xFF, 0xFF, 0xFF, 0x00, 0xE9, 0x02,
+                0x0F, 0x00, 0xCE, 0xB4, 0xCA, 0x00, 0x00, 0x0A, 0x00, 0x00,
+                0x00, 0x02, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x09, 0x83, 0x8F, 0x00, 0x03, 0x00, 0x00, 0x00, 0x83,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x04, 0xEF,
+                0xB3, 0xDA, 0xB2, 0xF0, 0xDF, 0xEF, 0xBA, 0xF4, 0xCE, 0x00,
+                0x00, 0xF0, 0xF4, 0xF5, 0xE4, 0x00, 0x00, 0x00, 0x66, 0x66,
+                0x26, 0x3F,
+            ],
+        }
+    }
+
+    pub fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        to_le_bytes!(len, buf, self.gender as u32);
+        to_le_bytes!(len, buf, self.race as u32);
+        to_le_bytes!(len, buf, self.class as u32);
+        to_le_bytes!(len, buf, self.voice);
+        copy_bytes!(len, buf, self.raw);
+        len
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Character {
     id: u32,
+    /// The account that owns this character
+    account_id: u32,
+    /// Set when the character enters the world, 0 while offline
     client_id: u16,
     name: String,
+    pub appearance: Appearance,
     location: Coord,
+    /// Unix time the character is permanently deleted, None unless the
+    /// player has asked for it to be deleted
+    deletion_time: Option<u32>,
     pub gear: Gear,
     pub stats: Stats,
     pub skills: Vec<Skill>,
-    pub items: HashMap<u32, RawItem>,
+    pub inventory: Inventory,
+    /// Buffs and debuffs, these are lost when the character leaves the world
+    pub effects: Effects,
+    /// Set by the killing blow and taken once the death has been dealt with
+    killer: Option<u32>,
+    /// NPCs leave the character alone until then
+    invincible_until: Option<Instant>,
+    /// Still in combat until then, recovery is slower in a fight
+    combat_until: Option<Instant>,
+    /// Sat down to recover faster
+    resting: bool,
     attack_sequence: u8,
+    /// Still swinging until then, auto attacks before it are ignored
+    swing_until: Option<Instant>,
+    /// Shouts before then are dropped, see [SHOUT_INTERVAL]
+    shout_until: Option<Instant>,
+    /// What is held in the main and off hand, set by [Character::wear]
+    weapons: [Option<Weapon>; 2],
 }
 impl Character {
-    pub fn new(id: u32, client_id: u16, location: Coord) -> Self {
-        let mut gear = Gear::new();
-        gear.set(Slot::MainHand, Item::new(100000768, SlotType::Visible));
-        gear.set(
-            Slot::OffHand,
-            Item::new(100000768, SlotType::VisibleOffHand),
-        );
-        gear.set(Slot::Shoulder, Item::new(112300777, SlotType::Visible));
-        gear.set(Slot::Chest, Item::new(110300873, SlotType::Visible));
-        gear.set(Slot::Legs, Item::new(113300848, SlotType::Visible));
-        gear.set(Slot::Boots, Item::new(114300884, SlotType::Visible));
-        gear.set(Slot::Gloves, Item::new(111300826, SlotType::Visible));
-        gear.set(
-            Slot::PowerShardLeft,
-            Item::new(169000002, SlotType::Visible),
-        );
-        gear.set(
-            Slot::PowerShardRight,
-            Item::new(169000002, SlotType::Visible),
-        );
-
+    /// Creates a character with the starter gear, skills and inventory
+    pub fn new(
+        id: u32,
+        account_id: u32,
+        name: String,
+        appearance: Appearance,
+        location: Coord,
+    ) -> Self {
         let skills = vec![
             Skill::new(1, 1, SkillType::Normal),
             Skill::new(4, 1, SkillType::Normal),
@@ -224,278 +543,253 @@ impl Character {
 
         let items = [
             // Kinah
-            RawItem::new(
-                0xC76BA3B4,
-                0x0ADF3401,
-                0x1569DB,
-                0xFFFF,
-                vec![0x00, 0x1E, 0x63, 0xED, 0x4F, 0x01, 0x00],
-            ),
+            InventoryItem::kinah(0xC76BA3B4, 85997),
             // Aldelle's Leather Shoes
-            RawItem::new(
-                0xC76BA3AC,
-                0x06D016E0,
-                0x1607EB,
-                0xFFFF,
-                vec![
-                    0x06, 0x20, 0x00, 0x00, 0x00, 0x02, 0x20, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0B, 0x01,
-                    0x00, //
-                    0xE0, 0x16, 0xD0, 0x06, //
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x0A, 0x20, 0x00, 0x09, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x7E, 0x02, 0x01, 0x00, 0x00, 0x00,
-                ],
-            ),
+            InventoryItem {
+                soulbound: true,
+                ..InventoryItem::equipment(
+                    0xC76BA3AC,
+                    0x06D016E0,
+                    0x1607EB,
+                    NO_SLOT,
+                    0x027E,
+                    Equipment {
+                        kind: EquipmentKind::Armour,
+                        equipped: 0x20,
+                        slots: 0x20,
+                        other_slots: 0x0,
+                        skin: 0x06D016E0,
+                        bonuses: vec![(0x20, 9)],
+                        unknown: Some(0),
+                    },
+                )
+            },
             // Night Sky Sword
-            RawItem::new(
-                0xC76BA3AD,
-                0x05F5E2C4,
-                0x15F72F,
-                0xFFFF,
-                vec![
-                    0x06, 0x02, 0x00, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00,
-                    0x02, 0x00, 0x00, 0x00, 0x0B, 0x01, 0x00, 0xC4, 0xE2, 0xF5,
-                    0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x0A, 0x1E, 0x00, 0x1D, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x7E, 0x0A, 0x01, 0x00, 0x00, 0x00,
-                ],
-            ),
-            // Minor Power Shard
-            RawItem::new(
-                0xC76BA3AE,
-                0x0A12BC43,
-                0x156AE7,
-                0xFFFF,
-                vec![
-                    0x06, 0x00, 0x20, 0x00, 0x00, 0x04, 0x00, 0x20, 0x00, 0x00,
-                    0x00, 0x40, 0x00, 0x00, 0x0B, 0x00, 0x00, 0x43, 0xBC, 0x12,
-                    0x0A, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3A, 0x61, 0x99, 0x00,
-                    0x00, 0x00,
-                ],
-            ),
+            InventoryItem {
+                soulbound: true,
+                ..InventoryItem::equipment(
+                    0xC76BA3AD,
+                    0x05F5E2C4,
+                    0x15F72F,
+                    NO_SLOT,
+                    0x0A7E,
+                    Equipment {
+                        kind: EquipmentKind::Weapon,
+                        equipped: 0x2,
+                        slots: 0x1,
+                        other_slots: 0x2,
+                        skin: 0x05F5E2C4,
+                        bonuses: vec![(0x1E, 29)],
+                        unknown: Some(0),
+                    },
+                )
+            },
+            // Minor Power Shard, the count is how much power it has left
+            InventoryItem {
+                count: 153,
+                ..InventoryItem::equipment(
+                    0xC76BA3AE,
+                    0x0A12BC43,
+                    0x156AE7,
+                    NO_SLOT,
+                    0x613A,
+                    Equipment {
+                        kind: EquipmentKind::Accessory,
+                        equipped: 0x2000,
+                        slots: 0x2000,
+                        other_slots: 0x4000,
+                        skin: 0x0A12BC43,
+                        bonuses: vec![],
+                        unknown: Some(0),
+                    },
+                )
+            },
             // Shania's Jewel Ring
-            RawItem::new(
+            InventoryItem::equipment(
                 0xC76BA3AF,
                 0x07459784,
                 0x17838D,
-                0xFFFF,
-                vec![
-                    0x06, 0x00, 0x01, 0x00, 0x00, 0x04, 0x00, 0x02, 0x00, 0x00,
-                    0x00, 0x01, 0x00, 0x00, 0x0B, 0x00, 0x00, 0x84, 0x97, 0x45,
-                    0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x0A, 0x12, 0x00, 0x1C, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x2C, 0x41, 0x01, 0x00, 0x00, 0x00,
-                ],
+                NO_SLOT,
+                0x412C,
+                Equipment {
+                    kind: EquipmentKind::Accessory,
+                    equipped: 0x100,
+                    slots: 0x200,
+                    other_slots: 0x100,
+                    skin: 0x07459784,
+                    bonuses: vec![(0x12, 28)],
+                    unknown: Some(0),
+                },
             ),
             // Black Opal
-            RawItem::new(
+            InventoryItem::equipment(
                 0xC76BA3B0,
                 0x0736532D,
                 0x169B73,
-                0xFFFF,
-                vec![
-                    0x06, 0x00, 0x04, 0x00, 0x00, 0x04, 0x00, 0x04, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x0B, 0x00, 0x00, 0x2D, 0x53, 0x36,
-                    0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x0A, 0x12, 0x00, 0x1F, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x2C, 0x41, 0x01, 0x00, 0x00, 0x00,
-                ],
+                NO_SLOT,
+                0x412C,
+                Equipment {
+                    kind: EquipmentKind::Accessory,
+                    equipped: 0x400,
+                    slots: 0x400,
+                    other_slots: 0x0,
+                    skin: 0x0736532D,
+                    bonuses: vec![(0x12, 31)],
+                    unknown: Some(0),
+                },
             ),
             // Shania's Leather Hat
-            RawItem::new(
+            InventoryItem::equipment(
                 0xC76BA3B1,
                 0x07736024,
                 0x169B31,
-                0xFFFF,
-                vec![
-                    0x06, 0x04, 0x00, 0x00, 0x00, 0x04, 0x04, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x0B, 0x00, 0x00, 0x24, 0x60, 0x73,
-                    0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x0A, 0x12, 0x00, 0x3D, 0x00,
-                    0x00, 0x00, 0x00, 0x0A, 0x1A, 0x00, 0x21, 0x00, 0x00, 0x00,
-                    0x00, 0x0A, 0x1C, 0x00, 0x11, 0x00, 0x00, 0x00, 0x00, 0x0A,
-                    0x1F, 0x00, 0x26, 0x00, 0x00, 0x00, 0x00, 0x00, 0x2C, 0x01,
-                    0x01, 0x00, 0x00, 0x00,
-                ],
+                NO_SLOT,
+                0x012C,
+                Equipment {
+                    kind: EquipmentKind::Accessory,
+                    equipped: 0x4,
+                    slots: 0x4,
+                    other_slots: 0x0,
+                    skin: 0x07736024,
+                    bonuses: vec![
+                        (0x12, 61),
+                        (0x1A, 33),
+                        (0x1C, 17),
+                        (0x1F, 38),
+                    ],
+                    unknown: Some(0),
+                },
             ),
             // Anturoon Jerkin
-            RawItem::new(
+            InventoryItem::equipment(
                 0xC76BA3B2,
                 0x06930FFE,
                 0x17891D,
-                0xFFFF,
-                vec![
-                    0x06, 0x08, 0x00, 0x00, 0x00, 0x02, 0x08, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0B, 0x00,
-                    0x00, 0xFE, 0x0F, 0x93, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0A,
-                    0x12, 0x00, 0x3D, 0x00, 0x00, 0x00, 0x00, 0x00, 0x2C, 0x00,
-                    0x01, 0x00, 0x00, 0x00,
-                ],
+                NO_SLOT,
+                0x002C,
+                Equipment {
+                    kind: EquipmentKind::Armour,
+                    equipped: 0x8,
+                    slots: 0x8,
+                    other_slots: 0x0,
+                    skin: 0x06930FFE,
+                    bonuses: vec![(0x12, 61)],
+                    unknown: Some(0),
+                },
             ),
             // Spirit Ring
-            RawItem::new(
+            InventoryItem::equipment(
                 0xC76BA3B3,
                 0x074595E5,
                 0x169B91,
-                0xFFFF,
-                vec![
-                    0x06, 0x00, 0x02, 0x00, 0x00, 0x04, 0x00, 0x02, 0x00, 0x00,
-                    0x00, 0x01, 0x00, 0x00, 0x0B, 0x00, 0x00, 0xE5, 0x95, 0x45,
-                    0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x0A, 0x12, 0x00, 0x10, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x2C, 0x41, 0x01, 0x00, 0x00, 0x00,
-                ],
+                NO_SLOT,
+                0x412C,
+                Equipment {
+                    kind: EquipmentKind::Accessory,
+                    equipped: 0x200,
+                    slots: 0x200,
+                    other_slots: 0x100,
+                    skin: 0x074595E5,
+                    bonuses: vec![(0x12, 16)],
+                    unknown: Some(0),
+                },
             ),
             // Noble's Leather Belt
-            RawItem::new(
-                0xC76BA3B5,
-                0x0754D670,
-                0x15AEC7,
-                0xFFFF,
-                vec![
-                    0x06, 0x00, 0x00, 0x01, 0x00, 0x04, 0x00, 0x00, 0x01, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x0B, 0x01, 0x00, 0x70, 0xD6, 0x54,
-                    0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x0A, 0x12, 0x00, 0x1B, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x7E, 0x43, 0x01, 0x00, 0x00, 0x00,
-                ],
-            ),
+            InventoryItem {
+                soulbound: true,
+                ..InventoryItem::equipment(
+                    0xC76BA3B5,
+                    0x0754D670,
+                    0x15AEC7,
+                    NO_SLOT,
+                    0x437E,
+                    Equipment {
+                        kind: EquipmentKind::Accessory,
+                        equipped: 0x10000,
+                        slots: 0x10000,
+                        other_slots: 0x0,
+                        skin: 0x0754D670,
+                        bonuses: vec![(0x12, 27)],
+                        unknown: Some(0),
+                    },
+                )
+            },
             // Karmic Sword
-            RawItem::new(
+            InventoryItem::equipment(
                 0xC76BA3B6,
                 0x05F5E380,
                 0x16990D,
-                0xFFFF,
-                vec![
-                    0x06, 0x01, 0x00, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00,
-                    0x02, 0x00, 0x00, 0x00, 0x0B, 0x00, 0x00, 0x80, 0xE3, 0xF5,
-                    0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x0A, 0x22, 0x00, 0x0D, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x2C, 0x08, 0x01, 0x00, 0x00, 0x00,
-                ],
+                NO_SLOT,
+                0x082C,
+                Equipment {
+                    kind: EquipmentKind::Weapon,
+                    equipped: 0x1,
+                    slots: 0x1,
+                    other_slots: 0x2,
+                    skin: 0x05F5E380,
+                    bonuses: vec![(0x22, 13)],
+                    unknown: None,
+                },
             ),
             // Fighter's Vambraces
-            RawItem::new(
+            InventoryItem::equipment(
                 0xC76BA3B7,
                 0x06A250A0,
                 0x169A47,
-                0xFFFF,
-                vec![
-                    0x06, 0x10, 0x00, 0x00, 0x00, 0x02, 0x10, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0B, 0x00,
-                    0x00, 0xA0, 0x50, 0xA2, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x2C, 0x00, 0x01, 0x00,
-                    0x00, 0x00,
-                ],
+                NO_SLOT,
+                0x002C,
+                Equipment {
+                    kind: EquipmentKind::Armour,
+                    equipped: 0x10,
+                    slots: 0x10,
+                    other_slots: 0x0,
+                    skin: 0x06A250A0,
+                    bonuses: vec![],
+                    unknown: None,
+                },
             ),
             // Anturoon Breeches
-            RawItem::new(
+            InventoryItem::equipment(
                 0xC76BA3B8,
                 0x06C0D537,
                 0x169A27,
                 0xFF,
-                vec![
-                    0x06, 0x00, 0x10, 0x00, 0x00, 0x02, 0x00, 0x10, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0B, 0x00,
-                    0x00, 0x37, 0xD5, 0xC0, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x2C, 0x00, 0x01, 0x00,
-                    0x00, 0x00,
-                ],
+                0x002C,
+                Equipment {
+                    kind: EquipmentKind::Armour,
+                    equipped: 0x1000,
+                    slots: 0x1000,
+                    other_slots: 0x0,
+                    skin: 0x06C0D537,
+                    bonuses: vec![],
+                    unknown: None,
+                },
             ),
             // Lesser Healing Potion
-            RawItem::new(
-                0xC76BA3B9,
-                0x09A7EC96,
-                0x157129,
-                0x26,
-                vec![0x00, 0x3E, 0x63, 0x04, 0x00, 0x00, 0x00],
-            ),
+            InventoryItem::stack(0xC76BA3B9, 0x09A7EC96, 0x157129, 4, 0x26),
             // Minor Accessory Flux
-            RawItem::new(
-                0xC76BA3BA,
-                0x090F8121,
-                0x1596D1,
-                0x09,
-                vec![0x00, 0x3E, 0x63, 0x03, 0x00, 0x00, 0x00],
-            ),
+            InventoryItem::stack(0xC76BA3BA, 0x090F8121, 0x1596D1, 3, 0x09),
             // Roast Brax
-            RawItem::new(
-                0xC76BA3BB,
-                0x098975AD,
-                0x15961,
-                0x09,
-                vec![0x00, 0x3E, 0x63, 0x03, 0x00, 0x00, 0x00],
-            ),
+            InventoryItem::stack(0xC76BA3BB, 0x098975AD, 0x15961, 3, 0x09),
             // Lesser Focus Agent
-            RawItem::new(
-                0xC76BA3C5,
-                0x098975E6,
-                0x178083,
-                0x11,
-                vec![0x00, 0x3E, 0x63, 0x09, 0x00, 0x00, 0x00],
-            ),
-        ]
-        .into_iter()
-        .map(|item| (item.id, item))
-        .collect();
+            InventoryItem::stack(0xC76BA3C5, 0x098975E6, 0x178083, 9, 0x11),
+        ];
+        let mut inventory = Inventory::default();
+        for item in items {
+            inventory.insert(item);
+        }
+        let gear = Gear::worn(inventory.equipped());
 
-        Self {
-            client_id,
+        let class = appearance.class;
+        let mut character = Self {
+            client_id: 0,
+            account_id,
             id,
             location,
+            deletion_time: None,
             gear,
-            name: "Azphelumbra".into(),
+            name,
+            appearance,
             stats: Stats {
                 level: 14,
                 exp: 226334,
@@ -552,28 +846,57 @@ impl Character {
                     max: 4000,
                     current: 2000,
                 },
-                attack_speed: 1750,
+                attack_speed: UNARMED_ATTACK_SPEED,
+                attack_range: UNARMED_ATTACK_RANGE,
                 move_speed: 6.0,
                 cast_speed: 1.0,
+                gear: Bonuses::default(),
             },
             skills,
+            effects: Effects::default(),
+            killer: None,
+            invincible_until: None,
+            combat_until: None,
+            resting: false,
             attack_sequence: 0,
-            items,
-        }
+            swing_until: None,
+            shout_until: None,
+            inventory,
+            weapons: [None; 2],
+        };
+        character.stats.set_level(class, STARTING_LEVEL);
+        character
     }
 
-    pub fn set_id(&mut self, id: u32) {
-        self.id = id;
-    }
     pub fn id(&self) -> u32 {
         self.id
     }
+    pub fn account_id(&self) -> u32 {
+        self.account_id
+    }
     pub fn client_id(&self) -> u16 {
         self.client_id
     }
+    pub fn set_client_id(&mut self, client_id: u16) {
+        self.client_id = client_id;
+    }
     pub fn name(&self) -> &String {
         &self.name
     }
+    pub fn deletion_time(&self) -> Option<u32> {
+        self.deletion_time
+    }
+    pub fn set_deletion_time(&mut self, deletion_time: Option<u32>) {
+        self.deletion_time = deletion_time;
+    }
+    /// Kinah is kept in the inventory so it is saved along with the items
+    pub fn kinah(&self) -> u32 {
+        self.inventory.kinah()
+    }
+    /// Returns the number of levels gained
+    pub fn gain_exp(&mut self, exp: u64) -> u16 {
+        self.stats.gain_exp(self.appearance.class, exp)
+    }
     pub fn set_location(&mut self, coord: Coord) {
         self.location = coord;
     }
@@ -589,20 +912,253 @@ impl Character {
         });
         len
     }
+    pub fn is_dead(&self) -> bool {
+        self.stats.hp.current() <= 0
+    }
+
+    /// Alive and not protected after resurrecting
+    pub fn is_attackable(&self) -> bool {
+        !self.is_dead() && self.invincible_remaining().is_zero()
+    }
+
+    pub fn invincible_remaining(&self) -> Duration {
+        self.invincible_until
+            .map(|until| until.saturating_duration_since(Instant::now()))
+            .unwrap_or_default()
+    }
+
+    /// Damage from an NPC or effect after shields, the blow that kills the
+    /// character is remembered so the death can be handled once
+    pub fn hurt(&mut self, damage: i32, attacker_id: u32) -> i32 {
+        if self.is_dead() {
+            return 0;
+        }
+        self.enter_combat();
+        let damage = self.effects.absorb(damage);
+        self.stats.hp.update(-damage);
+        if self.is_dead() {
+            self.killer = Some(attacker_id);
+        }
+        damage
+    }
+
+    /// Attacking or being attacked, this also gets the character up
+    pub fn enter_combat(&mut self) {
+        self.combat_until = Some(Instant::now() + COMBAT_TIMEOUT);
+        self.resting = false;
+    }
+
+    pub fn in_combat(&self) -> bool {
+        self.combat_until
+            .is_some_and(|until| until > Instant::now())
+    }
+
+    /// Whether the character can shout yet, starting the wait for the next
+    /// shout when it can
+    pub fn start_shout(&mut self) -> bool {
+        let now = Instant::now();
+        if self.shout_until.is_some_and(|until| until > now) {
+            return false;
+        }
+        self.shout_until = Some(now + SHOUT_INTERVAL);
+        true
+    }
+
+    pub fn set_resting(&mut self, resting: bool) {
+        self.resting = resting;
+    }
+
+    /// Recover one regeneration interval's worth of HP, MP and DP
+    pub fn regenerate(&mut self) -> Regenerated {
+        let pace = if self.in_combat() {
+            Pace::Combat
+        } else if self.resting {
+            Pace::Resting
+        } else {
+            Pace::Normal
+        };
+        let stats = &mut self.stats;
+        let hp = regen::hp(stats.hp.max(), stats.primary.health, pace);
+        let mp = regen::mp(stats.mp.max(), stats.primary.will, pace);
+
+        let before = stats.hp.current();
+        stats.hp.update(hp);
+        Regenerated {
+            hp: stats.hp.current() != before,
+            mp: stats.mp.gain(mp) > 0,
+            dp: stats.dp.gain(regen::dp(pace)) > 0,
+        }
+    }
+
+    /// Who killed the character, only returned once per death
+    pub fn take_killer(&mut self) -> Option<u32> {
+        self.killer.take()
+    }
+
+    /// Back on their feet somewhere else with part of their HP and MP
+    pub fn resurrect(&mut self, location: Coord) {
+        let hp = &mut self.stats.hp;
+        hp.current = hp.max * RESURRECT_PERCENT / 100;
+        let mp = &mut self.stats.mp;
+        mp.current = mp.max * RESURRECT_PERCENT as u32 / 100;
+        self.location = location;
+        self.killer = None;
+        self.invincible_until = Some(Instant::now() + RESURRECT_PROTECTION);
+    }
+
+    /// The client picks when to swing and at what, a swing only starts
+    /// when the target is in reach of the main hand and the last swing is
+    /// over
+    pub fn start_swing(&mut self, target: &Coord) -> Result<(), &'static str> {
+        if self.location.distance(target)
+            > self.stats.attack_range + REACH_SLACK
+        {
+            return Err("out of reach");
+        }
+        let now = Instant::now();
+        if self.swing_until.is_some_and(|until| until > now) {
+            return Err("still swinging");
+        }
+        let speed = Duration::from_millis(self.stats.attack_speed.into());
+        self.swing_until = Some(now + speed.mul_f32(SWING_TOLERANCE));
+        Ok(())
+    }
+
+    /// Auto attack, the off hand only swings when it is holding a weapon
     pub fn attack(&mut self, entity: &mut Entity) -> Vec<Hit> {
-        let mut dmg = 0;
+        let mut rng = rand::thread_rng();
+        let defender = entity.fighter();
 
-        let hits = vec![
-            Hit::new(110, Type::MainHand),
-            Hit::new(48, Type::OffHand),
-            Hit::new(4, Type::OffHand),
-        ];
-        for hit in &hits {
-            dmg += hit.damage;
+        let mut hits = vec![combat::swing(
+            &self.fighter(Hand::Main),
+            &defender,
+            Hand::Main,
+            &mut rng,
+        )];
+        if self.weapon(Slot::OffHand).is_some() {
+            hits.push(combat::swing(
+                &self.fighter(Hand::Off),
+                &defender,
+                Hand::Off,
+                &mut rng,
+            ));
         }
+        let dmg: i32 = hits.iter().map(|hit| hit.damage).sum();
         entity.hp.update(-dmg);
         self.attack_sequence = self.attack_sequence.wrapping_add(1);
 
         hits
     }
+
+    /// Combat stats for a swing with one hand
+    pub fn fighter(&self, hand: Hand) -> Fighter {
+        let secondary = &self.stats.secondary;
+        let (slot, attack, accuracy, crit) = match hand {
+            Hand::Main => (
+                Slot::MainHand,
+                secondary.main_hand_attack,
+                secondary.main_hand_accuracy,
+                secondary.main_hand_crit,
+            ),
+            Hand::Off => (
+                Slot::OffHand,
+                secondary.off_hand_attack,
+                secondary.off_hand_accuracy,
+                secondary.off_hand_crit,
+            ),
+        };
+        Fighter {
+            level: self.stats.level,
+            weapon: self.weapon(slot).unwrap_or(Weapon::UNARMED),
+            attack: i32::from(attack) + self.effects.attack(),
+            accuracy: accuracy.into(),
+            crit: crit.into(),
+            defence: i32::from(secondary.physical_defence)
+                + self.effects.defence(),
+            evasion: secondary.evasion.into(),
+            parry: secondary.parry.into(),
+            block: secondary.block.into(),
+        }
+    }
+
+    fn weapon(&self, slot: Slot) -> Option<Weapon> {
+        self.weapons.get(slot as usize).copied().flatten()
+    }
+
+    /// Wear an item from the cube if the class and level allow it. The slot
+    /// the client asks for is used when the item fits it, otherwise the
+    /// first empty slot the item fits
+    pub fn equip(
+        &mut self,
+        id: u32,
+        slot: u32,
+        templates: &ItemTemplates,
+    ) -> Result<Vec<Change>, &'static str> {
+        let item = self.inventory.get(id).ok_or("no item")?;
+        let template = templates.get(item.item_id).ok_or("no template")?;
+        let level = template
+            .required_level(self.appearance.class as u8)
+            .ok_or("wrong class")?;
+        if self.stats.level < level {
+            return Err("level too low");
+        }
+
+        let worn = self
+            .inventory
+            .equipped()
+            .fold(0, |worn, item| worn | item.equipped());
+        let slots = [
+            slot & template.slots,
+            template.slots & !worn,
+            template.slots,
+        ];
+        let slots = slots.into_iter().find(|slots| *slots != 0);
+        let slots = slots.ok_or("can't be equipped")?;
+        // Just the lowest slot
+        let changes = self.inventory.equip(id, slots & slots.wrapping_neg())?;
+        self.wear(templates);
+        Ok(changes)
+    }
+
+    pub fn unequip(
+        &mut self,
+        id: u32,
+        templates: &ItemTemplates,
+    ) -> Result<Vec<Change>, &'static str> {
+        let changes = self.inventory.unequip(id)?;
+        self.wear(templates);
+        Ok(changes)
+    }
+
+    /// Rebuild the gear everyone sees and the stats it gives from whatever
+    /// is equipped
+    pub fn wear(&mut self, templates: &ItemTemplates) {
+        self.gear = Gear::worn(self.inventory.equipped());
+        self.weapons = [None; 2];
+        self.stats.attack_speed = UNARMED_ATTACK_SPEED;
+        self.stats.attack_range = UNARMED_ATTACK_RANGE;
+
+        let mut bonuses = Bonuses::default();
+        for item in self.inventory.equipped() {
+            let Some(template) = templates.get(item.item_id) else {
+                println!("WARNING: No template for item {}", item.item_id);
+                continue;
+            };
+            bonuses.add(&template.bonuses);
+            for hand in [Slot::MainHand, Slot::OffHand] {
+                if item.equipped() & hand.mask() == 0 {
+                    continue;
+                }
+                self.weapons[hand as usize] = template.damage.map(Weapon::from);
+                if matches!(hand, Slot::MainHand) && template.attack_delay > 0 {
+                    self.stats.attack_speed = template.attack_delay;
+                }
+                if matches!(hand, Slot::MainHand) && template.attack_range > 0.
+                {
+                    self.stats.attack_range = template.attack_range;
+                }
+            }
+        }
+        self.stats.set_gear(self.appearance.class, bonuses);
+    }
 }
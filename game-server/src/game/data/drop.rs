@@ -0,0 +1,176 @@
+//! Drop tables, what each NPC template can leave on its corpse. These aren't
+//! in the client so they live in our own drops.xml
+
+use std::collections::HashMap;
+use std::fs;
+
+use quick_xml::de::from_str;
+use rand::Rng;
+use serde::Deserialize;
+
+use super::item::{KINAH, KINAH_NAME_ID};
+use crate::error::{Error, Result};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename = "drops")]
+struct DropFile {
+    #[serde(rename = "npc", default)]
+    npcs: Vec<NpcDrops>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NpcDrops {
+    #[serde(rename = "@template_id")]
+    template_id: u32,
+    #[serde(rename = "@min_kinah", default)]
+    min_kinah: u32,
+    #[serde(rename = "@max_kinah", default)]
+    max_kinah: u32,
+    #[serde(rename = "item", default)]
+    items: Vec<Entry>,
+}
+
+/// One item an NPC might drop
+#[derive(Debug, Clone, Deserialize)]
+pub struct Entry {
+    #[serde(rename = "@item_id")]
+    pub item_id: u32,
+    /// The client looks the displayed name up from this
+    #[serde(rename = "@name_id")]
+    pub name_id: u32,
+    /// Percent
+    #[serde(rename = "@chance")]
+    pub chance: f32,
+    #[serde(rename = "@min_count", default = "one")]
+    pub min_count: u32,
+    #[serde(rename = "@max_count", default = "one")]
+    pub max_count: u32,
+}
+
+fn one() -> u32 {
+    1
+}
+
+/// An item rolled from a drop table, waiting on a corpse to be looted
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dropped {
+    pub item_id: u32,
+    pub name_id: u32,
+    pub count: u32,
+}
+
+/// Everything one NPC template can drop
+#[derive(Debug)]
+pub struct Table {
+    pub kinah: (u32, u32),
+    pub items: Vec<Entry>,
+}
+
+impl Table {
+    /// Every item rolls on its own, kinah is always dropped when the table
+    /// has any
+    pub fn roll(&self, rng: &mut impl Rng) -> Vec<Dropped> {
+        let mut dropped = Vec::new();
+        for entry in &self.items {
+            if rng.gen::<f32>() * 100. >= entry.chance {
+                continue;
+            }
+            let count = rng.gen_range(
+                entry.min_count..=entry.max_count.max(entry.min_count),
+            );
+            if count > 0 {
+                dropped.push(Dropped {
+                    item_id: entry.item_id,
+                    name_id: entry.name_id,
+                    count,
+                });
+            }
+        }
+
+        let (min, max) = self.kinah;
+        let kinah = rng.gen_range(min..=max.max(min));
+        if kinah > 0 {
+            dropped.push(Dropped {
+                item_id: KINAH,
+                name_id: KINAH_NAME_ID,
+                count: kinah,
+            });
+        }
+        dropped
+    }
+}
+
+/// Every drop table keyed by NPC template id
+pub struct Tables {
+    tables: HashMap<u32, Table>,
+}
+
+impl Tables {
+    pub fn load(path: &str) -> Result<Self> {
+        let xml = fs::read_to_string(path).map_err(Error::DataFile)?;
+        Self::parse(&xml)
+    }
+
+    pub fn parse(xml: &str) -> Result<Self> {
+        let file: DropFile = from_str(xml).map_err(Error::DataParse)?;
+        let tables = file
+            .npcs
+            .into_iter()
+            .map(|npc| {
+                let table = Table {
+                    kinah: (npc.min_kinah, npc.max_kinah),
+                    items: npc.items,
+                };
+                (npc.template_id, table)
+            })
+            .collect();
+        Ok(Self { tables })
+    }
+
+    pub fn get(&self, template_id: u32) -> Option<&Table> {
+        self.tables.get(&template_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::mock::StepRng;
+
+    use super::*;
+
+    #[test]
+    fn roll_drop_table() {
+        let tables = Tables::parse(
+            r#"<drops>
+                <npc template_id="1" min_kinah="10" max_kinah="10">
+                    <item item_id="2" name_id="3" chance="100"
+                        min_count="2" max_count="2" />
+                    <item item_id="4" name_id="5" chance="0" />
+                </npc>
+            </drops>"#,
+        )
+        .unwrap();
+
+        let dropped = tables.get(1).unwrap().roll(&mut StepRng::new(0, 1));
+        assert_eq!(
+            dropped,
+            vec![
+                Dropped {
+                    item_id: 2,
+                    name_id: 3,
+                    count: 2,
+                },
+                Dropped {
+                    item_id: KINAH,
+                    name_id: KINAH_NAME_ID,
+                    count: 10,
+                },
+            ]
+        );
+        assert!(tables.get(2).is_none());
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{copy_bytes, game::Serialise, to_le_bytes};
+use ::data::item::Damage;
+
+use super::item::InventoryItem;
+use crate::{game::Serialise, to_le_bytes};
 
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy)]
@@ -23,6 +26,40 @@ pub enum Slot {
     Waist = 16,
 }
 
+impl TryFrom<u8> for Slot {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Self::MainHand,
+            1 => Self::OffHand,
+            2 => Self::Head,
+            3 => Self::Chest,
+            4 => Self::Gloves,
+            5 => Self::Boots,
+            6 => Self::EarRingLeft,
+            7 => Self::EarRingRight,
+            8 => Self::RingLeft,
+            9 => Self::RingRight,
+            10 => Self::Necklace,
+            11 => Self::Shoulder,
+            12 => Self::Legs,
+            13 => Self::PowerShardLeft,
+            14 => Self::PowerShardRight,
+            15 => Self::Wings,
+            16 => Self::Waist,
+            slot => return Err(slot),
+        })
+    }
+}
+
+impl Slot {
+    /// Slots are bits in the equipment masks
+    pub fn mask(self) -> u32 {
+        1 << self as u8
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
@@ -32,6 +69,30 @@ pub enum SlotType {
     VisibleOffHand,
 }
 
+impl From<u8> for SlotType {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => Self::Visible,
+            2 => Self::VisibleOffHand,
+            _ => Self::Invisible,
+        }
+    }
+}
+
+impl From<Slot> for SlotType {
+    fn from(slot: Slot) -> Self {
+        match slot {
+            Slot::OffHand => Self::VisibleOffHand,
+            Slot::EarRingLeft
+            | Slot::EarRingRight
+            | Slot::RingLeft
+            | Slot::RingRight
+            | Slot::Necklace => Self::Invisible,
+            _ => Self::Visible,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Item {
     id: u32,
@@ -43,6 +104,34 @@ impl Item {
     }
 }
 
+/// Damage range of a weapon, rolled on every swing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Weapon {
+    pub min_damage: i32,
+    pub max_damage: i32,
+}
+
+impl Weapon {
+    pub const UNARMED: Weapon = Weapon {
+        min_damage: 1,
+        max_damage: 4,
+    };
+
+    /// Bounds the wrong way round are swapped, rolling an empty range panics
+    pub fn new(min_damage: i32, max_damage: i32) -> Self {
+        Self {
+            min_damage: min_damage.min(max_damage),
+            max_damage: min_damage.max(max_damage),
+        }
+    }
+}
+
+impl From<Damage> for Weapon {
+    fn from(damage: Damage) -> Self {
+        Self::new(damage.min.into(), damage.max.into())
+    }
+}
+
 /// Only the first 16 items are "visible" items
 #[derive(Debug, Clone)]
 pub struct Gear {
@@ -61,6 +150,25 @@ impl Gear {
         self.inner[slot as usize] = Some(item);
     }
 
+    /// What everything equipped looks like, an item is drawn in every slot
+    /// it is equipped in
+    pub fn worn<'a>(
+        items: impl IntoIterator<Item = &'a InventoryItem>,
+    ) -> Self {
+        let mut gear = Self::new();
+        for item in items {
+            let Some(equipment) = &item.equipment else {
+                continue;
+            };
+            for slot in (0..).map_while(|bit| Slot::try_from(bit).ok()) {
+                if equipment.equipped & slot.mask() != 0 {
+                    gear.set(slot, Item::new(equipment.skin, slot.into()));
+                }
+            }
+        }
+        gear
+    }
+
     pub fn serialiase_put_user(&self, buf: &mut [u8]) -> usize {
         let mut len = 2;
         let mut mask = 0;
@@ -92,49 +200,6 @@ impl Gear {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct RawItem {
-    pub id: u32,
-    item_id: u32,
-    unknown_id: u32,
-    position: u16,
-    raw: Vec<u8>,
-}
-impl RawItem {
-    pub fn new(
-        id: u32,
-        item_id: u32,
-        unknown_id: u32,
-        position: u16,
-        raw: Vec<u8>,
-    ) -> Self {
-        Self {
-            raw,
-            id,
-            item_id,
-            unknown_id,
-            position,
-        }
-    }
-}
-impl Serialise for RawItem {
-    fn serialise(&self, buf: &mut [u8]) -> usize {
-        let mut len = 0;
-        let padding = [0u8; 25];
-        to_le_bytes!(len, buf, self.id);
-        to_le_bytes!(len, buf, self.item_id);
-        to_le_bytes!(len, buf, 0x24 as u16);
-        to_le_bytes!(len, buf, self.unknown_id);
-        to_le_bytes!(len, buf, 0x0 as u16);
-        to_le_bytes!(len, buf, (padding.len() + self.raw.len()) as u16);
-        copy_bytes!(len, buf, self.raw);
-        copy_bytes!(len, buf, padding);
-        to_le_bytes!(len, buf, self.position);
-        to_le_bytes!(len, buf, 0x0 as u8);
-        len
-    }
-}
-
 #[derive(Debug, Clone)]
 pub struct LootItem {
     id: u32,
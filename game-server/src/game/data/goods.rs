@@ -0,0 +1,167 @@
+//! What merchants sell, read from the client_npc_goodslist.xml that `bxml`
+//! decodes out of the client's pak files. NPC templates name the lists they
+//! sell from and the lists name their items
+
+use std::collections::HashMap;
+use std::fs;
+
+use quick_xml::de::from_str;
+use serde::Deserialize;
+
+use super::item::Templates as ItemTemplates;
+use super::npc::Template;
+use crate::error::{Error, Result};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename = "client_npc_goodslists")]
+struct ClientGoodsLists {
+    #[serde(rename = "client_npc_goodslist", default)]
+    lists: Vec<ClientGoodsList>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClientGoodsList {
+    name: String,
+    #[serde(default)]
+    goods_list: ClientGoods,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ClientGoods {
+    #[serde(rename = "data", default)]
+    goods: Vec<ClientGood>,
+}
+
+/// Items are named rather than given by id
+#[derive(Debug, Deserialize)]
+struct ClientGood {
+    item: String,
+}
+
+/// Item ids on every goods list keyed by the list's name
+pub struct Lists {
+    lists: HashMap<String, Vec<u32>>,
+}
+
+impl Lists {
+    pub fn load(path: &str, items: &ItemTemplates) -> Result<Self> {
+        let xml = fs::read_to_string(path).map_err(Error::DataFile)?;
+        Self::parse(&xml, items)
+    }
+
+    /// Items missing from the item templates are left off the lists
+    pub fn parse(xml: &str, items: &ItemTemplates) -> Result<Self> {
+        let lists: ClientGoodsLists =
+            from_str(xml).map_err(Error::DataParse)?;
+        let ids: HashMap<&str, u32> = items
+            .iter()
+            .map(|template| (template.name.as_str(), template.id))
+            .collect();
+        let lists = lists
+            .lists
+            .into_iter()
+            .map(|list| {
+                let goods = list
+                    .goods_list
+                    .goods
+                    .iter()
+                    .filter_map(|good| ids.get(good.item.as_str()).copied())
+                    .collect();
+                (list.name, goods)
+            })
+            .collect();
+        Ok(Self { lists })
+    }
+
+    /// Everything an NPC sells, empty when it isn't a merchant
+    pub fn goods(&self, template: &Template) -> Vec<u32> {
+        template
+            .goods_lists
+            .iter()
+            .filter_map(|name| self.lists.get(name))
+            .flatten()
+            .copied()
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lists.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::npc::Templates as NpcTemplates;
+    use super::*;
+
+    #[test]
+    fn parse_goods_lists() {
+        let items = ItemTemplates::parse(
+            "<client_items>
+                <client_item>
+                    <id>162000022</id>
+                    <name>potion_hp_lesser</name>
+                </client_item>
+                <client_item>
+                    <id>160003501</id>
+                    <name>food_roast_brax</name>
+                </client_item>
+            </client_items>",
+        )
+        .unwrap();
+        let lists = Lists::parse(
+            "<client_npc_goodslists>
+                <client_npc_goodslist>
+                    <id>1</id>
+                    <name>LF1_General_Goods</name>
+                    <goods_list>
+                        <data><item>food_roast_brax</item></data>
+                        <data><item>missing_item</item></data>
+                    </goods_list>
+                </client_npc_goodslist>
+                <client_npc_goodslist>
+                    <id>2</id>
+                    <name>LF1_Potions</name>
+                    <goods_list>
+                        <data><item>potion_hp_lesser</item></data>
+                    </goods_list>
+                </client_npc_goodslist>
+            </client_npc_goodslists>",
+            &items,
+        )
+        .unwrap();
+        assert_eq!(lists.len(), 2);
+
+        let npcs = NpcTemplates::parse(
+            "<npc_clients>
+                <npc_client>
+                    <id>203072</id>
+                    <name>LF1_Merchant_General</name>
+                    <name_id>302073</name_id>
+                    <level>10</level>
+                    <max_hp>1000</max_hp>
+                    <attack_delay>2000</attack_delay>
+                    <npc_type>Peace</npc_type>
+                    <trade_info>
+                        <tab_name>LF1_General_Goods</tab_name>
+                        <tab_name>LF1_Potions</tab_name>
+                        <tab_name>LF1_Missing</tab_name>
+                    </trade_info>
+                </npc_client>
+                <npc_client>
+                    <id>210564</id>
+                    <name>LF1_Mosbear_Starved</name>
+                    <name_id>300703</name_id>
+                    <level>13</level>
+                    <max_hp>1817</max_hp>
+                    <attack_delay>1750</attack_delay>
+                    <npc_type>Aggressive</npc_type>
+                </npc_client>
+            </npc_clients>",
+        )
+        .unwrap();
+        let merchant = npcs.get(203072).unwrap();
+        assert_eq!(lists.goods(&merchant), [160003501, 162000022]);
+        assert!(lists.goods(&npcs.get(210564).unwrap()).is_empty());
+    }
+}
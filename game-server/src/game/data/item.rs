@@ -0,0 +1,337 @@
+//! Items characters carry, in the cube or equipped. The client gets the same
+//! item info in the inventory load, add and change messages
+
+pub use ::data::item::{Bonuses, Template, Templates};
+
+use crate::{copy_bytes, game::Serialise, to_le_bytes};
+
+/// Kinah is an item like any other, it is just always in the inventory
+pub const KINAH: u32 = 182400001;
+pub const KINAH_NAME_ID: u32 = 0x1569DB;
+/// Kinah and equipped items aren't in a cube slot
+pub const NO_SLOT: u16 = 0xFFFF;
+pub const MANASTONE_SLOTS: usize = 6;
+
+// TODO: These come from the item templates
+const KINAH_MASK: u16 = 0x631E;
+const STACK_MASK: u16 = 0x633E;
+const EQUIPMENT_MASK: u16 = 0x002C;
+
+/// Head, ears, fingers, neck, power shards, wings and waist
+const ACCESSORY_SLOTS: u32 = 0x1E7C4;
+
+/// Enchant, manastones and the rest are padded out to this
+const ATTRIBUTES_LEN: usize = 50;
+/// Item info is followed by this much nothing
+const PADDING_LEN: usize = 25;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum EquipmentKind {
+    Weapon = 1,
+    Armour = 2,
+    Accessory = 4,
+}
+
+impl TryFrom<u8> for EquipmentKind {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            1 => Self::Weapon,
+            2 => Self::Armour,
+            4 => Self::Accessory,
+            kind => return Err(kind),
+        })
+    }
+}
+
+/// What makes equipment different from the stuff that stacks, the slot masks
+/// have a bit set for each [super::gear::Slot]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Equipment {
+    pub kind: EquipmentKind,
+    /// Where it is worn, 0 while in the cube
+    pub equipped: u32,
+    pub slots: u32,
+    /// Rings, earrings and power shards can go in either side
+    pub other_slots: u32,
+    /// Item id of the model the client draws
+    pub skin: u32,
+    /// Random bonus stats, stat id and value
+    pub bonuses: Vec<(u16, u32)>,
+    // TODO: Most captured items had 4 more bytes here, always 0
+    pub unknown: Option<u32>,
+}
+
+impl Equipment {
+    /// A new copy of a template the way merchants sell it, with no random
+    /// bonuses. It goes in the first slot it fits and can be moved to the
+    /// others. None for templates that can't be worn
+    pub fn new(template: &Template) -> Option<Self> {
+        if template.slots == 0 {
+            return None;
+        }
+        let kind = if template.damage.is_some() {
+            EquipmentKind::Weapon
+        } else if template.slots & ACCESSORY_SLOTS != 0 {
+            EquipmentKind::Accessory
+        } else {
+            EquipmentKind::Armour
+        };
+        let slots = template.slots & template.slots.wrapping_neg();
+        Some(Self {
+            kind,
+            equipped: 0,
+            slots,
+            other_slots: template.slots & !slots,
+            skin: template.id,
+            bonuses: vec![],
+            unknown: Some(0),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InventoryItem {
+    /// Object id, unique across every item
+    pub id: u32,
+    /// Template id
+    pub item_id: u32,
+    /// The client looks the displayed name up from this
+    pub name_id: u32,
+    pub count: u32,
+    /// Position in the cube or [NO_SLOT]
+    pub slot: u16,
+    /// Can't be traded once bound
+    pub soulbound: bool,
+    pub enchant_level: u8,
+    /// Item ids of the manastones socketed into each slot
+    pub manastones: [u32; MANASTONE_SLOTS],
+    /// None for items that don't wear out
+    pub durability: Option<u16>,
+    /// Unix time the item disappears, None for items that last forever
+    pub expires_at: Option<u32>,
+    /// Template flags, tradable, sellable and so on
+    pub mask: u16,
+    pub equipment: Option<Equipment>,
+}
+
+impl InventoryItem {
+    /// Consumables, crafting materials and anything else that stacks
+    pub fn stack(
+        id: u32,
+        item_id: u32,
+        name_id: u32,
+        count: u32,
+        slot: u16,
+    ) -> Self {
+        Self {
+            id,
+            item_id,
+            name_id,
+            count,
+            slot,
+            soulbound: false,
+            enchant_level: 0,
+            manastones: [0; MANASTONE_SLOTS],
+            durability: None,
+            expires_at: None,
+            mask: if item_id == KINAH {
+                KINAH_MASK
+            } else {
+                STACK_MASK
+            },
+            equipment: None,
+        }
+    }
+
+    pub fn kinah(id: u32, count: u32) -> Self {
+        Self::stack(id, KINAH, KINAH_NAME_ID, count, NO_SLOT)
+    }
+
+    pub fn equipment(
+        id: u32,
+        item_id: u32,
+        name_id: u32,
+        slot: u16,
+        mask: u16,
+        equipment: Equipment,
+    ) -> Self {
+        Self {
+            mask,
+            equipment: Some(equipment),
+            ..Self::stack(id, item_id, name_id, 1, slot)
+        }
+    }
+
+    /// Equipment straight off its template, not enchanted, with empty
+    /// manastone slots
+    pub fn from_template(id: u32, template: &Template) -> Option<Self> {
+        Some(Self::equipment(
+            id,
+            template.id,
+            template.name_id,
+            NO_SLOT,
+            EQUIPMENT_MASK,
+            Equipment::new(template)?,
+        ))
+    }
+
+    pub fn is_kinah(&self) -> bool {
+        self.item_id == KINAH
+    }
+
+    pub fn is_stackable(&self) -> bool {
+        self.equipment.is_none()
+    }
+
+    /// Slot mask of where the item is worn, 0 while it isn't
+    pub fn equipped(&self) -> u32 {
+        self.equipment
+            .as_ref()
+            .map_or(0, |equipment| equipment.equipped)
+    }
+
+    pub fn set_equipped(&mut self, equipped: u32) {
+        if let Some(equipment) = &mut self.equipment {
+            equipment.equipped = equipped;
+        }
+    }
+
+    /// Everything about the item but its ids, shared by every message that
+    /// carries an item
+    pub fn serialise_info(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        to_le_bytes!(len, buf, 0x24_u16);
+        to_le_bytes!(len, buf, self.name_id);
+        to_le_bytes!(len, buf, 0_u16);
+        let size_at = len;
+        len += 2;
+
+        let start = len;
+        if let Some(equipment) = &self.equipment {
+            len += self.serialise_equipment(equipment, &mut buf[len..]);
+        }
+        to_le_bytes!(len, buf, 0_u8);
+        to_le_bytes!(len, buf, self.mask);
+        to_le_bytes!(len, buf, self.count);
+        copy_bytes!(len, buf, [0u8; PADDING_LEN]);
+        let size = (len - start) as u16;
+        buf[size_at..size_at + 2].copy_from_slice(&size.to_le_bytes());
+
+        to_le_bytes!(len, buf, self.slot);
+        to_le_bytes!(len, buf, 0_u8);
+        len
+    }
+
+    // TODO: Verify where enchant, manastones, durability and expiry go, they
+    // were always 0 in the captures
+    fn serialise_equipment(
+        &self,
+        equipment: &Equipment,
+        buf: &mut [u8],
+    ) -> usize {
+        let mut len = 0;
+        to_le_bytes!(len, buf, 0x06_u8);
+        to_le_bytes!(len, buf, equipment.equipped);
+        to_le_bytes!(len, buf, equipment.kind as u8);
+        to_le_bytes!(len, buf, equipment.slots);
+        to_le_bytes!(len, buf, equipment.other_slots);
+        if equipment.kind == EquipmentKind::Armour {
+            to_le_bytes!(len, buf, 0_u32);
+        }
+        to_le_bytes!(len, buf, 0x0B_u8);
+        to_le_bytes!(len, buf, self.soulbound as u8);
+        to_le_bytes!(len, buf, 0_u8);
+        to_le_bytes!(len, buf, equipment.skin);
+        let attributes = len;
+        to_le_bytes!(len, buf, self.enchant_level);
+        for manastone in self.manastones {
+            to_le_bytes!(len, buf, manastone);
+        }
+        to_le_bytes!(len, buf, self.durability.unwrap_or_default());
+        to_le_bytes!(len, buf, self.expires_at.unwrap_or_default());
+        buf[len..attributes + ATTRIBUTES_LEN].fill(0);
+        len = attributes + ATTRIBUTES_LEN;
+        if let Some(unknown) = equipment.unknown {
+            to_le_bytes!(len, buf, unknown);
+        }
+
+        for (stat, value) in &equipment.bonuses {
+            to_le_bytes!(len, buf, 0x0A_u8);
+            to_le_bytes!(len, buf, *stat);
+            to_le_bytes!(len, buf, *value);
+            to_le_bytes!(len, buf, 0_u8);
+        }
+        len
+    }
+}
+
+impl Serialise for InventoryItem {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        to_le_bytes!(len, buf, self.id);
+        to_le_bytes!(len, buf, self.item_id);
+        len += self.serialise_info(&mut buf[len..]);
+        len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Aldelle's Leather Shoes as sent by the official server
+    const SHOES: [u8; 140] = [
+        0xAC, 0xA3, 0x6B, 0xC7, 0xE0, 0x16, 0xD0, 0x06, 0x24, 0x00, 0xEB, 0x07,
+        0x16, 0x00, 0x00, 0x00, 0x77, 0x00, 0x06, 0x20, 0x00, 0x00, 0x00, 0x02,
+        0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x0B, 0x01, 0x00, 0xE0, 0x16, 0xD0, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x0A, 0x20, 0x00, 0x09, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7E, 0x02,
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x00,
+    ];
+
+    #[test]
+    fn serialise_like_the_official_server() {
+        let shoes = InventoryItem {
+            soulbound: true,
+            ..InventoryItem::equipment(
+                0xC76BA3AC,
+                0x06D016E0,
+                0x1607EB,
+                NO_SLOT,
+                0x027E,
+                Equipment {
+                    kind: EquipmentKind::Armour,
+                    equipped: 0x20,
+                    slots: 0x20,
+                    other_slots: 0,
+                    skin: 0x06D016E0,
+                    bonuses: vec![(0x20, 9)],
+                    unknown: Some(0),
+                },
+            )
+        };
+        let mut buf = [0; 256];
+        let len = shoes.serialise(&mut buf);
+        assert_eq!(buf[..len], SHOES);
+
+        let kinah = InventoryItem::kinah(0xC76BA3B4, 0x014FED);
+        let len = kinah.serialise(&mut buf);
+        assert_eq!(
+            buf[14..25],
+            [
+                0x00, 0x00, 0x20, 0x00, 0x00, 0x1E, 0x63, 0xED, 0x4F, 0x01,
+                0x00
+            ]
+        );
+        assert_eq!(len, 53);
+    }
+}
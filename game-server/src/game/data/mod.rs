@@ -1,9 +1,21 @@
 use super::Deserialise;
+use crate::error::{Error, Result};
 
+pub mod drop;
 pub mod gear;
+pub mod goods;
+pub mod item;
 pub mod npc;
 pub mod skill;
 
+/// Templates and tables read once at start up, the game never changes them
+pub struct GameData {
+    pub skills: skill::Templates,
+    pub drops: drop::Tables,
+    pub items: item::Templates,
+    pub goods: goods::Lists,
+}
+
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum Emote {
@@ -19,14 +31,15 @@ impl From<Emote> for u8 {
     }
 }
 impl Deserialise for Emote {
-    fn deserialise(buf: &[u8]) -> Self
+    fn deserialise(buf: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
-        match buf.first().unwrap() {
+        let emote = buf.first().ok_or(Error::PacketLength(buf.len()))?;
+        Ok(match emote {
             19 => Self::Dance,
             emote => Self::Unknown(*emote),
-        }
+        })
     }
 }
 
@@ -81,11 +94,12 @@ impl From<ActionType> for u8 {
     }
 }
 impl Deserialise for ActionType {
-    fn deserialise(buf: &[u8]) -> Self
+    fn deserialise(buf: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
-        match buf.first().unwrap() {
+        let action = buf.first().ok_or(Error::PacketLength(buf.len()))?;
+        Ok(match action {
             0 => Self::Target,
             1 => Self::Jump,
             2 => Self::Rest,
@@ -93,7 +107,7 @@ impl Deserialise for ActionType {
             13 => Self::Fly,
             14 => Self::Land,
             18 => Self::Die,
-            21 => Self::StartEmote(Emote::deserialise(&buf[1..])),
+            21 => Self::StartEmote(Emote::deserialise(&buf[1..])?),
             22 => Self::EndEmote,
             24 => Self::Attack,
             25 => Self::SheathWeapon,
@@ -106,6 +120,6 @@ impl Deserialise for ActionType {
             41 => Self::EndLoot,
             56 => Self::EndGlideSprint,
             action => Self::Unknown(*action),
-        }
+        })
     }
 }
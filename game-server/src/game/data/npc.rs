@@ -1,11 +1,15 @@
-#[repr(u32)]
-#[derive(Debug, Clone, Copy)]
-pub enum Id {
-    SpringOutlaw = 210484,
-    LakeSpirit = 210660,
-    TahabataPyrelord = 217166,
-    StarvedMosbear = 210564,
-}
+//! NPC templates, read from the client_npcs.xml that `bxml` decodes out of the
+//! client's pak files. Every spawned NPC is built from one of these
+
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
+
+use quick_xml::de::from_str;
+use serde::Deserialize;
+
+use crate::error::{Error, Result};
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy)]
@@ -14,16 +18,9 @@ pub enum State {
     DeadNoLoot = 0x07,
 }
 
-#[repr(u32)]
-#[derive(Debug, Clone, Copy)]
-pub enum Name {
-    SprigOutlaw = 300623,
-    StarvedMosbear = 300703,
-}
-
 // TODO: Verify this
 #[repr(u8)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
 pub enum Type {
     Attackable = 0,
     Peace = 2,
@@ -32,3 +29,159 @@ pub enum Type {
     Friend = 38,
     Support = 54,
 }
+
+#[derive(Debug, Deserialize)]
+#[serde(rename = "npc_clients")]
+struct ClientNpcs {
+    #[serde(rename = "npc_client")]
+    npcs: Vec<ClientNpc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClientNpc {
+    id: u32,
+    name: String,
+    name_id: u32,
+    level: u16,
+    max_hp: i32,
+    #[serde(default)]
+    physical_attack: i32,
+    #[serde(default)]
+    physical_defend: i32,
+    /// Milliseconds between attacks
+    attack_delay: u32,
+    #[serde(default)]
+    tribe: String,
+    npc_type: Type,
+    #[serde(default)]
+    aggro_range: f32,
+    #[serde(default)]
+    trade_info: TradeInfo,
+}
+
+/// Merchants name the goods lists in client_npc_goodslist.xml they sell from
+#[derive(Debug, Default, Deserialize)]
+struct TradeInfo {
+    #[serde(rename = "tab_name", default)]
+    tabs: Vec<String>,
+}
+
+/// Everything NPCs spawned from the same template have in common
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct Template {
+    /// Also tells the client which model and status bar to use
+    pub id: u32,
+    pub name: String,
+    /// The client looks the displayed name up from this
+    pub name_id: u32,
+    pub level: u16,
+    pub max_hp: i32,
+    pub attack: i32,
+    pub defence: i32,
+    pub attack_speed: Duration,
+    pub tribe: String,
+    pub ty: Type,
+    /// How close a character has to get before an aggressive NPC attacks
+    pub aggro_range: f32,
+    /// Names of the goods lists a merchant sells from, empty for everyone
+    /// else
+    pub goods_lists: Vec<String>,
+}
+
+impl From<ClientNpc> for Template {
+    fn from(npc: ClientNpc) -> Self {
+        Self {
+            id: npc.id,
+            name: npc.name,
+            name_id: npc.name_id,
+            level: npc.level,
+            max_hp: npc.max_hp,
+            attack: npc.physical_attack,
+            defence: npc.physical_defend,
+            attack_speed: Duration::from_millis(npc.attack_delay.into()),
+            tribe: npc.tribe,
+            ty: npc.npc_type,
+            aggro_range: npc.aggro_range,
+            goods_lists: npc.trade_info.tabs,
+        }
+    }
+}
+
+/// Every NPC template keyed by its id
+pub struct Templates {
+    templates: HashMap<u32, Arc<Template>>,
+}
+
+impl Templates {
+    pub fn load(path: &str) -> Result<Self> {
+        let xml = fs::read_to_string(path).map_err(Error::DataFile)?;
+        Self::parse(&xml)
+    }
+
+    pub fn parse(xml: &str) -> Result<Self> {
+        let npcs: ClientNpcs = from_str(xml).map_err(Error::DataParse)?;
+        let templates = npcs
+            .npcs
+            .into_iter()
+            .map(|npc| (npc.id, Arc::new(Template::from(npc))))
+            .collect();
+        Ok(Self { templates })
+    }
+
+    pub fn get(&self, id: u32) -> Option<Arc<Template>> {
+        self.templates.get(&id).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.templates.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_client_npcs() {
+        let templates = Templates::parse(
+            "<npc_clients>
+                <npc_client>
+                    <id>210564</id>
+                    <name>LF1_Mosbear_Starved</name>
+                    <name_id>300703</name_id>
+                    <level>13</level>
+                    <max_hp>1817</max_hp>
+                    <physical_attack>128</physical_attack>
+                    <attack_delay>1750</attack_delay>
+                    <npc_type>Aggressive</npc_type>
+                    <aggro_range>10</aggro_range>
+                </npc_client>
+                <npc_client>
+                    <id>203072</id>
+                    <name>LF1_Merchant_General</name>
+                    <name_id>302073</name_id>
+                    <level>10</level>
+                    <max_hp>1000</max_hp>
+                    <attack_delay>2000</attack_delay>
+                    <npc_type>Peace</npc_type>
+                    <trade_info>
+                        <tab_name>LF1_General_Goods</tab_name>
+                        <tab_name>LF1_Potions</tab_name>
+                    </trade_info>
+                </npc_client>
+            </npc_clients>",
+        )
+        .unwrap();
+
+        let mosbear = templates.get(210564).unwrap();
+        assert_eq!(mosbear.level, 13);
+        assert_eq!(mosbear.attack_speed, Duration::from_millis(1750));
+        assert_eq!(mosbear.ty, Type::Aggressive);
+        assert_eq!(mosbear.defence, 0);
+        assert!(mosbear.goods_lists.is_empty());
+        let merchant = templates.get(203072).unwrap();
+        assert_eq!(merchant.goods_lists, ["LF1_General_Goods", "LF1_Potions"]);
+        assert!(templates.get(1).is_none());
+    }
+}
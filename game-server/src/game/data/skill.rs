@@ -1,3 +1,16 @@
+//! Skills a character has learnt and the templates, read from the
+//! client_skills.xml `bxml` decodes out of the client's pak files, that say
+//! what using one does
+
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
+
+use quick_xml::de::from_str;
+use serde::Deserialize;
+
+use crate::error::{Error, Result};
 use crate::{game::Serialise, to_le_bytes};
 
 #[repr(u8)]
@@ -7,6 +20,15 @@ pub enum SkillType {
     Stigma = 1,
 }
 
+impl From<u8> for SkillType {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => Self::Stigma,
+            _ => Self::Normal,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Skill {
     /// Major skill levels are different ID's Ambush I and Ambush II have
@@ -44,6 +66,32 @@ impl Skill {
             typ,
         }
     }
+    /// Rebuild a skill exactly as it was saved
+    pub fn from_parts(
+        id: u16,
+        level: u32,
+        unknown: u32,
+        typ: SkillType,
+    ) -> Self {
+        Self {
+            id,
+            level,
+            unknown,
+            typ,
+        }
+    }
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+    pub fn unknown(&self) -> u32 {
+        self.unknown
+    }
+    pub fn typ(&self) -> SkillType {
+        self.typ
+    }
 }
 
 impl Serialise for Skill {
@@ -58,3 +106,248 @@ impl Serialise for Skill {
         ptr
     }
 }
+
+/// What using a skill costs
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum CostParameter {
+    #[serde(rename = "MP")]
+    Mp,
+    #[serde(rename = "DP")]
+    Dp,
+}
+
+/// Who a skill is used on
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum Target {
+    Me,
+    Target,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+enum EffectType {
+    PhysicalDamage,
+    Heal,
+    StatAttack,
+    StatDefence,
+    Stun,
+    Root,
+    DamageOverTime,
+    HealOverTime,
+    Shield,
+}
+
+/// What a skill does once it goes off, everything but damage and heal lasts
+/// for the skill's duration
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Effect {
+    /// Added to the attack of a main hand swing
+    Damage(i32),
+    Heal(i32),
+    /// Negative values lower the stat
+    Attack(i32),
+    Defence(i32),
+    /// Can't move, attack or use skills
+    Stun,
+    /// Can't move
+    Root,
+    /// Every effect tick
+    DamageOverTime(i32),
+    HealOverTime(i32),
+    /// Absorbs damage until it is used up
+    Shield(i32),
+}
+
+impl Effect {
+    /// Happens once rather than lasting for a duration
+    pub fn is_instant(&self) -> bool {
+        matches!(self, Effect::Damage(_) | Effect::Heal(_))
+    }
+
+    pub fn is_harmful(&self) -> bool {
+        match self {
+            Effect::Damage(_)
+            | Effect::Stun
+            | Effect::Root
+            | Effect::DamageOverTime(_) => true,
+            Effect::Attack(value) | Effect::Defence(value) => *value < 0,
+            Effect::Heal(_) | Effect::HealOverTime(_) | Effect::Shield(_) => {
+                false
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename = "client_skills")]
+struct ClientSkills {
+    #[serde(rename = "client_skill")]
+    skills: Vec<ClientSkill>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClientSkill {
+    id: u16,
+    name: String,
+    /// Milliseconds
+    #[serde(default)]
+    casting_delay: u32,
+    /// Milliseconds before the skill can be used again
+    #[serde(default)]
+    delay_time: u32,
+    cost_parameter: Option<CostParameter>,
+    #[serde(default)]
+    cost_end: u32,
+    first_target: Target,
+    #[serde(default)]
+    first_target_valid_distance: f32,
+    effect1_type: Option<EffectType>,
+    #[serde(default)]
+    effect1_value: i32,
+    effect2_type: Option<EffectType>,
+    #[serde(default)]
+    effect2_value: i32,
+    /// Milliseconds timed effects last
+    #[serde(default)]
+    duration: u32,
+    /// Effects in the same stack group replace each other
+    stack: Option<String>,
+    #[serde(default)]
+    stack_level: u8,
+}
+
+/// Everything every character using a skill has in common
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct Template {
+    pub id: u16,
+    pub name: String,
+    pub cast_time: Duration,
+    pub cooldown: Duration,
+    pub cost: Option<(CostParameter, u32)>,
+    pub target: Target,
+    /// How far away the target can be, also checked when the cast finishes
+    pub range: f32,
+    pub effects: Vec<Effect>,
+    /// How long the timed effects last
+    pub duration: Duration,
+    /// A higher or equal level replaces an effect in the same group, a lower
+    /// one is refused. Effects without a group only replace themselves
+    pub stack: Option<String>,
+    pub stack_level: u8,
+}
+
+impl Template {
+    /// Has effects that last after the skill goes off
+    pub fn is_timed(&self) -> bool {
+        !self.duration.is_zero()
+            && self.effects.iter().any(|effect| !effect.is_instant())
+    }
+
+    /// Buffs can be turned off by whoever has them, debuffs can't
+    pub fn is_harmful(&self) -> bool {
+        self.effects.iter().any(Effect::is_harmful)
+    }
+}
+
+impl From<ClientSkill> for Template {
+    fn from(skill: ClientSkill) -> Self {
+        let effects = [
+            (skill.effect1_type, skill.effect1_value),
+            (skill.effect2_type, skill.effect2_value),
+        ]
+        .into_iter()
+        .filter_map(|(ty, value)| {
+            Some(match ty? {
+                EffectType::PhysicalDamage => Effect::Damage(value),
+                EffectType::Heal => Effect::Heal(value),
+                EffectType::StatAttack => Effect::Attack(value),
+                EffectType::StatDefence => Effect::Defence(value),
+                EffectType::Stun => Effect::Stun,
+                EffectType::Root => Effect::Root,
+                EffectType::DamageOverTime => Effect::DamageOverTime(value),
+                EffectType::HealOverTime => Effect::HealOverTime(value),
+                EffectType::Shield => Effect::Shield(value),
+            })
+        })
+        .collect();
+
+        Self {
+            id: skill.id,
+            name: skill.name,
+            cast_time: Duration::from_millis(skill.casting_delay.into()),
+            cooldown: Duration::from_millis(skill.delay_time.into()),
+            cost: skill
+                .cost_parameter
+                .map(|parameter| (parameter, skill.cost_end)),
+            target: skill.first_target,
+            range: skill.first_target_valid_distance,
+            effects,
+            duration: Duration::from_millis(skill.duration.into()),
+            stack: skill.stack,
+            stack_level: skill.stack_level,
+        }
+    }
+}
+
+/// Every skill template keyed by skill id
+pub struct Templates {
+    templates: HashMap<u16, Arc<Template>>,
+}
+
+impl Templates {
+    pub fn load(path: &str) -> Result<Self> {
+        let xml = fs::read_to_string(path).map_err(Error::DataFile)?;
+        Self::parse(&xml)
+    }
+
+    pub fn parse(xml: &str) -> Result<Self> {
+        let skills: ClientSkills = from_str(xml).map_err(Error::DataParse)?;
+        let templates = skills
+            .skills
+            .into_iter()
+            .map(|skill| (skill.id, Arc::new(Template::from(skill))))
+            .collect();
+        Ok(Self { templates })
+    }
+
+    pub fn get(&self, id: u16) -> Option<Arc<Template>> {
+        self.templates.get(&id).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.templates.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_client_skills() {
+        let templates = Templates::parse(
+            "<client_skills>
+                <client_skill>
+                    <id>1</id>
+                    <name>FI_FerociousStrike_G1</name>
+                    <casting_delay>500</casting_delay>
+                    <delay_time>6000</delay_time>
+                    <cost_parameter>MP</cost_parameter>
+                    <cost_end>18</cost_end>
+                    <first_target>Target</first_target>
+                    <first_target_valid_distance>6</first_target_valid_distance>
+                    <effect1_type>PhysicalDamage</effect1_type>
+                    <effect1_value>77</effect1_value>
+                </client_skill>
+            </client_skills>",
+        )
+        .unwrap();
+
+        let strike = templates.get(1).unwrap();
+        assert_eq!(strike.cast_time, Duration::from_millis(500));
+        assert_eq!(strike.cost, Some((CostParameter::Mp, 18)));
+        assert_eq!(strike.target, Target::Target);
+        assert_eq!(strike.effects, vec![Effect::Damage(77)]);
+        assert!(templates.get(2).is_none());
+    }
+}
@@ -0,0 +1,149 @@
+//! Physical combat. Every swing is rolled against the defender's evasion,
+//! parry and block then for a critical, damage comes from the weapon and the
+//! attacker's attack stat less the defender's physical defence, with the
+//! level difference tipping both the rolls and the damage
+
+use rand::Rng;
+
+use super::super::data::gear::Weapon;
+use super::damage::{Hit, Type};
+
+/// Avoidance and critical chances are capped so every fight stays winnable
+const MAX_CHANCE: f32 = 50.;
+/// Extra percent chance to avoid a hit per level the defender is above the
+/// attacker
+const LEVEL_AVOIDANCE: f32 = 2.;
+/// Damage changes by this fraction per level of difference
+const LEVEL_DAMAGE: f32 = 0.05;
+const CRITICAL_MULTIPLIER: f32 = 1.5;
+/// Fraction of damage still taken after a parry or block
+const PARRY_MULTIPLIER: f32 = 0.6;
+const BLOCK_MULTIPLIER: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hand {
+    Main,
+    Off,
+}
+
+/// The stats on one side of a fight that the formula cares about, built from
+/// a character's [Stats](super::super::character::Stats) or an NPC template
+#[derive(Debug, Clone)]
+pub struct Fighter {
+    pub level: u16,
+    pub weapon: Weapon,
+    pub attack: i32,
+    pub accuracy: i32,
+    pub crit: i32,
+    pub defence: i32,
+    pub evasion: i32,
+    pub parry: i32,
+    pub block: i32,
+}
+
+/// Roll a single swing, the damage still needs to be taken off the defender
+pub fn swing(
+    attacker: &Fighter,
+    defender: &Fighter,
+    hand: Hand,
+    rng: &mut impl Rng,
+) -> Hit {
+    let level_gap = attacker.level as f32 - defender.level as f32;
+    let avoid = |stat: i32| {
+        let chance = (stat - attacker.accuracy) as f32 / 10.
+            - level_gap * LEVEL_AVOIDANCE;
+        chance.clamp(0., MAX_CHANCE)
+    };
+    let crit = (attacker.crit as f32 / 10.).clamp(0., MAX_CHANCE);
+
+    if roll(rng, avoid(defender.evasion)) {
+        return Hit::new(0, Type::Evade);
+    }
+    let (multiplier, ty) = if roll(rng, avoid(defender.parry)) {
+        (PARRY_MULTIPLIER, hand.pick(Type::Parry, Type::OffHandParry))
+    } else if roll(rng, avoid(defender.block)) {
+        (BLOCK_MULTIPLIER, hand.pick(Type::Block, Type::OffHandBlock))
+    } else if roll(rng, crit) {
+        (
+            CRITICAL_MULTIPLIER,
+            hand.pick(Type::Critical, Type::OffHandCritical),
+        )
+    } else {
+        (1., hand.pick(Type::MainHand, Type::OffHand))
+    };
+
+    let weapon = attacker.weapon;
+    let rolled = rng.gen_range(weapon.min_damage..=weapon.max_damage);
+    let level = (1. + level_gap * LEVEL_DAMAGE).clamp(0.5, 1.5);
+    let damage = ((rolled + attacker.attack) as f32 * multiplier * level)
+        as i32
+        - defender.defence / 10;
+
+    Hit::new(damage.max(1), ty)
+}
+
+/// True `chance` percent of the time
+fn roll(rng: &mut impl Rng, chance: f32) -> bool {
+    rng.gen_range(0. ..100.) < chance
+}
+
+impl Hand {
+    fn pick(self, main: Type, off: Type) -> Type {
+        match self {
+            Hand::Main => main,
+            Hand::Off => off,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn fighter(level: u16) -> Fighter {
+        Fighter {
+            level,
+            weapon: Weapon {
+                min_damage: 20,
+                max_damage: 30,
+            },
+            attack: 50,
+            accuracy: 400,
+            crit: 0,
+            defence: 100,
+            evasion: 0,
+            parry: 0,
+            block: 0,
+        }
+    }
+
+    #[test]
+    fn damage_follows_weapon_and_level() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let attacker = fighter(10);
+
+        let hit = swing(&attacker, &fighter(10), Hand::Off, &mut rng);
+        assert_eq!(hit.ty(), Type::OffHand);
+        assert!((60..=70).contains(&hit.damage), "{}", hit.damage);
+
+        // Ten levels under the defender halves the damage
+        let hit = swing(&attacker, &fighter(20), Hand::Main, &mut rng);
+        assert!((25..=30).contains(&hit.damage), "{}", hit.damage);
+    }
+
+    #[test]
+    fn avoidance_is_capped() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut defender = fighter(10);
+        defender.evasion = 10_000;
+
+        let evaded = (0..1000)
+            .map(|_| swing(&fighter(10), &defender, Hand::Main, &mut rng))
+            .filter(|hit| hit.ty() == Type::Evade)
+            .count();
+        assert!((400..600).contains(&evaded), "{evaded}");
+    }
+}
@@ -1,10 +1,16 @@
 use crate::{game::Serialise, to_le_bytes};
 
 #[repr(u16)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Type {
     Unknown = 0x00,
     Evade = 0x01,
+    Parry = 0x02,
+    OffHandParry = 0x03,
+    Block = 0x04,
+    OffHandBlock = 0x05,
+    Critical = 0x08,
+    OffHandCritical = 0x09,
     MainHand = 0x0A,
     OffHand = 0x0B,
 }
@@ -21,6 +27,10 @@ impl Hit {
     pub fn new(damage: i32, ty: Type) -> Self {
         Self { damage, ty }
     }
+    #[allow(dead_code)]
+    pub fn ty(&self) -> Type {
+        self.ty
+    }
 }
 
 impl Serialise for Hit {
@@ -0,0 +1,305 @@
+//! Timed effects left behind by skills, buffs and debuffs change stats or
+//! stop their target doing things and damage or heal over time effects go off
+//! every [TICK_INTERVAL] until they run out
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::super::data::skill::{Effect, Template};
+
+/// How often over time effects go off
+pub const TICK_INTERVAL: Duration = Duration::from_secs(3);
+
+/// The client draws these as icons on the status bar
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum Slot {
+    Buff = 1,
+    Debuff = 2,
+}
+
+/// Bits of the abnormal state the client is told about
+// TODO: Verify these
+pub mod abnormal {
+    pub const POISON: u32 = 0x02;
+    pub const ROOT: u32 = 0x10;
+    pub const STUN: u32 = 0x1000;
+}
+
+#[derive(Debug, Clone)]
+struct Active {
+    template: Arc<Template>,
+    level: u8,
+    /// Who to blame for damage over time
+    caster_id: u32,
+    ends_at: Instant,
+    next_tick: Instant,
+    /// Damage the shield can still soak up
+    shield: i32,
+}
+
+/// What happened to an object's effects during a tick
+#[derive(Debug, Default, PartialEq)]
+pub struct Ticked {
+    /// Damage over time and who caused it
+    pub damage: Vec<(u32, i32)>,
+    pub heal: i32,
+    /// Effects ran out so the client needs the new list
+    pub expired: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Applied {
+    Added,
+    /// Took the place of the effect from this skill
+    Replaced(u16),
+    /// Something stronger in the same stack group is already there
+    Refused,
+}
+
+/// Every timed effect on a character or NPC
+#[derive(Debug, Clone, Default)]
+pub struct Effects {
+    active: Vec<Active>,
+}
+
+impl Effects {
+    /// Add the timed effects of a skill following its stacking rules
+    pub fn apply(
+        &mut self,
+        template: Arc<Template>,
+        level: u8,
+        caster_id: u32,
+    ) -> Applied {
+        let existing = self.active.iter().position(|active| {
+            active.template.id == template.id
+                || (template.stack.is_some()
+                    && active.template.stack == template.stack)
+        });
+        let applied = match existing {
+            Some(i)
+                if self.active[i].template.stack_level
+                    > template.stack_level =>
+            {
+                return Applied::Refused;
+            }
+            Some(i) => Applied::Replaced(self.active.remove(i).template.id),
+            None => Applied::Added,
+        };
+
+        let now = Instant::now();
+        let shield = template
+            .effects
+            .iter()
+            .map(|effect| match effect {
+                Effect::Shield(shield) => *shield,
+                _ => 0,
+            })
+            .sum();
+        self.active.push(Active {
+            ends_at: now + template.duration,
+            next_tick: now + TICK_INTERVAL,
+            template,
+            level,
+            caster_id,
+            shield,
+        });
+        applied
+    }
+
+    /// Take off the effects of a skill, false if there weren't any
+    pub fn remove(&mut self, skill_id: u16) -> bool {
+        let len = self.active.len();
+        self.active.retain(|active| active.template.id != skill_id);
+        self.active.len() != len
+    }
+
+    /// Drop everything, used when the object dies
+    pub fn clear(&mut self) -> bool {
+        let had_effects = !self.active.is_empty();
+        self.active.clear();
+        had_effects
+    }
+
+    pub fn has(&self, skill_id: u16) -> Option<&Arc<Template>> {
+        self.active
+            .iter()
+            .find(|active| active.template.id == skill_id)
+            .map(|active| &active.template)
+    }
+
+    /// Run over time effects that are due and drop the ones that ran out
+    pub fn tick(&mut self) -> Ticked {
+        let now = Instant::now();
+        let mut ticked = Ticked::default();
+
+        for active in &mut self.active {
+            // Catch up on every tick missed, the last one lands as it ends
+            while active.next_tick <= now.min(active.ends_at) {
+                active.next_tick += TICK_INTERVAL;
+                for effect in &active.template.effects {
+                    match effect {
+                        Effect::DamageOverTime(damage) => {
+                            ticked.damage.push((active.caster_id, *damage))
+                        }
+                        Effect::HealOverTime(heal) => ticked.heal += heal,
+                        _ => (),
+                    }
+                }
+            }
+        }
+
+        let len = self.active.len();
+        self.active.retain(|active| active.ends_at > now);
+        ticked.expired = self.active.len() != len;
+        ticked
+    }
+
+    /// Soak up damage with any shields, returns what gets through. A shield
+    /// that breaks takes the rest of its skill's effects with it
+    pub fn absorb(&mut self, mut damage: i32) -> i32 {
+        let mut broken = false;
+        for active in &mut self.active {
+            if active.shield <= 0 || damage <= 0 {
+                continue;
+            }
+            let absorbed = active.shield.min(damage);
+            active.shield -= absorbed;
+            damage -= absorbed;
+            broken |= active.shield == 0;
+        }
+        if broken {
+            self.active.retain(|active| {
+                active.shield > 0
+                    || !active
+                        .template
+                        .effects
+                        .iter()
+                        .any(|effect| matches!(effect, Effect::Shield(_)))
+            });
+        }
+        damage
+    }
+
+    pub fn attack(&self) -> i32 {
+        self.sum(|effect| match effect {
+            Effect::Attack(attack) => *attack,
+            _ => 0,
+        })
+    }
+
+    pub fn defence(&self) -> i32 {
+        self.sum(|effect| match effect {
+            Effect::Defence(defence) => *defence,
+            _ => 0,
+        })
+    }
+
+    pub fn stunned(&self) -> bool {
+        self.state() & abnormal::STUN != 0
+    }
+
+    pub fn rooted(&self) -> bool {
+        self.state() & (abnormal::ROOT | abnormal::STUN) != 0
+    }
+
+    /// Abnormal state bits for the client
+    pub fn state(&self) -> u32 {
+        self.active
+            .iter()
+            .flat_map(|active| &active.template.effects)
+            .fold(0, |state, effect| {
+                state
+                    | match effect {
+                        Effect::Stun => abnormal::STUN,
+                        Effect::Root => abnormal::ROOT,
+                        Effect::DamageOverTime(_) => abnormal::POISON,
+                        _ => 0,
+                    }
+            })
+    }
+
+    /// Skill id, level, status bar slot and time left of everything active
+    pub fn statuses(&self) -> Vec<(u16, u8, Slot, Duration)> {
+        let now = Instant::now();
+        self.active
+            .iter()
+            .map(|active| {
+                let slot = if active.template.is_harmful() {
+                    Slot::Debuff
+                } else {
+                    Slot::Buff
+                };
+                (
+                    active.template.id,
+                    active.level,
+                    slot,
+                    active.ends_at.saturating_duration_since(now),
+                )
+            })
+            .collect()
+    }
+
+    fn sum(&self, value: impl Fn(&Effect) -> i32) -> i32 {
+        self.active
+            .iter()
+            .flat_map(|active| &active.template.effects)
+            .map(value)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::data::skill::Templates;
+    use super::*;
+
+    const SKILLS: &str = "<client_skills>
+        <client_skill>
+            <id>1</id>
+            <name>Weak</name>
+            <first_target>Me</first_target>
+            <effect1_type>StatAttack</effect1_type>
+            <effect1_value>10</effect1_value>
+            <duration>60000</duration>
+            <stack>ATTACK</stack>
+            <stack_level>1</stack_level>
+        </client_skill>
+        <client_skill>
+            <id>2</id>
+            <name>Strong</name>
+            <first_target>Me</first_target>
+            <effect1_type>StatAttack</effect1_type>
+            <effect1_value>20</effect1_value>
+            <effect2_type>Shield</effect2_type>
+            <effect2_value>100</effect2_value>
+            <duration>60000</duration>
+            <stack>ATTACK</stack>
+            <stack_level>2</stack_level>
+        </client_skill>
+    </client_skills>";
+
+    #[test]
+    fn stack_groups_keep_the_strongest() {
+        let templates = Templates::parse(SKILLS).unwrap();
+        let mut effects = Effects::default();
+
+        assert_eq!(
+            effects.apply(templates.get(1).unwrap(), 1, 0),
+            Applied::Added
+        );
+        assert_eq!(
+            effects.apply(templates.get(2).unwrap(), 1, 0),
+            Applied::Replaced(1)
+        );
+        assert_eq!(
+            effects.apply(templates.get(1).unwrap(), 1, 0),
+            Applied::Refused
+        );
+        assert_eq!(effects.attack(), 20);
+
+        assert_eq!(effects.absorb(60), 0);
+        assert_eq!(effects.absorb(60), 20);
+        assert!(effects.has(2).is_none());
+    }
+}
@@ -0,0 +1,299 @@
+//! Trading between two characters. Each side puts up items and kinah, both
+//! lock their offers in and then both accept before anything changes hands
+
+use std::collections::HashMap;
+
+use super::super::data::item::{KINAH, KINAH_NAME_ID};
+use super::inventory::{Change, Inventory};
+
+/// What one side is putting up
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Offer {
+    /// Object id and count of each item
+    pub items: Vec<(u32, u32)>,
+    pub kinah: u32,
+    locked: bool,
+    accepted: bool,
+}
+
+impl Offer {
+    /// How many of an item are already offered
+    pub fn count(&self, id: u32) -> u32 {
+        self.items
+            .iter()
+            .find(|(item, _)| *item == id)
+            .map_or(0, |(_, count)| *count)
+    }
+}
+
+#[derive(Debug)]
+struct Side {
+    partner: u32,
+    offer: Offer,
+}
+
+#[derive(Debug, Default)]
+pub struct Exchanges {
+    /// Both characters in an exchange have a side
+    sides: HashMap<u32, Side>,
+    /// Who asked each character with a request waiting on an answer
+    requests: HashMap<u32, u32>,
+}
+
+impl Exchanges {
+    /// Ask a character to trade, neither of them can be trading already
+    pub fn request(&mut self, from: u32, to: u32) -> Result<(), &'static str> {
+        if from == to {
+            return Err("can't trade with yourself");
+        }
+        if self.sides.contains_key(&from) || self.sides.contains_key(&to) {
+            return Err("already trading");
+        }
+        if self.requests.contains_key(&to) {
+            return Err("already asked");
+        }
+        self.requests.insert(to, from);
+        Ok(())
+    }
+
+    /// Whoever asked the character to trade, the request is used up
+    pub fn take_request(&mut self, to: u32) -> Option<u32> {
+        self.requests.remove(&to)
+    }
+
+    pub fn start(&mut self, from: u32, to: u32) -> Result<(), &'static str> {
+        if self.sides.contains_key(&from) || self.sides.contains_key(&to) {
+            return Err("already trading");
+        }
+        for (id, partner) in [(from, to), (to, from)] {
+            let offer = Offer::default();
+            self.sides.insert(id, Side { partner, offer });
+        }
+        Ok(())
+    }
+
+    pub fn partner(&self, id: u32) -> Option<u32> {
+        Some(self.sides.get(&id)?.partner)
+    }
+
+    pub fn offer(&self, id: u32) -> Option<&Offer> {
+        Some(&self.sides.get(&id)?.offer)
+    }
+
+    /// Put up more of an item, on top of whatever of it is already offered
+    pub fn add_item(
+        &mut self,
+        id: u32,
+        item: u32,
+        count: u32,
+    ) -> Result<(), &'static str> {
+        let offer = self.unlocked(id)?;
+        match offer.items.iter_mut().find(|(offered, _)| *offered == item) {
+            Some((_, offered)) => *offered += count,
+            None => offer.items.push((item, count)),
+        }
+        Ok(())
+    }
+
+    pub fn remove_item(
+        &mut self,
+        id: u32,
+        item: u32,
+    ) -> Result<(), &'static str> {
+        let offer = self.unlocked(id)?;
+        let len = offer.items.len();
+        offer.items.retain(|(offered, _)| *offered != item);
+        if offer.items.len() == len {
+            return Err("not offered");
+        }
+        Ok(())
+    }
+
+    /// Put up more kinah
+    pub fn add_kinah(
+        &mut self,
+        id: u32,
+        kinah: u32,
+    ) -> Result<(), &'static str> {
+        let offer = self.unlocked(id)?;
+        offer.kinah = offer.kinah.checked_add(kinah).ok_or("too much")?;
+        Ok(())
+    }
+
+    /// Lock an offer in, it can't change after this
+    pub fn lock(&mut self, id: u32) -> Result<(), &'static str> {
+        self.unlocked(id)?.locked = true;
+        Ok(())
+    }
+
+    /// Agree to the trade once both offers are locked in, true once both
+    /// sides have
+    pub fn accept(&mut self, id: u32) -> Result<bool, &'static str> {
+        let partner = self.partner(id).ok_or("not trading")?;
+        if ![id, partner].iter().all(|id| self.sides[id].offer.locked) {
+            return Err("not locked");
+        }
+        self.sides.get_mut(&id).unwrap().offer.accepted = true;
+        Ok(self.sides[&partner].offer.accepted)
+    }
+
+    /// Finish or cancel the exchange, any request from or to the character
+    /// is dropped too. Gives back the partner and both offers, the
+    /// character's first
+    pub fn end(&mut self, id: u32) -> Option<(u32, Offer, Offer)> {
+        self.requests.remove(&id);
+        self.requests.retain(|_, from| *from != id);
+        let side = self.sides.remove(&id)?;
+        let partner = self.sides.remove(&side.partner)?;
+        Some((side.partner, side.offer, partner.offer))
+    }
+
+    fn unlocked(&mut self, id: u32) -> Result<&mut Offer, &'static str> {
+        let side = self.sides.get_mut(&id).ok_or("not trading")?;
+        if side.offer.locked {
+            return Err("locked");
+        }
+        Ok(&mut side.offer)
+    }
+}
+
+/// Check one side can still hand over everything it offered and the other
+/// side has room for it. Items can have been moved, used or bound since
+pub fn check(
+    from: &Inventory,
+    offer: &Offer,
+    to: &Inventory,
+) -> Result<(), &'static str> {
+    for (id, count) in &offer.items {
+        from.tradable(*id, *count)?;
+    }
+    if from.kinah() < offer.kinah {
+        return Err("not enough kinah");
+    }
+    if usize::from(to.free_slots()) < offer.items.len() {
+        return Err("cube full");
+    }
+    Ok(())
+}
+
+/// How many new ids handing an offer over needs, one for each part of a
+/// stack and one for the kinah if there is none where it is going
+pub fn new_ids(from: &Inventory, offer: &Offer, to: &Inventory) -> usize {
+    let parts = offer
+        .items
+        .iter()
+        .filter(|(id, count)| {
+            from.get(*id).is_some_and(|item| item.count > *count)
+        })
+        .count();
+    let kinah = offer.kinah > 0 && to.items().all(|item| !item.is_kinah());
+    parts + usize::from(kinah)
+}
+
+/// Move everything offered from one inventory to the other, [check] it can
+/// all go and get the [new_ids] it needs first so it can't stop partway
+pub fn hand_over(
+    from: &mut Inventory,
+    offer: &Offer,
+    to: &mut Inventory,
+    mut new_id: impl FnMut() -> Option<u32>,
+) -> Result<(Vec<Change>, Vec<Change>), &'static str> {
+    let (mut given, mut received) = (Vec::new(), Vec::new());
+    for (id, count) in &offer.items {
+        let (item, change) = from.take(*id, *count, &mut new_id)?;
+        given.push(change);
+        received.push(to.give(item)?);
+    }
+    given.extend(from.spend_kinah(offer.kinah)?);
+    if offer.kinah > 0 {
+        received.extend(
+            to.store(KINAH, KINAH_NAME_ID, offer.kinah, &mut new_id)
+                .ok_or("no id")?,
+        );
+    }
+    Ok((given, received))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::data::item::InventoryItem;
+    use super::*;
+
+    #[test]
+    fn offer_lock_and_accept() {
+        let mut exchanges = Exchanges::default();
+        assert!(exchanges.request(1, 1).is_err());
+        exchanges.request(1, 2).unwrap();
+        assert!(exchanges.request(3, 2).is_err());
+        assert_eq!(exchanges.take_request(2), Some(1));
+        exchanges.start(1, 2).unwrap();
+        assert!(exchanges.request(3, 2).is_err());
+        assert_eq!(exchanges.partner(2), Some(1));
+
+        exchanges.add_item(1, 100, 5).unwrap();
+        exchanges.add_item(1, 100, 2).unwrap();
+        exchanges.add_item(1, 101, 1).unwrap();
+        exchanges.remove_item(1, 101).unwrap();
+        assert!(exchanges.remove_item(1, 101).is_err());
+        exchanges.add_kinah(2, 50).unwrap();
+        assert_eq!(exchanges.offer(1).unwrap().count(100), 7);
+
+        exchanges.lock(1).unwrap();
+        assert!(exchanges.add_item(1, 101, 1).is_err());
+        assert!(exchanges.accept(1).is_err());
+        exchanges.lock(2).unwrap();
+        assert_eq!(exchanges.accept(1), Ok(false));
+        assert_eq!(exchanges.accept(2), Ok(true));
+
+        let (partner, offer, other) = exchanges.end(2).unwrap();
+        assert_eq!(partner, 1);
+        assert_eq!(offer.kinah, 50);
+        assert_eq!(other.items, [(100, 7)]);
+        assert!(exchanges.partner(1).is_none());
+    }
+
+    #[test]
+    fn hand_over_items_and_kinah() {
+        let mut from = Inventory::default();
+        from.insert(InventoryItem::stack(10, 7, 0, 20, 0));
+        from.insert(InventoryItem::stack(11, 8, 0, 1, 1));
+        from.insert(InventoryItem::kinah(12, 100));
+        let mut to = Inventory::default();
+        to.insert(InventoryItem::stack(20, 7, 0, 5, 0));
+
+        let offer = Offer {
+            items: vec![(10, 15), (11, 1)],
+            kinah: 40,
+            ..Offer::default()
+        };
+        assert_eq!(check(&from, &offer, &to), Ok(()));
+        assert_eq!(new_ids(&from, &offer, &to), 2);
+        let mut ids = vec![31, 30];
+        let (given, received) =
+            hand_over(&mut from, &offer, &mut to, || ids.pop()).unwrap();
+        assert!(ids.is_empty());
+        assert_eq!(
+            given,
+            [
+                Change::Updated(10),
+                Change::Removed(11),
+                Change::Updated(12)
+            ]
+        );
+        assert_eq!(
+            received,
+            [Change::Added(30), Change::Added(11), Change::Added(31)]
+        );
+        assert_eq!(from.get(10).unwrap().count, 5);
+        assert_eq!(to.get(30).unwrap().count, 15);
+        assert_eq!(to.get(11).unwrap().slot, 2);
+        assert_eq!((from.kinah(), to.kinah()), (60, 40));
+
+        assert!(check(&from, &offer, &to).is_err());
+        let offer = Offer {
+            kinah: 61,
+            ..Offer::default()
+        };
+        assert_eq!(check(&from, &offer, &to), Err("not enough kinah"));
+    }
+}
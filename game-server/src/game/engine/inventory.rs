@@ -0,0 +1,518 @@
+//! The cube, every item a character carries. Items that stack are topped up
+//! before a new stack is started and stacks can be split and merged
+
+use std::collections::HashMap;
+
+use super::super::data::item::{InventoryItem, KINAH, NO_SLOT};
+
+/// Slots in the cube
+// TODO: Cube expansions
+pub const CUBE_SIZE: u16 = 108;
+// TODO: Comes from the item templates
+const MAX_STACK: u32 = 100;
+
+/// What happened to an item so the client can be told
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Change {
+    Added(u32),
+    Updated(u32),
+    Removed(u32),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Inventory {
+    items: HashMap<u32, InventoryItem>,
+}
+
+impl Inventory {
+    /// Put an item back as it was, used when loading a character
+    pub fn insert(&mut self, item: InventoryItem) {
+        self.items.insert(item.id, item);
+    }
+
+    pub fn get(&self, id: u32) -> Option<&InventoryItem> {
+        self.items.get(&id)
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = &InventoryItem> {
+        self.items.values()
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    /// Items being worn rather than sitting in the cube
+    pub fn equipped(&self) -> impl Iterator<Item = &InventoryItem> {
+        self.items.values().filter(|item| item.equipped() != 0)
+    }
+
+    /// The lowest empty slot in the cube
+    pub fn free_slot(&self) -> Option<u16> {
+        (0..CUBE_SIZE).find(|slot| self.at(*slot).is_none())
+    }
+
+    pub fn free_slots(&self) -> u16 {
+        let used = self
+            .items
+            .values()
+            .filter(|item| item.slot < CUBE_SIZE)
+            .count() as u16;
+        CUBE_SIZE.saturating_sub(used)
+    }
+
+    fn at(&self, slot: u16) -> Option<&InventoryItem> {
+        self.items.values().find(|item| item.slot == slot)
+    }
+
+    /// Add some of an item, topping up stacks of the same item before using
+    /// new slots. Nothing changes unless all of it fits
+    pub fn store(
+        &mut self,
+        item_id: u32,
+        name_id: u32,
+        mut count: u32,
+        mut new_id: impl FnMut() -> Option<u32>,
+    ) -> Option<Vec<Change>> {
+        let max = max_stack(item_id);
+        let mut stacks: Vec<u32> = self
+            .items
+            .values()
+            .filter(|item| {
+                item.item_id == item_id
+                    && item.is_stackable()
+                    && !item.soulbound
+                    && item.count < max
+            })
+            .map(|item| item.id)
+            .collect();
+        stacks.sort_unstable();
+
+        let topped_up: u64 = stacks
+            .iter()
+            .map(|id| u64::from(max - self.items[id].count))
+            .sum();
+        let room = topped_up + u64::from(self.free_slots()) * u64::from(max);
+        if item_id != KINAH && room < u64::from(count) {
+            return None;
+        }
+        // Ids for the new stacks are taken before anything changes, running
+        // out part way through would leave some of the item stored
+        let rest = u64::from(count).saturating_sub(topped_up) as u32;
+        let mut ids = (0..rest.div_ceil(max))
+            .map(|_| new_id())
+            .collect::<Option<Vec<_>>>()?
+            .into_iter();
+
+        let mut changes = Vec::new();
+        for id in stacks {
+            if count == 0 {
+                break;
+            }
+            let item = self.items.get_mut(&id)?;
+            let added = count.min(max - item.count);
+            item.count += added;
+            count -= added;
+            changes.push(Change::Updated(id));
+        }
+        while count > 0 {
+            let slot = if item_id == KINAH {
+                NO_SLOT
+            } else {
+                self.free_slot()?
+            };
+            let added = count.min(max);
+            let item = InventoryItem::stack(
+                ids.next()?,
+                item_id,
+                name_id,
+                added,
+                slot,
+            );
+            changes.push(Change::Added(item.id));
+            self.insert(item);
+            count -= added;
+        }
+        Some(changes)
+    }
+
+    pub fn kinah(&self) -> u32 {
+        self.items
+            .values()
+            .filter(|item| item.is_kinah())
+            .fold(0, |kinah, item| kinah.saturating_add(item.count))
+    }
+
+    /// Pay with kinah, taken from as many stacks as it needs. Nothing
+    /// changes unless there is enough
+    pub fn spend_kinah(
+        &mut self,
+        mut amount: u32,
+    ) -> Result<Vec<Change>, &'static str> {
+        if amount > self.kinah() {
+            return Err("not enough kinah");
+        }
+        let mut stacks: Vec<&mut InventoryItem> = self
+            .items
+            .values_mut()
+            .filter(|item| item.is_kinah() && item.count > 0)
+            .collect();
+        stacks.sort_unstable_by_key(|item| item.id);
+
+        let mut changes = Vec::new();
+        for item in stacks {
+            if amount == 0 {
+                break;
+            }
+            let spent = amount.min(item.count);
+            item.count -= spent;
+            amount -= spent;
+            changes.push(Change::Updated(item.id));
+        }
+        Ok(changes)
+    }
+
+    /// Check some of an item can be handed to another character, it has to
+    /// be in the cube and not bound to this one
+    pub fn tradable(
+        &self,
+        id: u32,
+        count: u32,
+    ) -> Result<&InventoryItem, &'static str> {
+        let item = self.items.get(&id).ok_or("no item")?;
+        if item.is_kinah() || item.equipped() != 0 || item.slot >= CUBE_SIZE {
+            return Err("not in the cube");
+        }
+        if item.soulbound {
+            return Err("soulbound");
+        }
+        if count == 0 || count > item.count {
+            return Err("bad count");
+        }
+        Ok(item)
+    }
+
+    /// Take some of an item out, all of it leaves as it is and part of a
+    /// stack leaves as a new item with the new id
+    pub fn take(
+        &mut self,
+        id: u32,
+        count: u32,
+        new_id: impl FnOnce() -> Option<u32>,
+    ) -> Result<(InventoryItem, Change), &'static str> {
+        let item = self.items.get_mut(&id).ok_or("no item")?;
+        if count == 0 || count > item.count {
+            return Err("bad count");
+        }
+        if count == item.count {
+            let item = self.items.remove(&id).unwrap();
+            return Ok((item, Change::Removed(id)));
+        }
+
+        let mut taken = item.clone();
+        taken.id = new_id().ok_or("no id")?;
+        taken.count = count;
+        item.count -= count;
+        Ok((taken, Change::Updated(id)))
+    }
+
+    /// Put an item from somewhere else in the lowest empty cube slot
+    pub fn give(
+        &mut self,
+        mut item: InventoryItem,
+    ) -> Result<Change, &'static str> {
+        item.slot = self.free_slot().ok_or("cube full")?;
+        item.set_equipped(0);
+        let id = item.id;
+        self.insert(item);
+        Ok(Change::Added(id))
+    }
+
+    /// Get rid of some of an item in the cube, all of it goes once the count
+    /// reaches 0
+    pub fn remove(
+        &mut self,
+        id: u32,
+        count: u32,
+    ) -> Result<Change, &'static str> {
+        let item = self.items.get_mut(&id).ok_or("no item")?;
+        if item.is_kinah() || item.equipped() != 0 || item.slot >= CUBE_SIZE {
+            return Err("not in the cube");
+        }
+        if count == 0 || count > item.count {
+            return Err("bad count");
+        }
+        if count == item.count {
+            self.items.remove(&id);
+            return Ok(Change::Removed(id));
+        }
+        item.count -= count;
+        Ok(Change::Updated(id))
+    }
+
+    /// Move an item to another cube slot, swapping with whatever is there
+    pub fn move_to(
+        &mut self,
+        id: u32,
+        slot: u16,
+    ) -> Result<Vec<Change>, &'static str> {
+        if slot >= CUBE_SIZE {
+            return Err("not a cube slot");
+        }
+        let from = self.items.get(&id).ok_or("no item")?.slot;
+        if from == NO_SLOT {
+            return Err("not in the cube");
+        }
+
+        let mut changes = vec![Change::Updated(id)];
+        if let Some(other) = self.at(slot).map(|other| other.id) {
+            if other == id {
+                return Ok(vec![]);
+            }
+            self.items.get_mut(&other).unwrap().slot = from;
+            changes.push(Change::Updated(other));
+        }
+        self.items.get_mut(&id).unwrap().slot = slot;
+        Ok(changes)
+    }
+
+    /// Take some of a stack and put it in an empty slot as a stack of its own
+    pub fn split(
+        &mut self,
+        id: u32,
+        count: u32,
+        slot: u16,
+        new_id: impl FnOnce() -> Option<u32>,
+    ) -> Result<Vec<Change>, &'static str> {
+        let item = self.items.get(&id).ok_or("no item")?;
+        if !item.is_stackable() || item.is_kinah() {
+            return Err("can't be split");
+        }
+        if count == 0 || count >= item.count {
+            return Err("bad count");
+        }
+        if slot >= CUBE_SIZE || self.at(slot).is_some() {
+            return Err("slot taken");
+        }
+
+        let mut split = item.clone();
+        split.id = new_id().ok_or("no id")?;
+        split.count = count;
+        split.slot = slot;
+        self.items.get_mut(&id).unwrap().count -= count;
+
+        let changes = vec![Change::Updated(id), Change::Added(split.id)];
+        self.insert(split);
+        Ok(changes)
+    }
+
+    /// Move some of one stack onto another stack of the same item, the first
+    /// stack goes away once it is empty
+    pub fn merge(
+        &mut self,
+        from: u32,
+        to: u32,
+        count: u32,
+    ) -> Result<Vec<Change>, &'static str> {
+        if from == to {
+            return Err("same stack");
+        }
+        let source = self.items.get(&from).ok_or("no item")?;
+        let target = self.items.get(&to).ok_or("no item")?;
+        if source.item_id != target.item_id
+            || !source.is_stackable()
+            || source.soulbound != target.soulbound
+        {
+            return Err("different items");
+        }
+        if count == 0 || count > source.count {
+            return Err("bad count");
+        }
+        target
+            .count
+            .checked_add(count)
+            .filter(|total| *total <= max_stack(target.item_id))
+            .ok_or("stack full")?;
+
+        self.items.get_mut(&to).unwrap().count += count;
+        let source = self.items.get_mut(&from).unwrap();
+        source.count -= count;
+        if source.count > 0 {
+            return Ok(vec![Change::Updated(from), Change::Updated(to)]);
+        }
+        self.items.remove(&from);
+        Ok(vec![Change::Removed(from), Change::Updated(to)])
+    }
+
+    /// Wear an item from the cube in a slot, given as its bit in the slot
+    /// masks. Whatever is already worn there goes back to the cube in the
+    /// slot the item came from
+    pub fn equip(
+        &mut self,
+        id: u32,
+        slot: u32,
+    ) -> Result<Vec<Change>, &'static str> {
+        let item = self.items.get(&id).ok_or("no item")?;
+        if item.equipment.is_none() {
+            return Err("not equipment");
+        }
+        if item.equipped() != 0 || item.slot >= CUBE_SIZE {
+            return Err("not in the cube");
+        }
+        let from = item.slot;
+
+        let mut changes = Vec::new();
+        let worn: Vec<u32> = self
+            .equipped()
+            .filter(|item| item.equipped() & slot != 0)
+            .map(|item| item.id)
+            .collect();
+        let mut free = Some(from);
+        for worn in worn {
+            let slot = match free.take() {
+                Some(slot) => slot,
+                None => self.free_slot().ok_or("cube full")?,
+            };
+            let item = self.items.get_mut(&worn).unwrap();
+            item.set_equipped(0);
+            item.slot = slot;
+            changes.push(Change::Updated(worn));
+        }
+
+        let item = self.items.get_mut(&id).unwrap();
+        item.set_equipped(slot);
+        item.slot = NO_SLOT;
+        changes.push(Change::Updated(id));
+        Ok(changes)
+    }
+
+    /// Take an item off and put it in the lowest empty cube slot
+    pub fn unequip(&mut self, id: u32) -> Result<Vec<Change>, &'static str> {
+        let item = self.items.get(&id).ok_or("no item")?;
+        if item.equipped() == 0 {
+            return Err("not equipped");
+        }
+        let slot = self.free_slot().ok_or("cube full")?;
+        let item = self.items.get_mut(&id).unwrap();
+        item.set_equipped(0);
+        item.slot = slot;
+        Ok(vec![Change::Updated(id)])
+    }
+}
+
+/// Kinah has no limit
+fn max_stack(item_id: u32) -> u32 {
+    if item_id == KINAH {
+        u32::MAX
+    } else {
+        MAX_STACK
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::data::item::{Equipment, EquipmentKind};
+    use super::*;
+
+    #[test]
+    fn stack_split_and_merge() {
+        let mut inventory = Inventory::default();
+        let mut ids = 1..;
+        let mut new_id = || ids.next();
+
+        inventory.insert(InventoryItem::stack(100, 7, 0, 90, 0));
+        let changes = inventory.store(7, 0, 20, &mut new_id).unwrap();
+        assert_eq!(changes, vec![Change::Updated(100), Change::Added(1)]);
+        assert_eq!(inventory.get(100).unwrap().count, 100);
+        assert_eq!(inventory.get(1).unwrap().count, 10);
+        assert_eq!(inventory.get(1).unwrap().slot, 1);
+
+        assert_eq!(
+            inventory.split(100, 30, 5, &mut new_id),
+            Ok(vec![Change::Updated(100), Change::Added(2)])
+        );
+        assert_eq!(inventory.get(2).unwrap().slot, 5);
+        assert!(inventory.split(100, 70, 5, &mut new_id).is_err());
+
+        assert!(inventory.merge(2, 100, 31).is_err());
+        assert_eq!(
+            inventory.merge(1, 2, 10),
+            Ok(vec![Change::Removed(1), Change::Updated(2)])
+        );
+        assert_eq!(inventory.get(2).unwrap().count, 40);
+
+        assert_eq!(
+            inventory.move_to(2, 0),
+            Ok(vec![Change::Updated(2), Change::Updated(100)])
+        );
+        assert_eq!(inventory.get(100).unwrap().slot, 5);
+
+        assert!(inventory
+            .store(7, 0, 100 * u32::from(CUBE_SIZE), new_id)
+            .is_none());
+        assert_eq!(inventory.get(2).unwrap().count, 40);
+
+        // Running out of ids leaves the stacks as they were
+        assert!(inventory.store(7, 0, 200, || None).is_none());
+        assert_eq!(inventory.get(100).unwrap().count, 70);
+    }
+
+    #[test]
+    fn kinah_across_stacks() {
+        let mut inventory = Inventory::default();
+        inventory.insert(InventoryItem::stack(
+            1,
+            KINAH,
+            0,
+            u32::MAX - 10,
+            NO_SLOT,
+        ));
+        inventory.insert(InventoryItem::stack(2, KINAH, 0, 30, NO_SLOT));
+        assert_eq!(inventory.kinah(), u32::MAX);
+        assert_eq!(inventory.merge(2, 1, 20), Err("stack full"));
+
+        assert_eq!(
+            inventory.spend_kinah(u32::MAX - 5),
+            Ok(vec![Change::Updated(1), Change::Updated(2)])
+        );
+        assert_eq!(inventory.get(2).unwrap().count, 25);
+        assert!(inventory.spend_kinah(26).is_err());
+        assert_eq!(inventory.kinah(), 25);
+    }
+
+    #[test]
+    fn equip_and_unequip() {
+        let equipment = |equipped| Equipment {
+            kind: EquipmentKind::Weapon,
+            equipped,
+            slots: 0x1,
+            other_slots: 0x2,
+            skin: 7,
+            bonuses: vec![],
+            unknown: None,
+        };
+        let mut inventory = Inventory::default();
+        inventory.insert(InventoryItem::equipment(1, 7, 0, 3, 0, equipment(0)));
+        inventory.insert(InventoryItem::equipment(
+            2,
+            7,
+            0,
+            NO_SLOT,
+            0,
+            equipment(0x1),
+        ));
+
+        assert_eq!(
+            inventory.equip(1, 0x1),
+            Ok(vec![Change::Updated(2), Change::Updated(1)])
+        );
+        assert_eq!(inventory.get(1).unwrap().slot, NO_SLOT);
+        assert_eq!(inventory.get(2).unwrap().slot, 3);
+        assert_eq!(inventory.equipped().count(), 1);
+        assert!(inventory.equip(1, 0x2).is_err());
+
+        assert_eq!(inventory.unequip(1), Ok(vec![Change::Updated(1)]));
+        assert_eq!(inventory.get(1).unwrap().slot, 0);
+        assert_eq!(inventory.equipped().count(), 0);
+    }
+}
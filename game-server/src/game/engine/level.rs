@@ -0,0 +1,120 @@
+//! Experience and levelling. Kills are worth more the tougher the NPC and less
+//! the further it is below the character, every level needs more experience
+//! than the last and each class grows its stats along its own table
+
+use super::super::character::Class;
+use super::super::data::npc::Template;
+
+pub const MAX_LEVEL: u16 = 55;
+/// NPCs this many levels or more below a character aren't worth anything
+const GREY_LEVELS: i32 = 10;
+/// Experience changes by this fraction per level of difference
+const LEVEL_EXP: f32 = 0.1;
+const MIN_MULTIPLIER: f32 = 0.1;
+const MAX_MULTIPLIER: f32 = 1.5;
+
+/// What a class starts with at level 1 and how much it gains each level
+#[derive(Debug)]
+pub struct Growth {
+    /// Power, health, agility, accuracy, knowledge and will, these are set by
+    /// the class and don't change with level
+    pub primary: [u16; 6],
+    pub hp: (i32, i32),
+    pub mp: (u32, u32),
+}
+
+const WARRIOR: Growth = Growth {
+    primary: [115, 115, 100, 100, 90, 90],
+    hp: (180, 72),
+    mp: (160, 56),
+};
+const SCOUT: Growth = Growth {
+    primary: [110, 100, 110, 110, 90, 90],
+    hp: (150, 60),
+    mp: (200, 77),
+};
+const MAGE: Growth = Growth {
+    primary: [90, 90, 95, 95, 115, 115],
+    hp: (110, 44),
+    mp: (260, 98),
+};
+const PRIEST: Growth = Growth {
+    primary: [95, 100, 95, 90, 105, 110],
+    hp: (140, 56),
+    mp: (230, 88),
+};
+
+/// Specialised classes grow the same as the class they started as
+pub fn growth(class: Class) -> &'static Growth {
+    match class {
+        Class::Warrior | Class::Gladiator | Class::Templar => &WARRIOR,
+        Class::Scout | Class::Assassin | Class::Ranger => &SCOUT,
+        Class::Mage | Class::Sorcerer | Class::SpiritMaster => &MAGE,
+        Class::Priest | Class::Cleric | Class::Chanter => &PRIEST,
+    }
+}
+
+/// Experience needed to get from `level` to the next one
+// TODO: Read the real table from the client
+pub fn exp_to_level(level: u16) -> u64 {
+    100 * u64::from(level).pow(3)
+}
+
+/// Experience a character earns for killing an NPC
+pub fn kill_exp(template: &Template, character_level: u16) -> u64 {
+    let gap = i32::from(template.level) - i32::from(character_level);
+    if gap <= -GREY_LEVELS {
+        return 0;
+    }
+    let base = template.max_hp.max(0) as u64 * u64::from(template.level) / 10;
+    let multiplier =
+        (1. + gap as f32 * LEVEL_EXP).clamp(MIN_MULTIPLIER, MAX_MULTIPLIER);
+
+    (base as f32 * multiplier) as u64
+}
+
+/// Split a party kill between the members in on it by level, higher levels
+/// take a bigger share
+pub fn share_exp(exp: u64, levels: &[u16]) -> Vec<u64> {
+    let total: u64 = levels.iter().copied().map(u64::from).sum();
+    levels
+        .iter()
+        .map(|level| exp * u64::from(*level) / total.max(1))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::data::npc::Templates;
+    use super::*;
+
+    const NPCS: &str = "<npc_clients>
+        <npc_client>
+            <id>1</id>
+            <name>Test</name>
+            <name_id>1</name_id>
+            <level>20</level>
+            <max_hp>1000</max_hp>
+            <attack_delay>2000</attack_delay>
+            <npc_type>Attackable</npc_type>
+        </npc_client>
+    </npc_clients>";
+
+    #[test]
+    fn kill_exp_follows_level_gap() {
+        let templates = Templates::parse(NPCS).unwrap();
+        let npc = templates.get(1).unwrap();
+
+        assert_eq!(kill_exp(&npc, 20), 2000);
+        assert_eq!(kill_exp(&npc, 15), 3000);
+        assert_eq!(kill_exp(&npc, 25), 1000);
+        assert_eq!(kill_exp(&npc, 30), 0);
+    }
+
+    #[test]
+    fn share_exp_by_level() {
+        assert_eq!(share_exp(1000, &[10]), [1000]);
+        assert_eq!(share_exp(1000, &[10, 30]), [250, 750]);
+        assert_eq!(share_exp(1000, &[]), [0; 0]);
+    }
+}
@@ -0,0 +1,48 @@
+//! Items left on a corpse, only the characters that earned them can take them
+
+use super::super::data::drop::Dropped;
+
+#[derive(Debug)]
+pub struct Loot {
+    /// Characters allowed to loot, the killer and whoever they are grouped
+    /// with
+    owners: Vec<u32>,
+    /// Taken items leave a gap, the client asks for items by index
+    items: Vec<Option<Dropped>>,
+}
+
+impl Loot {
+    pub fn new(owners: Vec<u32>, items: Vec<Dropped>) -> Self {
+        Self {
+            owners,
+            items: items.into_iter().map(Some).collect(),
+        }
+    }
+
+    pub fn can_loot(&self, character_id: u32) -> bool {
+        self.owners.contains(&character_id)
+    }
+
+    /// What is left and the index of each
+    pub fn items(&self) -> impl Iterator<Item = (u32, &Dropped)> {
+        self.items
+            .iter()
+            .enumerate()
+            .filter_map(|(i, item)| Some((i as u32, item.as_ref()?)))
+    }
+
+    pub fn take(&mut self, index: u32) -> Option<Dropped> {
+        self.items.get_mut(index as usize)?.take()
+    }
+
+    /// Put back an item that couldn't be picked up
+    pub fn put_back(&mut self, index: u32, item: Dropped) {
+        if let Some(slot) = self.items.get_mut(index as usize) {
+            *slot = Some(item);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.iter().all(Option::is_none)
+    }
+}
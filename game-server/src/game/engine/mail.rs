@@ -0,0 +1,179 @@
+//! Letters between characters, online or not. A letter can carry an item and
+//! kinah, both are held by the letter until the recipient takes them. Letters
+//! left with something on them for too long go back to whoever sent them
+
+use super::super::data::item::{InventoryItem, KINAH, KINAH_NAME_ID};
+use super::inventory::{Change, Inventory};
+
+/// Letters a mailbox holds before it turns new ones away
+pub const MAILBOX_SIZE: usize = 100;
+/// Seconds a letter waits to be dealt with before it is returned or thrown
+/// away
+pub const EXPIRY: u32 = 30 * 24 * 60 * 60;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Letter {
+    pub id: u32,
+    pub sender_id: u32,
+    pub sender_name: String,
+    pub recipient_id: u32,
+    pub recipient_name: String,
+    pub title: String,
+    pub message: String,
+    pub item: Option<InventoryItem>,
+    pub kinah: u32,
+    pub unread: bool,
+    /// Unix time it was sent, or sent back
+    pub sent_at: u32,
+    /// On its way back to the sender, it is thrown away rather than returned
+    /// again
+    pub returned: bool,
+}
+
+impl Letter {
+    pub fn has_attachments(&self) -> bool {
+        self.item.is_some() || self.kinah > 0
+    }
+
+    /// Send an expired letter back along with whatever is still on it. None
+    /// when it should be thrown away instead
+    pub fn return_to_sender(self, now: u32) -> Option<Letter> {
+        if self.returned || !self.has_attachments() {
+            return None;
+        }
+        Some(Letter {
+            sender_id: self.recipient_id,
+            sender_name: self.recipient_name,
+            recipient_id: self.sender_id,
+            recipient_name: self.sender_name,
+            unread: true,
+            sent_at: now,
+            returned: true,
+            ..self
+        })
+    }
+
+    /// Move the attached item into the cube
+    pub fn take_item(
+        &mut self,
+        inventory: &mut Inventory,
+    ) -> Result<Change, &'static str> {
+        let item = self.item.take().ok_or("no item")?;
+        inventory
+            .give(item.clone())
+            .inspect_err(|_| self.item = Some(item))
+    }
+
+    /// Move the attached kinah into the inventory
+    pub fn take_kinah(
+        &mut self,
+        inventory: &mut Inventory,
+        new_id: impl FnMut() -> Option<u32>,
+    ) -> Result<Vec<Change>, &'static str> {
+        if self.kinah == 0 {
+            return Err("no kinah");
+        }
+        let changes = inventory
+            .store(KINAH, KINAH_NAME_ID, self.kinah, new_id)
+            .ok_or("no id")?;
+        self.kinah = 0;
+        Ok(changes)
+    }
+}
+
+/// Take some of an item and kinah out of the sender's inventory to go on a
+/// letter. Nothing changes unless both can go
+pub fn attach(
+    inventory: &mut Inventory,
+    item: Option<(u32, u32)>,
+    kinah: u32,
+    new_id: impl FnOnce() -> Option<u32>,
+) -> Result<(Option<InventoryItem>, Vec<Change>), &'static str> {
+    if let Some((id, count)) = item {
+        inventory.tradable(id, count)?;
+    }
+    if inventory.kinah() < kinah {
+        return Err("not enough kinah");
+    }
+
+    let mut changes = Vec::new();
+    let item = match item {
+        Some((id, count)) => {
+            let (item, change) = inventory.take(id, count, new_id)?;
+            changes.push(change);
+            Some(item)
+        }
+        None => None,
+    };
+    changes.extend(inventory.spend_kinah(kinah)?);
+    Ok((item, changes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn letter(item: Option<InventoryItem>, kinah: u32) -> Letter {
+        Letter {
+            id: 1,
+            sender_id: 10,
+            sender_name: "Sender".into(),
+            recipient_id: 20,
+            recipient_name: "Recipient".into(),
+            title: "Title".into(),
+            message: "Message".into(),
+            item,
+            kinah,
+            unread: false,
+            sent_at: 1000,
+            returned: false,
+        }
+    }
+
+    #[test]
+    fn attach_and_take() {
+        let mut sender = Inventory::default();
+        sender.insert(InventoryItem::stack(1, 7, 0, 10, 0));
+        sender.insert(InventoryItem::kinah(2, 100));
+
+        assert_eq!(
+            attach(&mut sender, Some((1, 11)), 0, || Some(3)),
+            Err("bad count")
+        );
+        assert_eq!(
+            attach(&mut sender, Some((1, 4)), 101, || Some(3)),
+            Err("not enough kinah")
+        );
+        let (item, changes) =
+            attach(&mut sender, Some((1, 4)), 60, || Some(3)).unwrap();
+        assert_eq!(changes, [Change::Updated(1), Change::Updated(2)]);
+        assert_eq!(sender.get(1).unwrap().count, 6);
+        assert_eq!(sender.kinah(), 40);
+
+        let mut letter = letter(item, 60);
+        let mut recipient = Inventory::default();
+        assert_eq!(letter.take_item(&mut recipient), Ok(Change::Added(3)));
+        assert_eq!(recipient.get(3).unwrap().count, 4);
+        assert!(letter.take_item(&mut recipient).is_err());
+        assert_eq!(
+            letter.take_kinah(&mut recipient, || Some(4)),
+            Ok(vec![Change::Added(4)])
+        );
+        assert_eq!(recipient.kinah(), 60);
+        assert!(!letter.has_attachments());
+    }
+
+    #[test]
+    fn return_expired() {
+        let empty = letter(None, 0);
+        assert_eq!(empty.return_to_sender(5000), None);
+
+        let returned = letter(None, 50).return_to_sender(5000).unwrap();
+        assert_eq!(returned.recipient_id, 10);
+        assert_eq!(returned.sender_name, "Recipient");
+        assert_eq!(returned.kinah, 50);
+        assert_eq!(returned.sent_at, 5000);
+        assert!(returned.unread);
+        assert_eq!(returned.return_to_sender(9000), None);
+    }
+}
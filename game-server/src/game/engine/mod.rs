@@ -1,8 +1,20 @@
 use crate::consume_le_bytes;
+use crate::error::Result;
 
+pub mod combat;
 pub mod damage;
-
-#[derive(Debug, Clone, Copy)]
+pub mod effect;
+pub mod exchange;
+pub mod inventory;
+pub mod level;
+pub mod loot;
+pub mod mail;
+pub mod party;
+pub mod regen;
+pub mod shop;
+pub mod skill;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Coord {
     x: f32,
     y: f32,
@@ -12,10 +24,20 @@ pub struct Coord {
 impl Coord {
     pub const LEN: usize = 12;
 
-    pub fn new(x: f32, y: f32, z: f32) -> Self {
+    pub const fn new(x: f32, y: f32, z: f32) -> Self {
         Self { x, y, z }
     }
 
+    pub fn x(&self) -> f32 {
+        self.x
+    }
+    pub fn y(&self) -> f32 {
+        self.y
+    }
+    pub fn z(&self) -> f32 {
+        self.z
+    }
+
     pub fn serialise(&self, buf: &mut [u8]) -> usize {
         assert!(buf.len() >= Self::LEN);
         buf[..4].copy_from_slice(&self.x.to_le_bytes());
@@ -25,13 +47,13 @@ impl Coord {
         Self::LEN
     }
 
-    pub fn deserialise(buf: &[u8; Self::LEN]) -> Self {
+    pub fn deserialise(buf: &[u8]) -> Result<Self> {
         let mut _len = 0;
-        Self {
+        Ok(Self {
             x: consume_le_bytes!(_len, buf, f32),
             y: consume_le_bytes!(_len, buf, f32),
             z: consume_le_bytes!(_len, buf, f32),
-        }
+        })
     }
 
     pub fn distance(&self, location: &Coord) -> f32 {
@@ -41,6 +63,20 @@ impl Coord {
         let distance = (dx * dx + dy * dy + dz * dz).sqrt();
         distance
     }
+
+    /// Move up to `distance` towards a location without overshooting it
+    pub fn step_towards(&self, location: &Coord, distance: f32) -> Coord {
+        let remaining = self.distance(location);
+        if remaining <= distance {
+            return *location;
+        }
+        let scale = distance / remaining;
+        Coord {
+            x: self.x + (location.x - self.x) * scale,
+            y: self.y + (location.y - self.y) * scale,
+            z: self.z + (location.z - self.z) * scale,
+        }
+    }
 }
 
 impl std::ops::Add for &Coord {
@@ -106,6 +142,12 @@ impl Direction {
         Self(direction % Self::MODULUS)
     }
 
+    /// Face from one location towards another, each step is 3 degrees
+    pub fn towards(from: &Coord, to: &Coord) -> Self {
+        let angle = (to.y - from.y).atan2(to.x - from.x).to_degrees();
+        Self::new((angle.rem_euclid(360.) / 3.) as u8)
+    }
+
     pub fn bits(&self) -> u8 {
         self.0
     }
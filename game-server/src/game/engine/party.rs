@@ -0,0 +1,325 @@
+//! Parties of up to [MAX_MEMBERS] characters. The leader invites, kicks and
+//! decides who gets to loot, members share the experience from their kills
+
+use std::collections::HashMap;
+
+pub const MAX_MEMBERS: usize = 6;
+
+/// Who can loot what the party kills
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum LootRule {
+    /// Anyone in the party nearby
+    FreeForAll = 0,
+    /// Each kill goes to the next member nearby in turn
+    RoundRobin = 1,
+    Leader = 2,
+}
+
+impl TryFrom<u32> for LootRule {
+    type Error = u32;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Self::FreeForAll,
+            1 => Self::RoundRobin,
+            2 => Self::Leader,
+            rule => return Err(rule),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Party {
+    id: u32,
+    leader: u32,
+    /// In the order they joined, the next leader is the longest serving
+    members: Vec<u32>,
+    loot_rule: LootRule,
+    /// Index into members of whoever gets the next round robin kill
+    next_looter: usize,
+}
+
+impl Party {
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn leader(&self) -> u32 {
+        self.leader
+    }
+
+    pub fn members(&self) -> &[u32] {
+        &self.members
+    }
+
+    pub fn loot_rule(&self) -> LootRule {
+        self.loot_rule
+    }
+
+    /// Who may loot a kill, out of the members close enough to it
+    pub fn looters(&mut self, nearby: &[u32]) -> Vec<u32> {
+        match self.loot_rule {
+            LootRule::FreeForAll => nearby.to_vec(),
+            LootRule::RoundRobin => {
+                for _ in 0..self.members.len() {
+                    let member = self.members[self.next_looter];
+                    self.next_looter =
+                        (self.next_looter + 1) % self.members.len();
+                    if nearby.contains(&member) {
+                        return vec![member];
+                    }
+                }
+                vec![]
+            }
+            LootRule::Leader if nearby.contains(&self.leader) => {
+                vec![self.leader]
+            }
+            // Nobody would be able to loot it otherwise
+            LootRule::Leader => nearby.to_vec(),
+        }
+    }
+}
+
+/// How a member came to leave their party
+#[derive(Debug)]
+pub struct Departure {
+    /// The party as it is without them
+    pub party: Party,
+    /// The party was down to one member and has gone
+    pub disbanded: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Parties {
+    parties: HashMap<u32, Party>,
+    /// Party id of every character in one
+    by_member: HashMap<u32, u32>,
+    /// Inviter of every character with an invite waiting on an answer
+    invites: HashMap<u32, u32>,
+    next_id: u32,
+}
+
+impl Parties {
+    /// The party a character is in
+    pub fn get(&self, character_id: u32) -> Option<&Party> {
+        self.parties.get(self.by_member.get(&character_id)?)
+    }
+
+    pub fn get_mut(&mut self, character_id: u32) -> Option<&mut Party> {
+        self.parties.get_mut(self.by_member.get(&character_id)?)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Party> {
+        self.parties.values()
+    }
+
+    /// Ask a character to join the inviter's party, only leaders can invite
+    /// once there is one
+    pub fn invite(
+        &mut self,
+        inviter: u32,
+        invitee: u32,
+    ) -> Result<(), &'static str> {
+        if inviter == invitee {
+            return Err("can't invite yourself");
+        }
+        if let Some(party) = self.get(inviter) {
+            if party.leader != inviter {
+                return Err("not the leader");
+            }
+            if party.members.len() >= MAX_MEMBERS {
+                return Err("party full");
+            }
+        }
+        if self.by_member.contains_key(&invitee) {
+            return Err("already in a party");
+        }
+        if self.invites.contains_key(&invitee) {
+            return Err("already invited");
+        }
+        self.invites.insert(invitee, inviter);
+        Ok(())
+    }
+
+    /// The inviter of a character's waiting invite, the invite is used up
+    pub fn take_invite(&mut self, invitee: u32) -> Option<u32> {
+        self.invites.remove(&invitee)
+    }
+
+    /// Put an invitee in the inviter's party, starting one with the inviter
+    /// as leader if they aren't in one yet
+    pub fn join(
+        &mut self,
+        inviter: u32,
+        invitee: u32,
+    ) -> Result<&Party, &'static str> {
+        if self.by_member.contains_key(&invitee) {
+            return Err("already in a party");
+        }
+        let id = match self.by_member.get(&inviter) {
+            Some(id) => *id,
+            None => {
+                self.next_id += 1;
+                let id = self.next_id;
+                self.parties.insert(
+                    id,
+                    Party {
+                        id,
+                        leader: inviter,
+                        members: vec![inviter],
+                        loot_rule: LootRule::FreeForAll,
+                        next_looter: 0,
+                    },
+                );
+                self.by_member.insert(inviter, id);
+                id
+            }
+        };
+
+        let party = self.parties.get_mut(&id).unwrap();
+        if party.members.len() >= MAX_MEMBERS {
+            return Err("party full");
+        }
+        party.members.push(invitee);
+        self.by_member.insert(invitee, id);
+        Ok(party)
+    }
+
+    /// Take a character out of their party, the longest serving member takes
+    /// over when the leader goes. Any invite they were waiting on is dropped
+    pub fn leave(&mut self, character_id: u32) -> Option<Departure> {
+        self.invites.remove(&character_id);
+        self.invites.retain(|_, inviter| *inviter != character_id);
+        let id = self.by_member.remove(&character_id)?;
+        let party = self.parties.get_mut(&id)?;
+        party.members.retain(|member| *member != character_id);
+        party.next_looter = 0;
+        if party.leader == character_id {
+            party.leader = party.members.first().copied().unwrap_or_default();
+        }
+
+        if party.members.len() > 1 {
+            return Some(Departure {
+                party: party.clone(),
+                disbanded: false,
+            });
+        }
+        let party = self.parties.remove(&id)?;
+        for member in &party.members {
+            self.by_member.remove(member);
+        }
+        Some(Departure {
+            party,
+            disbanded: true,
+        })
+    }
+
+    /// The leader removes a member
+    pub fn kick(
+        &mut self,
+        leader: u32,
+        member: u32,
+    ) -> Result<Departure, &'static str> {
+        self.check_leader(leader, member)?;
+        self.leave(member).ok_or("not in a party")
+    }
+
+    /// The leader hands the party over to another member
+    pub fn change_leader(
+        &mut self,
+        leader: u32,
+        member: u32,
+    ) -> Result<&Party, &'static str> {
+        self.check_leader(leader, member)?;
+        let party = self.get_mut(leader).ok_or("not in a party")?;
+        party.leader = member;
+        Ok(party)
+    }
+
+    pub fn set_loot_rule(
+        &mut self,
+        leader: u32,
+        loot_rule: LootRule,
+    ) -> Result<&Party, &'static str> {
+        let party = self.get_mut(leader).ok_or("not in a party")?;
+        if party.leader != leader {
+            return Err("not the leader");
+        }
+        party.loot_rule = loot_rule;
+        Ok(party)
+    }
+
+    /// The leader acting on another member of the same party
+    fn check_leader(
+        &self,
+        leader: u32,
+        member: u32,
+    ) -> Result<(), &'static str> {
+        let party = self.get(leader).ok_or("not in a party")?;
+        if party.leader != leader {
+            return Err("not the leader");
+        }
+        if leader == member || !party.members.contains(&member) {
+            return Err("not a member");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invite_join_and_leave() {
+        let mut parties = Parties::default();
+        assert!(parties.invite(1, 1).is_err());
+        assert_eq!(parties.invite(1, 2), Ok(()));
+        assert!(parties.invite(3, 2).is_err());
+        assert_eq!(parties.take_invite(2), Some(1));
+        assert_eq!(parties.join(1, 2).unwrap().members(), [1, 2]);
+
+        assert!(parties.invite(2, 3).is_err());
+        parties.invite(1, 3).unwrap();
+        parties.join(1, 3).unwrap();
+        assert_eq!(parties.get(3).unwrap().leader(), 1);
+
+        assert!(parties.kick(2, 3).is_err());
+        let departure = parties.leave(1).unwrap();
+        assert!(!departure.disbanded);
+        assert_eq!(departure.party.leader(), 2);
+        assert!(parties.get(1).is_none());
+
+        let departure = parties.kick(2, 3).unwrap();
+        assert!(departure.disbanded);
+        assert_eq!(departure.party.members(), [2]);
+        assert!(parties.get(2).is_none());
+        assert_eq!(parties.iter().count(), 0);
+    }
+
+    #[test]
+    fn loot_rules() {
+        let mut parties = Parties::default();
+        for member in 2..=4 {
+            parties.invite(1, member).unwrap();
+            parties.join(1, member).unwrap();
+        }
+        let party = parties.get_mut(1).unwrap();
+        assert_eq!(party.looters(&[1, 3]), [1, 3]);
+
+        let party = parties.set_loot_rule(1, LootRule::RoundRobin).unwrap();
+        assert_eq!(party.loot_rule(), LootRule::RoundRobin);
+        let party = parties.get_mut(1).unwrap();
+        assert_eq!(party.looters(&[2, 3]), [2]);
+        assert_eq!(party.looters(&[2, 3]), [3]);
+        assert_eq!(party.looters(&[2, 3]), [2]);
+
+        assert!(parties.set_loot_rule(2, LootRule::Leader).is_err());
+        parties.set_loot_rule(1, LootRule::Leader).unwrap();
+        parties.change_leader(1, 4).unwrap();
+        let party = parties.get_mut(1).unwrap();
+        assert_eq!(party.looters(&[1, 4]), [4]);
+        assert_eq!(party.looters(&[1, 2]), [1, 2]);
+    }
+}
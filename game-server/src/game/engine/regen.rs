@@ -0,0 +1,59 @@
+//! HP, MP and DP recovery. Characters recover slowly in a fight, faster out
+//! of one and fastest sat resting, health and will speed up HP and MP. DP
+//! only builds up while fighting
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pace {
+    Combat,
+    Normal,
+    Resting,
+}
+
+/// What changed so the client can be told
+#[derive(Debug, Default, PartialEq)]
+pub struct Regenerated {
+    pub hp: bool,
+    pub mp: bool,
+    pub dp: bool,
+}
+
+/// DP gained each interval spent in combat
+const COMBAT_DP: u16 = 10;
+
+/// Percent of max HP or MP recovered each interval at a primary stat of 100
+fn percent(pace: Pace) -> f32 {
+    match pace {
+        Pace::Combat => 1.,
+        Pace::Normal => 4.,
+        Pace::Resting => 10.,
+    }
+}
+
+pub fn hp(max: i32, health: u16, pace: Pace) -> i32 {
+    (max as f32 * percent(pace) / 100. * f32::from(health) / 100.) as i32
+}
+
+pub fn mp(max: u32, will: u16, pace: Pace) -> u32 {
+    (max as f32 * percent(pace) / 100. * f32::from(will) / 100.) as u32
+}
+
+pub fn dp(pace: Pace) -> u16 {
+    match pace {
+        Pace::Combat => COMBAT_DP,
+        Pace::Normal | Pace::Resting => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resting_is_fastest() {
+        assert_eq!(hp(1000, 100, Pace::Combat), 10);
+        assert_eq!(hp(1000, 100, Pace::Normal), 40);
+        assert_eq!(hp(1000, 120, Pace::Resting), 120);
+        assert_eq!(mp(1000, 50, Pace::Normal), 20);
+        assert_eq!(dp(Pace::Resting), 0);
+    }
+}
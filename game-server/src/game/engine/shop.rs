@@ -0,0 +1,215 @@
+//! Buying from and selling to merchant NPCs. Merchants charge the price on
+//! the item template and buy back for a fraction of it
+
+use super::super::data::item::{
+    InventoryItem, Templates, KINAH, KINAH_NAME_ID,
+};
+use super::inventory::{Change, Inventory};
+
+// TODO: Verify this, merchants might pay different rates
+/// Merchants pay back a quarter of what they charge
+const SELL_DIVISOR: u32 = 4;
+
+/// What a merchant pays for one of an item
+pub fn sell_price(price: u32) -> u32 {
+    price / SELL_DIVISOR
+}
+
+/// Buy item ids and counts off a merchant's goods. Nothing changes unless
+/// the character can pay for all of it and has room for it
+pub fn buy(
+    inventory: &mut Inventory,
+    goods: &[u32],
+    items: &Templates,
+    purchases: &[(u32, u32)],
+    mut new_id: impl FnMut() -> Option<u32>,
+) -> Result<Vec<Change>, &'static str> {
+    let mut cost = 0_u32;
+    for (item_id, count) in purchases {
+        if !goods.contains(item_id) {
+            return Err("not sold here");
+        }
+        let template = items.get(*item_id).ok_or("no template")?;
+        if *count == 0 {
+            return Err("bad count");
+        }
+        cost = template
+            .price
+            .checked_mul(*count)
+            .and_then(|price| cost.checked_add(price))
+            .ok_or("too expensive")?;
+    }
+    if inventory.kinah() < cost {
+        return Err("not enough kinah");
+    }
+
+    // Everything is stored on a copy first, it only fits if all of it does
+    let mut bought = inventory.clone();
+    let mut changes = bought.spend_kinah(cost)?;
+    for (item_id, count) in purchases {
+        let template = items.get(*item_id).ok_or("no template")?;
+        if template.slots == 0 {
+            changes.extend(
+                bought
+                    .store(*item_id, template.name_id, *count, &mut new_id)
+                    .ok_or("cube full")?,
+            );
+            continue;
+        }
+        // Equipment doesn't stack, each one takes a slot of its own
+        for _ in 0..*count {
+            let id = new_id().ok_or("no id")?;
+            let item = InventoryItem::from_template(id, template)
+                .ok_or("not equipment")?;
+            changes.push(bought.give(item)?);
+        }
+    }
+    *inventory = bought;
+    Ok(changes)
+}
+
+/// Sell object ids and counts from the cube for kinah, nothing changes
+/// unless all of it can be sold
+pub fn sell(
+    inventory: &mut Inventory,
+    items: &Templates,
+    sales: &[(u32, u32)],
+    new_id: impl FnMut() -> Option<u32>,
+) -> Result<Vec<Change>, &'static str> {
+    let mut earned = 0_u32;
+    for (i, (id, count)) in sales.iter().enumerate() {
+        if sales[..i].iter().any(|(sold, _)| sold == id) {
+            return Err("sold twice");
+        }
+        let item = inventory.get(*id).ok_or("no item")?;
+        let price = items.get(item.item_id).map_or(0, |item| item.price);
+        if price == 0 {
+            return Err("can't be sold");
+        }
+        earned = sell_price(price)
+            .checked_mul(*count)
+            .and_then(|price| earned.checked_add(price))
+            .ok_or("too expensive")?;
+    }
+
+    let mut sold = inventory.clone();
+    let mut changes = sales
+        .iter()
+        .map(|(id, count)| sold.remove(*id, *count))
+        .collect::<Result<Vec<_>, _>>()?;
+    if earned > 0 {
+        changes.extend(
+            sold.store(KINAH, KINAH_NAME_ID, earned, new_id)
+                .ok_or("no id")?,
+        );
+    }
+    *inventory = sold;
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::data::item::EquipmentKind;
+    use super::*;
+
+    fn templates() -> Templates {
+        Templates::parse(
+            "<client_items>
+                <client_item>
+                    <id>7</id>
+                    <name>potion_hp_lesser</name>
+                    <name_id>70</name_id>
+                    <price>40</price>
+                </client_item>
+                <client_item>
+                    <id>8</id>
+                    <name>sword_n_c1_10a</name>
+                    <equipment_slots>main_or_sub</equipment_slots>
+                    <min_damage>10</min_damage>
+                    <max_damage>20</max_damage>
+                    <price>1000</price>
+                </client_item>
+                <client_item>
+                    <id>9</id>
+                    <name>quest_item</name>
+                </client_item>
+            </client_items>",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn buy_goods() {
+        let items = templates();
+        let mut inventory = Inventory::default();
+        inventory.insert(InventoryItem::kinah(1, 500));
+        let mut ids = 10..;
+
+        assert_eq!(
+            buy(&mut inventory, &[7, 8], &items, &[(7, 10)], || ids.next()),
+            Ok(vec![Change::Updated(1), Change::Added(10)])
+        );
+        assert_eq!(inventory.kinah(), 100);
+        assert_eq!(inventory.get(10).unwrap().name_id, 70);
+
+        let mut try_buy = |purchases: &[(u32, u32)]| {
+            buy(&mut inventory, &[7, 8], &items, purchases, || ids.next())
+        };
+        assert_eq!(try_buy(&[(7, 3)]), Err("not enough kinah"));
+        assert_eq!(try_buy(&[(9, 1)]), Err("not sold here"));
+        assert_eq!(try_buy(&[(8, 1)]), Err("not enough kinah"));
+        assert_eq!(try_buy(&[(7, u32::MAX)]), Err("too expensive"));
+        assert_eq!(inventory.kinah(), 100);
+    }
+
+    #[test]
+    fn buy_equipment() {
+        let items = templates();
+        let mut inventory = Inventory::default();
+        inventory.insert(InventoryItem::kinah(1, 2500));
+        let mut ids = 10..;
+
+        assert_eq!(
+            buy(&mut inventory, &[8], &items, &[(8, 2)], || ids.next()),
+            Ok(vec![
+                Change::Updated(1),
+                Change::Added(10),
+                Change::Added(11)
+            ])
+        );
+        assert_eq!(inventory.kinah(), 500);
+        let sword = inventory.get(11).unwrap();
+        assert_eq!((sword.count, sword.slot, sword.enchant_level), (1, 1, 0));
+        let equipment = sword.equipment.as_ref().unwrap();
+        assert_eq!(equipment.kind, EquipmentKind::Weapon);
+        assert_eq!((equipment.slots, equipment.other_slots), (0x1, 0x2));
+    }
+
+    #[test]
+    fn sell_for_kinah() {
+        let items = templates();
+        let mut inventory = Inventory::default();
+        inventory.insert(InventoryItem::stack(1, 7, 70, 5, 0));
+        inventory.insert(InventoryItem::stack(2, 9, 90, 1, 1));
+        let mut ids = 10..;
+
+        assert_eq!(
+            sell(&mut inventory, &items, &[(1, 2)], || ids.next()),
+            Ok(vec![Change::Updated(1), Change::Added(10)])
+        );
+        assert_eq!(inventory.kinah(), 20);
+        assert_eq!(inventory.get(1).unwrap().count, 3);
+
+        let mut try_sell = |sales: &[(u32, u32)]| {
+            sell(&mut inventory, &items, sales, || ids.next())
+        };
+        assert_eq!(try_sell(&[(2, 1)]), Err("can't be sold"));
+        assert_eq!(try_sell(&[(1, 2), (1, 1)]), Err("sold twice"));
+        assert_eq!(try_sell(&[(1, 4)]), Err("bad count"));
+        assert_eq!(
+            try_sell(&[(1, 3)]),
+            Ok(vec![Change::Removed(1), Change::Updated(10)])
+        );
+        assert_eq!(inventory.kinah(), 50);
+    }
+}
@@ -0,0 +1,92 @@
+//! Skills partway through being cast and skills waiting to come off cooldown
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::super::data::skill::Template;
+
+/// A skill with a cast time, it goes off once the time is up unless the
+/// caster moves first
+#[derive(Debug)]
+pub struct Cast {
+    pub template: Arc<Template>,
+    pub level: u16,
+    pub target_id: u32,
+    finish_at: Instant,
+}
+
+impl Cast {
+    pub fn new(template: Arc<Template>, level: u16, target_id: u32) -> Self {
+        Self {
+            finish_at: Instant::now() + template.cast_time,
+            template,
+            level,
+            target_id,
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.finish_at <= Instant::now()
+    }
+}
+
+/// When each skill a character has used can be used again
+#[derive(Debug, Default)]
+pub struct Cooldowns {
+    ready_at: HashMap<u16, Instant>,
+}
+
+impl Cooldowns {
+    pub fn start(&mut self, skill_id: u16, cooldown: Duration) {
+        if !cooldown.is_zero() {
+            self.ready_at.insert(skill_id, Instant::now() + cooldown);
+        }
+    }
+
+    /// None once the skill is ready
+    pub fn remaining(&self, skill_id: u16) -> Option<Duration> {
+        let ready_at = self.ready_at.get(&skill_id)?;
+        let remaining = ready_at.saturating_duration_since(Instant::now());
+        (!remaining.is_zero()).then_some(remaining)
+    }
+
+    /// Every skill still cooling down, ones that have finished are dropped
+    pub fn active(&mut self) -> Vec<(u16, Duration)> {
+        let now = Instant::now();
+        self.ready_at.retain(|_, ready_at| *ready_at > now);
+        self.ready_at
+            .iter()
+            .map(|(skill_id, ready_at)| (*skill_id, *ready_at - now))
+            .collect()
+    }
+
+    /// Make every skill ready again, returns the skills that were cooling down
+    #[allow(dead_code)]
+    pub fn reset(&mut self) -> Vec<u16> {
+        self.active();
+        self.ready_at
+            .drain()
+            .map(|(skill_id, _)| skill_id)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cooldowns_expire() {
+        let mut cooldowns = Cooldowns::default();
+        cooldowns.start(1, Duration::from_secs(60));
+        cooldowns.start(2, Duration::ZERO);
+
+        assert!(cooldowns.remaining(1).is_some());
+        assert!(cooldowns.remaining(2).is_none());
+        assert_eq!(cooldowns.active().len(), 1);
+
+        assert_eq!(cooldowns.reset(), vec![1]);
+        assert!(cooldowns.remaining(1).is_none());
+    }
+}
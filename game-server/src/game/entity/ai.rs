@@ -0,0 +1,385 @@
+//! What an NPC does each tick. Anything that hits or, for aggressive NPCs,
+//! wanders into range goes on the hate list and the NPC chases whoever is at
+//! the top of it. Pulled too far from home it gives up, runs back and heals
+
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+use std::ops::Range;
+
+use rand::Rng;
+
+use super::super::{
+    character::Character,
+    data::{npc, ActionType},
+    engine::{Coord, Direction, MoveType},
+    message::server as s,
+    Messages, ServerUpdate, TICK_RATE,
+};
+use super::Entity;
+
+/// How close an NPC has to be to hit its target
+pub const ATTACK_RANGE: f32 = 5.;
+/// How far from home an NPC will chase before giving up
+const LEASH_RANGE: f32 = 40.;
+/// Metres per second
+const RUN_SPEED: f32 = 6.;
+const WALK_SPEED: f32 = 1.5;
+/// Seconds an NPC stands around between patrols
+const IDLE_TIME: Range<f32> = 5. ..15.;
+/// How far a target can move before the NPC tells observers where it is
+/// heading now
+const REPATH_DISTANCE: f32 = 1.;
+
+/// Characters an NPC wants to fight and how much, the one with the most hate
+/// is the target
+#[derive(Debug, Default)]
+pub struct HateList {
+    hate: HashMap<u32, i32>,
+}
+
+impl HateList {
+    pub fn add(&mut self, character_id: u32, hate: i32) {
+        *self.hate.entry(character_id).or_default() += hate;
+    }
+
+    /// Start fighting a character without adding any hate
+    pub fn notice(&mut self, character_id: u32) {
+        self.hate.entry(character_id).or_default();
+    }
+
+    pub fn remove(&mut self, character_id: u32) {
+        self.hate.remove(&character_id);
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(u32) -> bool) {
+        self.hate.retain(|character_id, _| keep(*character_id));
+    }
+
+    pub fn clear(&mut self) {
+        self.hate.clear();
+    }
+
+    /// Ties go to whoever has the lowest id so the target doesn't flip
+    /// between characters every tick
+    pub fn top(&self) -> Option<u32> {
+        self.hate
+            .iter()
+            .max_by(|(a_id, a), (b_id, b)| a.cmp(b).then(b_id.cmp(a_id)))
+            .map(|(character_id, _)| *character_id)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Behaviour {
+    /// Ticks left before going for a walk
+    Idle(u32),
+    Patrol(Coord),
+    /// Picked a target, weapon is drawn next tick it gives chase
+    Aggro,
+    Chase,
+    Attack,
+    /// Running home after losing every target
+    Return,
+    Dead,
+}
+
+impl Entity {
+    /// Run a single tick of AI. `nearby` is every character that can see this
+    /// NPC, returns true if it moved
+    pub(in crate::game) fn think(
+        &mut self,
+        characters: &mut HashMap<u32, Character>,
+        nearby: &[u32],
+        messages: &mut Messages,
+    ) -> bool {
+        if self.is_dead() {
+            if self.behaviour != Behaviour::Dead {
+                self.behaviour = Behaviour::Dead;
+                self.hate.clear();
+                self.effects.clear();
+                self.target = None;
+            }
+            return false;
+        }
+
+        // Targets that left the world, died or are protected are forgotten
+        self.hate.retain(|id| {
+            characters
+                .get(&id)
+                .is_some_and(|character| character.is_attackable())
+        });
+
+        if self.template.ty == npc::Type::Aggressive
+            && self.behaviour != Behaviour::Return
+        {
+            for id in nearby {
+                let in_range = characters.get(id).is_some_and(|character| {
+                    character.is_attackable()
+                        && self.location.distance(character.location())
+                            <= self.template.aggro_range
+                });
+                if in_range {
+                    self.hate.notice(*id);
+                }
+            }
+        }
+
+        let top = self.hate.top();
+        if top != self.target {
+            self.target = top;
+            if let Some(target_id) = top {
+                self.behaviour = Behaviour::Aggro;
+                self.sent_destination = None;
+                messages.observers.entry(self.id).or_default().extend(
+                    [
+                        s::Message::NpcChangedTarget(s::NpcChangedTarget::new(
+                            target_id, self.id,
+                        )),
+                        s::Message::Action(s::Action::new(
+                            self.id,
+                            ActionType::EntityDrawWeapon,
+                            RUN_SPEED,
+                            target_id,
+                        )),
+                        s::Message::Action(s::Action::new(
+                            self.id,
+                            ActionType::Attack,
+                            RUN_SPEED,
+                            target_id,
+                        )),
+                    ]
+                    .map(ServerUpdate::new),
+                );
+                return false;
+            }
+        }
+
+        if self.effects.stunned() {
+            return false;
+        }
+
+        let Some(target) = self
+            .target
+            .and_then(|target_id| characters.get_mut(&target_id))
+        else {
+            return match self.behaviour {
+                Behaviour::Aggro | Behaviour::Chase | Behaviour::Attack => {
+                    self.go_home(messages);
+                    false
+                }
+                Behaviour::Return => self.walk_home(messages),
+                Behaviour::Idle(ticks) => {
+                    self.idle(ticks, messages);
+                    false
+                }
+                Behaviour::Patrol(destination) => {
+                    if self.step(destination, WALK_SPEED) {
+                        self.behaviour = Behaviour::Idle(idle_ticks());
+                    }
+                    true
+                }
+                Behaviour::Dead => false,
+            };
+        };
+
+        if self.location.distance(&self.home) > LEASH_RANGE {
+            self.hate.clear();
+            self.target = None;
+            self.go_home(messages);
+            return false;
+        }
+
+        let in_range =
+            self.location.distance(target.location()) <= ATTACK_RANGE;
+        match (self.behaviour, in_range) {
+            (Behaviour::Attack, true) => {
+                self.swing(target, messages);
+                false
+            }
+            (_, true) => {
+                self.behaviour = Behaviour::Attack;
+                self.sent_destination = None;
+                self.send_move(
+                    *target.location(),
+                    MoveType::NPC_RUN_FAST,
+                    s::MoveData::Stop,
+                    messages,
+                );
+                false
+            }
+            (_, false) => {
+                self.behaviour = Behaviour::Chase;
+                let destination = *target.location();
+                let repath = self.sent_destination.is_none_or(|sent| {
+                    sent.distance(&destination) > REPATH_DISTANCE
+                });
+                if repath {
+                    self.sent_destination = Some(destination);
+                    self.send_move(
+                        destination,
+                        MoveType::NPC_RUN_FAST,
+                        s::MoveData::Direct { dest: destination },
+                        messages,
+                    );
+                }
+                self.step(destination, RUN_SPEED);
+                true
+            }
+        }
+    }
+
+    /// Add hate from a character, the NPC turns on them if they now top the
+    /// list
+    pub fn add_hate(&mut self, character_id: u32, hate: i32) {
+        if !self.is_dead() {
+            self.hate.add(character_id, hate);
+        }
+    }
+
+    /// Stop fighting a character, used when they leave the world
+    pub fn forget(&mut self, character_id: u32) {
+        self.hate.remove(character_id);
+    }
+
+    fn swing(&mut self, target: &mut Character, messages: &mut Messages) {
+        let Some(hits) = self.attack(target) else {
+            return;
+        };
+        messages.observers.entry(self.id).or_default().extend(
+            [
+                s::Message::Attack(s::Attack::new(
+                    target.id(),
+                    self.id,
+                    0,
+                    hits,
+                )),
+                s::Message::HitPointOther(s::HitPointOther::new(
+                    target.id(),
+                    target.stats.hp.percent(),
+                )),
+            ]
+            .map(ServerUpdate::new),
+        );
+        messages.direct.entry(target.client_id()).or_default().push(
+            ServerUpdate::new(s::Message::HitPoint(s::HitPoint::new(
+                target.stats.hp.current(),
+                target.stats.hp.max(),
+            ))),
+        );
+    }
+
+    fn go_home(&mut self, messages: &mut Messages) {
+        self.behaviour = Behaviour::Return;
+        self.sent_destination = None;
+        self.send_move(
+            self.home,
+            MoveType::NPC_RUN_FAST,
+            s::MoveData::Direct { dest: self.home },
+            messages,
+        );
+    }
+
+    /// Once home the NPC is back to full health
+    fn walk_home(&mut self, messages: &mut Messages) -> bool {
+        if !self.step(self.home, RUN_SPEED) {
+            return true;
+        }
+        self.hp.set_current(self.hp.max());
+        self.attack_cooldown = self.attack_speed;
+        self.behaviour = Behaviour::Idle(idle_ticks());
+        messages
+            .observers
+            .entry(self.id)
+            .or_default()
+            .push(ServerUpdate::new(s::Message::HitPointOther(
+                s::HitPointOther::new(self.id, self.hp.percent()),
+            )));
+        true
+    }
+
+    fn idle(&mut self, ticks: u32, messages: &mut Messages) {
+        if ticks > 0 {
+            self.behaviour = Behaviour::Idle(ticks - 1);
+            return;
+        }
+        if self.walk_radius <= 0. {
+            self.behaviour = Behaviour::Idle(idle_ticks());
+            return;
+        }
+
+        let mut rng = rand::thread_rng();
+        let angle = rng.gen_range(0. ..TAU);
+        let distance = rng.gen_range(0. ..self.walk_radius);
+        let destination = Coord::new(
+            self.home.x() + angle.cos() * distance,
+            self.home.y() + angle.sin() * distance,
+            self.home.z(),
+        );
+        self.behaviour = Behaviour::Patrol(destination);
+        self.send_move(
+            destination,
+            MoveType::NPC_WALK_SLOW,
+            s::MoveData::Direct { dest: destination },
+            messages,
+        );
+    }
+
+    /// Move one tick towards a location, returns true once it is reached
+    fn step(&mut self, destination: Coord, speed: f32) -> bool {
+        if self.effects.rooted() {
+            return false;
+        }
+        self.location =
+            self.location.step_towards(&destination, speed / TICK_RATE);
+        self.location.distance(&destination) < f32::EPSILON
+    }
+
+    fn send_move(
+        &mut self,
+        towards: Coord,
+        ty: MoveType,
+        data: s::MoveData,
+        messages: &mut Messages,
+    ) {
+        let direction = Direction::towards(&self.location, &towards);
+        self.heading = direction.bits();
+        messages
+            .observers
+            .entry(self.id)
+            .or_default()
+            .push(ServerUpdate::new(s::Message::MoveNew(s::MoveNew::new(
+                self.id,
+                self.location,
+                direction,
+                ty,
+                data,
+            ))));
+    }
+}
+
+fn idle_ticks() -> u32 {
+    (rand::thread_rng().gen_range(IDLE_TIME) * TICK_RATE) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn most_hated_is_targeted() {
+        let mut hate = HateList::default();
+        assert_eq!(hate.top(), None);
+
+        hate.notice(7);
+        hate.notice(3);
+        assert_eq!(hate.top(), Some(3));
+
+        hate.add(7, 100);
+        assert_eq!(hate.top(), Some(7));
+
+        hate.remove(7);
+        assert_eq!(hate.top(), Some(3));
+        hate.retain(|id| id != 3);
+        assert_eq!(hate.top(), None);
+    }
+}
@@ -0,0 +1,147 @@
+mod ai;
+
+use std::sync::Arc;
+
+use ai::{Behaviour, HateList, ATTACK_RANGE};
+
+use super::{
+    character::{Character, Hp},
+    data::{gear::Weapon, npc},
+    engine::{
+        combat::{self, Fighter, Hand},
+        damage::Hit,
+        effect::Effects,
+        loot::Loot,
+        Coord,
+    },
+    TICK_RATE,
+};
+
+/// Accuracy and evasion NPCs gain per level
+const RATING_PER_LEVEL: i32 = 30;
+/// Tenths of a percent
+const NPC_CRIT: i32 = 50;
+
+#[derive(Debug)]
+pub struct Entity {
+    id: u32,
+    hate: HateList,
+    /// Whoever was top of the hate list last tick
+    target: Option<u32>,
+    behaviour: Behaviour,
+    location: Coord,
+    /// Where it spawned, patrols stay within `walk_radius` of here
+    home: Coord,
+    walk_radius: f32,
+    /// Last destination observers were told about while chasing
+    sent_destination: Option<Coord>,
+    heading: u8,
+    template: Arc<npc::Template>,
+    pub hp: Hp,
+    pub effects: Effects,
+    /// Rolled when it dies, None once there is nothing left to take
+    pub loot: Option<Loot>,
+    attack_sequence: u8,
+    // Ticks
+    attack_speed: f32,
+    attack_cooldown: f32,
+}
+impl Entity {
+    pub fn new(
+        id: u32,
+        location: Coord,
+        heading: u8,
+        walk_radius: f32,
+        template: Arc<npc::Template>,
+    ) -> Self {
+        let attack_speed = template.attack_speed.as_secs_f32() * TICK_RATE;
+        Self {
+            id,
+            hate: HateList::default(),
+            target: None,
+            behaviour: Behaviour::Idle(0),
+            location,
+            home: location,
+            walk_radius,
+            sent_destination: None,
+            heading,
+            attack_speed,
+            attack_cooldown: attack_speed,
+            attack_sequence: 0,
+            hp: Hp::new(template.max_hp),
+            effects: Effects::default(),
+            loot: None,
+            template,
+        }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.hp.current() <= 0
+    }
+    pub fn is_alive(&self) -> bool {
+        !self.is_dead()
+    }
+
+    pub fn attack(&mut self, character: &mut Character) -> Option<Vec<Hit>> {
+        // If attack not on cooldown
+        if self.attack_cooldown >= 0. {
+            self.attack_cooldown -= 1.;
+            return None;
+        }
+
+        // If in range
+        if self.location.distance(character.location()) > ATTACK_RANGE {
+            return None;
+        }
+
+        self.attack_cooldown = self.attack_speed;
+        Some(self.calculate_damage(character))
+    }
+
+    fn calculate_damage(&mut self, character: &mut Character) -> Vec<Hit> {
+        let hit = combat::swing(
+            &self.fighter(),
+            &character.fighter(Hand::Main),
+            Hand::Main,
+            &mut rand::thread_rng(),
+        );
+        character.hurt(hit.damage, self.id);
+        self.attack_sequence = self.attack_sequence.wrapping_add(1);
+
+        vec![hit]
+    }
+
+    /// NPC templates only carry attack and defence, everything else scales
+    /// with level
+    pub fn fighter(&self) -> Fighter {
+        let attack = self.template.attack.max(0);
+        let rating = self.template.level as i32 * RATING_PER_LEVEL;
+        Fighter {
+            level: self.template.level,
+            weapon: Weapon::new(attack * 9 / 10, attack * 11 / 10),
+            attack: self.effects.attack(),
+            accuracy: rating,
+            crit: NPC_CRIT,
+            defence: self.template.defence + self.effects.defence(),
+            evasion: rating,
+            parry: 0,
+            block: 0,
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+    pub fn location(&self) -> &Coord {
+        &self.location
+    }
+    pub fn heading(&self) -> u8 {
+        self.heading
+    }
+    pub fn template(&self) -> &npc::Template {
+        &self.template
+    }
+    pub fn level(&self) -> u16 {
+        self.template.level
+    }
+}
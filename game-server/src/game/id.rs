@@ -0,0 +1,193 @@
+//! Every object the client can see shares one id space, each kind of object
+//! is handed ids from its own range so they can never collide
+
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use super::repository::CharacterRepository;
+use crate::error::{Error, Result};
+
+/// Ids are reserved in the repository a block at a time so we only write
+/// once per block instead of once per id
+const BLOCK_SIZE: u32 = 1000;
+
+/// How long a released id sits unused before it is handed out again, gives
+/// clients time to forget about the old object
+const RECYCLE_DELAY: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ObjectKind {
+    Character = 0,
+    Npc = 1,
+    DroppedItem = 2,
+    InventoryItem = 3,
+    Mail = 4,
+}
+
+impl ObjectKind {
+    const ALL: [Self; 5] = [
+        Self::Character,
+        Self::Npc,
+        Self::DroppedItem,
+        Self::InventoryItem,
+        Self::Mail,
+    ];
+
+    /// NPC and inventory item ranges line up with the ids seen in captures
+    fn range(self) -> Range<u32> {
+        match self {
+            Self::Character => 0x0000_0001..0x8000_0000,
+            Self::Npc => 0x8000_0000..0xC000_0000,
+            Self::InventoryItem => 0xC000_0000..0xF000_0000,
+            Self::Mail => 0xF000_0000..0xF800_0000,
+            Self::DroppedItem => 0xF800_0000..0xFFFF_FFFF,
+        }
+    }
+
+    /// Only objects that never reach the repository can have their ids
+    /// reused, anything persisted may still be referenced
+    fn recyclable(self) -> bool {
+        matches!(self, Self::Npc | Self::DroppedItem)
+    }
+}
+
+struct Pool {
+    next: u32,
+    /// Everything below this has been reserved in the repository
+    reserved: u32,
+    released: VecDeque<(u32, Instant)>,
+}
+
+pub struct IdAllocator {
+    pools: [Pool; 5],
+    recycle_delay: Duration,
+}
+
+impl IdAllocator {
+    /// Carry on from the high-water marks saved by a previous run, ids
+    /// reserved but never handed out are skipped
+    pub fn load(repository: &dyn CharacterRepository) -> Result<Self> {
+        let mut pools = ObjectKind::ALL.map(|kind| Pool {
+            next: kind.range().start,
+            reserved: kind.range().start,
+            released: VecDeque::new(),
+        });
+        for kind in ObjectKind::ALL {
+            if let Some(mark) = repository.high_water_mark(kind)? {
+                let range = kind.range();
+                let mark = mark.clamp(range.start, range.end);
+                let pool = &mut pools[kind as usize];
+                pool.next = mark;
+                pool.reserved = mark;
+            }
+        }
+
+        Ok(Self {
+            pools,
+            recycle_delay: RECYCLE_DELAY,
+        })
+    }
+
+    pub fn allocate(
+        &mut self,
+        kind: ObjectKind,
+        repository: &mut dyn CharacterRepository,
+    ) -> Result<u32> {
+        let pool = &mut self.pools[kind as usize];
+
+        if let Some((id, released_at)) = pool.released.front() {
+            if released_at.elapsed() >= self.recycle_delay {
+                let id = *id;
+                pool.released.pop_front();
+                return Ok(id);
+            }
+        }
+
+        if pool.next == pool.reserved {
+            let reserved = pool.reserved.saturating_add(BLOCK_SIZE);
+            let reserved = reserved.min(kind.range().end);
+            if reserved == pool.next {
+                return Err(Error::IdsExhausted(kind));
+            }
+            repository.set_high_water_mark(kind, reserved)?;
+            pool.reserved = reserved;
+        }
+
+        let id = pool.next;
+        pool.next += 1;
+        Ok(id)
+    }
+
+    /// Return the id of a despawned object, persisted kinds are never reused
+    pub fn release(&mut self, kind: ObjectKind, id: u32) {
+        if !kind.recyclable() || !kind.range().contains(&id) {
+            return;
+        }
+        self.pools[kind as usize]
+            .released
+            .push_back((id, Instant::now()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::repository::MemoryRepository;
+
+    #[test]
+    fn ranges_do_not_overlap() {
+        for a in ObjectKind::ALL {
+            for b in ObjectKind::ALL {
+                if a == b {
+                    continue;
+                }
+                let (a, b) = (a.range(), b.range());
+                assert!(a.end <= b.start || b.end <= a.start);
+            }
+        }
+    }
+
+    #[test]
+    fn continues_after_restart() {
+        let mut repository = MemoryRepository::new();
+
+        let mut ids = IdAllocator::load(&repository).unwrap();
+        let first = ids.allocate(ObjectKind::Npc, &mut repository).unwrap();
+        assert_eq!(first, ObjectKind::Npc.range().start);
+
+        let mut ids = IdAllocator::load(&repository).unwrap();
+        let second = ids.allocate(ObjectKind::Npc, &mut repository).unwrap();
+        assert!(second > first);
+    }
+
+    #[test]
+    fn recycles_after_delay() {
+        let mut repository = MemoryRepository::new();
+        let mut ids = IdAllocator::load(&repository).unwrap();
+
+        let npc = ids.allocate(ObjectKind::Npc, &mut repository).unwrap();
+        ids.release(ObjectKind::Npc, npc);
+        assert_ne!(
+            ids.allocate(ObjectKind::Npc, &mut repository).unwrap(),
+            npc
+        );
+
+        ids.recycle_delay = Duration::ZERO;
+        assert_eq!(
+            ids.allocate(ObjectKind::Npc, &mut repository).unwrap(),
+            npc
+        );
+
+        let character = ids
+            .allocate(ObjectKind::Character, &mut repository)
+            .unwrap();
+        ids.release(ObjectKind::Character, character);
+        assert_ne!(
+            ids.allocate(ObjectKind::Character, &mut repository)
+                .unwrap(),
+            character
+        );
+    }
+}
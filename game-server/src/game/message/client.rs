@@ -7,11 +7,14 @@ use krypt::game::{decrypt, decrypt_client_opcode};
 
 use super::server as s;
 use crate::consume_le_bytes;
+use crate::error::{Error, Result};
 use crate::game::data::ActionType;
 use crate::game::engine::{Coord, Direction, MoveType};
 use crate::game::session::Account;
 use crate::game::{ClientUpdate, Deserialise};
-use crossbeam_channel::Sender;
+
+/// Opcode, client byte and checksum that start every message
+pub const HEADER_LEN: usize = 5;
 
 macro_rules! define_messages {
     ($(($message_const_name:ident, $message:ident, $opcode:expr)),* $(,)?) => {
@@ -28,10 +31,11 @@ macro_rules! define_messages {
         }
 
         impl Message {
+            /// The buffer must hold at least [HEADER_LEN] bytes
             pub fn deserialise(
                 mut buf: &mut [u8],
                 mut key: &mut [u8; 8],
-            ) -> Option<Self> {
+            ) -> Result<Self> {
                 decrypt(&mut key, buf);
 
                 let mut len = 0;
@@ -43,27 +47,23 @@ macro_rules! define_messages {
                 let checksum = consume_le_bytes!(len, buf, u16);
 
                 if checksum != !opcode {
-                    panic!("Packet Checksum Failed, {opcode:02X}-{checksum:02X}");
+                    return Err(Error::PacketChecksum { opcode, checksum });
                 }
                 let opcode = decrypt_client_opcode(opcode);
 
                 buf = &mut buf[len..];
                 match opcode {
                     $(
-                        $message_const_name => Some(Message::$message(<$message>::deserialise(buf))),
+                        $message_const_name => <$message>::deserialise(buf).map(Message::$message),
                     )*
-                    _ => {
-                        println!("WARNING: Client Opcode {opcode:02X} not implemented");
-                        println!("C RAW: {:02X?}", buf);
-                        None
-                    }
+                    _ => Err(Error::UnknownOpcode(opcode)),
                 }
             }
 
-            pub fn handle(self, tx: &Sender<ClientUpdate>, session: &mut Account) -> Vec<s::Message> {
+            pub fn handle(self, updates: &mut Vec<ClientUpdate>, session: &mut Account) -> Vec<s::Message> {
                 match self {
                     $(
-                        Self::$message(message) => message.handle(&tx, session),
+                        Self::$message(message) => message.handle(updates, session),
                     )*
                 }
             }
@@ -74,23 +74,50 @@ macro_rules! define_messages {
 define_messages!(
     (VERSION, Version, 0x00),
     (ASK_QUIT, AskQuit, 0x03),
+    (DEAD_RESTART, DeadRestart, 0x05),
     (ENTER_WORLD, EnterWorld, 0x08),
     (LEVEL_READY, LevelReady, 0x09),
     (READY_TO_QUIT, ReadyToQuit, 0x04),
     (SAVE_CLIENT_SETTINGS, SaveClientSettings, 0x0A),
     (SYNC_TIME, SyncTime, 0x12),
+    (SAY, Say, 0x1B),
+    (WHISPER, Whisper, 0x1C),
     (CHANGE_TARGET, ChangeTarget, 0x1F),
     (ATTACK, Attack, 0x20),
     (USE_SKILL, UseSkill, 0x21),
+    (TURN_OFF_ABNORMAL_STATUS, TurnOffAbnormalStatus, 0x23),
     (USE_EQUIPMENT_ITEM, UseEquipmentItem, 0x26),
+    (ANSWER, Answer, 0x32),
+    (BUY_SELL, BuySell, 0x33),
+    (START_DIALOG, StartDialog, 0x34),
+    (END_DIALOG, EndDialog, 0x35),
     (ACTION, Action, 0x2B),
     (ALIVE, Alive, 0x2C),
     (MOVE_NEW, MoveNew, 0x30),
     (ASK_LOG, AskLog, 0x3E),
+    (ASK_XCHG, AskXchg, 0x3F),
+    (ADD_XCHG, AddXchg, 0x40),
+    (REMOVE_XCHG, RemoveXchg, 0x41),
+    (XCHG_GOLD, XchgGold, 0x42),
+    (CHECK_XCHG, CheckXchg, 0x43),
+    (ACCEPT_XCHG, AcceptXchg, 0x44),
+    (CANCEL_XCHG, CancelXchg, 0x45),
+    (PARTY, Party, 0x60),
+    (PARTY_BY_NAME, PartyByName, 0x61),
     (QUERY_BUDDY, QueryBuddy, 0x6E),
     (CHARACTER_LIST, CharacterList, 0x96),
+    (CREATE_CHARACTER, CreateCharacter, 0x97),
+    (DELETE_CHARACTER, DeleteCharacter, 0x98),
+    (RESTORE_CHARACTER, RestoreCharacter, 0x99),
+    (MAIL_WRITE, MailWrite, 0x84),
+    (MAIL_LIST, MailList, 0x85),
+    (MAIL_READ, MailRead, 0x86),
+    (MAIL_GETITEM, MailGetItem, 0x88),
+    (MAIL_DELETE, MailDelete, 0x89),
     (LOOT, Loot, 0x9A),
     (LOOT_ITEM, LootItem, 0x9B),
+    (MOVE_ITEM_TO_ANOTHER_SLOT, MoveItemToAnotherSlot, 0x9C),
+    (MOVE_STACKABLE_ITEM, MoveStackableItem, 0x9D),
     (RECIPE_LIST, RecipeList, 0x9E),
     (RECONNECT_AUTH, ReconnectAuth, 0xB8),
     (INSTANCE_DUNGEON_COOLTIMES, InstanceDungeonCooltimes, 0xC1),
@@ -100,6 +127,7 @@ define_messages!(
     (QUERY_BLOCK, QueryBlock, 0xA8),
     (SIGN_CLIENT, SignClient, 0xAE),
     (SA_ACCOUNT_ITEM_QUERY, SaAccountItemQuery, 0xBB),
+    (GROUP_CHANGE_LOOTDIST, GroupChangeLootdist, 0xBA),
     (ROUTE_INFO, RouteInfo, 0xBE),
     (SECOND_PASSWORD, SecondPassword, 0xD2),
     (READY_ENTER_WORLD_ACK, ReadyEnterWorldAck, 0xE2),
@@ -121,7 +149,7 @@ pub struct SyncTime {
 impl SyncTime {
     pub fn handle(
         &self,
-        _: &Sender<ClientUpdate>,
+        _: &mut Vec<ClientUpdate>,
         _: &mut Account,
     ) -> Vec<s::Message> {
         // ty is set to 1 when its an ack
@@ -133,71 +161,183 @@ impl SyncTime {
     }
 }
 impl Deserialise for SyncTime {
-    fn deserialise(buf: &[u8]) -> Self
+    fn deserialise(buf: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
-        Self {
-            ticks: u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]),
-            ty: buf[4],
-            unknown: [buf[5], buf[6], buf[7], buf[8]],
-            sequence: u32::from_le_bytes([buf[9], buf[10], buf[11], buf[12]]),
-        }
+        let mut _len = 0;
+        Ok(Self {
+            ticks: consume_le_bytes!(_len, buf, u32),
+            ty: consume_le_bytes!(_len, buf, u8),
+            unknown: consume_le_bytes!(_len, buf, u32).to_le_bytes(),
+            sequence: consume_le_bytes!(_len, buf, u32),
+        })
     }
 }
 
-// TODO: Get this from external state
 #[derive(Debug, Clone)]
 pub struct CharacterList;
 impl CharacterList {
     pub fn handle(
         self,
-        tx: &Sender<ClientUpdate>,
+        updates: &mut Vec<ClientUpdate>,
         session: &mut Account,
     ) -> Vec<s::Message> {
-        tx.send(session.send(Message::CharacterList(self))).unwrap();
+        updates.push(session.send(Message::CharacterList(self)));
         vec![s::Message::BuilderLevel(s::BuilderLevel::new())]
     }
 }
 impl Deserialise for CharacterList {
-    fn deserialise(_: &[u8]) -> Self
+    fn deserialise(_: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self)
+    }
+}
+
+/// Sent from the character creation screen
+#[derive(Debug, Clone)]
+pub struct CreateCharacter {
+    pub name: String,
+    pub gender: u32,
+    pub race: u32,
+    pub class: u32,
+    pub voice: u32,
+    /// Colours, face and body sliders, see [crate::game::character::Appearance]
+    pub appearance: Vec<u8>,
+}
+impl CreateCharacter {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::CreateCharacter(self)));
+        vec![]
+    }
+}
+impl Deserialise for CreateCharacter {
+    fn deserialise(buf: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut len = 0;
+
+        // unknown
+        len += 4;
+
+        // Null terminated UTF-16 in a fixed size field
+        let name: Vec<u16> = buf
+            .get(len..len + 52)
+            .ok_or(Error::PacketLength(buf.len()))?
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .take_while(|c| *c != 0)
+            .collect();
+        len += 52;
+
+        Ok(Self {
+            name: String::from_utf16_lossy(&name),
+            gender: consume_le_bytes!(len, buf, u32),
+            race: consume_le_bytes!(len, buf, u32),
+            class: consume_le_bytes!(len, buf, u32),
+            voice: consume_le_bytes!(len, buf, u32),
+            appearance: buf[len..].to_vec(),
+        })
+    }
+}
+
+/// Starts the deletion countdown for a character
+#[derive(Debug, Clone)]
+pub struct DeleteCharacter {
+    pub character_id: u32,
+}
+impl DeleteCharacter {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::DeleteCharacter(self)));
+        vec![]
+    }
+}
+impl Deserialise for DeleteCharacter {
+    fn deserialise(buf: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
-        Self
+        let mut _ptr = 0;
+        // unknown
+        _ptr += 4;
+        Ok(Self {
+            character_id: consume_le_bytes!(_ptr, buf, u32),
+        })
+    }
+}
+
+/// Cancels the deletion countdown for a character
+#[derive(Debug, Clone)]
+pub struct RestoreCharacter {
+    pub character_id: u32,
+}
+impl RestoreCharacter {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::RestoreCharacter(self)));
+        vec![]
+    }
+}
+impl Deserialise for RestoreCharacter {
+    fn deserialise(buf: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut _ptr = 0;
+        // unknown
+        _ptr += 4;
+        Ok(Self {
+            character_id: consume_le_bytes!(_ptr, buf, u32),
+        })
     }
 }
 
 /// Client sends a packet chosing which character they wish to log in as
 #[derive(Debug, Clone)]
 pub struct EnterWorld {
-    character_id: u32,
+    pub character_id: u32,
 }
 impl Deserialise for EnterWorld {
-    fn deserialise(buf: &[u8]) -> Self
+    fn deserialise(buf: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
         let mut _ptr = 0;
-        Self {
+        Ok(Self {
             character_id: consume_le_bytes!(_ptr, buf, u32),
-        }
+        })
     }
 }
 
 impl EnterWorld {
-    /// We prompt for a PIN if [Account] pin_enabled is true
+    /// We prompt for a PIN if [Account] pin_enabled is true. The session
+    /// isn't tied to the character until the game loop has checked the
+    /// account owns it, the EnterWorldCheck comes back from there
     pub fn handle(
-        &self,
-        _: &Sender<ClientUpdate>,
+        self,
+        updates: &mut Vec<ClientUpdate>,
         session: &mut Account,
     ) -> Vec<s::Message> {
-        session.set_character_id(self.character_id);
+        updates.push(session.send(Message::EnterWorld(self)));
 
         if session.pin_enabled() {
             vec![s::Message::SecondPassword(s::SecondPassword::request())]
         } else {
-            vec![s::Message::EnterWorldCheck(s::EnterWorldCheck::new())]
+            vec![]
         }
     }
 }
@@ -208,21 +348,18 @@ pub struct SecondPassword;
 impl SecondPassword {
     pub fn handle(
         &self,
-        _: &Sender<ClientUpdate>,
+        _: &mut Vec<ClientUpdate>,
         _: &mut Account,
     ) -> Vec<s::Message> {
-        vec![
-            s::Message::SecondPassword(s::SecondPassword::response()),
-            s::Message::EnterWorldCheck(s::EnterWorldCheck::new()),
-        ]
+        vec![s::Message::SecondPassword(s::SecondPassword::response())]
     }
 }
 impl Deserialise for SecondPassword {
-    fn deserialise(_: &[u8]) -> Self
+    fn deserialise(_: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
-        Self
+        Ok(Self)
     }
 }
 
@@ -233,18 +370,18 @@ impl SaAccountItemQuery {
     /// The client will hang unless we respond to this query
     pub fn handle(
         &self,
-        _: &Sender<ClientUpdate>,
+        _: &mut Vec<ClientUpdate>,
         _: &mut Account,
     ) -> Vec<s::Message> {
         vec![s::Message::SaAccountItemNoti(s::SaAccountItemNoti::new())]
     }
 }
 impl Deserialise for SaAccountItemQuery {
-    fn deserialise(_: &[u8]) -> Self
+    fn deserialise(_: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
-        Self
+        Ok(Self)
     }
 }
 
@@ -258,7 +395,7 @@ pub struct HqLogin {
 impl HqLogin {
     pub fn handle(
         &self,
-        _: &Sender<ClientUpdate>,
+        _: &mut Vec<ClientUpdate>,
         session: &mut Account,
     ) -> Vec<s::Message> {
         session.auth_server.id = self.id;
@@ -274,7 +411,7 @@ impl HqLogin {
 }
 
 impl Deserialise for HqLogin {
-    fn deserialise(buf: &[u8]) -> Self
+    fn deserialise(buf: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
@@ -286,12 +423,14 @@ impl Deserialise for HqLogin {
         // unknown
         len += 8;
 
-        let authn_token = std::str::from_utf8(&buf[len..]).unwrap().into();
-        Self {
+        let authn_token =
+            buf.get(len..).ok_or(Error::PacketLength(buf.len()))?;
+        let authn_token = String::from_utf8_lossy(authn_token).into();
+        Ok(Self {
             id,
             key,
             authn_token,
-        }
+        })
     }
 }
 
@@ -314,7 +453,7 @@ pub struct Version {
 impl Version {
     pub fn handle(
         self,
-        _: &Sender<ClientUpdate>,
+        _: &mut Vec<ClientUpdate>,
         _: &mut Account,
     ) -> Vec<s::Message> {
         vec![
@@ -325,31 +464,24 @@ impl Version {
 }
 
 impl Deserialise for Version {
-    fn deserialise(mut buf: &[u8]) -> Self
+    fn deserialise(buf: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
-        let aion_version = u16::from_le_bytes(buf[..2].try_into().unwrap());
-        buf = &buf[2..];
-
-        let unknown = buf[..2].try_into().unwrap();
-        buf = &buf[2..];
-
-        let ansi_encoding = u32::from_le_bytes(buf[..4].try_into().unwrap());
-        buf = &buf[4..];
-
-        let os_major_version = u32::from_le_bytes(buf[..4].try_into().unwrap());
-        buf = &buf[4..];
-
-        let os_minor_version = u32::from_le_bytes(buf[..4].try_into().unwrap());
+        let mut _len = 0;
+        let aion_version = consume_le_bytes!(_len, buf, u16);
+        let unknown = consume_le_bytes!(_len, buf, u16).to_le_bytes();
+        let ansi_encoding = consume_le_bytes!(_len, buf, u32);
+        let os_major_version = consume_le_bytes!(_len, buf, u32);
+        let os_minor_version = consume_le_bytes!(_len, buf, u32);
 
-        Self {
+        Ok(Self {
             aion_version,
             unknown,
             ansi_encoding,
             os_major_version,
             os_minor_version,
-        }
+        })
     }
 }
 
@@ -359,7 +491,7 @@ pub struct CurStatus;
 impl CurStatus {
     pub fn handle(
         self,
-        tx: &Sender<ClientUpdate>,
+        updates: &mut Vec<ClientUpdate>,
         session: &mut Account,
     ) -> Vec<s::Message> {
         let change_channel = [0x00, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x00];
@@ -826,7 +958,7 @@ impl CurStatus {
             0xBB, 0x81,
         ];
 
-        tx.send(session.send(Message::CurStatus(self))).unwrap();
+        updates.push(session.send(Message::CurStatus(self)));
 
         let game_time = 148641933;
         vec![
@@ -872,11 +1004,11 @@ impl CurStatus {
     }
 }
 impl Deserialise for CurStatus {
-    fn deserialise(_: &[u8]) -> Self
+    fn deserialise(_: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
-        Self
+        Ok(Self)
     }
 }
 
@@ -884,20 +1016,24 @@ impl Deserialise for CurStatus {
 pub struct ReadyToQuit;
 
 impl ReadyToQuit {
+    /// The game loop saves and removes the character, the client is then
+    /// back on the character select screen without one
     pub fn handle(
-        &self,
-        _: &Sender<ClientUpdate>,
-        _: &mut Account,
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
     ) -> Vec<s::Message> {
+        updates.push(session.send(Message::ReadyToQuit(self)));
+        session.set_character_id(0);
         vec![]
     }
 }
 impl Deserialise for ReadyToQuit {
-    fn deserialise(_: &[u8]) -> Self
+    fn deserialise(_: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
-        Self
+        Ok(Self)
     }
 }
 
@@ -907,18 +1043,18 @@ pub struct AskQuit;
 impl AskQuit {
     pub fn handle(
         &self,
-        _: &Sender<ClientUpdate>,
+        _: &mut Vec<ClientUpdate>,
         _: &mut Account,
     ) -> Vec<s::Message> {
         vec![s::Message::AskQuitResult(s::AskQuitResult::new())]
     }
 }
 impl Deserialise for AskQuit {
-    fn deserialise(_: &[u8]) -> Self
+    fn deserialise(_: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
-        Self
+        Ok(Self)
     }
 }
 
@@ -927,18 +1063,18 @@ pub struct _00D8;
 impl _00D8 {
     pub fn handle(
         &self,
-        _: &Sender<ClientUpdate>,
+        _: &mut Vec<ClientUpdate>,
         _: &mut Account,
     ) -> Vec<s::Message> {
         vec![s::Message::_0102(s::_0102::new())]
     }
 }
 impl Deserialise for _00D8 {
-    fn deserialise(_: &[u8]) -> Self
+    fn deserialise(_: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
-        Self
+        Ok(Self)
     }
 }
 
@@ -947,7 +1083,7 @@ pub struct RecipeList;
 impl RecipeList {
     pub fn handle(
         &self,
-        _: &Sender<ClientUpdate>,
+        _: &mut Vec<ClientUpdate>,
         _: &mut Account,
     ) -> Vec<s::Message> {
         vec![s::Message::RecipeList(s::RecipeList::new(
@@ -960,11 +1096,11 @@ impl RecipeList {
     }
 }
 impl Deserialise for RecipeList {
-    fn deserialise(_: &[u8]) -> Self
+    fn deserialise(_: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
-        Self
+        Ok(Self)
     }
 }
 
@@ -973,18 +1109,18 @@ pub struct QueryBuddy;
 impl QueryBuddy {
     pub fn handle(
         &self,
-        _: &Sender<ClientUpdate>,
+        _: &mut Vec<ClientUpdate>,
         _: &mut Account,
     ) -> Vec<s::Message> {
         vec![s::Message::BuddyList(s::BuddyList::new())]
     }
 }
 impl Deserialise for QueryBuddy {
-    fn deserialise(_: &[u8]) -> Self
+    fn deserialise(_: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
-        Self
+        Ok(Self)
     }
 }
 
@@ -993,18 +1129,18 @@ pub struct QueryBlock;
 impl QueryBlock {
     pub fn handle(
         &self,
-        _: &Sender<ClientUpdate>,
+        _: &mut Vec<ClientUpdate>,
         _: &mut Account,
     ) -> Vec<s::Message> {
         vec![]
     }
 }
 impl Deserialise for QueryBlock {
-    fn deserialise(_: &[u8]) -> Self
+    fn deserialise(_: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
-        Self
+        Ok(Self)
     }
 }
 
@@ -1043,10 +1179,10 @@ pub struct MoveNew {
 impl MoveNew {
     pub fn handle(
         self,
-        tx: &Sender<ClientUpdate>,
+        updates: &mut Vec<ClientUpdate>,
         session: &mut Account,
     ) -> Vec<s::Message> {
-        tx.send(session.send(Message::MoveNew(self))).unwrap();
+        updates.push(session.send(Message::MoveNew(self)));
         vec![]
     }
 
@@ -1106,12 +1242,12 @@ impl MoveNew {
     }
 }
 impl Deserialise for MoveNew {
-    fn deserialise(buf: &[u8]) -> Self
+    fn deserialise(buf: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
         let mut _len = 0;
-        let coord = Coord::deserialise(&buf[..12].try_into().unwrap());
+        let coord = Coord::deserialise(buf)?;
         _len += Coord::LEN;
 
         let direction = Direction::new(consume_le_bytes!(_len, buf, u8));
@@ -1155,12 +1291,12 @@ impl Deserialise for MoveNew {
             MoveData::Stop
         };
 
-        Self {
+        Ok(Self {
             coord,
             direction,
             ty,
             data,
-        }
+        })
     }
 }
 #[derive(Debug, Clone)]
@@ -1168,18 +1304,18 @@ pub struct RequestSerialKillerList;
 impl RequestSerialKillerList {
     pub fn handle(
         &self,
-        _: &Sender<ClientUpdate>,
+        _: &mut Vec<ClientUpdate>,
         _: &mut Account,
     ) -> Vec<s::Message> {
         vec![]
     }
 }
 impl Deserialise for RequestSerialKillerList {
-    fn deserialise(_: &[u8]) -> Self
+    fn deserialise(_: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
-        Self
+        Ok(Self)
     }
 }
 #[derive(Debug, Clone)]
@@ -1187,18 +1323,18 @@ pub struct InstanceDungeonCooltimes;
 impl InstanceDungeonCooltimes {
     pub fn handle(
         &self,
-        _: &Sender<ClientUpdate>,
+        _: &mut Vec<ClientUpdate>,
         _: &mut Account,
     ) -> Vec<s::Message> {
         vec![]
     }
 }
 impl Deserialise for InstanceDungeonCooltimes {
-    fn deserialise(_: &[u8]) -> Self
+    fn deserialise(_: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
-        Self
+        Ok(Self)
     }
 }
 #[derive(Debug, Clone)]
@@ -1208,22 +1344,22 @@ pub struct SignClient {
 impl SignClient {
     pub fn handle(
         &self,
-        _: &Sender<ClientUpdate>,
+        _: &mut Vec<ClientUpdate>,
         _: &mut Account,
     ) -> Vec<s::Message> {
-        vec![
-        //s::Message::SignClient(s::SignClient::new())
-        ]
+        vec![]
     }
 }
 impl Deserialise for SignClient {
-    fn deserialise(buf: &[u8]) -> Self
+    fn deserialise(buf: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
-        Self {
-            unknown: buf.try_into().unwrap(),
-        }
+        Ok(Self {
+            unknown: buf
+                .try_into()
+                .map_err(|_| Error::PacketLength(buf.len()))?,
+        })
     }
 }
 
@@ -1236,14 +1372,14 @@ pub struct SaveClientSettings {
 impl SaveClientSettings {
     pub fn handle(
         &self,
-        _: &Sender<ClientUpdate>,
+        _: &mut Vec<ClientUpdate>,
         _: &mut Account,
     ) -> Vec<s::Message> {
         vec![]
     }
 }
 impl Deserialise for SaveClientSettings {
-    fn deserialise(buf: &[u8]) -> Self
+    fn deserialise(buf: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
@@ -1251,10 +1387,13 @@ impl Deserialise for SaveClientSettings {
         let ty = consume_le_bytes!(_len, buf, u8);
         _ = consume_le_bytes!(_len, buf, u16);
         let body_len = consume_le_bytes!(_len, buf, u16) as usize;
-        Self {
+        Ok(Self {
             ty,
-            raw: buf[_len..body_len].to_vec(),
-        }
+            raw: buf
+                .get(_len..body_len)
+                .ok_or(Error::PacketLength(buf.len()))?
+                .to_vec(),
+        })
     }
 }
 #[derive(Debug, Clone)]
@@ -1262,18 +1401,18 @@ pub struct RouteInfo;
 impl RouteInfo {
     pub fn handle(
         &self,
-        _: &Sender<ClientUpdate>,
+        _: &mut Vec<ClientUpdate>,
         _: &mut Account,
     ) -> Vec<s::Message> {
         vec![]
     }
 }
 impl Deserialise for RouteInfo {
-    fn deserialise(_: &[u8]) -> Self
+    fn deserialise(_: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
-        Self
+        Ok(Self)
     }
 }
 
@@ -1284,20 +1423,22 @@ pub struct ReadyEnterWorldAck {
 impl ReadyEnterWorldAck {
     pub fn handle(
         &self,
-        _: &Sender<ClientUpdate>,
+        _: &mut Vec<ClientUpdate>,
         _: &mut Account,
     ) -> Vec<s::Message> {
         vec![]
     }
 }
 impl Deserialise for ReadyEnterWorldAck {
-    fn deserialise(buf: &[u8]) -> Self
+    fn deserialise(buf: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
-        Self {
-            unknown: buf.try_into().unwrap(),
-        }
+        Ok(Self {
+            unknown: buf
+                .try_into()
+                .map_err(|_| Error::PacketLength(buf.len()))?,
+        })
     }
 }
 
@@ -1306,18 +1447,18 @@ pub struct AskLog;
 impl AskLog {
     pub fn handle(
         &self,
-        _: &Sender<ClientUpdate>,
+        _: &mut Vec<ClientUpdate>,
         _: &mut Account,
     ) -> Vec<s::Message> {
         vec![]
     }
 }
 impl Deserialise for AskLog {
-    fn deserialise(_: &[u8]) -> Self
+    fn deserialise(_: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
-        Self
+        Ok(Self)
     }
 }
 
@@ -1329,20 +1470,56 @@ pub struct LevelReady;
 impl LevelReady {
     pub fn handle(
         self,
-        tx: &Sender<ClientUpdate>,
+        updates: &mut Vec<ClientUpdate>,
         session: &mut Account,
     ) -> Vec<s::Message> {
-        tx.send(session.send(Message::LevelReady(self))).unwrap();
+        updates.push(session.send(Message::LevelReady(self)));
 
-        vec![s::Message::InvincibleTime(s::InvincibleTime::finish())]
+        vec![]
     }
 }
 impl Deserialise for LevelReady {
-    fn deserialise(_: &[u8]) -> Self
+    fn deserialise(_: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Restart {
+    BindPoint,
+    Unknown(u8),
+}
+
+/// The player picked where to resurrect from the death dialog
+#[derive(Debug, Clone)]
+pub struct DeadRestart {
+    pub restart: Restart,
+}
+impl DeadRestart {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::DeadRestart(self)));
+
+        vec![]
+    }
+}
+impl Deserialise for DeadRestart {
+    fn deserialise(buf: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
-        Self
+        let restart = match buf.first() {
+            Some(0) => Restart::BindPoint,
+            restart => Restart::Unknown(restart.copied().unwrap_or_default()),
+        };
+
+        Ok(Self { restart })
     }
 }
 #[derive(Debug, Clone)]
@@ -1355,16 +1532,16 @@ pub struct UseSkill {
 impl UseSkill {
     pub fn handle(
         self,
-        tx: &Sender<ClientUpdate>,
+        updates: &mut Vec<ClientUpdate>,
         session: &mut Account,
     ) -> Vec<s::Message> {
-        tx.send(session.send(Message::UseSkill(self))).unwrap();
+        updates.push(session.send(Message::UseSkill(self)));
 
         vec![]
     }
 }
 impl Deserialise for UseSkill {
-    fn deserialise(buf: &[u8]) -> Self
+    fn deserialise(buf: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
@@ -1375,12 +1552,40 @@ impl Deserialise for UseSkill {
         let target_id = consume_le_bytes!(ptr, buf, u32);
         let unknown = buf[ptr..].to_vec();
 
-        Self {
+        Ok(Self {
             skill_id: id,
             skill_level: level,
             target_id,
             unknown,
-        }
+        })
+    }
+}
+
+/// The player right clicked one of their buffs to get rid of it
+#[derive(Debug, Clone)]
+pub struct TurnOffAbnormalStatus {
+    pub skill_id: u16,
+}
+impl TurnOffAbnormalStatus {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::TurnOffAbnormalStatus(self)));
+
+        vec![]
+    }
+}
+impl Deserialise for TurnOffAbnormalStatus {
+    fn deserialise(buf: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut _len = 0;
+        let skill_id = consume_le_bytes!(_len, buf, u16);
+
+        Ok(Self { skill_id })
     }
 }
 
@@ -1392,23 +1597,23 @@ pub struct Action {
 impl Action {
     pub fn handle(
         self,
-        tx: &Sender<ClientUpdate>,
+        updates: &mut Vec<ClientUpdate>,
         session: &mut Account,
     ) -> Vec<s::Message> {
-        tx.send(session.send(Message::Action(self))).unwrap();
+        updates.push(session.send(Message::Action(self)));
 
         vec![]
     }
 }
 impl Deserialise for Action {
-    fn deserialise(buf: &[u8]) -> Self
+    fn deserialise(buf: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
-        Self {
-            ty: ActionType::deserialise(buf),
+        Ok(Self {
+            ty: ActionType::deserialise(buf)?,
             raw: buf.to_vec(),
-        }
+        })
     }
 }
 
@@ -1437,24 +1642,24 @@ pub struct Loot {
 impl Loot {
     pub fn handle(
         self,
-        tx: &Sender<ClientUpdate>,
+        updates: &mut Vec<ClientUpdate>,
         session: &mut Account,
     ) -> Vec<s::Message> {
-        tx.send(session.send(Message::Loot(self))).unwrap();
+        updates.push(session.send(Message::Loot(self)));
 
         vec![]
     }
 }
 
 impl Deserialise for Loot {
-    fn deserialise(buf: &[u8]) -> Self
+    fn deserialise(buf: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
         let mut _len = 0;
         let entity_id = consume_le_bytes!(_len, buf, u32);
         let action = consume_le_bytes!(_len, buf, u8).into();
-        Self { entity_id, action }
+        Ok(Self { entity_id, action })
     }
 }
 
@@ -1466,24 +1671,111 @@ pub struct LootItem {
 impl LootItem {
     pub fn handle(
         self,
-        tx: &Sender<ClientUpdate>,
+        updates: &mut Vec<ClientUpdate>,
         session: &mut Account,
     ) -> Vec<s::Message> {
-        tx.send(session.send(Message::LootItem(self))).unwrap();
+        updates.push(session.send(Message::LootItem(self)));
 
         vec![]
     }
 }
 
 impl Deserialise for LootItem {
-    fn deserialise(buf: &[u8]) -> Self
+    fn deserialise(buf: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
         let mut _len = 0;
         let entity_id = consume_le_bytes!(_len, buf, u32);
         let index = consume_le_bytes!(_len, buf, u32);
-        Self { entity_id, index }
+        Ok(Self { entity_id, index })
+    }
+}
+
+/// Dragging an item to another slot in the cube
+// TODO: Verify this, only the cube is supported
+#[derive(Debug, Clone)]
+pub struct MoveItemToAnotherSlot {
+    pub item_id: u32,
+    pub source: u8,
+    pub destination: u8,
+    pub slot: u16,
+}
+impl MoveItemToAnotherSlot {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::MoveItemToAnotherSlot(self)));
+
+        vec![]
+    }
+}
+
+impl Deserialise for MoveItemToAnotherSlot {
+    fn deserialise(buf: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut _len = 0;
+        let item_id = consume_le_bytes!(_len, buf, u32);
+        let source = consume_le_bytes!(_len, buf, u8);
+        let destination = consume_le_bytes!(_len, buf, u8);
+        let slot = consume_le_bytes!(_len, buf, u16);
+        Ok(Self {
+            item_id,
+            source,
+            destination,
+            slot,
+        })
+    }
+}
+
+/// Splitting a stack into an empty slot or merging it onto another stack
+// TODO: Verify this, only the cube is supported
+#[derive(Debug, Clone)]
+pub struct MoveStackableItem {
+    pub source_id: u32,
+    pub source: u8,
+    pub count: u32,
+    /// 0 when splitting into an empty slot
+    pub target_id: u32,
+    pub destination: u8,
+    pub slot: u16,
+}
+impl MoveStackableItem {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::MoveStackableItem(self)));
+
+        vec![]
+    }
+}
+
+impl Deserialise for MoveStackableItem {
+    fn deserialise(buf: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut _len = 0;
+        let source_id = consume_le_bytes!(_len, buf, u32);
+        let source = consume_le_bytes!(_len, buf, u8);
+        let count = consume_le_bytes!(_len, buf, u32);
+        let target_id = consume_le_bytes!(_len, buf, u32);
+        let destination = consume_le_bytes!(_len, buf, u8);
+        let slot = consume_le_bytes!(_len, buf, u16);
+        Ok(Self {
+            source_id,
+            source,
+            count,
+            target_id,
+            destination,
+            slot,
+        })
     }
 }
 
@@ -1494,23 +1786,23 @@ pub struct ChangeTarget {
 impl ChangeTarget {
     pub fn handle(
         self,
-        tx: &Sender<ClientUpdate>,
+        updates: &mut Vec<ClientUpdate>,
         account: &mut Account,
     ) -> Vec<s::Message> {
-        tx.send(account.send(Message::ChangeTarget(self))).unwrap();
+        updates.push(account.send(Message::ChangeTarget(self)));
 
         vec![]
     }
 }
 impl Deserialise for ChangeTarget {
-    fn deserialise(buf: &[u8]) -> Self
+    fn deserialise(buf: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
         let mut _len = 0;
         let target_id = consume_le_bytes!(_len, buf, u32);
 
-        Self { target_id }
+        Ok(Self { target_id })
     }
 }
 
@@ -1526,15 +1818,15 @@ pub struct Attack {
 impl Attack {
     pub fn handle(
         self,
-        tx: &Sender<ClientUpdate>,
+        updates: &mut Vec<ClientUpdate>,
         session: &mut Account,
     ) -> Vec<s::Message> {
-        tx.send(session.send(Message::Attack(self))).unwrap();
+        updates.push(session.send(Message::Attack(self)));
         vec![]
     }
 }
 impl Deserialise for Attack {
-    fn deserialise(buf: &[u8]) -> Self
+    fn deserialise(buf: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
@@ -1545,34 +1837,68 @@ impl Deserialise for Attack {
 
         let _unknown = buf[ptr..].to_vec();
 
-        Self {
+        Ok(Self {
             target_id,
             sequence,
             _unknown,
+        })
+    }
+}
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EquipAction {
+    Equip,
+    Unequip,
+    /// Swap the main and off hand weapon sets
+    SwitchWeapons,
+    Unknown(u8),
+}
+
+impl From<u8> for EquipAction {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::Equip,
+            1 => Self::Unequip,
+            2 => Self::SwitchWeapons,
+            action => Self::Unknown(action),
         }
     }
 }
+
+/// Double clicking or dragging an item to put it on or take it off
+// TODO: Verify this
 #[derive(Debug, Clone)]
 pub struct UseEquipmentItem {
-    unknown: [u8; 9],
+    pub action: EquipAction,
+    /// The slot mask of where the item was dragged to
+    pub slot: u32,
+    pub item_id: u32,
 }
 impl UseEquipmentItem {
     pub fn handle(
-        &self,
-        _: &Sender<ClientUpdate>,
-        _: &mut Account,
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
     ) -> Vec<s::Message> {
+        updates.push(session.send(Message::UseEquipmentItem(self)));
+
         vec![]
     }
 }
 impl Deserialise for UseEquipmentItem {
-    fn deserialise(buf: &[u8]) -> Self
+    fn deserialise(buf: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
-        let unknown = buf.try_into().unwrap();
+        let mut _len = 0;
+        let action = consume_le_bytes!(_len, buf, u8).into();
+        let slot = consume_le_bytes!(_len, buf, u32);
+        let item_id = consume_le_bytes!(_len, buf, u32);
 
-        Self { unknown }
+        Ok(Self {
+            action,
+            slot,
+            item_id,
+        })
     }
 }
 #[derive(Debug, Clone)]
@@ -1580,18 +1906,18 @@ pub struct ReconnectAuth;
 impl ReconnectAuth {
     pub fn handle(
         &self,
-        _: &Sender<ClientUpdate>,
+        _: &mut Vec<ClientUpdate>,
         _: &mut Account,
     ) -> Vec<s::Message> {
         vec![s::Message::ReconnectKey(s::ReconnectKey::new())]
     }
 }
 impl Deserialise for ReconnectAuth {
-    fn deserialise(_: &[u8]) -> Self
+    fn deserialise(_: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
-        Self
+        Ok(Self)
     }
 }
 
@@ -1600,17 +1926,787 @@ pub struct Alive;
 impl Alive {
     pub fn handle(
         &self,
-        _: &Sender<ClientUpdate>,
+        _: &mut Vec<ClientUpdate>,
         _: &mut Account,
     ) -> Vec<s::Message> {
         vec![s::Message::Alive(s::Alive::new())]
     }
 }
 impl Deserialise for Alive {
-    fn deserialise(_: &[u8]) -> Self
+    fn deserialise(_: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self)
+    }
+}
+
+/// Where a chat message goes, whispers have a message of their own
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChatType {
+    /// Everyone nearby
+    Normal,
+    /// Everyone in the zone
+    Shout,
+    Whisper,
+    Group,
+    Alliance,
+    Legion,
+    Unknown(u8),
+}
+
+impl From<u8> for ChatType {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::Normal,
+            3 => Self::Shout,
+            4 => Self::Whisper,
+            5 => Self::Group,
+            6 => Self::Alliance,
+            8 => Self::Legion,
+            ty => Self::Unknown(ty),
+        }
+    }
+}
+
+impl From<ChatType> for u8 {
+    fn from(value: ChatType) -> Self {
+        match value {
+            ChatType::Normal => 0,
+            ChatType::Shout => 3,
+            ChatType::Whisper => 4,
+            ChatType::Group => 5,
+            ChatType::Alliance => 6,
+            ChatType::Legion => 8,
+            ChatType::Unknown(ty) => ty,
+        }
+    }
+}
+
+/// Typing in the chat box, to anyone but a single player
+#[derive(Debug, Clone)]
+pub struct Say {
+    pub ty: ChatType,
+    pub message: String,
+}
+impl Say {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::Say(self)));
+
+        vec![]
+    }
+}
+impl Deserialise for Say {
+    fn deserialise(buf: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut len = 0;
+        let ty = consume_le_bytes!(len, buf, u8).into();
+        let message = consume_utf16(&mut len, buf);
+
+        Ok(Self { ty, message })
+    }
+}
+
+/// A private message to a player by name
+#[derive(Debug, Clone)]
+pub struct Whisper {
+    pub name: String,
+    pub message: String,
+}
+impl Whisper {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::Whisper(self)));
+
+        vec![]
+    }
+}
+impl Deserialise for Whisper {
+    fn deserialise(buf: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut len = 0;
+        let name = consume_utf16(&mut len, buf);
+        let message = consume_utf16(&mut len, buf);
+
+        Ok(Self { name, message })
+    }
+}
+
+/// Yes or no to a question the server asked with an S_ASK
+#[derive(Debug, Clone)]
+pub struct Answer {
+    pub question_id: u32,
+    pub accepted: bool,
+    /// Whoever the question is from
+    pub sender_id: u32,
+}
+impl Answer {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::Answer(self)));
+
+        vec![]
+    }
+}
+impl Deserialise for Answer {
+    fn deserialise(buf: &[u8]) -> Result<Self>
     where
         Self: Sized,
     {
-        Self
+        let mut _len = 0;
+        let question_id = consume_le_bytes!(_len, buf, u32);
+        let accepted = consume_le_bytes!(_len, buf, u8) != 0;
+        let sender_id = consume_le_bytes!(_len, buf, u32);
+
+        Ok(Self {
+            question_id,
+            accepted,
+            sender_id,
+        })
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PartyAction {
+    Leave,
+    Kick,
+    ChangeLeader,
+    Unknown(u8),
+}
+
+impl From<u8> for PartyAction {
+    fn from(value: u8) -> Self {
+        match value {
+            2 => Self::Leave,
+            3 => Self::Kick,
+            4 => Self::ChangeLeader,
+            action => Self::Unknown(action),
+        }
+    }
+}
+
+/// Managing the party from the party window
+// TODO: Verify this
+#[derive(Debug, Clone)]
+pub struct Party {
+    pub action: PartyAction,
+    /// The member acted on, the sender themselves when leaving
+    pub character_id: u32,
+}
+impl Party {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::Party(self)));
+
+        vec![]
+    }
+}
+impl Deserialise for Party {
+    fn deserialise(buf: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut _len = 0;
+        let action = consume_le_bytes!(_len, buf, u8).into();
+        let character_id = consume_le_bytes!(_len, buf, u32);
+
+        Ok(Self {
+            action,
+            character_id,
+        })
+    }
+}
+
+/// Invite a player to the party, `/invite` and the target's menu both send
+/// the name
+#[derive(Debug, Clone)]
+pub struct PartyByName {
+    pub name: String,
+}
+impl PartyByName {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::PartyByName(self)));
+
+        vec![]
+    }
+}
+impl Deserialise for PartyByName {
+    fn deserialise(buf: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut len = 0;
+        let name = consume_utf16(&mut len, buf);
+
+        Ok(Self { name })
+    }
+}
+
+/// The leader changing who gets to loot the party's kills
+// TODO: The item quality thresholds for rolling follow the rule
+#[derive(Debug, Clone)]
+pub struct GroupChangeLootdist {
+    pub loot_rule: u32,
+}
+impl GroupChangeLootdist {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::GroupChangeLootdist(self)));
+
+        vec![]
+    }
+}
+impl Deserialise for GroupChangeLootdist {
+    fn deserialise(buf: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut _len = 0;
+        let loot_rule = consume_le_bytes!(_len, buf, u32);
+
+        Ok(Self { loot_rule })
+    }
+}
+
+/// Asking the target to trade
+#[derive(Debug, Clone)]
+pub struct AskXchg {
+    pub target_id: u32,
+}
+impl AskXchg {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::AskXchg(self)));
+
+        vec![]
+    }
+}
+impl Deserialise for AskXchg {
+    fn deserialise(buf: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut _len = 0;
+        let target_id = consume_le_bytes!(_len, buf, u32);
+
+        Ok(Self { target_id })
+    }
+}
+
+/// Putting some of an item up for trade
+// TODO: Verify this
+#[derive(Debug, Clone)]
+pub struct AddXchg {
+    pub item_id: u32,
+    pub count: u32,
+}
+impl AddXchg {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::AddXchg(self)));
+
+        vec![]
+    }
+}
+impl Deserialise for AddXchg {
+    fn deserialise(buf: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut _len = 0;
+        // unknown
+        _len += 1;
+        let item_id = consume_le_bytes!(_len, buf, u32);
+        let count = consume_le_bytes!(_len, buf, u32);
+
+        Ok(Self { item_id, count })
+    }
+}
+
+/// Taking an item back off the trade window
+// TODO: Verify this
+#[derive(Debug, Clone)]
+pub struct RemoveXchg {
+    pub item_id: u32,
+}
+impl RemoveXchg {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::RemoveXchg(self)));
+
+        vec![]
+    }
+}
+impl Deserialise for RemoveXchg {
+    fn deserialise(buf: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut _len = 0;
+        let item_id = consume_le_bytes!(_len, buf, u32);
+
+        Ok(Self { item_id })
+    }
+}
+
+/// Putting kinah up for trade, on top of what is already there
+#[derive(Debug, Clone)]
+pub struct XchgGold {
+    pub kinah: u32,
+}
+impl XchgGold {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::XchgGold(self)));
+
+        vec![]
+    }
+}
+impl Deserialise for XchgGold {
+    fn deserialise(buf: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut _len = 0;
+        let kinah = consume_le_bytes!(_len, buf, u32);
+
+        Ok(Self { kinah })
+    }
+}
+
+/// Locking the offer in, the first of the two confirmations
+#[derive(Debug, Clone)]
+pub struct CheckXchg;
+impl CheckXchg {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::CheckXchg(self)));
+
+        vec![]
+    }
+}
+impl Deserialise for CheckXchg {
+    fn deserialise(_: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self)
+    }
+}
+
+/// Agreeing to the trade once both offers are locked in
+#[derive(Debug, Clone)]
+pub struct AcceptXchg;
+impl AcceptXchg {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::AcceptXchg(self)));
+
+        vec![]
+    }
+}
+impl Deserialise for AcceptXchg {
+    fn deserialise(_: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self)
+    }
+}
+
+/// Closing the trade window
+#[derive(Debug, Clone)]
+pub struct CancelXchg;
+impl CancelXchg {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::CancelXchg(self)));
+
+        vec![]
+    }
+}
+impl Deserialise for CancelXchg {
+    fn deserialise(_: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self)
+    }
+}
+
+/// Talking to an NPC, merchants open their shop
+// TODO: Verify this
+#[derive(Debug, Clone)]
+pub struct StartDialog {
+    pub target_id: u32,
+}
+impl StartDialog {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::StartDialog(self)));
+
+        vec![]
+    }
+}
+impl Deserialise for StartDialog {
+    fn deserialise(buf: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut _len = 0;
+        let target_id = consume_le_bytes!(_len, buf, u32);
+        // unknown u32
+
+        Ok(Self { target_id })
+    }
+}
+
+/// Walking away from or closing an NPC's dialog
+// TODO: Verify this
+#[derive(Debug, Clone)]
+pub struct EndDialog {
+    pub target_id: u32,
+}
+impl EndDialog {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::EndDialog(self)));
+
+        vec![]
+    }
+}
+impl Deserialise for EndDialog {
+    fn deserialise(buf: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut _len = 0;
+        let target_id = consume_le_bytes!(_len, buf, u32);
+
+        Ok(Self { target_id })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TradeAction {
+    /// From the merchant
+    Buy,
+    /// To the merchant
+    Sell,
+    Unknown(u16),
+}
+
+impl From<u16> for TradeAction {
+    fn from(value: u16) -> Self {
+        match value {
+            0 => Self::Buy,
+            1 => Self::Sell,
+            action => Self::Unknown(action),
+        }
+    }
+}
+
+/// Everything in the shop window's basket at once
+// TODO: Verify this
+#[derive(Debug, Clone)]
+pub struct BuySell {
+    pub npc_id: u32,
+    pub action: TradeAction,
+    /// Item ids and counts when buying, object ids and counts when selling
+    pub items: Vec<(u32, u32)>,
+}
+impl BuySell {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::BuySell(self)));
+
+        vec![]
+    }
+}
+impl Deserialise for BuySell {
+    fn deserialise(buf: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut len = 0;
+        let npc_id = consume_le_bytes!(len, buf, u32);
+        let action = consume_le_bytes!(len, buf, u16).into();
+        let count = consume_le_bytes!(len, buf, u16);
+        // Each entry is followed by an unknown u32, a short buffer ends the
+        // list early
+        let items = buf[len..]
+            .chunks_exact(12)
+            .take(usize::from(count))
+            .map(|entry| {
+                let mut _len = 0;
+                let id = consume_le_bytes!(_len, entry, u32);
+                let count = consume_le_bytes!(_len, entry, u32);
+                Ok((id, count))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            npc_id,
+            action,
+            items,
+        })
+    }
+}
+
+/// Sending a letter by name, it can carry some of one item and kinah
+// TODO: Verify this
+#[derive(Debug, Clone)]
+pub struct MailWrite {
+    pub recipient: String,
+    pub title: String,
+    pub message: String,
+    /// Object id, 0 when no item is attached
+    pub item_id: u32,
+    pub count: u32,
+    pub kinah: u32,
+}
+impl MailWrite {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::MailWrite(self)));
+
+        vec![]
+    }
+}
+impl Deserialise for MailWrite {
+    fn deserialise(buf: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut _len = 0;
+        let recipient = consume_utf16(&mut _len, buf);
+        let title = consume_utf16(&mut _len, buf);
+        let message = consume_utf16(&mut _len, buf);
+        let item_id = consume_le_bytes!(_len, buf, u32);
+        let count = consume_le_bytes!(_len, buf, u32);
+        // unknown u32
+        _len += 4;
+        let kinah = consume_le_bytes!(_len, buf, u32);
+
+        Ok(Self {
+            recipient,
+            title,
+            message,
+            item_id,
+            count,
+            kinah,
+        })
+    }
+}
+
+/// Opening the mailbox
+// TODO: Verify this
+#[derive(Debug, Clone)]
+pub struct MailList;
+impl MailList {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::MailList(self)));
+
+        vec![]
+    }
+}
+impl Deserialise for MailList {
+    fn deserialise(_: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self)
+    }
+}
+
+/// Opening a letter, it is marked as read
+// TODO: Verify this
+#[derive(Debug, Clone)]
+pub struct MailRead {
+    pub letter_id: u32,
+}
+impl MailRead {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::MailRead(self)));
+
+        vec![]
+    }
+}
+impl Deserialise for MailRead {
+    fn deserialise(buf: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut _len = 0;
+        let letter_id = consume_le_bytes!(_len, buf, u32);
+
+        Ok(Self { letter_id })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Attachment {
+    Item,
+    Kinah,
+    Unknown(u8),
+}
+
+impl From<u8> for Attachment {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::Item,
+            1 => Self::Kinah,
+            attachment => Self::Unknown(attachment),
+        }
+    }
+}
+
+impl From<Attachment> for u8 {
+    fn from(value: Attachment) -> Self {
+        match value {
+            Attachment::Item => 0,
+            Attachment::Kinah => 1,
+            Attachment::Unknown(attachment) => attachment,
+        }
+    }
+}
+
+/// Taking the item or the kinah off a letter
+// TODO: Verify this
+#[derive(Debug, Clone)]
+pub struct MailGetItem {
+    pub letter_id: u32,
+    pub attachment: Attachment,
+}
+impl MailGetItem {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::MailGetItem(self)));
+
+        vec![]
+    }
+}
+impl Deserialise for MailGetItem {
+    fn deserialise(buf: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut _len = 0;
+        let letter_id = consume_le_bytes!(_len, buf, u32);
+        let attachment = consume_le_bytes!(_len, buf, u8).into();
+
+        Ok(Self {
+            letter_id,
+            attachment,
+        })
+    }
+}
+
+/// Throwing a letter away
+// TODO: Verify this
+#[derive(Debug, Clone)]
+pub struct MailDelete {
+    pub letter_id: u32,
+}
+impl MailDelete {
+    pub fn handle(
+        self,
+        updates: &mut Vec<ClientUpdate>,
+        session: &mut Account,
+    ) -> Vec<s::Message> {
+        updates.push(session.send(Message::MailDelete(self)));
+
+        vec![]
+    }
+}
+impl Deserialise for MailDelete {
+    fn deserialise(buf: &[u8]) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut _len = 0;
+        let letter_id = consume_le_bytes!(_len, buf, u32);
+
+        Ok(Self { letter_id })
+    }
+}
+
+/// Null terminated UTF-16, a missing terminator ends the string at the end of
+/// the buffer
+fn consume_utf16(len: &mut usize, buf: &[u8]) -> String {
+    let chars: Vec<u16> = buf[(*len).min(buf.len())..]
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|c| *c != 0)
+        .collect();
+    *len += (chars.len() + 1) * 2;
+    String::from_utf16_lossy(&chars)
+}
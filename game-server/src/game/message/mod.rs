@@ -1,87 +1,218 @@
-use crossbeam_channel::{Receiver, Sender};
+use std::io::{self, Write};
+
+use crossbeam_channel::Sender;
 use krypt::game::encrypt;
 
 pub mod client;
 pub mod server;
 
+use crate::error::{Error, Result};
 use crate::game::{session::Account, Serialise};
 
-use super::{ClientUpdate, ServerUpdate};
+use super::ClientUpdate;
+
+/// Every message starts with a u16 length so none can be bigger than this
+pub const MAX_MESSAGE_LEN: usize = u16::MAX as usize;
 
 pub struct ClientMessages {
     messages: Vec<client::Message>,
+    /// Opcodes of the frames that were skipped
+    unknown: Vec<u16>,
 }
 
 impl ClientMessages {
-    pub fn empty() -> Self {
-        Self {
-            messages: Vec::with_capacity(0),
-        }
-    }
-    pub fn deserialise(mut buffer: &mut [u8], key: &mut [u8; 8]) -> Self {
+    /// Take every complete message off the front of the buffer, a message
+    /// split across reads is left in place until the rest of it arrives.
+    /// Unknown opcodes are skipped but kept count of, anything else means we
+    /// have lost track of the stream and the client has to go
+    pub fn deserialise(
+        buffer: &mut Vec<u8>,
+        key: &mut [u8; 8],
+    ) -> Result<Self> {
         let mut messages = Vec::new();
+        let mut unknown = Vec::new();
+        let mut consumed = 0;
 
-        loop {
-            if buffer.len() < 2 {
-                break;
+        let result = loop {
+            let frame = &mut buffer[consumed..];
+            if frame.len() < 2 {
+                break Ok(());
             }
 
-            let len = u16::from_le_bytes([buffer[0], buffer[1]]) as usize;
-            if len > buffer.len() || len < 2 {
-                panic!("Invalid Packet Len");
+            let len = u16::from_le_bytes([frame[0], frame[1]]) as usize;
+            if len < 2 + client::HEADER_LEN {
+                break Err(Error::PacketLength(len));
+            }
+            if len > frame.len() {
+                break Ok(());
             }
 
-            if let Some(message) =
-                client::Message::deserialise(&mut buffer[2..len], key)
-            {
-                println!("C: {message:02X?}");
-                messages.push(message);
+            match client::Message::deserialise(&mut frame[2..len], key) {
+                Ok(message) => {
+                    println!("C: {message:02X?}");
+                    messages.push(message);
+                }
+                Err(Error::UnknownOpcode(opcode)) => unknown.push(opcode),
+                Err(e) => break Err(e),
             }
+            consumed += len;
+        };
 
-            buffer = &mut buffer[len..];
-        }
-        Self { messages }
+        buffer.drain(..consumed);
+        result.map(|()| Self { messages, unknown })
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
     }
 
+    pub fn unknown(&self) -> &[u16] {
+        &self.unknown
+    }
+
+    /// Whether the buffer starts with a whole message, or at least the length
+    /// of one that is too short to be valid
+    pub fn complete(buffer: &[u8]) -> bool {
+        buffer.len() >= 2
+            && u16::from_le_bytes([buffer[0], buffer[1]]) as usize
+                <= buffer.len()
+    }
+
+    /// Answer what can be answered on this thread and pass the rest on to
+    /// the game loop without ever blocking, false when the game loop's queue
+    /// is full or gone and the client has to go
     pub fn handle(
         self,
-        mut buf: &mut [u8],
+        buf: &mut SendBuffer,
         key: &mut [u8; 8],
         account: &mut Account,
-        rx: &Receiver<ServerUpdate>,
         tx: &Sender<ClientUpdate>,
-    ) -> usize {
-        let mut total_len = 0;
-
+    ) -> bool {
+        let mut updates = Vec::new();
         // Thread local messages
         for message in self.messages {
-            let replies = message.handle(&tx, account);
+            let replies = message.handle(&mut updates, account);
 
             for reply in replies {
                 println!("[LOCAL]: {reply:02X?}");
-
-                let len = reply.serialise(buf);
-                encrypt(key, &mut buf[2..len]);
-                buf = &mut buf[len..];
-                total_len += len;
+                buf.push(&reply, key);
             }
         }
 
-        // Messages from another thread
-        for update in rx.try_iter() {
-            let message = update.message();
+        updates
+            .into_iter()
+            .all(|update| tx.try_send(update).is_ok())
+    }
+}
 
-            let len = message.serialise(buf);
-            encrypt(key, &mut buf[2..len]);
-            buf = &mut buf[len..];
-            total_len += len;
+/// Encrypted messages waiting for the socket to accept them
+pub struct SendBuffer {
+    pending: Vec<u8>,
+    /// Messages are serialised here first, the serialisers skip over bytes
+    /// they want to be zero so it is cleared after every use
+    scratch: Box<[u8]>,
+}
+
+impl SendBuffer {
+    pub fn new() -> Self {
+        Self {
+            pending: Vec::with_capacity(1024 * 64),
+            scratch: vec![0u8; MAX_MESSAGE_LEN].into_boxed_slice(),
         }
-        total_len
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Serialise and encrypt a message onto the end of the buffer
+    pub fn push(&mut self, message: &server::Message, key: &mut [u8; 8]) {
+        let len = message.serialise(&mut self.scratch);
+        encrypt(key, &mut self.scratch[2..len]);
+        self.pending.extend_from_slice(&self.scratch[..len]);
+        self.scratch[..len].fill(0);
+    }
+
+    /// Write as much as the writer will take without blocking, anything left
+    /// over stays buffered until the next call
+    pub fn write_to(&mut self, writer: &mut impl Write) -> io::Result<()> {
+        let mut written = 0;
+        let result = loop {
+            if written == self.pending.len() {
+                break Ok(());
+            }
+            match writer.write(&self.pending[written..]) {
+                Ok(0) => break Err(io::ErrorKind::WriteZero.into()),
+                Ok(len) => written += len,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => break Err(e),
+            }
+        };
+        self.pending.drain(..written);
+        result
     }
 }
 
-/// Make a S_KEY packet to be the first message from server to client
-pub fn make_key_packet(buf: &mut [u8; 11], key: u32) -> usize {
+/// Make a S_KEY packet to be the first message from server to client, unlike
+/// everything after it this one is not encrypted
+pub fn make_key_packet(buf: &mut SendBuffer, key: u32) {
     let key = server::Message::Key(server::Key::new(key));
-    key.serialise(buf)
+    let len = key.serialise(&mut buf.scratch);
+    buf.pending.extend_from_slice(&buf.scratch[..len]);
+    buf.scratch[..len].fill(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Deserialise;
+
+    /// C_VERSION captured along with the key it was encrypted with
+    const KEY: [u8; 8] = [0x74, 0x92, 0xB2, 0x35, 0xA1, 0x6C, 0x54, 0x87];
+    const VERSION: [u8; 23] = [
+        0x17, 0x00, 0x86, 0x5f, 0xcd, 0xda, 0xd3, 0x1c, 0x3c, 0xc2, 0x86, 0xb1,
+        0x51, 0x28, 0xeb, 0xf1, 0xdf, 0x3e, 0x08, 0xf3, 0x12, 0x11, 0xfe,
+    ];
+
+    #[test]
+    fn reassembles_split_messages() {
+        let mut key = KEY;
+        let mut buffer = VERSION[..10].to_vec();
+        assert!(!ClientMessages::complete(&buffer));
+        let messages = ClientMessages::deserialise(&mut buffer, &mut key);
+        assert_eq!(messages.unwrap().len(), 0);
+        assert_eq!(buffer.len(), 10);
+
+        buffer.extend_from_slice(&VERSION[10..]);
+        buffer.extend_from_slice(&VERSION[..1]);
+        assert!(ClientMessages::complete(&buffer));
+        let messages = ClientMessages::deserialise(&mut buffer, &mut key);
+        assert_eq!(messages.unwrap().len(), 1);
+        assert_eq!(buffer, VERSION[..1]);
+    }
+
+    #[test]
+    fn rejects_bad_frames() {
+        let mut buffer = vec![0x03, 0x00, 0x00];
+        let messages = ClientMessages::deserialise(&mut buffer, &mut [0; 8]);
+        assert!(matches!(messages, Err(Error::PacketLength(3))));
+
+        let mut buffer = VERSION.to_vec();
+        let messages = ClientMessages::deserialise(&mut buffer, &mut [0; 8]);
+        assert!(matches!(messages, Err(Error::PacketChecksum { .. })));
+    }
+
+    #[test]
+    fn rejects_short_messages() {
+        let sync_time = client::SyncTime::deserialise(&[0; 12]);
+        assert!(matches!(sync_time, Err(Error::PacketLength(12))));
+        assert!(client::SyncTime::deserialise(&[0; 13]).is_ok());
+
+        // Recipient, title and message but no attachment
+        let mail = client::MailWrite::deserialise(&[0x41, 0, 0, 0, 0, 0, 0, 0]);
+        assert!(matches!(mail, Err(Error::PacketLength(8))));
+        let character = client::CreateCharacter::deserialise(&[0; 20]);
+        assert!(matches!(character, Err(Error::PacketLength(20))));
+    }
 }
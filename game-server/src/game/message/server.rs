@@ -1,17 +1,28 @@
+use std::time::Duration;
+
 use krypt::game::encrypt_server_opcode;
 
 use crate::{
     copy_bytes,
     game::{
-        character::Character,
+        character::{Character, Race},
         data::{
-            gear::{Gear, LootItem, RawItem},
+            gear::{Gear, LootItem},
+            item::InventoryItem,
             npc,
             skill::Skill,
             ActionType,
         },
-        engine::{damage::Hit, Coord, Direction, MoveType},
+        engine::{
+            damage::Hit,
+            effect::Slot,
+            mail::Letter,
+            party::{LootRule, Party},
+            Coord, Direction, MoveType,
+        },
         entity::Entity,
+        message::client::{Attachment, ChatType},
+        WORLD_ID,
     },
     to_le_bytes,
 };
@@ -24,48 +35,74 @@ const LENGTH_LEN: usize = 2;
 const VERSION_CHECK: u16 = 0x0000;
 const STATUS: u16 = 0x0001;
 const HIT_POINT: u16 = 0x0003;
+const MANA_POINT: u16 = 0x0004;
 const HIT_POINT_OTHER: u16 = 0x0005;
+const DP: u16 = 0x0006;
+const EXP: u16 = 0x0008;
 const ENTER_WORLD_CHECK: u16 = 0x000D;
 const PUT_NPC: u16 = 0x000E;
 const WORLD: u16 = 0x000F;
+const REMOVE_OBJECT: u16 = 0x0016;
+const MESSAGE: u16 = 0x0018;
 const MESSAGE_CODE: u16 = 0x0019;
 const LOAD_INVENTORY: u16 = 0x001A;
+const ADD_INVENTORY: u16 = 0x001B;
+const REMOVE_INVENTORY: u16 = 0x001C;
 const CHANGE_ITEM_DESC: u16 = 0x001D;
 const LOAD_CLIENT_SETTINGS: u16 = 0x001E;
 const PUT_USER: u16 = 0x0020;
 const USE_SKILL: u16 = 0x0021;
+const WIELD: u16 = 0x0024;
 const TIME: u16 = 0x0026;
 const ACTION: u16 = 0x0025;
 const SYNC_TIME: u16 = 0x0027;
 const NPC_CHANGED_TARGET: u16 = 0x0028;
 const TARGET_INFO: u16 = 0x0029;
+const SKILL_CANCELED: u16 = 0x002A;
 const SKILL_SUCCEDED: u16 = 0x002B;
 const ADD_SKILL: u16 = 0x002C;
 const ABNORMAL_STATUS: u16 = 0x0031;
 const ABNORMAL_STATUS_OTHER: u16 = 0x0032;
+const LOAD_SKILL_COOLTIME: u16 = 0x0033;
+const ASK: u16 = 0x0034;
 const ATTACK: u16 = 0x0036;
 const MOVE_NEW: u16 = 0x0037;
 const WEATHER: u16 = 0x0042;
 const INVISIBLE_LEVEL: u16 = 0x0043;
+const EFFECT: u16 = 0x0045;
 const KEY: u16 = 0x0047;
+const RESET_SKILL_COOLING_TIME: u16 = 0x0048;
+const XCHG_START: u16 = 0x0049;
+const ADD_XCHG: u16 = 0x004A;
+const REMOVE_XCHG: u16 = 0x004B;
+const XCHG_GOLD: u16 = 0x004C;
+const XCHG_RESULT: u16 = 0x004D;
+const PARTY_INFO: u16 = 0x0059;
+const PARTY_MEMBER_INFO: u16 = 0x005A;
 const ASK_QUIT_RESULT: u16 = 0x0061;
 const LOAD_ITEM_COOLTIME: u16 = 0x0066;
 const BUDDY_LIST: u16 = 0x0083;
+const SHOP_SELL_LIST: u16 = 0x0085;
 const SA_ACCOUNT_ITEM_NOTI: u16 = 0x0088;
 const WORLD_SCENE_STATUS: u16 = 0x008B;
 const ALIVE: u16 = 0x008D;
 const CUSTOM_ANIM: u16 = 0x0093;
+const MAIL: u16 = 0x00A0;
 const TITLE: u16 = 0x00AF;
 const SECOND_PASSWORD: u16 = 0x00B0;
+const RESURRECT_INFO: u16 = 0x00C0;
 const L2AUTH_LOGIN_CHECK: u16 = 0x00C6;
 const CHARACTER_LIST: u16 = 0x00C7;
 const CREATE_CHARACTER: u16 = 0x00C8;
+const DELETE_CHARACTER: u16 = 0x00C9;
+const RESTORE_CHARACTER: u16 = 0x00CA;
 const LOOT_ITEMLIST: u16 = 0x00CD;
 const LOOT: u16 = 0xCC;
 const RECIPE_LIST: u16 = 0x00CE;
 const CUR_STATUS: u16 = 0x00E3;
 const CHANGE_CHANNEL: u16 = 0x00E5;
 const SIGN_CLIENT: u16 = 0x00E6;
+const RESURRECT_LOC_INFO: u16 = 0x00EB;
 const BUILDER_LEVEL: u16 = 0x00EE;
 const WORLD_INFO: u16 = 0x00EC;
 const INVINCIBLE_TIME: u16 = 0x00FE;
@@ -101,6 +138,9 @@ pub enum Message {
     Attack(Attack),
     L2AuthLoginCheck(L2AuthLoginCheck),
     CharacterList(CharacterList),
+    CreateCharacter(CreateCharacter),
+    DeleteCharacter(DeleteCharacter),
+    RestoreCharacter(RestoreCharacter),
     RecipeList(RecipeList),
     CurStatus(CurStatus),
     BuilderLevel(BuilderLevel),
@@ -139,6 +179,30 @@ pub enum Message {
     LoadItemCooltime(LoadItemCooltime),
     AskQuitResult(AskQuitResult),
     PutNpc(PutNpc),
+    RemoveObject(RemoveObject),
+    SkillCanceled(SkillCanceled),
+    LoadSkillCooltime(LoadSkillCooltime),
+    ResetSkillCoolingTime(ResetSkillCoolingTime),
+    Exp(Exp),
+    PlayEffect(PlayEffect),
+    ResurrectInfo(ResurrectInfo),
+    ResurrectLocInfo(ResurrectLocInfo),
+    ManaPoint(ManaPoint),
+    Dp(Dp),
+    AddInventory(AddInventory),
+    RemoveInventory(RemoveInventory),
+    Wield(Wield),
+    ChatMessage(ChatMessage),
+    Ask(Ask),
+    PartyInfo(PartyInfo),
+    PartyMemberInfo(PartyMemberInfo),
+    XchgStart(XchgStart),
+    AddXchg(AddXchg),
+    RemoveXchg(RemoveXchg),
+    XchgGold(XchgGold),
+    XchgResult(XchgResult),
+    ShopSellList(ShopSellList),
+    Mail(Mail),
 }
 
 impl Serialise for Message {
@@ -148,12 +212,16 @@ impl Serialise for Message {
             Message::Status(msg) => msg.serialise(&mut buf[2..]),
             Message::World(msg) => msg.serialise(&mut buf[2..]),
             Message::PutNpc(msg) => msg.serialise(&mut buf[2..]),
+            Message::RemoveObject(msg) => msg.serialise(&mut buf[2..]),
             Message::EnterWorldCheck(msg) => msg.serialise(&mut buf[2..]),
             Message::LoadClientSettings(msg) => msg.serialise(&mut buf[2..]),
             Message::UseSkill(msg) => msg.serialise(&mut buf[2..]),
             Message::SkillSucceded(msg) => msg.serialise(&mut buf[2..]),
             Message::PutUser(msg) => msg.serialise(&mut buf[2..]),
             Message::CharacterList(msg) => msg.serialise(&mut buf[2..]),
+            Message::CreateCharacter(msg) => msg.serialise(&mut buf[2..]),
+            Message::DeleteCharacter(msg) => msg.serialise(&mut buf[2..]),
+            Message::RestoreCharacter(msg) => msg.serialise(&mut buf[2..]),
             Message::CurStatus(msg) => msg.serialise(&mut buf[2..]),
             Message::Key(msg) => msg.serialise(&mut buf[2..]),
             Message::ServerEnv(msg) => msg.serialise(&mut buf[2..]),
@@ -201,6 +269,29 @@ impl Serialise for Message {
             Message::ReconnectKey(msg) => msg.serialise(&mut buf[2..]),
             Message::HitPointOther(msg) => msg.serialise(&mut buf[2..]),
             Message::HitPoint(msg) => msg.serialise(&mut buf[2..]),
+            Message::SkillCanceled(msg) => msg.serialise(&mut buf[2..]),
+            Message::LoadSkillCooltime(msg) => msg.serialise(&mut buf[2..]),
+            Message::ResetSkillCoolingTime(msg) => msg.serialise(&mut buf[2..]),
+            Message::Exp(msg) => msg.serialise(&mut buf[2..]),
+            Message::PlayEffect(msg) => msg.serialise(&mut buf[2..]),
+            Message::ResurrectInfo(msg) => msg.serialise(&mut buf[2..]),
+            Message::ResurrectLocInfo(msg) => msg.serialise(&mut buf[2..]),
+            Message::ManaPoint(msg) => msg.serialise(&mut buf[2..]),
+            Message::Dp(msg) => msg.serialise(&mut buf[2..]),
+            Message::AddInventory(msg) => msg.serialise(&mut buf[2..]),
+            Message::RemoveInventory(msg) => msg.serialise(&mut buf[2..]),
+            Message::Wield(msg) => msg.serialise(&mut buf[2..]),
+            Message::ChatMessage(msg) => msg.serialise(&mut buf[2..]),
+            Message::Ask(msg) => msg.serialise(&mut buf[2..]),
+            Message::PartyInfo(msg) => msg.serialise(&mut buf[2..]),
+            Message::PartyMemberInfo(msg) => msg.serialise(&mut buf[2..]),
+            Message::XchgStart(msg) => msg.serialise(&mut buf[2..]),
+            Message::AddXchg(msg) => msg.serialise(&mut buf[2..]),
+            Message::RemoveXchg(msg) => msg.serialise(&mut buf[2..]),
+            Message::XchgGold(msg) => msg.serialise(&mut buf[2..]),
+            Message::XchgResult(msg) => msg.serialise(&mut buf[2..]),
+            Message::ShopSellList(msg) => msg.serialise(&mut buf[2..]),
+            Message::Mail(msg) => msg.serialise(&mut buf[2..]),
         };
 
         len += LENGTH_LEN;
@@ -222,6 +313,15 @@ fn add_prelude(opcode: u16, buf: &mut [u8]) -> usize {
     len
 }
 
+/// Null terminated UTF-16
+fn add_utf16(value: &str, buf: &mut [u8]) -> usize {
+    let mut len = 0;
+    for c in value.encode_utf16().chain(Some(0)) {
+        to_le_bytes!(len, buf, c);
+    }
+    len
+}
+
 #[derive(Debug, Clone)]
 pub struct Key {
     key: u32,
@@ -496,80 +596,57 @@ impl Serialise for ReplyNpAuthToken {
     }
 }
 
+/// Size of the UTF-16 name field on the character select screen
+const CHARACTER_NAME_LEN: usize = 52;
+
+/// How a single character is described on the character select screen, used
+/// by [CharacterList] and [CreateCharacter]
+fn serialise_character_info(character: &Character, buf: &mut [u8]) -> usize {
+    let mut len = 0;
+
+    // TODO: Work out the meaning of the unknowns
+    let footer = [
+        0x04, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xC5, 0x01,
+    ];
+    let last_logged_in: u32 = 1722072570;
+    let world_id: u32 = 0x0D1D6430;
+
+    to_le_bytes!(len, buf, character.id());
+    character.serialise_name_utf16(&mut buf[len..]);
+    len += CHARACTER_NAME_LEN;
+    len += character.appearance.serialise(&mut buf[len..]);
+    to_le_bytes!(len, buf, 0x000186A3u32);
+    to_le_bytes!(len, buf, world_id);
+    len += character.location().serialise(&mut buf[len..]);
+    // Direction?
+    to_le_bytes!(len, buf, 0x11u32);
+    to_le_bytes!(len, buf, character.stats.level() as u32);
+    to_le_bytes!(len, buf, 0x34u32);
+    len += 88;
+    to_le_bytes!(len, buf, last_logged_in);
+    len += character.gear.serialiase_character_list(&mut buf[len..]);
+    // TODO: Verify, the client counts down from this when it is set
+    to_le_bytes!(len, buf, character.deletion_time().unwrap_or(0));
+    len += 4;
+    copy_bytes!(len, buf, footer);
+    len += 116;
+
+    len
+}
+
 #[derive(Debug, Clone)]
 pub struct CharacterList {
     unknown1: u8,
     auth_server_id: [u8; 4],
-    len: u8,
-    character_id: u32,
-    name: String,
-    header: [u8; 238],
-    gear: Gear,
-    last_logged_in: u32,
-    footer: [u8; 146],
+    characters: Vec<Character>,
 }
 impl CharacterList {
-    pub fn new(
-        auth_server_id: [u8; 4],
-        name: String,
-        gear: Gear,
-        last_logged_in: u32,
-        character_id: u32,
-    ) -> Self {
-        let header = [
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
-            0x00, 0x01, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x02, 0x00,
-            0x00, 0x00, 0xAB, 0xAB, 0xBC, 0x00, 0xFF, 0xFF, 0xFF, 0x00, 0xE9,
-            0x02, 0x0F, 0x00, 0xCE, 0xB4, 0xCA, 0x00, 0x00, 0x0A, 0x00, 0x00,
-            0x00, 0x02, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x09, 0x83, 0x8F, 0x00, 0x03, 0x00, 0x00, 0x00, 0x83, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x04, 0xEF, 0xB3, 0xDA, 0xB2,
-            0xF0, 0xDF, 0xEF, 0xBA, 0xF4, 0xCE, 0x00, 0x00, 0xF0, 0xF4, 0xF5,
-            0xE4, 0x00, 0x00, 0x00, 0x66, 0x66, 0x26, 0x3F, 0xA3, 0x86, 0x01,
-            0x00, 0x30, 0x64, 0x1D, 0x0D, // Coords
-            0x92, 0xDA, 0xEB, 0x44, 0xFC, 0x67, 0x2F, 0x44, 0x2D, 0x15, 0x81,
-            0x43, // Direction?
-            0x11, 0x00, 0x00, 0x00, 0x0E, 0x00, 0x00, 0x00, 0x34, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00,
-        ];
-
-        let footer = [
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00,
-            0x00, 0x04, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xC5, 0x01, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00,
-        ];
-
+    pub fn new(auth_server_id: [u8; 4], characters: Vec<Character>) -> Self {
         Self {
             unknown1: 2,
             auth_server_id,
-            len: 1,
-            character_id,
-            name,
-            header,
-            last_logged_in,
-            gear,
-            footer,
+            characters,
         }
     }
 }
@@ -585,27 +662,105 @@ impl Serialise for CharacterList {
             .copy_from_slice(&self.auth_server_id);
         len += self.auth_server_id.len();
 
-        buf[len] = self.len;
-        len += 1;
+        to_le_bytes!(len, buf, self.characters.len() as u8);
 
-        to_le_bytes!(len, buf, self.character_id);
+        for character in &self.characters {
+            len += serialise_character_info(character, &mut buf[len..]);
+        }
 
-        self.name.encode_utf16().for_each(|c| {
-            buf[len + 0] = c as u8;
-            buf[len + 1] = (c >> 8) as u8;
-            len += 2;
-        });
+        len
+    }
+}
 
-        buf[len..len + self.header.len()].copy_from_slice(&self.header);
-        len += self.header.len();
+#[derive(Debug, Clone, Copy)]
+#[repr(u32)]
+pub enum CreateCharacterResult {
+    Ok = 0,
+    // TODO: Verify these against the client strings
+    Failed = 1,
+    DatabaseError = 2,
+    ServerLimitExceeded = 4,
+    InvalidName = 5,
+    NameAlreadyUsed = 7,
+}
 
-        buf[len..len + 4].copy_from_slice(&self.last_logged_in.to_le_bytes());
-        len += 4;
+#[derive(Debug, Clone)]
+pub struct CreateCharacter {
+    result: CreateCharacterResult,
+    character: Option<Character>,
+}
+impl CreateCharacter {
+    pub fn ok(character: &Character) -> Self {
+        Self {
+            result: CreateCharacterResult::Ok,
+            character: Some(character.clone()),
+        }
+    }
+    pub fn error(result: CreateCharacterResult) -> Self {
+        Self {
+            result,
+            character: None,
+        }
+    }
+}
+impl Serialise for CreateCharacter {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(CREATE_CHARACTER, buf);
+
+        to_le_bytes!(len, buf, self.result as u32);
+        if let Some(character) = &self.character {
+            len += serialise_character_info(character, &mut buf[len..]);
+        }
+
+        len
+    }
+}
+
+/// Starts the deletion countdown on the character select screen
+#[derive(Debug, Clone)]
+pub struct DeleteCharacter {
+    character_id: u32,
+    deletion_time: u32,
+}
+impl DeleteCharacter {
+    pub fn new(character_id: u32, deletion_time: u32) -> Self {
+        Self {
+            character_id,
+            deletion_time,
+        }
+    }
+}
+impl Serialise for DeleteCharacter {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(DELETE_CHARACTER, buf);
+
+        to_le_bytes!(len, buf, 0u32);
+        to_le_bytes!(len, buf, self.character_id);
+        to_le_bytes!(len, buf, self.deletion_time);
+
+        len
+    }
+}
 
-        len += self.gear.serialiase_character_list(&mut buf[len..]);
+/// Cancels a pending deletion
+#[derive(Debug, Clone)]
+pub struct RestoreCharacter {
+    character_id: u32,
+}
+impl RestoreCharacter {
+    pub fn new(character_id: u32) -> Self {
+        Self { character_id }
+    }
+}
+impl Serialise for RestoreCharacter {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(RESTORE_CHARACTER, buf);
 
-        buf[len..len + self.footer.len()].copy_from_slice(&self.footer);
-        len += self.footer.len();
+        to_le_bytes!(len, buf, 0u32);
+        to_le_bytes!(len, buf, self.character_id);
 
         len
     }
@@ -1207,10 +1362,10 @@ impl Serialise for AddSkill {
 pub struct LoadInventory {
     /// 0x01 on first inventory message else 0x00
     first: bool,
-    items: Vec<RawItem>,
+    items: Vec<InventoryItem>,
 }
 impl LoadInventory {
-    pub fn start(items: Vec<RawItem>) -> Self {
+    pub fn start(items: Vec<InventoryItem>) -> Self {
         Self { items, first: true }
     }
     /// Must be called after the last LoadInventory message is sent
@@ -1239,6 +1394,79 @@ impl Serialise for LoadInventory {
     }
 }
 
+/// Items that just arrived in the inventory
+#[derive(Debug, Clone)]
+pub struct AddInventory {
+    items: Vec<InventoryItem>,
+}
+impl AddInventory {
+    pub fn new(items: Vec<InventoryItem>) -> Self {
+        Self { items }
+    }
+}
+impl Serialise for AddInventory {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(ADD_INVENTORY, buf);
+
+        // TODO: Verify this
+        to_le_bytes!(len, buf, 0x19_u16);
+        to_le_bytes!(len, buf, self.items.len() as u16);
+        for item in &self.items {
+            len += item.serialise(&mut buf[len..]);
+        }
+
+        len
+    }
+}
+
+/// An item left the inventory, used up, merged into another stack or given
+/// away
+#[derive(Debug, Clone)]
+pub struct RemoveInventory {
+    id: u32,
+}
+impl RemoveInventory {
+    pub fn new(id: u32) -> Self {
+        Self { id }
+    }
+}
+impl Serialise for RemoveInventory {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(REMOVE_INVENTORY, buf);
+
+        to_le_bytes!(len, buf, self.id);
+
+        len
+    }
+}
+
+/// Gear someone else can see changed, looks the same as the gear in
+/// [PutUser]
+// TODO: Verify this
+#[derive(Debug, Clone)]
+pub struct Wield {
+    character_id: u32,
+    gear: Gear,
+}
+impl Wield {
+    pub fn new(character_id: u32, gear: Gear) -> Self {
+        Self { character_id, gear }
+    }
+}
+impl Serialise for Wield {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(WIELD, buf);
+
+        to_le_bytes!(len, buf, self.character_id);
+        len += self.gear.serialiase_put_user(&mut buf[len..]);
+
+        len
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ChangeChannel {
     raw: Vec<u8>,
@@ -1388,11 +1616,6 @@ impl Serialise for Status {
 pub struct _0151 {
     raw: [u8; 6],
 }
-impl _0151 {
-    pub fn new() -> Self {
-        Self { raw: [0u8; 6] }
-    }
-}
 impl Serialise for _0151 {
     fn serialise(&self, buf: &mut [u8]) -> usize {
         let mut len = 0;
@@ -1452,19 +1675,16 @@ impl Serialise for GamepassInfo {
     }
 }
 
-/// Sends a message to the players chat box
-/// TODO: Implement params
+/// Sends a message to the players chat box, the client looks the text up by
+/// id and fills the params in
 #[derive(Debug, Clone)]
 pub struct MessageCode {
     message_id: u32,
-    params_len: u16,
+    params: Vec<String>,
 }
 impl MessageCode {
-    pub fn new(message_id: u32) -> Self {
-        Self {
-            message_id,
-            params_len: 0,
-        }
+    pub fn with_params(message_id: u32, params: Vec<String>) -> Self {
+        Self { message_id, params }
     }
 }
 impl Serialise for MessageCode {
@@ -1475,18 +1695,24 @@ impl Serialise for MessageCode {
         to_le_bytes!(len, buf, 0x19 as u8);
         copy_bytes!(len, buf, [0u8; 5]);
         to_le_bytes!(len, buf, self.message_id);
-        to_le_bytes!(len, buf, self.params_len);
+        to_le_bytes!(len, buf, self.params.len() as u16);
+        for param in &self.params {
+            len += add_utf16(param, &mut buf[len..]);
+        }
 
         len
     }
 }
+/// The character's own buffs and debuffs
 #[derive(Debug, Clone)]
 pub struct AbnormalStatus {
-    raw: [u8; 10],
+    /// Bits from [abnormal](crate::game::engine::effect::abnormal)
+    state: u32,
+    effects: Vec<Effect>,
 }
 impl AbnormalStatus {
-    pub fn new() -> Self {
-        Self { raw: [0u8; 10] }
+    pub fn new(state: u32, effects: Vec<Effect>) -> Self {
+        Self { state, effects }
     }
 }
 impl Serialise for AbnormalStatus {
@@ -1494,8 +1720,12 @@ impl Serialise for AbnormalStatus {
         let mut len = 0;
         len += add_prelude(ABNORMAL_STATUS, buf);
 
-        buf[len..len + self.raw.len()].copy_from_slice(&self.raw);
-        len += self.raw.len();
+        to_le_bytes!(len, buf, self.state);
+        to_le_bytes!(len, buf, 0u32);
+        to_le_bytes!(len, buf, self.effects.len() as u16);
+        for effect in &self.effects {
+            len += effect.serialise(&mut buf[len..])
+        }
 
         len
     }
@@ -1503,26 +1733,28 @@ impl Serialise for AbnormalStatus {
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy)]
-enum EntityType {
+pub enum EntityType {
     Npc = 1,
     Character = 2,
 }
 
+/// A single icon on the status bar
 #[derive(Debug, Clone)]
-struct Effect {
+pub struct Effect {
     skill_id: u16,
-    unknown1: u8,
-    unknown2: u8,
-    unknown3: u32,
+    level: u8,
+    slot: Slot,
+    /// Milliseconds
+    remaining: u32,
 }
 
 impl Effect {
-    pub fn new() -> Self {
+    pub fn new(skill_id: u16, level: u8, slot: Slot, remaining: u32) -> Self {
         Self {
-            skill_id: 555,
-            unknown1: 9,
-            unknown2: 1,
-            unknown3: 0x07D0,
+            skill_id,
+            level,
+            slot,
+            remaining,
         }
     }
 }
@@ -1531,13 +1763,14 @@ impl Serialise for Effect {
     fn serialise(&self, buf: &mut [u8]) -> usize {
         let mut len = 0;
         to_le_bytes!(len, buf, self.skill_id);
-        to_le_bytes!(len, buf, self.unknown1);
-        to_le_bytes!(len, buf, self.unknown2);
-        to_le_bytes!(len, buf, self.unknown3);
+        to_le_bytes!(len, buf, self.level);
+        to_le_bytes!(len, buf, self.slot as u8);
+        to_le_bytes!(len, buf, self.remaining);
         len
     }
 }
 
+/// Buffs and debuffs on something else
 #[derive(Debug, Clone)]
 pub struct AbnormalStatusOther {
     entity_id: u32,
@@ -1545,18 +1778,15 @@ pub struct AbnormalStatusOther {
     effects: Vec<Effect>,
 }
 impl AbnormalStatusOther {
-    pub fn new(entity_id: u32) -> Self {
-        Self {
-            entity_id,
-            entity_type: EntityType::Npc,
-            effects: vec![Effect::new()],
-        }
-    }
-    pub fn dead(entity_id: u32) -> Self {
+    pub fn new(
+        entity_id: u32,
+        entity_type: EntityType,
+        effects: Vec<Effect>,
+    ) -> Self {
         Self {
             entity_id,
-            entity_type: EntityType::Npc,
-            effects: vec![],
+            entity_type,
+            effects,
         }
     }
 }
@@ -1604,20 +1834,14 @@ impl Serialise for Time {
         len
     }
 }
+/// Counts down the time NPCs leave the character alone for
 #[derive(Debug, Clone)]
 pub struct InvincibleTime {
-    raw: [u8; 4],
+    remaining: Duration,
 }
 impl InvincibleTime {
-    pub fn start() -> Self {
-        Self {
-            raw: [0x60, 0xEA, 0x00, 0x00],
-        }
-    }
-    pub fn finish() -> Self {
-        Self {
-            raw: [0x00, 0x00, 0x00, 0x00],
-        }
+    pub fn new(remaining: Duration) -> Self {
+        Self { remaining }
     }
 }
 impl Serialise for InvincibleTime {
@@ -1625,162 +1849,608 @@ impl Serialise for InvincibleTime {
         let mut len = 0;
         len += add_prelude(INVINCIBLE_TIME, buf);
 
-        buf[len..len + self.raw.len()].copy_from_slice(&self.raw);
-        len += self.raw.len();
+        to_le_bytes!(len, buf, self.remaining.as_millis() as u32);
 
         len
     }
 }
+/// Unloads a character or NPC from the client
 #[derive(Debug, Clone)]
-pub struct InvisibleLevel {
-    character_id: u32,
-    raw: [u8; 3],
+pub struct RemoveObject {
+    object_id: u32,
 }
-impl InvisibleLevel {
-    pub fn start(character_id: u32) -> Self {
-        Self {
-            raw: [0x40, 0x00, 0x01],
-            character_id,
-        }
-    }
-    pub fn finish(character_id: u32) -> Self {
-        Self {
-            raw: [0x00, 0x00, 0x00],
-            character_id,
-        }
+impl RemoveObject {
+    pub fn new(object_id: u32) -> Self {
+        Self { object_id }
     }
 }
-impl Serialise for InvisibleLevel {
+impl Serialise for RemoveObject {
     fn serialise(&self, buf: &mut [u8]) -> usize {
         let mut len = 0;
-        len += add_prelude(INVISIBLE_LEVEL, buf);
+        len += add_prelude(REMOVE_OBJECT, buf);
 
-        to_le_bytes!(len, buf, self.character_id);
-        buf[len..len + self.raw.len()].copy_from_slice(&self.raw);
-        len += self.raw.len();
+        to_le_bytes!(len, buf, self.object_id);
+        // TODO: Verify, looks like a despawn animation
+        to_le_bytes!(len, buf, 0u8);
 
         len
     }
 }
+
+/// A chat message from a player
+// TODO: Verify this, the layout is from older versions of the game
 #[derive(Debug, Clone)]
-pub struct WorldSceneStatus {
-    raw: [u8; 9],
-}
-impl WorldSceneStatus {
-    pub fn new() -> Self {
+pub struct ChatMessage {
+    ty: ChatType,
+    race: Race,
+    sender_id: u32,
+    sender_name: String,
+    message: String,
+}
+impl ChatMessage {
+    pub fn new(sender: &Character, ty: ChatType, message: String) -> Self {
         Self {
-            raw: [0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+            ty,
+            race: sender.appearance.race,
+            sender_id: sender.id(),
+            sender_name: sender.name().clone(),
+            message,
         }
     }
 }
-impl Serialise for WorldSceneStatus {
+impl Serialise for ChatMessage {
     fn serialise(&self, buf: &mut [u8]) -> usize {
         let mut len = 0;
-        len += add_prelude(WORLD_SCENE_STATUS, buf);
+        len += add_prelude(MESSAGE, buf);
 
-        buf[len..len + self.raw.len()].copy_from_slice(&self.raw);
-        len += self.raw.len();
+        to_le_bytes!(len, buf, u8::from(self.ty));
+        to_le_bytes!(len, buf, self.race as u8);
+        to_le_bytes!(len, buf, self.sender_id);
+        if self.ty == ChatType::Normal {
+            to_le_bytes!(len, buf, 0_u16);
+        }
+        len += add_utf16(&self.sender_name, &mut buf[len..]);
+        len += add_utf16(&self.message, &mut buf[len..]);
 
         len
     }
 }
+
+/// A yes or no question, the answer comes back in a C_ANSWER
+// TODO: Verify this, the layout is from older versions of the game
 #[derive(Debug, Clone)]
-pub struct ResultPassport {
-    unknown1: [u8; 8],
-    character_id: u32,
-    unknown2: [u8; 12],
+pub struct Ask {
+    question_id: u32,
+    sender_id: u32,
+    params: Vec<String>,
 }
-impl ResultPassport {
-    pub fn new(character_id: u32) -> Self {
+impl Ask {
+    pub fn new(question_id: u32, sender_id: u32, params: Vec<String>) -> Self {
         Self {
-            unknown1: [0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
-            character_id,
-            unknown2: [
-                0x15, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00,
-            ],
+            question_id,
+            sender_id,
+            params,
         }
     }
 }
-impl Serialise for ResultPassport {
+impl Serialise for Ask {
     fn serialise(&self, buf: &mut [u8]) -> usize {
         let mut len = 0;
-        len += add_prelude(RESULT_PASSPORT, buf);
-
-        buf[len..len + self.unknown1.len()].copy_from_slice(&self.unknown1);
-        len += self.unknown1.len();
+        len += add_prelude(ASK, buf);
 
-        to_le_bytes!(len, buf, self.character_id);
-
-        buf[len..len + self.unknown2.len()].copy_from_slice(&self.unknown2);
-        len += self.unknown2.len();
+        to_le_bytes!(len, buf, self.question_id);
+        to_le_bytes!(len, buf, self.sender_id);
+        to_le_bytes!(len, buf, self.params.len() as u16);
+        for param in &self.params {
+            len += add_utf16(param, &mut buf[len..]);
+        }
 
         len
     }
 }
+
+/// The party window, who leads and how loot is shared
+// TODO: Verify this, the layout is from older versions of the game
 #[derive(Debug, Clone)]
-pub struct Weather {
-    raw: [u8; 4],
+pub struct PartyInfo {
+    party_id: u32,
+    leader_id: u32,
+    loot_rule: LootRule,
 }
-impl Weather {
-    pub fn new() -> Self {
+impl PartyInfo {
+    pub fn new(party: &Party) -> Self {
         Self {
-            raw: [0x00, 0x02, 0x00, 0x00],
+            party_id: party.id(),
+            leader_id: party.leader(),
+            loot_rule: party.loot_rule(),
         }
     }
 }
-impl Serialise for Weather {
+impl Serialise for PartyInfo {
     fn serialise(&self, buf: &mut [u8]) -> usize {
         let mut len = 0;
-        len += add_prelude(WEATHER, buf);
+        len += add_prelude(PARTY_INFO, buf);
 
-        buf[len..len + self.raw.len()].copy_from_slice(&self.raw);
-        len += self.raw.len();
+        to_le_bytes!(len, buf, self.party_id);
+        to_le_bytes!(len, buf, self.leader_id);
+        to_le_bytes!(len, buf, self.loot_rule as u32);
+        // Automatic distribution and the item quality thresholds for rolling
+        copy_bytes!(len, buf, [0u8; 28]);
 
         len
     }
 }
-#[derive(Debug, Clone)]
-pub struct SignClient {
-    raw: [u8; 68],
+
+/// What happened to a party member
+// TODO: Verify these
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum PartyEvent {
+    Leave = 0,
+    /// HP, MP or location changed
+    Update = 1,
+    Kicked = 2,
+    Disband = 3,
+    Join = 5,
 }
-impl SignClient {
-    pub fn new() -> Self {
+
+/// A member's portrait in the party window
+// TODO: Verify this, the layout is from older versions of the game
+#[derive(Debug, Clone)]
+pub struct PartyMemberInfo {
+    party_id: u32,
+    character_id: u32,
+    name: String,
+    hp: (i32, i32),
+    mp: (u32, u32),
+    flight_time: (u32, u32),
+    location: Coord,
+    class: u8,
+    gender: u8,
+    level: u8,
+    event: PartyEvent,
+}
+impl PartyMemberInfo {
+    pub fn new(party_id: u32, member: &Character, event: PartyEvent) -> Self {
+        let stats = &member.stats;
         Self {
-            raw: [
-                0x40, 0x00, 0x00, 0x00, 0x2A, 0xCA, 0x3A, 0x34, 0x7C, 0xA1,
-                0xA1, 0xAE, 0x02, 0x67, 0xFE, 0x23, 0x4A, 0x29, 0xB4, 0x32,
-                0xCF, 0xF8, 0x4C, 0x44, 0x4D, 0x61, 0x9C, 0xAD, 0x57, 0x31,
-                0xC6, 0xA9, 0x78, 0x75, 0x16, 0x55, 0x1B, 0xB5, 0x83, 0x58,
-                0x43, 0x26, 0x42, 0x22, 0xA0, 0x48, 0xF3, 0xEF, 0x76, 0xB5,
-                0x9D, 0x69, 0x9B, 0x93, 0x6C, 0xC3, 0xD8, 0x46, 0xA1, 0xC2,
-                0xB8, 0xA6, 0x72, 0x32, 0x6C, 0xE6, 0xBD, 0x92,
-            ],
+            party_id,
+            character_id: member.id(),
+            name: member.name().clone(),
+            hp: (stats.hp.max(), stats.hp.current()),
+            mp: (stats.mp.max(), stats.mp.current()),
+            flight_time: (stats.flight_time_max, stats.flight_time_current),
+            location: *member.location(),
+            class: member.appearance.class as u8,
+            gender: member.appearance.gender as u8,
+            level: stats.level() as u8,
+            event,
         }
     }
 }
-impl Serialise for SignClient {
+impl Serialise for PartyMemberInfo {
     fn serialise(&self, buf: &mut [u8]) -> usize {
         let mut len = 0;
-        len += add_prelude(SIGN_CLIENT, buf);
+        len += add_prelude(PARTY_MEMBER_INFO, buf);
 
-        buf[len..len + self.raw.len()].copy_from_slice(&self.raw);
-        len += self.raw.len();
+        to_le_bytes!(len, buf, self.party_id);
+        to_le_bytes!(len, buf, self.character_id);
+        to_le_bytes!(len, buf, self.hp.0);
+        to_le_bytes!(len, buf, self.hp.1);
+        to_le_bytes!(len, buf, self.mp.0);
+        to_le_bytes!(len, buf, self.mp.1);
+        to_le_bytes!(len, buf, self.flight_time.0);
+        to_le_bytes!(len, buf, self.flight_time.1);
+        to_le_bytes!(len, buf, WORLD_ID);
+        to_le_bytes!(len, buf, WORLD_ID);
+        len += self.location.serialise(&mut buf[len..]);
+        to_le_bytes!(len, buf, self.class);
+        to_le_bytes!(len, buf, self.gender);
+        to_le_bytes!(len, buf, self.level);
+        to_le_bytes!(len, buf, self.event as u8);
+        to_le_bytes!(len, buf, 0_u16);
+        len += add_utf16(&self.name, &mut buf[len..]);
 
         len
     }
 }
+
+/// Opens the trade window on both sides
 #[derive(Debug, Clone)]
-pub struct Title {
+pub struct XchgStart {
+    partner_name: String,
+}
+impl XchgStart {
+    pub fn new(partner_name: String) -> Self {
+        Self { partner_name }
+    }
+}
+impl Serialise for XchgStart {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(XCHG_START, buf);
+
+        len += add_utf16(&self.partner_name, &mut buf[len..]);
+
+        len
+    }
+}
+
+/// Which half of the trade window an offer goes in
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum XchgSide {
+    Own = 0,
+    Partner = 1,
+}
+
+/// An item put up for trade
+// TODO: Verify this, the layout is from older versions of the game
+#[derive(Debug, Clone)]
+pub struct AddXchg {
+    side: XchgSide,
+    item: InventoryItem,
+}
+impl AddXchg {
+    pub fn new(side: XchgSide, item: InventoryItem) -> Self {
+        Self { side, item }
+    }
+}
+impl Serialise for AddXchg {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(ADD_XCHG, buf);
+
+        to_le_bytes!(len, buf, self.side as u8);
+        len += self.item.serialise(&mut buf[len..]);
+
+        len
+    }
+}
+
+/// An item taken back off the trade window
+// TODO: Verify this
+#[derive(Debug, Clone)]
+pub struct RemoveXchg {
+    side: XchgSide,
+    item_id: u32,
+}
+impl RemoveXchg {
+    pub fn new(side: XchgSide, item_id: u32) -> Self {
+        Self { side, item_id }
+    }
+}
+impl Serialise for RemoveXchg {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(REMOVE_XCHG, buf);
+
+        to_le_bytes!(len, buf, self.side as u8);
+        to_le_bytes!(len, buf, self.item_id);
+
+        len
+    }
+}
+
+/// All the kinah put up for trade so far
+#[derive(Debug, Clone)]
+pub struct XchgGold {
+    side: XchgSide,
+    kinah: u32,
+}
+impl XchgGold {
+    pub fn new(side: XchgSide, kinah: u32) -> Self {
+        Self { side, kinah }
+    }
+}
+impl Serialise for XchgGold {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(XCHG_GOLD, buf);
+
+        to_le_bytes!(len, buf, self.side as u8);
+        to_le_bytes!(len, buf, self.kinah);
+
+        len
+    }
+}
+
+// TODO: Verify these
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u32)]
+pub enum XchgStatus {
+    /// The partner locked their offer in
+    Locked = 0,
+    Cancelled = 1,
+    Completed = 2,
+    /// The partner agreed to the trade
+    Accepted = 3,
+}
+
+/// How the trade is going, the window closes once it is over
+#[derive(Debug, Clone)]
+pub struct XchgResult {
+    status: XchgStatus,
+}
+impl XchgResult {
+    pub fn new(status: XchgStatus) -> Self {
+        Self { status }
+    }
+}
+impl Serialise for XchgResult {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(XCHG_RESULT, buf);
+
+        to_le_bytes!(len, buf, self.status as u32);
+
+        len
+    }
+}
+
+/// What a merchant has for sale, opens the shop window
+// TODO: Verify this, the rate is a guess
+#[derive(Debug, Clone)]
+pub struct ShopSellList {
+    npc_id: u32,
+    /// Item ids
+    goods: Vec<u32>,
+}
+impl ShopSellList {
+    pub fn new(npc_id: u32, goods: Vec<u32>) -> Self {
+        Self { npc_id, goods }
+    }
+}
+impl Serialise for ShopSellList {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(SHOP_SELL_LIST, buf);
+
+        to_le_bytes!(len, buf, self.npc_id);
+        to_le_bytes!(len, buf, 1_u8);
+        // Percent of the template price charged
+        to_le_bytes!(len, buf, 100_u32);
+        to_le_bytes!(len, buf, self.goods.len() as u16);
+        for item_id in &self.goods {
+            to_le_bytes!(len, buf, *item_id);
+        }
+
+        len
+    }
+}
+
+// TODO: Verify these
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u32)]
+pub enum MailResult {
+    Sent = 0,
+    MailboxFull = 1,
+    NoRecipient = 3,
+    Failed = 4,
+}
+
+#[derive(Debug, Clone)]
+enum MailService {
+    Unread(u16),
+    Result(MailResult),
+    List(u32, Vec<Letter>),
+    Read(Box<Letter>),
+    Taken(u32, Attachment),
+    Deleted(u32),
+}
+
+/// Everything the mailbox shows, each kind of update has its own sub type
+// TODO: Verify this, the sub types come from older versions of the game
+#[derive(Debug, Clone)]
+pub struct Mail {
+    service: MailService,
+}
+impl Mail {
+    /// The envelope icon, shown on entering the world and as letters arrive
+    pub fn unread(count: u16) -> Self {
+        Self {
+            service: MailService::Unread(count),
+        }
+    }
+
+    /// How sending a letter went
+    pub fn result(result: MailResult) -> Self {
+        Self {
+            service: MailService::Result(result),
+        }
+    }
+
+    pub fn list(character_id: u32, letters: Vec<Letter>) -> Self {
+        Self {
+            service: MailService::List(character_id, letters),
+        }
+    }
+
+    pub fn read(letter: Letter) -> Self {
+        Self {
+            service: MailService::Read(Box::new(letter)),
+        }
+    }
+
+    pub fn taken(letter_id: u32, attachment: Attachment) -> Self {
+        Self {
+            service: MailService::Taken(letter_id, attachment),
+        }
+    }
+
+    pub fn deleted(letter_id: u32) -> Self {
+        Self {
+            service: MailService::Deleted(letter_id),
+        }
+    }
+}
+impl Serialise for Mail {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(MAIL, buf);
+
+        match &self.service {
+            MailService::Unread(count) => {
+                to_le_bytes!(len, buf, 0_u8);
+                to_le_bytes!(len, buf, (*count > 0) as u8);
+                to_le_bytes!(len, buf, *count);
+            }
+            MailService::Result(result) => {
+                to_le_bytes!(len, buf, 1_u8);
+                to_le_bytes!(len, buf, *result as u32);
+            }
+            MailService::List(character_id, letters) => {
+                to_le_bytes!(len, buf, 2_u8);
+                to_le_bytes!(len, buf, *character_id);
+                to_le_bytes!(len, buf, 0_u8);
+                to_le_bytes!(len, buf, letters.len() as u16);
+                for letter in letters {
+                    to_le_bytes!(len, buf, letter.id);
+                    len += add_utf16(&letter.sender_name, &mut buf[len..]);
+                    len += add_utf16(&letter.title, &mut buf[len..]);
+                    to_le_bytes!(len, buf, !letter.unread as u8);
+                    to_le_bytes!(len, buf, letter.item.is_some() as u8);
+                    to_le_bytes!(len, buf, letter.kinah);
+                    to_le_bytes!(len, buf, letter.returned as u8);
+                }
+            }
+            MailService::Read(letter) => {
+                to_le_bytes!(len, buf, 3_u8);
+                to_le_bytes!(len, buf, letter.recipient_id);
+                to_le_bytes!(len, buf, letter.id);
+                len += add_utf16(&letter.sender_name, &mut buf[len..]);
+                len += add_utf16(&letter.title, &mut buf[len..]);
+                len += add_utf16(&letter.message, &mut buf[len..]);
+                match &letter.item {
+                    Some(item) => {
+                        to_le_bytes!(len, buf, 1_u8);
+                        len += item.serialise(&mut buf[len..]);
+                    }
+                    None => {
+                        to_le_bytes!(len, buf, 0_u8);
+                    }
+                }
+                to_le_bytes!(len, buf, letter.kinah);
+                to_le_bytes!(len, buf, letter.returned as u8);
+            }
+            MailService::Taken(letter_id, attachment) => {
+                to_le_bytes!(len, buf, 5_u8);
+                to_le_bytes!(len, buf, *letter_id);
+                to_le_bytes!(len, buf, u8::from(*attachment));
+            }
+            MailService::Deleted(letter_id) => {
+                to_le_bytes!(len, buf, 6_u8);
+                to_le_bytes!(len, buf, *letter_id);
+            }
+        }
+
+        len
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InvisibleLevel {
+    character_id: u32,
     raw: [u8; 3],
 }
-impl Title {
+impl InvisibleLevel {
+    pub fn finish(character_id: u32) -> Self {
+        Self {
+            raw: [0x00, 0x00, 0x00],
+            character_id,
+        }
+    }
+}
+impl Serialise for InvisibleLevel {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(INVISIBLE_LEVEL, buf);
+
+        to_le_bytes!(len, buf, self.character_id);
+        buf[len..len + self.raw.len()].copy_from_slice(&self.raw);
+        len += self.raw.len();
+
+        len
+    }
+}
+#[derive(Debug, Clone)]
+pub struct WorldSceneStatus {
+    raw: [u8; 9],
+}
+impl Serialise for WorldSceneStatus {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(WORLD_SCENE_STATUS, buf);
+
+        buf[len..len + self.raw.len()].copy_from_slice(&self.raw);
+        len += self.raw.len();
+
+        len
+    }
+}
+#[derive(Debug, Clone)]
+pub struct ResultPassport {
+    unknown1: [u8; 8],
+    character_id: u32,
+    unknown2: [u8; 12],
+}
+impl Serialise for ResultPassport {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(RESULT_PASSPORT, buf);
+
+        buf[len..len + self.unknown1.len()].copy_from_slice(&self.unknown1);
+        len += self.unknown1.len();
+
+        to_le_bytes!(len, buf, self.character_id);
+
+        buf[len..len + self.unknown2.len()].copy_from_slice(&self.unknown2);
+        len += self.unknown2.len();
+
+        len
+    }
+}
+#[derive(Debug, Clone)]
+pub struct Weather {
+    raw: [u8; 4],
+}
+impl Weather {
     pub fn new() -> Self {
         Self {
-            raw: [0x01, 0x34, 0x00],
+            raw: [0x00, 0x02, 0x00, 0x00],
         }
     }
 }
+impl Serialise for Weather {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(WEATHER, buf);
+
+        buf[len..len + self.raw.len()].copy_from_slice(&self.raw);
+        len += self.raw.len();
+
+        len
+    }
+}
+#[derive(Debug, Clone)]
+pub struct SignClient {
+    raw: [u8; 68],
+}
+impl Serialise for SignClient {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(SIGN_CLIENT, buf);
+
+        buf[len..len + self.raw.len()].copy_from_slice(&self.raw);
+        len += self.raw.len();
+
+        len
+    }
+}
+#[derive(Debug, Clone)]
+pub struct Title {
+    raw: [u8; 3],
+}
 impl Serialise for Title {
     fn serialise(&self, buf: &mut [u8]) -> usize {
         let mut len = 0;
@@ -1816,13 +2486,6 @@ impl Serialise for LoadItemCooltime {
 pub struct CustomAnim {
     raw: [u8; 3],
 }
-impl CustomAnim {
-    pub fn new() -> Self {
-        Self {
-            raw: [0x01, 0x00, 0x00],
-        }
-    }
-}
 impl Serialise for CustomAnim {
     fn serialise(&self, buf: &mut [u8]) -> usize {
         let mut len = 0;
@@ -1841,13 +2504,13 @@ pub struct PutNpc {
     location: Coord,
     /// Unique ID of specific npc
     entity_id: u32,
-    status_bar_id: npc::Id,
-    appearence_id: npc::Id,
+    status_bar_id: u32,
+    appearence_id: u32,
     ty: npc::Type,
     state: npc::State,
     rotation: u8,
     /// Name is an Id that client references to find string of name
-    name: npc::Name,
+    name: u32,
     current_hp_pct: u8,
     current_hp: i32,
     level: u16,
@@ -1859,12 +2522,12 @@ impl PutNpc {
         Self {
             location: *entity.location(),
             entity_id: entity.id(),
-            status_bar_id: entity.npc_id(),
-            appearence_id: entity.npc_id(),
-            ty: npc::Type::Aggressive,
+            status_bar_id: entity.template().id,
+            appearence_id: entity.template().id,
+            ty: entity.template().ty,
             state: npc::State::Alive,
-            rotation: 60,
-            name: entity.npc_name(),
+            rotation: entity.heading(),
+            name: entity.template().name_id,
             current_hp_pct: entity.hp.percent(),
             current_hp: entity.hp.current(),
             level: entity.level(),
@@ -1879,12 +2542,12 @@ impl Serialise for PutNpc {
 
         len += self.location.serialise(&mut buf[len..]);
         to_le_bytes!(len, buf, self.entity_id);
-        to_le_bytes!(len, buf, self.status_bar_id as u32);
-        to_le_bytes!(len, buf, self.appearence_id as u32);
+        to_le_bytes!(len, buf, self.status_bar_id);
+        to_le_bytes!(len, buf, self.appearence_id);
         to_le_bytes!(len, buf, self.ty as u8);
         to_le_bytes!(len, buf, self.state as u16);
         to_le_bytes!(len, buf, self.rotation);
-        to_le_bytes!(len, buf, self.name as u32);
+        to_le_bytes!(len, buf, self.name);
         copy_bytes!(len, buf, [0u8; 17]);
         to_le_bytes!(len, buf, self.current_hp_pct);
         to_le_bytes!(len, buf, self.current_hp);
@@ -2066,11 +2729,12 @@ impl Serialise for Loot {
 #[derive(Debug, Clone)]
 pub struct LootItemlist {
     entity_id: u32,
-    loot: Vec<LootItem>,
+    /// Each item with its index on the corpse
+    loot: Vec<(u32, LootItem)>,
 }
 
 impl LootItemlist {
-    pub fn new(entity_id: u32, loot: Vec<LootItem>) -> Self {
+    pub fn new(entity_id: u32, loot: Vec<(u32, LootItem)>) -> Self {
         Self { entity_id, loot }
     }
 }
@@ -2082,8 +2746,8 @@ impl Serialise for LootItemlist {
 
         to_le_bytes!(len, buf, self.entity_id);
         to_le_bytes!(len, buf, self.loot.len() as u16);
-        for (i, loot) in self.loot.iter().enumerate() {
-            to_le_bytes!(len, buf, i as u32);
+        for (index, loot) in &self.loot {
+            to_le_bytes!(len, buf, *index);
             len += loot.serialise(&mut buf[len..]);
             // TODO: Untradable?
             to_le_bytes!(len, buf, 0u16);
@@ -2093,11 +2757,13 @@ impl Serialise for LootItemlist {
 }
 
 #[derive(Debug, Clone)]
-pub struct ChangeItemDesc {}
+pub struct ChangeItemDesc {
+    item: InventoryItem,
+}
 
 impl ChangeItemDesc {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(item: InventoryItem) -> Self {
+        Self { item }
     }
 }
 
@@ -2106,18 +2772,9 @@ impl Serialise for ChangeItemDesc {
         let mut len = 0;
         len += add_prelude(CHANGE_ITEM_DESC, buf);
 
-        copy_bytes!(
-            len,
-            buf,
-            [
-                0xC5, 0xA3, 0x6B, 0xC7, 0x24, 0x00, //foo
-                0x83, 0x80, 0x17, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x3E,
-                0x63, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                0x19, 0x00, 0x00, 0x00, 0x00
-            ]
-        );
+        to_le_bytes!(len, buf, self.item.id);
+        len += self.item.serialise_info(&mut buf[len..]);
+        to_le_bytes!(len, buf, 0_u16);
 
         len
     }
@@ -2299,6 +2956,85 @@ impl Serialise for SkillSucceded {
     }
 }
 
+/// Stops a cast bar, either for a skill the server refused or one that was
+/// interrupted
+// TODO: Verify the layout
+#[derive(Debug, Clone)]
+pub struct SkillCanceled {
+    entity_id: u32,
+    skill_id: u16,
+}
+impl SkillCanceled {
+    pub fn new(entity_id: u32, skill_id: u16) -> Self {
+        Self {
+            entity_id,
+            skill_id,
+        }
+    }
+}
+impl Serialise for SkillCanceled {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(SKILL_CANCELED, buf);
+
+        to_le_bytes!(len, buf, self.entity_id);
+        to_le_bytes!(len, buf, self.skill_id);
+
+        len
+    }
+}
+
+/// Skills still cooling down when the character enters the world
+#[derive(Debug, Clone)]
+pub struct LoadSkillCooltime {
+    /// Skill id and deciseconds left
+    cooldowns: Vec<(u16, u32)>,
+}
+impl LoadSkillCooltime {
+    pub fn new(cooldowns: Vec<(u16, u32)>) -> Self {
+        Self { cooldowns }
+    }
+}
+impl Serialise for LoadSkillCooltime {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(LOAD_SKILL_COOLTIME, buf);
+
+        to_le_bytes!(len, buf, self.cooldowns.len() as u16);
+        for (skill_id, remaining) in &self.cooldowns {
+            to_le_bytes!(len, buf, *skill_id);
+            to_le_bytes!(len, buf, *remaining);
+        }
+
+        len
+    }
+}
+
+/// Makes skills usable again before their cooldown is up
+#[derive(Debug, Clone)]
+pub struct ResetSkillCoolingTime {
+    skill_ids: Vec<u16>,
+}
+impl ResetSkillCoolingTime {
+    #[allow(dead_code)]
+    pub fn new(skill_ids: Vec<u16>) -> Self {
+        Self { skill_ids }
+    }
+}
+impl Serialise for ResetSkillCoolingTime {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(RESET_SKILL_COOLING_TIME, buf);
+
+        to_le_bytes!(len, buf, self.skill_ids.len() as u16);
+        for skill_id in &self.skill_ids {
+            to_le_bytes!(len, buf, *skill_id);
+        }
+
+        len
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct HitPoint {
     hp_current: i32,
@@ -2321,6 +3057,28 @@ impl Serialise for HitPoint {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct ManaPoint {
+    mp_current: u32,
+    mp_max: u32,
+}
+impl ManaPoint {
+    pub fn new(mp_current: u32, mp_max: u32) -> Self {
+        Self { mp_current, mp_max }
+    }
+}
+impl Serialise for ManaPoint {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(MANA_POINT, buf);
+
+        to_le_bytes!(len, buf, self.mp_current);
+        to_le_bytes!(len, buf, self.mp_max);
+
+        len
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct HitPointOther {
     entity_id: u32,
@@ -2350,6 +3108,128 @@ impl Serialise for HitPointOther {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct Dp {
+    dp_current: u16,
+}
+impl Dp {
+    pub fn new(dp_current: u16) -> Self {
+        Self { dp_current }
+    }
+}
+impl Serialise for Dp {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(DP, buf);
+
+        to_le_bytes!(len, buf, self.dp_current);
+
+        len
+    }
+}
+
+/// Experience bar after a gain
+#[derive(Debug, Clone)]
+pub struct Exp {
+    exp: u64,
+    exp_to_level: u64,
+}
+impl Exp {
+    pub fn new(exp: u64, exp_to_level: u64) -> Self {
+        Self { exp, exp_to_level }
+    }
+}
+impl Serialise for Exp {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(EXP, buf);
+
+        to_le_bytes!(len, buf, self.exp);
+        // Recoverable experience lost on death
+        to_le_bytes!(len, buf, 0_u64);
+        to_le_bytes!(len, buf, self.exp_to_level);
+
+        len
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(u16)]
+pub enum EffectType {
+    LevelUp = 0,
+}
+
+/// Plays an animation on an object, everyone nearby sees it
+#[derive(Debug, Clone)]
+pub struct PlayEffect {
+    object_id: u32,
+    effect: EffectType,
+    level: u16,
+}
+impl PlayEffect {
+    pub fn level_up(object_id: u32, level: u16) -> Self {
+        Self {
+            object_id,
+            effect: EffectType::LevelUp,
+            level,
+        }
+    }
+}
+impl Serialise for PlayEffect {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(EFFECT, buf);
+
+        to_le_bytes!(len, buf, self.object_id);
+        to_le_bytes!(len, buf, self.effect as u16);
+        to_le_bytes!(len, buf, self.level);
+
+        len
+    }
+}
+
+/// Opens the death dialog, only resurrecting at the bind point is offered
+#[derive(Debug, Clone)]
+pub struct ResurrectInfo;
+impl Serialise for ResurrectInfo {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(RESURRECT_INFO, buf);
+
+        // Resurrect with a skill, with an item, then time left on a kisk
+        to_le_bytes!(len, buf, 0_u8);
+        to_le_bytes!(len, buf, 0_u8);
+        to_le_bytes!(len, buf, 0_u32);
+        to_le_bytes!(len, buf, 0_u8);
+
+        len
+    }
+}
+
+/// Where the character will resurrect, shown on the map
+// TODO: Verify this
+#[derive(Debug, Clone)]
+pub struct ResurrectLocInfo {
+    world_id: u32,
+    location: Coord,
+}
+impl ResurrectLocInfo {
+    pub fn new(world_id: u32, location: Coord) -> Self {
+        Self { world_id, location }
+    }
+}
+impl Serialise for ResurrectLocInfo {
+    fn serialise(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        len += add_prelude(RESURRECT_LOC_INFO, buf);
+
+        to_le_bytes!(len, buf, self.world_id);
+        len += self.location.serialise(&mut buf[len..]);
+
+        len
+    }
+}
+
 /// TODO: Create real authentication
 #[derive(Debug, Clone)]
 pub struct ReconnectKey {
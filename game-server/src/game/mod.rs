@@ -1,8 +1,9 @@
 use std::{
     collections::HashMap,
-    io::{Read, Write},
-    net::TcpStream,
+    io::{self, Read},
+    net::SocketAddr,
     sync::{Arc, Mutex},
+    thread,
     time::{Duration, Instant},
 };
 
@@ -10,25 +11,74 @@ mod character;
 mod data;
 mod engine;
 mod entity;
+mod id;
 mod message;
+mod repository;
 mod session;
+mod spawn;
 mod state;
+mod world;
 
-use crossbeam_channel::{Receiver, Sender};
+use crossbeam_channel::{Receiver, Sender, TryRecvError, TrySendError};
 use krypt::game::{gen_xor_key, gen_xor_seed};
-use message::{client as c, server as s, ClientMessages};
+use message::{client as c, server as s, ClientMessages, SendBuffer};
+use mio::{net::TcpStream, Waker};
 use session::Account;
 
+pub use data::drop::Tables as DropTables;
+pub use data::goods::Lists as GoodsLists;
+pub use data::item::Templates as ItemTemplates;
+pub use data::npc::Templates as NpcTemplates;
+pub use data::skill::Templates as SkillTemplates;
+pub use data::GameData;
+pub use id::ObjectKind;
+pub use repository::{CharacterRepository, SqliteRepository};
+pub use spawn::Spawner;
+
+use crate::error::{Error, Result};
+
 use self::state::State;
 
 const TICK_RATE: f32 = 144.;
 
+/// Everyone is in the same map for now
+pub const WORLD_ID: u32 = 220030000;
+
+/// How far the game loop can fall behind before it gives up on catching up
+/// and skips the missed ticks
+const MAX_TICKS_BEHIND: u32 = 10;
+
+/// Bytes read from the socket at a time
+const READ_CHUNK: usize = 1024 * 8;
+
+/// Unhandled bytes a client can have buffered, a few of the biggest messages.
+/// Reading stops as soon as a whole message is in so only a client that lies
+/// about its message lengths gets near this
+const MAX_READ_BUF: usize = 4 * message::MAX_MESSAGE_LEN;
+
+/// A client that has sent nothing for this long is assumed dead, an idle
+/// client still sends Alive and SyncTime so this is never hit normally
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Messages a client can send each second, a client that sends more is
+/// disconnected rather than having some of its messages lost
+const MAX_MESSAGES_PER_SECOND: usize = 100;
+
+/// Messages with opcodes we don't know yet are only logged this often, a
+/// client can send as many of them as it likes
+const UNKNOWN_OPCODE_WARNING: Duration = Duration::from_secs(10);
+
+/// Once this much is waiting on a slow socket we stop taking updates off the
+/// outbound queue, when that fills up too the client gets disconnected
+const MAX_PENDING_WRITE: usize = 1024 * 1024;
+
 trait Serialise {
     fn serialise(&self, buf: &mut [u8]) -> usize;
 }
 
+/// A buffer too short for the message is an [Error::PacketLength]
 trait Deserialise {
-    fn deserialise(buf: &[u8]) -> Self
+    fn deserialise(buf: &[u8]) -> Result<Self>
     where
         Self: Sized;
 }
@@ -67,7 +117,9 @@ macro_rules! to_le_bytes {
     };
 }
 
-/// Deserialise any value that implements .from_le_bytes() onto the given buffer
+/// Deserialise any value that implements .from_le_bytes() onto the given
+/// buffer, returning an [Error::PacketLength] from the enclosing function when
+/// the buffer runs out
 /// ```
 /// let mut len = 0;
 /// let mut buf = [0u8; 100];
@@ -78,30 +130,55 @@ macro_rules! to_le_bytes {
 macro_rules! consume_le_bytes {
     ($ptr:expr, $buf:expr, $ty:ty) => {{
         let size = core::mem::size_of::<$ty>();
-        let ret =
-            <$ty>::from_le_bytes($buf[$ptr..$ptr + size].try_into().unwrap());
+        let bytes = $buf
+            .get($ptr..$ptr + size)
+            .ok_or($crate::error::Error::PacketLength($buf.len()))?;
         $ptr += size;
-        ret
+        <$ty>::from_le_bytes(bytes.try_into().unwrap())
     }};
 }
 
 #[derive(Debug, Clone)]
 pub struct ServerUpdate {
     message: s::Message,
+    /// Set when the game loop has let the client into the world, the
+    /// session only acts for a character from then on
+    entered_world: Option<u32>,
 }
 impl ServerUpdate {
     pub fn new(message: s::Message) -> Self {
-        Self { message }
+        Self {
+            message,
+            entered_world: None,
+        }
+    }
+    pub fn enter_world(character_id: u32, message: s::Message) -> Self {
+        Self {
+            message,
+            entered_world: Some(character_id),
+        }
     }
     pub fn message(&self) -> &s::Message {
         &self.message
     }
+    pub fn entered_world(&self) -> Option<u32> {
+        self.entered_world
+    }
+}
+
+/// What happened to a client that the game loop needs to know about
+#[derive(Clone)]
+pub enum Event {
+    Message(c::Message),
+    /// The connection is gone, either the client closed it or we did
+    Disconnected,
 }
 
 #[derive(Clone)]
 pub struct ClientUpdate {
-    message: c::Message,
+    event: Event,
     client_id: u16,
+    account_id: u32,
     character_id: u32,
 }
 
@@ -109,16 +186,37 @@ impl ClientUpdate {
     pub fn client_id(&self) -> u16 {
         self.client_id
     }
+    pub fn account_id(&self) -> u32 {
+        self.account_id
+    }
     pub fn character_id(&self) -> u32 {
         self.character_id
     }
-    pub fn message(&self) -> &c::Message {
-        &self.message
+    pub fn event(&self) -> &Event {
+        &self.event
     }
-    pub fn new(client_id: u16, character_id: u32, message: c::Message) -> Self {
+    pub fn new(
+        client_id: u16,
+        account_id: u32,
+        character_id: u32,
+        message: c::Message,
+    ) -> Self {
         Self {
-            message,
+            event: Event::Message(message),
+            client_id,
+            account_id,
+            character_id,
+        }
+    }
+    pub fn disconnected(
+        client_id: u16,
+        account_id: u32,
+        character_id: u32,
+    ) -> Self {
+        Self {
+            event: Event::Disconnected,
             client_id,
+            account_id,
             character_id,
         }
     }
@@ -129,8 +227,13 @@ impl ClientUpdate {
 struct Messages {
     /// Only send to the requesting client
     pub direct: HashMap<u16, Vec<ServerUpdate>>,
-    /// Only send to other clients
-    pub others: HashMap<u16, Vec<ServerUpdate>>,
+    /// Send to every character that can see the object, including the object
+    /// itself when it is a character
+    pub observers: HashMap<u32, Vec<ServerUpdate>>,
+    /// Send to every other character that can see the object
+    pub others: HashMap<u32, Vec<ServerUpdate>>,
+    /// Send to every member of the character's party, including them
+    pub party: HashMap<u32, Vec<ServerUpdate>>,
     /// Send to all clients
     pub broadcast: Vec<ServerUpdate>,
 }
@@ -139,30 +242,108 @@ impl Messages {
     pub fn new() -> Self {
         Self {
             direct: HashMap::with_capacity(1000),
+            observers: HashMap::with_capacity(1000),
             others: HashMap::with_capacity(1000),
+            party: HashMap::with_capacity(1000),
             broadcast: Vec::with_capacity(1000),
         }
     }
 }
 
+/// Counts the messages a client sends in one second windows
+struct RateLimit {
+    window: Instant,
+    count: usize,
+}
+
+impl RateLimit {
+    fn new() -> Self {
+        Self {
+            window: Instant::now(),
+            count: 0,
+        }
+    }
+
+    /// Count the messages against the current window, false once the
+    /// window is over its limit
+    fn allow(&mut self, messages: usize) -> bool {
+        if self.window.elapsed() >= Duration::from_secs(1) {
+            self.window = Instant::now();
+            self.count = 0;
+        }
+        self.count += messages;
+        self.count <= MAX_MESSAGES_PER_SECOND
+    }
+}
+
+/// Fixed timestep clock for the game loop, sleeps until the next tick is due
+/// rather than spinning. A late tick runs straight away so the loop keeps
+/// pace, unless it is so far behind it is better to skip ahead
+struct Ticker {
+    interval: Duration,
+    next: Instant,
+}
+
+impl Ticker {
+    fn new(rate: f32) -> Self {
+        Self {
+            interval: Duration::from_secs_f32(1. / rate),
+            next: Instant::now(),
+        }
+    }
+
+    fn wait(&mut self) {
+        let now = Instant::now();
+        if now < self.next {
+            thread::sleep(self.next - now);
+        } else if now - self.next > self.interval * MAX_TICKS_BEHIND {
+            println!(
+                "WARNING: Game loop is {:?} behind, skipping ticks",
+                now - self.next
+            );
+            self.next = now;
+        }
+        self.next += self.interval;
+    }
+}
+
+/// Queue an update without ever blocking the game loop, a client that can't
+/// keep up is dropped rather than holding up everyone else
+fn queue(
+    client_id: u16,
+    client: &Sender<ServerUpdate>,
+    update: ServerUpdate,
+) -> bool {
+    match client.try_send(update) {
+        Ok(()) => true,
+        Err(TrySendError::Full(_)) => {
+            println!(
+                "WARNING: Outbound queue full for {client_id:X}, disconnecting"
+            );
+            false
+        }
+        Err(TrySendError::Disconnected(_)) => false,
+    }
+}
+
 /// Propagates events from one clients to all relevent clients
 /// Takes a turn per game tick for the server actions
 pub fn game_update(
     clients: Arc<Mutex<HashMap<u16, Sender<ServerUpdate>>>>,
+    waker: Arc<Waker>,
     server_rx: Receiver<ClientUpdate>,
+    repository: Box<dyn CharacterRepository + Send>,
+    spawner: Spawner,
+    data: GameData,
 ) {
     let mut disconnected_clients = Vec::with_capacity(100);
-    let mut state = State::new();
+    let mut state = State::new(repository, spawner, data);
     let mut messages = Messages::new();
 
-    let mut now = Instant::now();
+    let mut ticker = Ticker::new(TICK_RATE);
     loop {
         // Only update on tick rate
-        if now.elapsed() < Duration::from_secs_f32(1. / TICK_RATE) {
-            std::thread::yield_now();
-            continue;
-        }
-        now = Instant::now();
+        ticker.wait();
 
         // Respond to client messages
         for update in server_rx.try_iter() {
@@ -173,6 +354,13 @@ pub fn game_update(
         // Server turn
         state.update(&mut messages);
 
+        // Resolve observers into the clients that can see them
+        state.route(&mut messages);
+
+        if messages.broadcast.is_empty() && messages.direct.is_empty() {
+            continue;
+        }
+
         // After calculating all updates we lock the clients list
         // to send them the updates
         let mut clients = clients.lock().unwrap();
@@ -181,46 +369,38 @@ pub fn game_update(
         for message in messages.broadcast.drain(..) {
             println!("[BROADCAST]: {:X?}", message.message());
             for (id, client) in clients.iter() {
-                if client.send(message.clone()).is_err() {
+                if !queue(*id, client, message.clone()) {
                     disconnected_clients.push(*id);
-                    break;
                 }
             }
         }
 
         // Send Direct Messages
         for (client_id, messages) in messages.direct.drain() {
-            let client = clients.get(&client_id).unwrap();
+            let Some(client) = clients.get(&client_id) else {
+                continue;
+            };
             for message in messages {
                 println!("[DIRECT:{:X}]: {:X?}", client_id, message.message());
-                if client.send(message).is_err() {
+                if !queue(client_id, client, message) {
                     disconnected_clients.push(client_id);
                     break;
                 }
             }
         }
 
-        // Send others to only
-        for (client_id, messages) in messages.others.drain() {
-            for (id, client) in clients.iter() {
-                if id == &client_id {
-                    println!("[OTHERS:{:X}]: {:X?}", client_id, messages);
-                    continue;
-                }
-                for message in messages.clone() {
-                    if client.send(message).is_err() {
-                        disconnected_clients.push(client_id);
-                        break;
-                    }
-                }
-            }
-        }
-
-        // Remove clients we cant send to
+        // Remove clients we cant send to, dropping the sender tells the
+        // network thread to close the connection
         for client in disconnected_clients.drain(..) {
             // We do not care if its already removed, this is just for saftey
             _ = clients.remove(&client);
         }
+        drop(clients);
+
+        // Let the network thread know there is something to write
+        if let Err(e) = waker.wake() {
+            println!("ERROR: Failed to wake the network thread: {e}");
+        }
     }
 }
 
@@ -240,17 +420,33 @@ impl Keys {
 
 pub struct Connection {
     stream: TcpStream,
+    peer_addr: SocketAddr,
     keys: Keys,
     account: Account,
     /// The game server tx to clien rx
     rx: Receiver<ServerUpdate>,
     /// The client tx to the game servers rx
     tx: Sender<ClientUpdate>,
+    /// Bytes read from the socket that have not been handled yet
+    read_buf: Vec<u8>,
+    /// Reading stopped with a whole message in the buffer, there may be more
+    /// waiting on the socket that won't raise another event
+    unread: bool,
+    /// Encrypted messages waiting for the socket to be writable
+    send_buf: SendBuffer,
+    rate_limit: RateLimit,
+    /// Unknown opcodes since the last warning about them and when that was,
+    /// see [UNKNOWN_OPCODE_WARNING]
+    unknown_opcodes: usize,
+    unknown_warned: Option<Instant>,
+    /// When the last message came in, see [HEARTBEAT_TIMEOUT]
+    last_seen: Instant,
 }
 
 impl Connection {
     pub fn new(
-        mut stream: TcpStream,
+        stream: TcpStream,
+        peer_addr: SocketAddr,
         client_id: u16,
         rx: Receiver<ServerUpdate>,
         tx: Sender<ClientUpdate>,
@@ -261,44 +457,166 @@ impl Connection {
         // calculated key we keep in memory
         let key = gen_xor_key(seed);
 
-        // S_KEY Packet
-        let mut buf = [0u8; 11];
-        let len = message::make_key_packet(&mut buf, seed);
-        stream.write(&buf[..len]).unwrap();
+        // S_KEY Packet, goes out with the first flush
+        let mut send_buf = SendBuffer::new();
+        message::make_key_packet(&mut send_buf, seed);
 
         Self {
             stream,
+            peer_addr,
             keys: Keys::new(key.to_le_bytes()),
             account: Account::new(client_id),
             rx,
             tx,
+            read_buf: Vec::with_capacity(READ_CHUNK),
+            unread: false,
+            send_buf,
+            rate_limit: RateLimit::new(),
+            unknown_opcodes: 0,
+            unknown_warned: None,
+            last_seen: Instant::now(),
         }
     }
 
-    pub fn handle(mut self) {
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+
+    /// Whether the socket needs reading again without waiting for an event
+    pub fn unread(&self) -> bool {
+        self.unread
+    }
+
+    pub fn timed_out(&self) -> bool {
+        self.last_seen.elapsed() > HEARTBEAT_TIMEOUT
+    }
+
+    /// Let the game loop clean up after this client
+    pub fn disconnect(&self) {
+        _ = self.tx.send(self.account.disconnected());
+    }
+
+    /// Service the connection after the socket or the game loop has
+    /// something for us, returns false once the connection should be closed
+    pub fn ready(&mut self, readable: bool) -> Result<bool> {
+        if readable && !self.read()? {
+            return Ok(false);
+        }
+        if !self.receive() {
+            return Ok(false);
+        }
+        self.send_buf
+            .write_to(&mut self.stream)
+            .map_err(Error::Network)?;
+        Ok(true)
+    }
+
+    /// Read until there is a whole message or the socket is drained then
+    /// handle every message in the buffer, false when the client has closed
+    /// the connection. Stopping at a whole message keeps one busy client from
+    /// holding up the rest, see [Connection::unread]
+    fn read(&mut self) -> Result<bool> {
+        let mut open = true;
+        self.unread = false;
         loop {
-            let mut recv_buffer = [0u8; 1024 * 64];
-            let messages = match self.stream.read(&mut recv_buffer) {
+            if ClientMessages::complete(&self.read_buf) {
+                self.unread = true;
+                break;
+            }
+            let start = self.read_buf.len();
+            if start >= MAX_READ_BUF {
+                return Err(Error::ReadBufferFull(start));
+            }
+            self.read_buf
+                .resize((start + READ_CHUNK).min(MAX_READ_BUF), 0);
+            match self.stream.read(&mut self.read_buf[start..]) {
                 // TCP len 0 means close
-                Ok(0) => return,
-                Ok(len) => ClientMessages::deserialise(
-                    &mut recv_buffer[..len],
-                    &mut self.keys.client,
-                ),
-                Err(_) => ClientMessages::empty(),
-            };
+                Ok(0) => {
+                    self.read_buf.truncate(start);
+                    open = false;
+                    break;
+                }
+                Ok(len) => self.read_buf.truncate(start + len),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    self.read_buf.truncate(start);
+                    break;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                    self.read_buf.truncate(start);
+                }
+                Err(e) => return Err(Error::Network(e)),
+            }
+        }
 
-            let mut send_buffer = [0u8; 1024 * 64];
-            let len = messages.handle(
-                &mut send_buffer,
+        let messages = ClientMessages::deserialise(
+            &mut self.read_buf,
+            &mut self.keys.client,
+        )?;
+        // Skipped messages still count, they cost as much to read
+        let frames = messages.len() + messages.unknown().len();
+        if frames != 0 {
+            self.last_seen = Instant::now();
+            if !self.rate_limit.allow(frames) {
+                println!(
+                    "WARNING: Disconnecting {}: more than {} messages a second",
+                    self.peer_addr, MAX_MESSAGES_PER_SECOND
+                );
+                return Ok(false);
+            }
+            self.warn_unknown(messages.unknown());
+            let queued = messages.handle(
+                &mut self.send_buf,
                 &mut self.keys.server,
                 &mut self.account,
-                &self.rx,
                 &self.tx,
             );
-            if len != 0 {
-                self.stream.write(&send_buffer[..len]).unwrap();
+            if !queued {
+                println!(
+                    "WARNING: Disconnecting {}: the game loop can't keep up",
+                    self.peer_addr
+                );
+                return Ok(false);
+            }
+        }
+
+        Ok(open)
+    }
+
+    fn warn_unknown(&mut self, opcodes: &[u16]) {
+        let Some(last) = opcodes.last() else {
+            return;
+        };
+        self.unknown_opcodes += opcodes.len();
+        if self
+            .unknown_warned
+            .is_some_and(|warned| warned.elapsed() < UNKNOWN_OPCODE_WARNING)
+        {
+            return;
+        }
+        println!(
+            "WARNING: {} sent {} messages with opcodes not implemented, the \
+             last {last:02X}",
+            self.peer_addr, self.unknown_opcodes
+        );
+        self.unknown_opcodes = 0;
+        self.unknown_warned = Some(Instant::now());
+    }
+
+    /// Move updates from the game loop into the send buffer, false once the
+    /// game loop has dropped this client
+    fn receive(&mut self) -> bool {
+        while self.send_buf.len() < MAX_PENDING_WRITE {
+            match self.rx.try_recv() {
+                Ok(update) => {
+                    if let Some(character_id) = update.entered_world() {
+                        self.account.set_character_id(character_id);
+                    }
+                    self.send_buf.push(update.message(), &mut self.keys.server)
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return false,
             }
         }
+        true
     }
 }
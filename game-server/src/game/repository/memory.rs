@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+use super::CharacterRepository;
+use crate::error::Result;
+use crate::game::character::Character;
+use crate::game::engine::mail::Letter;
+use crate::game::id::ObjectKind;
+
+/// Keeps characters in memory, nothing survives a restart. Used for tests
+pub struct MemoryRepository {
+    characters: HashMap<u32, Character>,
+    letters: HashMap<u32, Letter>,
+    high_water_marks: HashMap<u8, u32>,
+}
+
+impl MemoryRepository {
+    pub fn new() -> Self {
+        Self {
+            characters: HashMap::new(),
+            letters: HashMap::new(),
+            high_water_marks: HashMap::new(),
+        }
+    }
+}
+
+impl CharacterRepository for MemoryRepository {
+    fn characters(&self, account_id: u32) -> Result<Vec<Character>> {
+        let mut characters: Vec<Character> = self
+            .characters
+            .values()
+            .filter(|character| character.account_id() == account_id)
+            .cloned()
+            .collect();
+        characters.sort_by_key(|character| character.id());
+        Ok(characters)
+    }
+
+    fn load(&self, character_id: u32) -> Result<Option<Character>> {
+        Ok(self.characters.get(&character_id).cloned())
+    }
+
+    fn create(&mut self, character: &Character) -> Result<()> {
+        self.characters.insert(character.id(), character.clone());
+        Ok(())
+    }
+
+    fn save(&mut self, character: &Character) -> Result<()> {
+        self.characters.insert(character.id(), character.clone());
+        Ok(())
+    }
+
+    fn save_all(&mut self, characters: &[&Character]) -> Result<()> {
+        for character in characters {
+            self.characters.insert(character.id(), (*character).clone());
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, character_id: u32) -> Result<()> {
+        self.characters.remove(&character_id);
+        self.letters
+            .retain(|_, letter| letter.recipient_id != character_id);
+        Ok(())
+    }
+
+    fn name_taken(&self, name: &str) -> Result<bool> {
+        Ok(self
+            .characters
+            .values()
+            .any(|character| character.name().eq_ignore_ascii_case(name)))
+    }
+
+    fn find_name(&self, name: &str) -> Result<Option<(u32, String)>> {
+        Ok(self
+            .characters
+            .values()
+            .find(|character| character.name().eq_ignore_ascii_case(name))
+            .map(|character| (character.id(), character.name().clone())))
+    }
+
+    fn mailbox(&self, character_id: u32) -> Result<Vec<Letter>> {
+        let mut letters: Vec<Letter> = self
+            .letters
+            .values()
+            .filter(|letter| letter.recipient_id == character_id)
+            .cloned()
+            .collect();
+        letters.sort_by_key(|letter| (letter.sent_at, letter.id));
+        Ok(letters)
+    }
+
+    fn letter(&self, letter_id: u32) -> Result<Option<Letter>> {
+        Ok(self.letters.get(&letter_id).cloned())
+    }
+
+    fn save_letter(
+        &mut self,
+        letter: &Letter,
+        character: Option<&Character>,
+    ) -> Result<()> {
+        self.letters.insert(letter.id, letter.clone());
+        if let Some(character) = character {
+            self.characters.insert(character.id(), character.clone());
+        }
+        Ok(())
+    }
+
+    fn delete_letter(&mut self, letter_id: u32) -> Result<()> {
+        self.letters.remove(&letter_id);
+        Ok(())
+    }
+
+    fn letters_before(&self, sent_at: u32) -> Result<Vec<Letter>> {
+        Ok(self
+            .letters
+            .values()
+            .filter(|letter| letter.sent_at < sent_at)
+            .cloned()
+            .collect())
+    }
+
+    fn high_water_mark(&self, kind: ObjectKind) -> Result<Option<u32>> {
+        Ok(self.high_water_marks.get(&(kind as u8)).copied())
+    }
+
+    fn set_high_water_mark(
+        &mut self,
+        kind: ObjectKind,
+        mark: u32,
+    ) -> Result<()> {
+        self.high_water_marks.insert(kind as u8, mark);
+        Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+//! Persistent storage for characters and object ids, the game loop only talks
+//! to storage through [CharacterRepository] so the backend can be swapped out
+
+#[cfg(test)]
+mod memory;
+mod sqlite;
+
+#[cfg(test)]
+pub use memory::MemoryRepository;
+pub use sqlite::SqliteRepository;
+
+use super::character::Character;
+use super::engine::mail::Letter;
+use super::id::ObjectKind;
+use crate::error::Result;
+
+pub trait CharacterRepository {
+    /// All characters owned by an account
+    fn characters(&self, account_id: u32) -> Result<Vec<Character>>;
+
+    /// Load a single character by its id
+    fn load(&self, character_id: u32) -> Result<Option<Character>>;
+
+    /// Store a brand new character, its id comes from the
+    /// [IdAllocator](super::id::IdAllocator)
+    fn create(&mut self, character: &Character) -> Result<()>;
+
+    /// Save location, HP, gear, inventory and skills of an existing character
+    fn save(&mut self, character: &Character) -> Result<()>;
+
+    /// Save characters something changed hands between, either all of them
+    /// are written or none are
+    fn save_all(&mut self, characters: &[&Character]) -> Result<()>;
+
+    /// Permanently remove a character and everything it owns
+    fn delete(&mut self, character_id: u32) -> Result<()>;
+
+    /// Names are unique across the whole server
+    fn name_taken(&self, name: &str) -> Result<bool>;
+
+    /// Id and name, as it was created, of the character with a name
+    /// ignoring case
+    fn find_name(&self, name: &str) -> Result<Option<(u32, String)>>;
+
+    /// Every letter sent to a character, oldest first
+    fn mailbox(&self, character_id: u32) -> Result<Vec<Letter>>;
+
+    fn letter(&self, letter_id: u32) -> Result<Option<Letter>>;
+
+    /// Store a new or changed letter. The character an attachment moved to
+    /// or from is saved along with it, so it can't end up in both or neither
+    fn save_letter(
+        &mut self,
+        letter: &Letter,
+        character: Option<&Character>,
+    ) -> Result<()>;
+
+    fn delete_letter(&mut self, letter_id: u32) -> Result<()>;
+
+    /// Letters sent before a unix time
+    fn letters_before(&self, sent_at: u32) -> Result<Vec<Letter>>;
+
+    /// Highest id reserved for a kind of object
+    fn high_water_mark(&self, kind: ObjectKind) -> Result<Option<u32>>;
+
+    fn set_high_water_mark(
+        &mut self,
+        kind: ObjectKind,
+        mark: u32,
+    ) -> Result<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::character::Appearance;
+    use crate::game::data::item::InventoryItem;
+    use crate::game::data::skill::{Skill, SkillType};
+    use crate::game::engine::Coord;
+
+    fn round_trip(repository: &mut dyn CharacterRepository) {
+        let mut character = Character::new(
+            1,
+            7,
+            "Tester".into(),
+            Appearance::starter(),
+            Coord::new(1., 2., 3.),
+        );
+        repository.create(&character).unwrap();
+        let created = repository.load(character.id()).unwrap().unwrap();
+        for item in character.inventory.items() {
+            assert_eq!(created.inventory.get(item.id), Some(item));
+        }
+
+        character.set_location(Coord::new(10., 20., 30.));
+        character.gain_exp(character.stats.exp_to_level);
+        character.stats.hp.update(-100);
+        character.stats.mp.spend(10);
+        character
+            .skills
+            .push(Skill::new(9999, 2, SkillType::Stigma));
+        character.inventory.clear();
+        character.set_deletion_time(Some(1337));
+        repository.save(&character).unwrap();
+
+        let loaded = repository.load(character.id()).unwrap().unwrap();
+        assert_eq!(loaded.name(), "Tester");
+        assert_eq!(loaded.account_id(), 7);
+        assert_eq!(loaded.location().x(), 10.);
+        assert_eq!(loaded.stats.hp.current(), character.stats.hp.current());
+        assert_eq!(loaded.stats.mp.current(), character.stats.mp.current());
+        assert_eq!(loaded.stats.level(), character.stats.level());
+        assert_eq!(loaded.stats.exp, character.stats.exp);
+        assert_eq!(loaded.skills.len(), character.skills.len());
+        assert_eq!(loaded.inventory.items().count(), 0);
+        assert_eq!(loaded.deletion_time(), Some(1337));
+        assert!(repository.name_taken("Tester").unwrap());
+
+        assert_eq!(repository.characters(7).unwrap().len(), 1);
+        assert!(repository.characters(8).unwrap().is_empty());
+        assert!(repository.load(character.id() + 1).unwrap().is_none());
+
+        repository.delete(character.id()).unwrap();
+        assert!(repository.load(character.id()).unwrap().is_none());
+        assert!(!repository.name_taken("Tester").unwrap());
+    }
+
+    fn mail(repository: &mut dyn CharacterRepository) {
+        let mut sender = Character::new(
+            1,
+            7,
+            "Sender".into(),
+            Appearance::starter(),
+            Coord::new(1., 2., 3.),
+        );
+        repository.create(&sender).unwrap();
+        assert_eq!(
+            repository.find_name("sENDER").unwrap(),
+            Some((1, "Sender".into()))
+        );
+        assert!(repository.find_name("Nobody").unwrap().is_none());
+
+        let item = sender.inventory.items().next().unwrap();
+        let (id, count) = (item.id, item.count);
+        let item = sender.inventory.take(id, count, || None).unwrap().0;
+        let letter = Letter {
+            id: 100,
+            sender_id: 1,
+            sender_name: "Sender".into(),
+            recipient_id: 2,
+            recipient_name: "Recipient".into(),
+            title: "Title".into(),
+            message: "Message".into(),
+            item: Some(item.clone()),
+            kinah: 50,
+            unread: true,
+            sent_at: 1000,
+            returned: false,
+        };
+        repository.save_letter(&letter, Some(&sender)).unwrap();
+        let saved = repository.load(1).unwrap().unwrap();
+        assert!(saved.inventory.get(item.id).is_none());
+
+        let mut other = letter.clone();
+        other.id = 101;
+        other.item = Some(InventoryItem::kinah(200, 1));
+        other.sent_at = 2000;
+        repository.save_letter(&other, None).unwrap();
+        assert_eq!(repository.mailbox(2).unwrap(), [letter.clone(), other]);
+        assert!(repository.mailbox(1).unwrap().is_empty());
+
+        let mut read = letter.clone();
+        read.unread = false;
+        read.item = None;
+        repository.save_letter(&read, None).unwrap();
+        assert_eq!(repository.letter(100).unwrap(), Some(read.clone()));
+        assert_eq!(repository.letters_before(1500).unwrap(), [read]);
+
+        repository.delete_letter(100).unwrap();
+        assert!(repository.letter(100).unwrap().is_none());
+        assert_eq!(repository.mailbox(2).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn memory_round_trip() {
+        round_trip(&mut MemoryRepository::new());
+    }
+
+    #[test]
+    fn sqlite_round_trip() {
+        round_trip(&mut SqliteRepository::open_in_memory().unwrap());
+    }
+
+    #[test]
+    fn memory_mail() {
+        mail(&mut MemoryRepository::new());
+    }
+
+    #[test]
+    fn sqlite_mail() {
+        mail(&mut SqliteRepository::open_in_memory().unwrap());
+    }
+
+    #[test]
+    fn sqlite_migrate_to_mail() {
+        let mut repository =
+            SqliteRepository::open_in_memory_untracked(1).unwrap();
+        round_trip(&mut repository);
+        mail(&mut repository);
+    }
+}
@@ -0,0 +1,677 @@
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use super::CharacterRepository;
+use crate::error::{Error, Result};
+use crate::game::character::{Appearance, Character, Class, Gender, Race};
+use crate::game::data::gear::Gear;
+use crate::game::data::item::{
+    Equipment, EquipmentKind, InventoryItem, MANASTONE_SLOTS,
+};
+use crate::game::data::skill::Skill;
+use crate::game::engine::inventory::Inventory;
+use crate::game::engine::mail::Letter;
+use crate::game::engine::Coord;
+use crate::game::id::ObjectKind;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS characters (
+    id INTEGER PRIMARY KEY,
+    account_id INTEGER NOT NULL,
+    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+    gender INTEGER NOT NULL,
+    race INTEGER NOT NULL,
+    class INTEGER NOT NULL,
+    voice INTEGER NOT NULL,
+    appearance BLOB NOT NULL,
+    x REAL NOT NULL,
+    y REAL NOT NULL,
+    z REAL NOT NULL,
+    hp INTEGER NOT NULL,
+    level INTEGER NOT NULL,
+    exp INTEGER NOT NULL,
+    bind_x REAL,
+    bind_y REAL,
+    bind_z REAL,
+    deletion_time INTEGER
+);
+CREATE INDEX IF NOT EXISTS characters_account_id ON characters (account_id);
+CREATE TABLE IF NOT EXISTS character_items (
+    character_id INTEGER NOT NULL REFERENCES characters (id),
+    object_id INTEGER NOT NULL,
+    item_id INTEGER NOT NULL,
+    name_id INTEGER NOT NULL,
+    count INTEGER NOT NULL,
+    slot INTEGER NOT NULL,
+    soulbound INTEGER NOT NULL,
+    enchant_level INTEGER NOT NULL,
+    manastones BLOB NOT NULL,
+    durability INTEGER,
+    expires_at INTEGER,
+    mask INTEGER NOT NULL,
+    kind INTEGER,
+    equipped INTEGER,
+    slots INTEGER,
+    other_slots INTEGER,
+    skin INTEGER,
+    bonuses BLOB,
+    unknown INTEGER,
+    PRIMARY KEY (character_id, object_id)
+);
+CREATE TABLE IF NOT EXISTS character_skills (
+    character_id INTEGER NOT NULL REFERENCES characters (id),
+    skill_id INTEGER NOT NULL,
+    level INTEGER NOT NULL,
+    unknown INTEGER NOT NULL,
+    type INTEGER NOT NULL,
+    PRIMARY KEY (character_id, skill_id)
+);
+CREATE TABLE IF NOT EXISTS object_ids (
+    kind INTEGER PRIMARY KEY,
+    high_water_mark INTEGER NOT NULL
+);
+";
+
+const MAIL_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS mail (
+    id INTEGER PRIMARY KEY,
+    sender_id INTEGER NOT NULL,
+    sender_name TEXT NOT NULL,
+    recipient_id INTEGER NOT NULL,
+    recipient_name TEXT NOT NULL,
+    title TEXT NOT NULL,
+    message TEXT NOT NULL,
+    kinah INTEGER NOT NULL,
+    unread INTEGER NOT NULL,
+    sent_at INTEGER NOT NULL,
+    returned INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS mail_recipient_id ON mail (recipient_id);
+CREATE INDEX IF NOT EXISTS mail_sent_at ON mail (sent_at);
+CREATE TABLE IF NOT EXISTS mail_items (
+    mail_id INTEGER NOT NULL REFERENCES mail (id),
+    object_id INTEGER NOT NULL,
+    item_id INTEGER NOT NULL,
+    name_id INTEGER NOT NULL,
+    count INTEGER NOT NULL,
+    slot INTEGER NOT NULL,
+    soulbound INTEGER NOT NULL,
+    enchant_level INTEGER NOT NULL,
+    manastones BLOB NOT NULL,
+    durability INTEGER,
+    expires_at INTEGER,
+    mask INTEGER NOT NULL,
+    kind INTEGER,
+    equipped INTEGER,
+    slots INTEGER,
+    other_slots INTEGER,
+    skin INTEGER,
+    bonuses BLOB,
+    unknown INTEGER,
+    PRIMARY KEY (mail_id, object_id)
+);
+";
+
+/// MP used to start full every time the character entered the world
+const MP_COLUMN: &str = "ALTER TABLE characters ADD COLUMN mp INTEGER;";
+
+/// Nothing ever set the bind point, it comes back along with a way to bind
+const DROP_BIND_POINT: &str = "
+ALTER TABLE characters DROP COLUMN bind_x;
+ALTER TABLE characters DROP COLUMN bind_y;
+ALTER TABLE characters DROP COLUMN bind_z;
+";
+
+/// Each step brings the database up from the version before it, the version
+/// it is on is kept in `user_version`. Steps are only ever added to the end
+const MIGRATIONS: &[&str] = &[SCHEMA, MAIL_SCHEMA, MP_COLUMN, DROP_BIND_POINT];
+
+const SELECT_CHARACTER: &str =
+    "SELECT id, account_id, name, gender, race, class,
+    voice, appearance, x, y, z, hp, deletion_time, level, exp, mp
+    FROM characters";
+
+/// Items are stored the same way in the inventory and on letters
+const ITEM_COLUMNS: &str = "object_id, item_id, name_id, count, slot,
+    soulbound, enchant_level, manastones, durability, expires_at, mask, kind,
+    equipped, slots, other_slots, skin, bonuses, unknown";
+
+const SELECT_LETTER: &str =
+    "SELECT id, sender_id, sender_name, recipient_id, recipient_name, title,
+    message, kinah, unread, sent_at, returned FROM mail";
+
+/// Embedded SQLite database, everything lives in a single file next to the
+/// server
+pub struct SqliteRepository {
+    conn: Connection,
+}
+
+impl SqliteRepository {
+    pub fn open(path: &str) -> Result<Self> {
+        Self::init(Connection::open(path).map_err(Error::Database)?)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory().map_err(Error::Database)?)
+    }
+
+    /// A database from before the schema version was kept, made by a server
+    /// that knew only the first few migrations
+    #[cfg(test)]
+    pub fn open_in_memory_untracked(applied: usize) -> Result<Self> {
+        let conn = Connection::open_in_memory().map_err(Error::Database)?;
+        for migration in &MIGRATIONS[..applied] {
+            conn.execute_batch(migration).map_err(Error::Database)?;
+        }
+        Self::init(conn)
+    }
+
+    fn init(mut conn: Connection) -> Result<Self> {
+        let version: usize = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(Error::Database)?;
+        if version > MIGRATIONS.len() {
+            return Err(Error::SchemaVersion(version));
+        }
+        for (version, migration) in MIGRATIONS.iter().enumerate().skip(version)
+        {
+            let tx = conn.transaction().map_err(Error::Database)?;
+            tx.execute_batch(migration).map_err(Error::Database)?;
+            tx.pragma_update(None, "user_version", version + 1)
+                .map_err(Error::Database)?;
+            tx.commit().map_err(Error::Database)?;
+        }
+        Ok(Self { conn })
+    }
+
+    /// Builds the character from its row then fills in everything stored in
+    /// the child tables
+    fn hydrate(&self, row: &Row) -> rusqlite::Result<Character> {
+        let id: u32 = row.get(0)?;
+        let appearance = Appearance {
+            gender: column(row, 3, Gender::try_from)?,
+            race: column(row, 4, Race::try_from)?,
+            class: column(row, 5, Class::try_from)?,
+            voice: row.get(6)?,
+            raw: row.get(7)?,
+        };
+        let location = Coord::new(row.get(8)?, row.get(9)?, row.get(10)?);
+        let class = appearance.class;
+        let mut character =
+            Character::new(id, row.get(1)?, row.get(2)?, appearance, location);
+        character.stats.set_level(class, row.get(13)?);
+        character.stats.exp = row.get(14)?;
+        character.set_deletion_time(row.get(12)?);
+
+        let mut inventory = Inventory::default();
+        for item in items(&self.conn, "character_items", "character_id", id)? {
+            inventory.insert(item);
+        }
+        character.gear = Gear::worn(inventory.equipped());
+        character.inventory = inventory;
+        // Gear can raise the maximums, they are only held to them once it
+        // is worn on entering the world
+        character.stats.hp.restore(row.get(11)?);
+        if let Some(mp) = row.get(15)? {
+            character.stats.mp.restore(mp);
+        }
+
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT skill_id, level, unknown, type FROM character_skills
+             WHERE character_id = ?1 ORDER BY rowid",
+        )?;
+        let skills = stmt
+            .query_map([id], |row| {
+                let typ: u8 = row.get(3)?;
+                Ok(Skill::from_parts(
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    typ.into(),
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        character.skills = skills;
+
+        Ok(character)
+    }
+
+    /// Builds a letter from its row in mail along with its item
+    fn letter_row(&self, row: &Row) -> rusqlite::Result<Letter> {
+        let id = row.get(0)?;
+        Ok(Letter {
+            id,
+            sender_id: row.get(1)?,
+            sender_name: row.get(2)?,
+            recipient_id: row.get(3)?,
+            recipient_name: row.get(4)?,
+            title: row.get(5)?,
+            message: row.get(6)?,
+            item: items(&self.conn, "mail_items", "mail_id", id)?.pop(),
+            kinah: row.get(7)?,
+            unread: row.get(8)?,
+            sent_at: row.get(9)?,
+            returned: row.get(10)?,
+        })
+    }
+
+    /// Everything about an existing character that changes as it plays
+    fn update(
+        conn: &Connection,
+        character: &Character,
+    ) -> rusqlite::Result<()> {
+        let location = character.location();
+        conn.execute(
+            "UPDATE characters
+             SET x = ?2, y = ?3, z = ?4, hp = ?5, deletion_time = ?6,
+             level = ?7, exp = ?8, mp = ?9
+             WHERE id = ?1",
+            params![
+                character.id(),
+                location.x(),
+                location.y(),
+                location.z(),
+                character.stats.hp.current(),
+                character.deletion_time(),
+                character.stats.level,
+                character.stats.exp,
+                character.stats.mp.current()
+            ],
+        )?;
+        Self::save_children(conn, character)
+    }
+
+    fn save_children(
+        conn: &Connection,
+        character: &Character,
+    ) -> rusqlite::Result<()> {
+        let id = character.id();
+        conn.execute(
+            "DELETE FROM character_items WHERE character_id = ?1",
+            [id],
+        )?;
+        conn.execute(
+            "DELETE FROM character_skills WHERE character_id = ?1",
+            [id],
+        )?;
+
+        for item in character.inventory.items() {
+            insert_item(conn, "character_items", "character_id", id, item)?;
+        }
+
+        let mut stmt = conn.prepare_cached(
+            "INSERT INTO character_skills
+             (character_id, skill_id, level, unknown, type)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        for skill in &character.skills {
+            stmt.execute(params![
+                id,
+                skill.id(),
+                skill.level(),
+                skill.unknown(),
+                skill.typ() as u8
+            ])?;
+        }
+
+        Ok(())
+    }
+}
+
+impl CharacterRepository for SqliteRepository {
+    fn characters(&self, account_id: u32) -> Result<Vec<Character>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(&format!(
+                "{SELECT_CHARACTER} WHERE account_id = ?1 ORDER BY id"
+            ))
+            .map_err(Error::Database)?;
+        let characters = stmt
+            .query_map([account_id], |row| self.hydrate(row))
+            .and_then(|rows| rows.collect())
+            .map_err(Error::Database)?;
+        Ok(characters)
+    }
+
+    fn load(&self, character_id: u32) -> Result<Option<Character>> {
+        self.conn
+            .query_row(
+                &format!("{SELECT_CHARACTER} WHERE id = ?1"),
+                [character_id],
+                |row| self.hydrate(row),
+            )
+            .optional()
+            .map_err(Error::Database)
+    }
+
+    fn create(&mut self, character: &Character) -> Result<()> {
+        let tx = self.conn.transaction().map_err(Error::Database)?;
+        let location = character.location();
+        let appearance = &character.appearance;
+        tx.execute(
+            "INSERT INTO characters (id, account_id, name, gender, race,
+             class, voice, appearance, x, y, z, hp, deletion_time, level, exp,
+             mp)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13,
+             ?14, ?15, ?16)",
+            params![
+                character.id(),
+                character.account_id(),
+                character.name(),
+                appearance.gender as u8,
+                appearance.race as u8,
+                appearance.class as u8,
+                appearance.voice,
+                appearance.raw,
+                location.x(),
+                location.y(),
+                location.z(),
+                character.stats.hp.current(),
+                character.deletion_time(),
+                character.stats.level,
+                character.stats.exp,
+                character.stats.mp.current()
+            ],
+        )
+        .map_err(Error::Database)?;
+
+        Self::save_children(&tx, character).map_err(Error::Database)?;
+        tx.commit().map_err(Error::Database)
+    }
+
+    fn save(&mut self, character: &Character) -> Result<()> {
+        let tx = self.conn.transaction().map_err(Error::Database)?;
+        Self::update(&tx, character).map_err(Error::Database)?;
+        tx.commit().map_err(Error::Database)
+    }
+
+    fn save_all(&mut self, characters: &[&Character]) -> Result<()> {
+        let tx = self.conn.transaction().map_err(Error::Database)?;
+        for character in characters {
+            Self::update(&tx, character).map_err(Error::Database)?;
+        }
+        tx.commit().map_err(Error::Database)
+    }
+
+    fn delete(&mut self, character_id: u32) -> Result<()> {
+        let tx = self.conn.transaction().map_err(Error::Database)?;
+        tx.execute(
+            "DELETE FROM mail_items WHERE mail_id IN
+             (SELECT id FROM mail WHERE recipient_id = ?1)",
+            [character_id],
+        )
+        .map_err(Error::Database)?;
+        tx.execute("DELETE FROM mail WHERE recipient_id = ?1", [character_id])
+            .map_err(Error::Database)?;
+        for table in ["character_items", "character_skills"] {
+            tx.execute(
+                &format!("DELETE FROM {table} WHERE character_id = ?1"),
+                [character_id],
+            )
+            .map_err(Error::Database)?;
+        }
+        tx.execute("DELETE FROM characters WHERE id = ?1", [character_id])
+            .map_err(Error::Database)?;
+        tx.commit().map_err(Error::Database)
+    }
+
+    fn name_taken(&self, name: &str) -> Result<bool> {
+        self.conn
+            .query_row(
+                "SELECT EXISTS (SELECT 1 FROM characters WHERE name = ?1)",
+                [name],
+                |row| row.get(0),
+            )
+            .map_err(Error::Database)
+    }
+
+    fn find_name(&self, name: &str) -> Result<Option<(u32, String)>> {
+        self.conn
+            .query_row(
+                "SELECT id, name FROM characters WHERE name = ?1",
+                [name],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(Error::Database)
+    }
+
+    fn mailbox(&self, character_id: u32) -> Result<Vec<Letter>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(&format!(
+                "{SELECT_LETTER} WHERE recipient_id = ?1 ORDER BY sent_at, id"
+            ))
+            .map_err(Error::Database)?;
+        stmt.query_map([character_id], |row| self.letter_row(row))
+            .and_then(|rows| rows.collect())
+            .map_err(Error::Database)
+    }
+
+    fn letter(&self, letter_id: u32) -> Result<Option<Letter>> {
+        self.conn
+            .query_row(
+                &format!("{SELECT_LETTER} WHERE id = ?1"),
+                [letter_id],
+                |row| self.letter_row(row),
+            )
+            .optional()
+            .map_err(Error::Database)
+    }
+
+    fn save_letter(
+        &mut self,
+        letter: &Letter,
+        character: Option<&Character>,
+    ) -> Result<()> {
+        let tx = self.conn.transaction().map_err(Error::Database)?;
+        tx.execute(
+            "INSERT OR REPLACE INTO mail (id, sender_id, sender_name,
+             recipient_id, recipient_name, title, message, kinah, unread,
+             sent_at, returned)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                letter.id,
+                letter.sender_id,
+                letter.sender_name,
+                letter.recipient_id,
+                letter.recipient_name,
+                letter.title,
+                letter.message,
+                letter.kinah,
+                letter.unread,
+                letter.sent_at,
+                letter.returned
+            ],
+        )
+        .map_err(Error::Database)?;
+        tx.execute("DELETE FROM mail_items WHERE mail_id = ?1", [letter.id])
+            .map_err(Error::Database)?;
+        if let Some(item) = &letter.item {
+            insert_item(&tx, "mail_items", "mail_id", letter.id, item)
+                .map_err(Error::Database)?;
+        }
+        if let Some(character) = character {
+            Self::update(&tx, character).map_err(Error::Database)?;
+        }
+        tx.commit().map_err(Error::Database)
+    }
+
+    fn delete_letter(&mut self, letter_id: u32) -> Result<()> {
+        let tx = self.conn.transaction().map_err(Error::Database)?;
+        tx.execute("DELETE FROM mail_items WHERE mail_id = ?1", [letter_id])
+            .map_err(Error::Database)?;
+        tx.execute("DELETE FROM mail WHERE id = ?1", [letter_id])
+            .map_err(Error::Database)?;
+        tx.commit().map_err(Error::Database)
+    }
+
+    fn letters_before(&self, sent_at: u32) -> Result<Vec<Letter>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(&format!("{SELECT_LETTER} WHERE sent_at < ?1"))
+            .map_err(Error::Database)?;
+        stmt.query_map([sent_at], |row| self.letter_row(row))
+            .and_then(|rows| rows.collect())
+            .map_err(Error::Database)
+    }
+
+    fn high_water_mark(&self, kind: ObjectKind) -> Result<Option<u32>> {
+        self.conn
+            .query_row(
+                "SELECT high_water_mark FROM object_ids WHERE kind = ?1",
+                [kind as u8],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(Error::Database)
+    }
+
+    fn set_high_water_mark(
+        &mut self,
+        kind: ObjectKind,
+        mark: u32,
+    ) -> Result<()> {
+        self.conn
+            .execute(
+                "INSERT INTO object_ids (kind, high_water_mark) VALUES (?1, ?2)
+                 ON CONFLICT (kind) DO UPDATE SET high_water_mark = ?2",
+                params![kind as u8, mark],
+            )
+            .map(|_| ())
+            .map_err(Error::Database)
+    }
+}
+
+/// Reads an integer column into one of our enums
+fn column<T>(
+    row: &Row,
+    index: usize,
+    convert: fn(u32) -> std::result::Result<T, u32>,
+) -> rusqlite::Result<T> {
+    let value: u32 = row.get(index)?;
+    convert(value).map_err(|value| {
+        rusqlite::Error::IntegralValueOutOfRange(index, value as i64)
+    })
+}
+
+/// Store an item in the inventory or on a letter, whichever owns it
+fn insert_item(
+    conn: &Connection,
+    table: &str,
+    owner_column: &str,
+    owner: u32,
+    item: &InventoryItem,
+) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "INSERT INTO {table} ({owner_column}, {ITEM_COLUMNS})
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13,
+         ?14, ?15, ?16, ?17, ?18, ?19)"
+    ))?;
+    let manastones: Vec<u8> = item
+        .manastones
+        .iter()
+        .flat_map(|id| id.to_le_bytes())
+        .collect();
+    let equipment = item.equipment.as_ref();
+    let bonuses = equipment.map(|equipment| {
+        equipment
+            .bonuses
+            .iter()
+            .flat_map(|(stat, value)| {
+                stat.to_le_bytes().into_iter().chain(value.to_le_bytes())
+            })
+            .collect::<Vec<u8>>()
+    });
+    stmt.execute(params![
+        owner,
+        item.id,
+        item.item_id,
+        item.name_id,
+        item.count,
+        item.slot,
+        item.soulbound,
+        item.enchant_level,
+        manastones,
+        item.durability,
+        item.expires_at,
+        item.mask,
+        equipment.map(|equipment| equipment.kind as u8),
+        equipment.map(|equipment| equipment.equipped),
+        equipment.map(|equipment| equipment.slots),
+        equipment.map(|equipment| equipment.other_slots),
+        equipment.map(|equipment| equipment.skin),
+        bonuses,
+        equipment.and_then(|equipment| equipment.unknown)
+    ])?;
+    Ok(())
+}
+
+/// Every item a character or letter owns
+fn items(
+    conn: &Connection,
+    table: &str,
+    owner_column: &str,
+    owner: u32,
+) -> rusqlite::Result<Vec<InventoryItem>> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {ITEM_COLUMNS} FROM {table} WHERE {owner_column} = ?1"
+    ))?;
+    stmt.query_map([owner], inventory_item)
+        .and_then(|rows| rows.collect())
+}
+
+/// Reads an item back from its row in character_items or mail_items,
+/// equipment has a kind and the columns after it while everything else
+/// leaves them null
+fn inventory_item(row: &Row) -> rusqlite::Result<InventoryItem> {
+    let blob: Vec<u8> = row.get(7)?;
+    let mut manastones = [0; MANASTONE_SLOTS];
+    for (manastone, bytes) in manastones.iter_mut().zip(blob.chunks_exact(4)) {
+        *manastone = u32::from_le_bytes(bytes.try_into().unwrap());
+    }
+
+    let kind: Option<u8> = row.get(11)?;
+    let equipment = match kind {
+        Some(kind) => {
+            let kind = EquipmentKind::try_from(kind).map_err(|kind| {
+                rusqlite::Error::IntegralValueOutOfRange(11, kind.into())
+            })?;
+            let blob: Vec<u8> = row.get(16)?;
+            let bonuses = blob
+                .chunks_exact(6)
+                .map(|bytes| {
+                    (
+                        u16::from_le_bytes([bytes[0], bytes[1]]),
+                        u32::from_le_bytes(bytes[2..].try_into().unwrap()),
+                    )
+                })
+                .collect();
+            Some(Equipment {
+                kind,
+                equipped: row.get(12)?,
+                slots: row.get(13)?,
+                other_slots: row.get(14)?,
+                skin: row.get(15)?,
+                bonuses,
+                unknown: row.get(17)?,
+            })
+        }
+        None => None,
+    };
+
+    Ok(InventoryItem {
+        id: row.get(0)?,
+        item_id: row.get(1)?,
+        name_id: row.get(2)?,
+        count: row.get(3)?,
+        slot: row.get(4)?,
+        soulbound: row.get(5)?,
+        enchant_level: row.get(6)?,
+        manastones,
+        durability: row.get(8)?,
+        expires_at: row.get(9)?,
+        mask: row.get(10)?,
+        equipment,
+    })
+}
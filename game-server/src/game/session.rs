@@ -23,7 +23,20 @@ impl Account {
     }
 
     pub fn send(&self, message: c::Message) -> ClientUpdate {
-        ClientUpdate::new(self.client_id, self.character_id, message)
+        ClientUpdate::new(
+            self.client_id,
+            self.auth_server.id,
+            self.character_id,
+            message,
+        )
+    }
+
+    pub fn disconnected(&self) -> ClientUpdate {
+        ClientUpdate::disconnected(
+            self.client_id,
+            self.auth_server.id,
+            self.character_id,
+        )
     }
 
     pub fn pin_enabled(&self) -> bool {
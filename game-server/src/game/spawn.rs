@@ -0,0 +1,283 @@
+//! Spawn tables say which NPCs live where, the spawner keeps every spawn
+//! point filled. Dead NPCs are left as a corpse for a while so they can be
+//! looted then removed, and come back once their respawn delay is up
+
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
+
+use quick_xml::de::from_str;
+use rand::Rng;
+use serde::Deserialize;
+
+use super::data::npc::{Template, Templates};
+use super::engine::Coord;
+use super::entity::Entity;
+use super::TICK_RATE;
+use crate::error::{Error, Result};
+
+/// How long a corpse stays around to be looted
+const CORPSE_DURATION: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Deserialize)]
+#[serde(rename = "spawns")]
+struct SpawnTable {
+    #[serde(rename = "spawn", default)]
+    spawns: Vec<Definition>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Definition {
+    #[serde(rename = "@template_id")]
+    template_id: u32,
+    #[serde(rename = "@world_id")]
+    world_id: u32,
+    #[serde(rename = "@x")]
+    x: f32,
+    #[serde(rename = "@y")]
+    y: f32,
+    #[serde(rename = "@z")]
+    z: f32,
+    #[serde(rename = "@heading", default)]
+    heading: u8,
+    #[serde(rename = "@count", default = "one")]
+    count: u32,
+    /// Seconds
+    #[serde(rename = "@respawn_delay")]
+    respawn_delay: u64,
+    /// NPCs are spread over and wander within this distance of the spawn
+    #[serde(rename = "@walk_radius", default)]
+    walk_radius: f32,
+}
+
+fn one() -> u32 {
+    1
+}
+
+enum Slot {
+    Empty {
+        spawn_at: u64,
+    },
+    Alive(u32),
+    Corpse {
+        id: u32,
+        despawn_at: u64,
+        respawn_at: u64,
+    },
+}
+
+struct SpawnPoint {
+    template: Arc<Template>,
+    location: Coord,
+    heading: u8,
+    walk_radius: f32,
+    respawn_delay: u64,
+    slots: Vec<Slot>,
+}
+
+impl SpawnPoint {
+    /// Somewhere random within the walk radius
+    fn random_location(&self) -> Coord {
+        if self.walk_radius <= 0. {
+            return self.location;
+        }
+        let mut rng = rand::thread_rng();
+        let angle = rng.gen_range(0. ..TAU);
+        let distance = rng.gen_range(0. ..self.walk_radius);
+        Coord::new(
+            self.location.x() + angle.cos() * distance,
+            self.location.y() + angle.sin() * distance,
+            self.location.z(),
+        )
+    }
+}
+
+/// NPCs that came and went during a tick, the caller keeps the world in sync
+#[derive(Default)]
+pub struct Changes {
+    pub spawned: Vec<u32>,
+    pub despawned: Vec<u32>,
+}
+
+pub struct Spawner {
+    points: Vec<SpawnPoint>,
+    tick: u64,
+}
+
+impl Spawner {
+    /// Load the spawns for a single world, spawns for NPCs we have no
+    /// template for are skipped
+    pub fn load(
+        path: &str,
+        templates: &Templates,
+        world_id: u32,
+    ) -> Result<Self> {
+        let xml = fs::read_to_string(path).map_err(Error::DataFile)?;
+        Self::parse(&xml, templates, world_id)
+    }
+
+    pub fn parse(
+        xml: &str,
+        templates: &Templates,
+        world_id: u32,
+    ) -> Result<Self> {
+        let table: SpawnTable = from_str(xml).map_err(Error::DataParse)?;
+
+        let mut points = Vec::new();
+        for spawn in table.spawns {
+            if spawn.world_id != world_id {
+                continue;
+            }
+            let Some(template) = templates.get(spawn.template_id) else {
+                println!(
+                    "WARNING: No template for NPC {}, skipping spawn",
+                    spawn.template_id
+                );
+                continue;
+            };
+            points.push(SpawnPoint {
+                template,
+                location: Coord::new(spawn.x, spawn.y, spawn.z),
+                heading: spawn.heading,
+                walk_radius: spawn.walk_radius,
+                respawn_delay: ticks(Duration::from_secs(spawn.respawn_delay)),
+                slots: (0..spawn.count)
+                    .map(|_| Slot::Empty { spawn_at: 0 })
+                    .collect(),
+            });
+        }
+
+        Ok(Self { points, tick: 0 })
+    }
+
+    /// Total NPCs across every spawn point
+    pub fn len(&self) -> usize {
+        self.points.iter().map(|point| point.slots.len()).sum()
+    }
+
+    /// Advance one tick, anything due to spawn is added to `entities` and
+    /// corpses that have been around long enough are removed from it
+    pub fn update(
+        &mut self,
+        entities: &mut HashMap<u32, Entity>,
+        mut allocate_id: impl FnMut() -> Option<u32>,
+    ) -> Changes {
+        self.tick += 1;
+        let now = self.tick;
+        let mut changes = Changes::default();
+
+        for point in &mut self.points {
+            for slot in 0..point.slots.len() {
+                match point.slots[slot] {
+                    Slot::Empty { spawn_at } if spawn_at <= now => {
+                        let Some(id) = allocate_id() else {
+                            continue;
+                        };
+                        let entity = Entity::new(
+                            id,
+                            point.random_location(),
+                            point.heading,
+                            point.walk_radius,
+                            point.template.clone(),
+                        );
+                        entities.insert(id, entity);
+                        point.slots[slot] = Slot::Alive(id);
+                        changes.spawned.push(id);
+                    }
+                    Slot::Alive(id) => {
+                        let dead = entities
+                            .get(&id)
+                            .is_none_or(|entity| entity.is_dead());
+                        if dead {
+                            point.slots[slot] = Slot::Corpse {
+                                id,
+                                despawn_at: now + ticks(CORPSE_DURATION),
+                                respawn_at: now + point.respawn_delay,
+                            };
+                        }
+                    }
+                    Slot::Corpse {
+                        id,
+                        despawn_at,
+                        respawn_at,
+                    } if despawn_at <= now => {
+                        entities.remove(&id);
+                        point.slots[slot] = Slot::Empty {
+                            spawn_at: respawn_at,
+                        };
+                        changes.despawned.push(id);
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        changes
+    }
+}
+
+fn ticks(duration: Duration) -> u64 {
+    (duration.as_secs_f32() * TICK_RATE) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEMPLATES: &str = "<npc_clients>
+        <npc_client>
+            <id>210564</id>
+            <name>LF1_Mosbear_Starved</name>
+            <name_id>300703</name_id>
+            <level>13</level>
+            <max_hp>1817</max_hp>
+            <attack_delay>1750</attack_delay>
+            <npc_type>Aggressive</npc_type>
+        </npc_client>
+    </npc_clients>";
+
+    #[test]
+    fn respawns_after_corpse_is_removed() {
+        let templates = Templates::parse(TEMPLATES).unwrap();
+        let mut spawner = Spawner::parse(
+            r#"<spawns>
+                <spawn template_id="210564" world_id="1" x="1" y="2" z="3"
+                    count="2" respawn_delay="0" walk_radius="5" />
+                <spawn template_id="210564" world_id="2" x="1" y="2" z="3"
+                    respawn_delay="0" />
+                <spawn template_id="1" world_id="1" x="1" y="2" z="3"
+                    respawn_delay="0" />
+            </spawns>"#,
+            &templates,
+            1,
+        )
+        .unwrap();
+        assert_eq!(spawner.len(), 2);
+
+        let mut entities = HashMap::new();
+        let mut next_id = 0;
+        let mut allocate_id = || {
+            next_id += 1;
+            Some(next_id)
+        };
+
+        let changes = spawner.update(&mut entities, &mut allocate_id);
+        assert_eq!(changes.spawned, vec![1, 2]);
+
+        entities.get_mut(&1).unwrap().hp.update(-10_000);
+        spawner.update(&mut entities, &mut allocate_id);
+        for _ in 0..ticks(CORPSE_DURATION) {
+            let changes = spawner.update(&mut entities, &mut allocate_id);
+            if !changes.despawned.is_empty() {
+                assert_eq!(changes.despawned, vec![1]);
+                break;
+            }
+        }
+        assert!(!entities.contains_key(&1));
+
+        let changes = spawner.update(&mut entities, &mut allocate_id);
+        assert_eq!(changes.spawned, vec![3]);
+    }
+}
@@ -0,0 +1,131 @@
+//! Chat, normal chat reaches everyone who can see the speaker, shouts reach
+//! everyone in the world, group chat the speaker's party and whispers go to
+//! one player by name. There are no legions yet so legion chat goes nowhere
+
+use super::super::character::Character;
+use super::super::message::{client as c, server as s};
+use super::super::{Messages, ServerUpdate};
+use super::State;
+
+// TODO: Verify these, they are from older versions of the game
+/// "No such player", takes the name
+const USER_NOT_FOUND: u32 = 1300013;
+/// "%0 is offline", takes the name
+const USER_OFFLINE: u32 = 1300627;
+// TODO: Find the real id, this one is a guess
+/// "You are not a member of a legion"
+const NOT_IN_LEGION: u32 = 1300375;
+
+impl State {
+    /// Normal chat goes to whoever can see the speaker, the other channels
+    /// to whoever is listening on them. Shouts are dropped quietly when the
+    /// speaker has shouted too recently
+    pub(super) fn say(
+        &mut self,
+        character_id: u32,
+        msg: c::Say,
+        messages: &mut Messages,
+    ) {
+        let Some(character) = self.characters.get_mut(&character_id) else {
+            return;
+        };
+        if msg.message.is_empty() {
+            return;
+        }
+        if matches!(msg.ty, c::ChatType::Shout) && !character.start_shout() {
+            return;
+        }
+        let update = ServerUpdate::new(s::Message::ChatMessage(
+            s::ChatMessage::new(character, msg.ty, msg.message),
+        ));
+
+        match msg.ty {
+            c::ChatType::Normal => messages
+                .observers
+                .entry(character_id)
+                .or_default()
+                .push(update),
+            c::ChatType::Shout => {
+                for listener in self.characters.values() {
+                    messages
+                        .direct
+                        .entry(listener.client_id())
+                        .or_default()
+                        .push(update.clone());
+                }
+            }
+            c::ChatType::Group => {
+                messages.party.entry(character_id).or_default().push(update)
+            }
+            c::ChatType::Legion => messages
+                .direct
+                .entry(character.client_id())
+                .or_default()
+                .push(ServerUpdate::new(s::Message::MessageCode(
+                    s::MessageCode::with_params(NOT_IN_LEGION, vec![]),
+                ))),
+            ty => println!("WARNING: Chat channel {ty:?} not handled"),
+        }
+    }
+
+    /// Whisper to a player in the world, the sender is told when they
+    /// aren't there
+    pub(super) fn whisper(
+        &mut self,
+        character_id: u32,
+        msg: c::Whisper,
+        messages: &mut Messages,
+    ) {
+        let Some(character) = self.characters.get(&character_id) else {
+            return;
+        };
+        if msg.message.is_empty() {
+            return;
+        }
+
+        let update = match self.online(&msg.name) {
+            Some(target) => {
+                messages.direct.entry(target.client_id()).or_default().push(
+                    ServerUpdate::new(s::Message::ChatMessage(
+                        s::ChatMessage::new(
+                            character,
+                            c::ChatType::Whisper,
+                            msg.message,
+                        ),
+                    )),
+                );
+                return;
+            }
+            None => self.not_online(msg.name),
+        };
+        messages
+            .direct
+            .entry(character.client_id())
+            .or_default()
+            .push(update);
+    }
+
+    /// A character in the world by name, names are unique ignoring case
+    pub(super) fn online(&self, name: &str) -> Option<&Character> {
+        self.characters
+            .values()
+            .find(|character| character.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Tell the sender the player they are after isn't in the world, or
+    /// doesn't exist at all
+    pub(super) fn not_online(&self, name: String) -> ServerUpdate {
+        let message_id = match self.repository.name_taken(&name) {
+            Ok(true) => USER_OFFLINE,
+            Ok(false) => USER_NOT_FOUND,
+            Err(err) => {
+                println!("ERROR: Failed to check name {name}: {err:?}");
+                USER_NOT_FOUND
+            }
+        };
+        ServerUpdate::new(s::Message::MessageCode(s::MessageCode::with_params(
+            message_id,
+            vec![name],
+        )))
+    }
+}
@@ -0,0 +1,141 @@
+//! Characters dying and coming back. A dead character drops out of every
+//! fight and waits on the death dialog until the player picks where to
+//! resurrect
+
+use super::super::character::Character;
+use super::super::data::ActionType;
+use super::super::engine::Coord;
+use super::super::message::{client as c, server as s};
+use super::super::{Messages, ServerUpdate, WORLD_ID};
+use super::{State, GAME_TIME, START_LOCATION};
+
+/// Where characters resurrect, there is no binding anywhere else yet
+// TODO: Read these from the client's world data
+const OBELISKS: [Coord; 1] = [START_LOCATION];
+
+impl State {
+    /// Deal with every character killed since the last update
+    pub(super) fn update_deaths(&mut self, messages: &mut Messages) {
+        let killed: Vec<(u32, u32)> = self
+            .characters
+            .values_mut()
+            .filter_map(|character| {
+                character
+                    .take_killer()
+                    .map(|killer| (character.id(), killer))
+            })
+            .collect();
+        for (character_id, killer_id) in killed {
+            self.die(character_id, killer_id, messages);
+        }
+    }
+
+    fn die(
+        &mut self,
+        character_id: u32,
+        killer_id: u32,
+        messages: &mut Messages,
+    ) {
+        self.cancel_cast(character_id, messages);
+        for entity in self.entities.values_mut() {
+            entity.forget(character_id);
+        }
+        let Some(character) = self.characters.get_mut(&character_id) else {
+            return;
+        };
+
+        let lost = character.stats.death_penalty();
+        println!(
+            "INFO: Character {character_id} was killed by {killer_id} and \
+             lost {lost} exp"
+        );
+        let had_effects = character.effects.clear();
+
+        messages.observers.entry(character_id).or_default().push(
+            ServerUpdate::new(s::Message::Action(s::Action::new(
+                character_id,
+                ActionType::Die,
+                character.stats.move_speed,
+                killer_id,
+            ))),
+        );
+        messages
+            .direct
+            .entry(character.client_id())
+            .or_default()
+            .extend(
+                [
+                    s::Message::Exp(s::Exp::new(
+                        character.stats.exp,
+                        character.stats.exp_to_level,
+                    )),
+                    s::Message::ResurrectInfo(s::ResurrectInfo),
+                ]
+                .map(ServerUpdate::new),
+            );
+        if had_effects {
+            self.show_effects(character_id, messages);
+        }
+    }
+
+    /// Resurrecting reloads the world on the client at the nearest obelisk,
+    /// it is put back into the world once the client says it is ready
+    pub(super) fn restart(
+        &mut self,
+        character_id: u32,
+        restart: c::Restart,
+        messages: &mut Messages,
+    ) {
+        let Some(character) = self.characters.get_mut(&character_id) else {
+            return;
+        };
+        if !character.is_dead() {
+            println!("WARNING: Character {character_id} isn't dead");
+            return;
+        }
+        if let c::Restart::Unknown(restart) = restart {
+            println!(
+                "WARNING: Unknown restart {restart} from {character_id}, \
+                 using the bind point"
+            );
+        }
+
+        let location = resurrect_location(character);
+        character.resurrect(location);
+        println!("INFO: Character {character_id} resurrected at {location:?}");
+        messages
+            .direct
+            .entry(character.client_id())
+            .or_default()
+            .extend(
+                [
+                    s::Message::World(s::World::new(&location)),
+                    s::Message::Status(s::Status::new(character, GAME_TIME)),
+                ]
+                .map(ServerUpdate::new),
+            );
+
+        let visibility = self.world.remove(character_id);
+        self.show(visibility, messages);
+    }
+
+    /// Tell the client where it will resurrect
+    pub(super) fn resurrect_loc_info(&self, character_id: u32) -> s::Message {
+        let location = self
+            .characters
+            .get(&character_id)
+            .map_or(START_LOCATION, resurrect_location);
+        s::Message::ResurrectLocInfo(s::ResurrectLocInfo::new(
+            WORLD_ID, location,
+        ))
+    }
+}
+
+/// The nearest obelisk
+fn resurrect_location(character: &Character) -> Coord {
+    let location = character.location();
+    OBELISKS
+        .into_iter()
+        .min_by(|a, b| location.distance(a).total_cmp(&location.distance(b)))
+        .unwrap_or(START_LOCATION)
+}
@@ -0,0 +1,198 @@
+//! Buffs and debuffs on characters and NPCs. Skills put them on, they tick
+//! every [State::update] and everyone who can see the object is shown them on
+//! its status bar
+
+use super::super::engine::effect::{Applied, Effects};
+use super::super::engine::skill::Cast;
+use super::super::message::server as s;
+use super::super::{Messages, ServerUpdate};
+use super::{death, State};
+
+impl State {
+    /// Put the timed effects of a skill that just went off on its target
+    pub(super) fn apply_effects(
+        &mut self,
+        caster_id: u32,
+        cast: &Cast,
+        messages: &mut Messages,
+    ) {
+        let effects = if let Some(entity) = self
+            .entities
+            .get_mut(&cast.target_id)
+            .filter(|entity| !entity.is_dead())
+        {
+            &mut entity.effects
+        } else if let Some(character) = self.characters.get_mut(&cast.target_id)
+        {
+            &mut character.effects
+        } else {
+            return;
+        };
+
+        let applied =
+            effects.apply(cast.template.clone(), cast.level as u8, caster_id);
+        if applied == Applied::Refused {
+            println!(
+                "INFO: {} already has something stronger than skill {}",
+                cast.target_id, cast.template.id
+            );
+            return;
+        }
+        self.show_effects(cast.target_id, messages);
+    }
+
+    /// Tell an object and everyone that can see it what effects it has
+    pub(super) fn show_effects(&self, id: u32, messages: &mut Messages) {
+        if let Some(character) = self.characters.get(&id) {
+            let effects = status_bar(&character.effects);
+            messages
+                .direct
+                .entry(character.client_id())
+                .or_default()
+                .push(ServerUpdate::new(s::Message::AbnormalStatus(
+                    s::AbnormalStatus::new(
+                        character.effects.state(),
+                        effects.clone(),
+                    ),
+                )));
+            messages
+                .others
+                .entry(id)
+                .or_default()
+                .push(ServerUpdate::new(s::Message::AbnormalStatusOther(
+                    s::AbnormalStatusOther::new(
+                        id,
+                        s::EntityType::Character,
+                        effects,
+                    ),
+                )));
+        } else if let Some(entity) = self.entities.get(&id) {
+            messages
+                .observers
+                .entry(id)
+                .or_default()
+                .push(ServerUpdate::new(s::Message::AbnormalStatusOther(
+                    s::AbnormalStatusOther::new(
+                        id,
+                        s::EntityType::Npc,
+                        status_bar(&entity.effects),
+                    ),
+                )));
+        }
+    }
+
+    /// Damage and heal over time go off and effects that ran out are dropped
+    pub(super) fn update_effects(&mut self, messages: &mut Messages) {
+        let mut changed = Vec::new();
+        let mut kills = Vec::new();
+
+        for character in self.characters.values_mut() {
+            if character.is_dead() {
+                continue;
+            }
+            let ticked = character.effects.tick();
+            if ticked.expired {
+                changed.push(character.id());
+            }
+            if ticked.damage.is_empty() && ticked.heal == 0 {
+                continue;
+            }
+
+            let hp = &mut character.stats.hp;
+            hp.set_current(hp.current() + ticked.heal);
+            for (caster_id, damage) in ticked.damage {
+                character.hurt(damage, caster_id);
+            }
+            let hp = &character.stats.hp;
+            let (hit_point, percent) =
+                (s::HitPoint::new(hp.current(), hp.max()), hp.percent());
+            messages
+                .direct
+                .entry(character.client_id())
+                .or_default()
+                .push(ServerUpdate::new(s::Message::HitPoint(hit_point)));
+            messages.others.entry(character.id()).or_default().push(
+                ServerUpdate::new(s::Message::HitPointOther(
+                    s::HitPointOther::new(character.id(), percent),
+                )),
+            );
+        }
+
+        for entity in self.entities.values_mut() {
+            if entity.is_dead() {
+                continue;
+            }
+            let ticked = entity.effects.tick();
+            if ticked.expired {
+                changed.push(entity.id());
+            }
+            if ticked.damage.is_empty() && ticked.heal == 0 {
+                continue;
+            }
+
+            entity.hp.set_current(entity.hp.current() + ticked.heal);
+            for (caster_id, damage) in ticked.damage {
+                entity.hp.update(-damage);
+                entity.add_hate(caster_id, damage);
+                if entity.is_dead() {
+                    let move_speed = self
+                        .characters
+                        .get(&caster_id)
+                        .map_or(0., |caster| caster.stats.move_speed);
+                    messages.observers.entry(entity.id()).or_default().extend(
+                        death(entity.id(), caster_id, move_speed)
+                            .map(ServerUpdate::new),
+                    );
+                    kills.push((entity.id(), caster_id));
+                    break;
+                }
+            }
+            messages.observers.entry(entity.id()).or_default().push(
+                ServerUpdate::new(s::Message::HitPointOther(
+                    s::HitPointOther::new(entity.id(), entity.hp.percent()),
+                )),
+            );
+        }
+
+        for id in changed {
+            self.show_effects(id, messages);
+        }
+        for (entity_id, killer_id) in kills {
+            self.reward_kill(entity_id, killer_id, messages);
+        }
+    }
+
+    /// Players can click off their own buffs but not their debuffs
+    pub(super) fn turn_off_effect(
+        &mut self,
+        character_id: u32,
+        skill_id: u16,
+        messages: &mut Messages,
+    ) {
+        let Some(character) = self.characters.get_mut(&character_id) else {
+            return;
+        };
+        match character.effects.has(skill_id) {
+            None => return,
+            Some(template) if template.is_harmful() => {
+                println!(
+                    "WARNING: Character {character_id} tried to turn off \
+                     debuff {skill_id}"
+                );
+                return;
+            }
+            Some(_) => character.effects.remove(skill_id),
+        };
+        self.show_effects(character_id, messages);
+    }
+}
+
+pub(super) fn status_bar(effects: &Effects) -> Vec<s::Effect> {
+    effects
+        .statuses()
+        .into_iter()
+        .map(|(skill_id, level, slot, remaining)| {
+            s::Effect::new(skill_id, level, slot, remaining.as_millis() as u32)
+        })
+        .collect()
+}
@@ -0,0 +1,437 @@
+//! Trading between two characters standing next to each other. Nothing
+//! changes hands until both have locked their offers in and accepted, then
+//! everything does at once
+
+use std::mem;
+
+use super::super::engine::exchange::{self, Offer};
+use super::super::id::ObjectKind;
+use super::super::message::{client as c, server as s};
+use super::super::{Messages, ServerUpdate};
+use super::inventory::inventory_updates;
+use super::State;
+
+/// "%0 wants to trade with you"
+pub(super) const EXCHANGE_QUESTION: u32 = 90001;
+// TODO: Verify this, it is from older versions of the game
+/// "%0 declined your request to trade", takes the name
+const EXCHANGE_DECLINED: u32 = 1300354;
+/// How close the two characters have to be to start and finish a trade
+const EXCHANGE_RANGE: f32 = 10.;
+
+impl State {
+    pub(super) fn ask_exchange(
+        &mut self,
+        character_id: u32,
+        msg: c::AskXchg,
+        messages: &mut Messages,
+    ) {
+        let (Some(character), Some(target)) = (
+            self.characters.get(&character_id),
+            self.characters.get(&msg.target_id),
+        ) else {
+            return;
+        };
+        let result = if self.in_range(character_id, msg.target_id) {
+            self.exchanges.request(character_id, msg.target_id)
+        } else {
+            Err("too far away")
+        };
+        if let Err(err) = result {
+            println!(
+                "WARNING: Character {character_id} can't trade with {}: {err}",
+                msg.target_id
+            );
+            return;
+        }
+
+        messages.direct.entry(target.client_id()).or_default().push(
+            ServerUpdate::new(s::Message::Ask(s::Ask::new(
+                EXCHANGE_QUESTION,
+                character_id,
+                vec![character.name().clone()],
+            ))),
+        );
+    }
+
+    /// Open the trade window on both sides or tell whoever asked they were
+    /// turned down
+    pub(super) fn answer_exchange(
+        &mut self,
+        character_id: u32,
+        msg: c::Answer,
+        messages: &mut Messages,
+    ) {
+        let Some(from) = self.exchanges.take_request(character_id) else {
+            println!("WARNING: Character {character_id} wasn't asked to trade");
+            return;
+        };
+        let (Some(character), Some(asker)) = (
+            self.characters.get(&character_id),
+            self.characters.get(&from),
+        ) else {
+            return;
+        };
+        if msg.sender_id != from {
+            println!(
+                "WARNING: Character {character_id} answered {} but was asked \
+                 by {from}",
+                msg.sender_id
+            );
+            return;
+        }
+        if !msg.accepted {
+            messages.direct.entry(asker.client_id()).or_default().push(
+                ServerUpdate::new(s::Message::MessageCode(
+                    s::MessageCode::with_params(
+                        EXCHANGE_DECLINED,
+                        vec![character.name().clone()],
+                    ),
+                )),
+            );
+            return;
+        }
+
+        let result = if self.in_range(character_id, from) {
+            self.exchanges.start(from, character_id)
+        } else {
+            Err("too far away")
+        };
+        if let Err(err) = result {
+            println!(
+                "WARNING: Character {character_id} can't trade with {from}: \
+                 {err}"
+            );
+            return;
+        }
+        for (character, partner) in [(character, asker), (asker, character)] {
+            messages
+                .direct
+                .entry(character.client_id())
+                .or_default()
+                .push(ServerUpdate::new(s::Message::XchgStart(
+                    s::XchgStart::new(partner.name().clone()),
+                )));
+        }
+    }
+
+    /// Put up some of an item, the partner sees it straight away
+    pub(super) fn add_exchange_item(
+        &mut self,
+        character_id: u32,
+        msg: c::AddXchg,
+        messages: &mut Messages,
+    ) {
+        let Some(character) = self.characters.get(&character_id) else {
+            return;
+        };
+        let total = self
+            .exchanges
+            .offer(character_id)
+            .ok_or("not trading")
+            .and_then(|offer| {
+                offer
+                    .count(msg.item_id)
+                    .checked_add(msg.count)
+                    .ok_or("bad count")
+            });
+        let result = total.and_then(|total| {
+            let mut item =
+                character.inventory.tradable(msg.item_id, total)?.clone();
+            item.count = total;
+            self.exchanges
+                .add_item(character_id, msg.item_id, msg.count)?;
+            Ok(item)
+        });
+        let item = match result {
+            Ok(item) => item,
+            Err(err) => {
+                println!(
+                    "WARNING: Character {character_id} can't trade {} of {}: \
+                     {err}",
+                    msg.count, msg.item_id
+                );
+                return;
+            }
+        };
+
+        self.tell_both(
+            character_id,
+            |side| s::Message::AddXchg(s::AddXchg::new(side, item.clone())),
+            messages,
+        );
+    }
+
+    pub(super) fn remove_exchange_item(
+        &mut self,
+        character_id: u32,
+        msg: c::RemoveXchg,
+        messages: &mut Messages,
+    ) {
+        if let Err(err) = self.exchanges.remove_item(character_id, msg.item_id)
+        {
+            println!(
+                "WARNING: Character {character_id} can't take back {}: {err}",
+                msg.item_id
+            );
+            return;
+        }
+        self.tell_both(
+            character_id,
+            |side| {
+                s::Message::RemoveXchg(s::RemoveXchg::new(side, msg.item_id))
+            },
+            messages,
+        );
+    }
+
+    /// Put up more kinah, the partner sees the new total
+    pub(super) fn add_exchange_kinah(
+        &mut self,
+        character_id: u32,
+        msg: c::XchgGold,
+        messages: &mut Messages,
+    ) {
+        let Some(character) = self.characters.get(&character_id) else {
+            return;
+        };
+        let kinah = character.kinah();
+        let result = self
+            .exchanges
+            .offer(character_id)
+            .ok_or("not trading")
+            .and_then(|offer| {
+                offer
+                    .kinah
+                    .checked_add(msg.kinah)
+                    .filter(|offered| *offered <= kinah)
+                    .ok_or("not enough kinah")
+            })
+            .and_then(|offered| {
+                self.exchanges.add_kinah(character_id, msg.kinah)?;
+                Ok(offered)
+            });
+        let offered = match result {
+            Ok(offered) => offered,
+            Err(err) => {
+                println!(
+                    "WARNING: Character {character_id} can't trade {} kinah: \
+                     {err}",
+                    msg.kinah
+                );
+                return;
+            }
+        };
+
+        self.tell_both(
+            character_id,
+            |side| s::Message::XchgGold(s::XchgGold::new(side, offered)),
+            messages,
+        );
+    }
+
+    /// The first confirmation, the offer can't change after this
+    pub(super) fn lock_exchange(
+        &mut self,
+        character_id: u32,
+        messages: &mut Messages,
+    ) {
+        if let Err(err) = self.exchanges.lock(character_id) {
+            println!("WARNING: Character {character_id} can't lock: {err}");
+            return;
+        }
+        self.tell_partner(character_id, s::XchgStatus::Locked, messages);
+    }
+
+    /// The second confirmation, once both sides have given it the trade goes
+    /// through
+    pub(super) fn accept_exchange(
+        &mut self,
+        character_id: u32,
+        messages: &mut Messages,
+    ) {
+        match self.exchanges.accept(character_id) {
+            Ok(false) => self.tell_partner(
+                character_id,
+                s::XchgStatus::Accepted,
+                messages,
+            ),
+            Ok(true) => {
+                let Some((partner, offer, other)) =
+                    self.exchanges.end(character_id)
+                else {
+                    return;
+                };
+                let status = match self.complete_exchange(
+                    (character_id, &offer),
+                    (partner, &other),
+                    messages,
+                ) {
+                    Ok(()) => s::XchgStatus::Completed,
+                    Err(err) => {
+                        println!(
+                            "WARNING: Character {character_id} can't trade \
+                             with {partner}: {err}"
+                        );
+                        s::XchgStatus::Cancelled
+                    }
+                };
+                for id in [character_id, partner] {
+                    self.send_to(
+                        id,
+                        s::Message::XchgResult(s::XchgResult::new(status)),
+                        messages,
+                    );
+                }
+            }
+            Err(err) => {
+                println!(
+                    "WARNING: Character {character_id} can't accept: {err}"
+                )
+            }
+        }
+    }
+
+    /// Close the trade window on both sides, also used when either of them
+    /// leaves the world
+    pub(super) fn cancel_exchange(
+        &mut self,
+        character_id: u32,
+        messages: &mut Messages,
+    ) {
+        let Some((partner, _, _)) = self.exchanges.end(character_id) else {
+            return;
+        };
+        for id in [character_id, partner] {
+            self.send_to(
+                id,
+                s::Message::XchgResult(s::XchgResult::new(
+                    s::XchgStatus::Cancelled,
+                )),
+                messages,
+            );
+        }
+    }
+
+    /// Hand both offers over, nothing changes unless all of it can
+    fn complete_exchange(
+        &mut self,
+        (id, offer): (u32, &Offer),
+        (partner_id, other): (u32, &Offer),
+        messages: &mut Messages,
+    ) -> Result<(), &'static str> {
+        if !self.in_range(id, partner_id) {
+            return Err("too far away");
+        }
+        let [Some(character), Some(partner)] =
+            self.characters.get_disjoint_mut([&id, &partner_id])
+        else {
+            return Err("not in the world");
+        };
+        // Both sides are worked out on copies and only swapped in once all
+        // of it went through
+        let (mut from, mut to) =
+            (character.inventory.clone(), partner.inventory.clone());
+        exchange::check(&from, offer, &to)?;
+        exchange::check(&to, other, &from)?;
+
+        let (ids, repository) = (&mut self.ids, &mut self.repository);
+        let needed = exchange::new_ids(&from, offer, &to)
+            + exchange::new_ids(&to, other, &from);
+        let mut new_ids = (0..needed)
+            .map(|_| {
+                ids.allocate(ObjectKind::InventoryItem, repository.as_mut())
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| {
+                println!("ERROR: Failed to allocate item id: {err:?}");
+                "no id"
+            })?;
+        let (mut given, mut received) =
+            exchange::hand_over(&mut from, offer, &mut to, || new_ids.pop())?;
+        let (other_given, other_received) =
+            exchange::hand_over(&mut to, other, &mut from, || new_ids.pop())?;
+        given.extend(other_received);
+        received.extend(other_given);
+
+        let inventories = (
+            mem::replace(&mut character.inventory, from),
+            mem::replace(&mut partner.inventory, to),
+        );
+        if let Err(err) = repository.save_all(&[character, partner]) {
+            println!(
+                "ERROR: Failed to save the trade between {id} and \
+                 {partner_id}: {err:?}"
+            );
+            (character.inventory, partner.inventory) = inventories;
+            return Err("repository error");
+        }
+        println!("INFO: Characters {id} and {partner_id} traded");
+
+        messages
+            .direct
+            .entry(character.client_id())
+            .or_default()
+            .extend(inventory_updates(&character.inventory, &given));
+        messages
+            .direct
+            .entry(partner.client_id())
+            .or_default()
+            .extend(inventory_updates(&partner.inventory, &received));
+        Ok(())
+    }
+
+    fn in_range(&self, id: u32, other: u32) -> bool {
+        match (self.characters.get(&id), self.characters.get(&other)) {
+            (Some(character), Some(other)) => {
+                character.location().distance(other.location())
+                    <= EXCHANGE_RANGE
+            }
+            _ => false,
+        }
+    }
+
+    /// Show a change to one side's offer on both trade windows
+    fn tell_both(
+        &self,
+        character_id: u32,
+        message: impl Fn(s::XchgSide) -> s::Message,
+        messages: &mut Messages,
+    ) {
+        let Some(partner) = self.exchanges.partner(character_id) else {
+            return;
+        };
+        self.send_to(character_id, message(s::XchgSide::Own), messages);
+        self.send_to(partner, message(s::XchgSide::Partner), messages);
+    }
+
+    fn tell_partner(
+        &self,
+        character_id: u32,
+        status: s::XchgStatus,
+        messages: &mut Messages,
+    ) {
+        if let Some(partner) = self.exchanges.partner(character_id) {
+            self.send_to(
+                partner,
+                s::Message::XchgResult(s::XchgResult::new(status)),
+                messages,
+            );
+        }
+    }
+
+    fn send_to(
+        &self,
+        character_id: u32,
+        message: s::Message,
+        messages: &mut Messages,
+    ) {
+        if let Some(character) = self.characters.get(&character_id) {
+            messages
+                .direct
+                .entry(character.client_id())
+                .or_default()
+                .push(ServerUpdate::new(message));
+        }
+    }
+}
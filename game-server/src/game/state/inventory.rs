@@ -0,0 +1,160 @@
+//! Rearranging the cube and putting gear on and taking it off. Only the cube
+//! is supported so far, the warehouses and the pet bags aren't
+
+use super::super::engine::inventory::{Change, Inventory};
+use super::super::id::ObjectKind;
+use super::super::message::{client as c, server as s};
+use super::super::{Messages, ServerUpdate};
+use super::{State, GAME_TIME};
+
+/// The storage id of the cube
+const CUBE: u8 = 0;
+
+impl State {
+    /// Swap an item with whatever is in the slot it is dragged to
+    pub(super) fn move_item(
+        &mut self,
+        character_id: u32,
+        msg: c::MoveItemToAnotherSlot,
+        messages: &mut Messages,
+    ) {
+        let Some(character) = self.characters.get_mut(&character_id) else {
+            return;
+        };
+        if msg.source != CUBE || msg.destination != CUBE {
+            println!(
+                "WARNING: Storage {} to {} not handled",
+                msg.source, msg.destination
+            );
+            return;
+        }
+        match character.inventory.move_to(msg.item_id, msg.slot) {
+            Ok(changes) => messages
+                .direct
+                .entry(character.client_id())
+                .or_default()
+                .extend(inventory_updates(&character.inventory, &changes)),
+            Err(err) => println!(
+                "WARNING: Character {character_id} can't move {}: {err}",
+                msg.item_id
+            ),
+        }
+    }
+
+    /// Split a stack into an empty slot, or merge it onto another stack when
+    /// there is a target
+    pub(super) fn move_stackable_item(
+        &mut self,
+        character_id: u32,
+        msg: c::MoveStackableItem,
+        messages: &mut Messages,
+    ) {
+        let Some(character) = self.characters.get_mut(&character_id) else {
+            return;
+        };
+        if msg.source != CUBE || msg.destination != CUBE {
+            println!(
+                "WARNING: Storage {} to {} not handled",
+                msg.source, msg.destination
+            );
+            return;
+        }
+        let inventory = &mut character.inventory;
+        let (ids, repository) = (&mut self.ids, &mut self.repository);
+        let changes = if msg.target_id == 0 {
+            inventory.split(msg.source_id, msg.count, msg.slot, || {
+                ids.allocate(ObjectKind::InventoryItem, repository.as_mut())
+                    .map_err(|err| {
+                        println!("ERROR: Failed to allocate item id: {err:?}")
+                    })
+                    .ok()
+            })
+        } else {
+            inventory.merge(msg.source_id, msg.target_id, msg.count)
+        };
+        match changes {
+            Ok(changes) => messages
+                .direct
+                .entry(character.client_id())
+                .or_default()
+                .extend(inventory_updates(&character.inventory, &changes)),
+            Err(err) => println!(
+                "WARNING: Character {character_id} can't move {} of {}: {err}",
+                msg.count, msg.source_id
+            ),
+        }
+    }
+
+    /// Equipment changes the character's stats and what everyone nearby
+    /// sees them wearing
+    pub(super) fn use_equipment_item(
+        &mut self,
+        character_id: u32,
+        msg: c::UseEquipmentItem,
+        messages: &mut Messages,
+    ) {
+        let Some(character) = self.characters.get_mut(&character_id) else {
+            return;
+        };
+        let changes = match msg.action {
+            c::EquipAction::Equip => {
+                character.equip(msg.item_id, msg.slot, &self.items)
+            }
+            c::EquipAction::Unequip => {
+                character.unequip(msg.item_id, &self.items)
+            }
+            action => {
+                println!("WARNING: Equip action {action:?} not handled");
+                return;
+            }
+        };
+        let changes = match changes {
+            Ok(changes) => changes,
+            Err(err) => {
+                println!(
+                    "WARNING: Character {character_id} can't {:?} {}: {err}",
+                    msg.action, msg.item_id
+                );
+                return;
+            }
+        };
+
+        let direct = messages.direct.entry(character.client_id()).or_default();
+        direct.extend(inventory_updates(&character.inventory, &changes));
+        direct.push(ServerUpdate::new(s::Message::Status(s::Status::new(
+            character, GAME_TIME,
+        ))));
+        messages.observers.entry(character_id).or_default().push(
+            ServerUpdate::new(s::Message::Wield(s::Wield::new(
+                character_id,
+                character.gear.clone(),
+            ))),
+        );
+    }
+}
+
+/// Tell the client about every item that was added, changed or removed
+pub(super) fn inventory_updates(
+    inventory: &Inventory,
+    changes: &[Change],
+) -> Vec<ServerUpdate> {
+    changes
+        .iter()
+        .filter_map(|change| {
+            Some(match *change {
+                Change::Added(id) => {
+                    s::Message::AddInventory(s::AddInventory::new(vec![
+                        inventory.get(id)?.clone(),
+                    ]))
+                }
+                Change::Updated(id) => s::Message::ChangeItemDesc(
+                    s::ChangeItemDesc::new(inventory.get(id)?.clone()),
+                ),
+                Change::Removed(id) => {
+                    s::Message::RemoveInventory(s::RemoveInventory::new(id))
+                }
+            })
+        })
+        .map(ServerUpdate::new)
+        .collect()
+}
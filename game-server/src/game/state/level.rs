@@ -0,0 +1,85 @@
+//! Experience from kills and the level ups it pays for
+
+use super::super::engine::level;
+use super::super::message::server as s;
+use super::super::{Messages, ServerUpdate};
+use super::{State, GAME_TIME};
+
+impl State {
+    /// Whoever landed the killing blow is paid for the NPC and gets its loot,
+    /// shared with their party when they are in one
+    pub(super) fn reward_kill(
+        &mut self,
+        entity_id: u32,
+        killer_id: u32,
+        messages: &mut Messages,
+    ) {
+        self.drop_loot(entity_id, killer_id);
+        let Some(entity) = self.entities.get(&entity_id) else {
+            return;
+        };
+        let (members, levels): (Vec<u32>, Vec<u16>) = self
+            .kill_party(killer_id, entity.location())
+            .into_iter()
+            .filter_map(|member| {
+                Some((member, self.characters.get(&member)?.stats.level()))
+            })
+            .unzip();
+        let Some(highest) = levels.iter().max() else {
+            return;
+        };
+        let exp = level::kill_exp(entity.template(), *highest);
+        for (member, exp) in
+            members.into_iter().zip(level::share_exp(exp, &levels))
+        {
+            self.gain_exp(member, exp, messages);
+        }
+    }
+
+    /// A level up sends the client its new stats and plays the level up
+    /// effect for everyone nearby
+    pub(super) fn gain_exp(
+        &mut self,
+        character_id: u32,
+        exp: u64,
+        messages: &mut Messages,
+    ) {
+        let Some(character) = self.characters.get_mut(&character_id) else {
+            return;
+        };
+        if exp == 0 {
+            return;
+        }
+
+        let levels = character.gain_exp(exp);
+        let stats = &character.stats;
+        let direct = messages.direct.entry(character.client_id()).or_default();
+        direct.push(ServerUpdate::new(s::Message::Exp(s::Exp::new(
+            stats.exp,
+            stats.exp_to_level,
+        ))));
+        if levels == 0 {
+            return;
+        }
+
+        println!(
+            "INFO: Character {character_id} reached level {}",
+            stats.level()
+        );
+        direct.push(ServerUpdate::new(s::Message::Status(s::Status::new(
+            character, GAME_TIME,
+        ))));
+        messages.observers.entry(character_id).or_default().push(
+            ServerUpdate::new(s::Message::PlayEffect(s::PlayEffect::level_up(
+                character_id,
+                stats.level(),
+            ))),
+        );
+        // Levelling up is a full heal
+        messages.others.entry(character_id).or_default().push(
+            ServerUpdate::new(s::Message::HitPointOther(
+                s::HitPointOther::new(character_id, stats.hp.percent()),
+            )),
+        );
+    }
+}
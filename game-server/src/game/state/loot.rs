@@ -0,0 +1,188 @@
+//! Looting corpses. Drops are rolled from the NPC's drop table when it dies,
+//! only the killer or whoever their party's loot rule picks can take them and
+//! whatever they take goes in their inventory
+
+use super::super::data::gear::LootItem;
+use super::super::data::ActionType;
+use super::super::engine::loot::Loot;
+use super::super::id::ObjectKind;
+use super::super::message::server as s;
+use super::super::{Messages, ServerUpdate};
+use super::inventory::inventory_updates;
+use super::State;
+
+/// How close a character has to be to a corpse to loot it
+const LOOT_RANGE: f32 = 10.;
+
+impl State {
+    /// Leave whatever the NPC dropped on its corpse, who can loot it is down
+    /// to the killer's party
+    pub(super) fn drop_loot(&mut self, entity_id: u32, killer_id: u32) {
+        let Some(entity) = self.entities.get(&entity_id) else {
+            return;
+        };
+        let Some(table) = self.drops.get(entity.template().id) else {
+            return;
+        };
+        let items = table.roll(&mut rand::thread_rng());
+        if items.is_empty() {
+            return;
+        }
+
+        let nearby = self.kill_party(killer_id, entity.location());
+        let owners = match self.parties.get_mut(killer_id) {
+            Some(party) => party.looters(&nearby),
+            None => nearby,
+        };
+        if let Some(entity) = self.entities.get_mut(&entity_id) {
+            entity.loot = Some(Loot::new(owners, items));
+        }
+    }
+
+    /// Open the loot window, anyone without the right to loot the corpse has
+    /// it closed straight away
+    pub(super) fn start_loot(
+        &mut self,
+        character_id: u32,
+        entity_id: u32,
+        messages: &mut Messages,
+    ) {
+        let Some(character) = self.characters.get(&character_id) else {
+            return;
+        };
+        let direct = messages.direct.entry(character.client_id()).or_default();
+        let Some(loot) = self.lootable(character_id, entity_id) else {
+            println!(
+                "WARNING: Character {character_id} can't loot {entity_id}"
+            );
+            direct.push(ServerUpdate::new(s::Message::Loot(s::Loot::new(
+                entity_id, 3,
+            ))));
+            return;
+        };
+
+        direct.extend(
+            [
+                s::Message::LootItemlist(loot_list(entity_id, loot)),
+                s::Message::Loot(s::Loot::new(entity_id, 2)),
+            ]
+            .map(ServerUpdate::new),
+        );
+        messages.observers.entry(character_id).or_default().push(
+            ServerUpdate::new(s::Message::Action(s::Action::new(
+                character_id,
+                ActionType::Loot,
+                character.stats.move_speed,
+                entity_id,
+            ))),
+        );
+    }
+
+    /// Move an item off the corpse into the character's inventory, the window
+    /// closes once the corpse is empty
+    pub(super) fn loot_item(
+        &mut self,
+        character_id: u32,
+        entity_id: u32,
+        index: u32,
+        messages: &mut Messages,
+    ) {
+        let Some(client_id) = self
+            .characters
+            .get(&character_id)
+            .map(|character| character.client_id())
+        else {
+            return;
+        };
+        if self.lootable(character_id, entity_id).is_none() {
+            println!(
+                "WARNING: Character {character_id} can't loot {entity_id}"
+            );
+            return;
+        }
+        let Some(loot) = self
+            .entities
+            .get_mut(&entity_id)
+            .and_then(|entity| entity.loot.as_mut())
+        else {
+            return;
+        };
+        let Some(dropped) = loot.take(index) else {
+            println!("WARNING: Nothing at {index} on {entity_id}");
+            return;
+        };
+
+        let character = self.characters.get_mut(&character_id).unwrap();
+        let (ids, repository) = (&mut self.ids, &mut self.repository);
+        let stored = character.inventory.store(
+            dropped.item_id,
+            dropped.name_id,
+            dropped.count,
+            || match ids
+                .allocate(ObjectKind::InventoryItem, repository.as_mut())
+            {
+                Ok(id) => Some(id),
+                Err(err) => {
+                    println!("ERROR: Failed to allocate item id: {err:?}");
+                    None
+                }
+            },
+        );
+        let Some(changes) = stored else {
+            println!(
+                "WARNING: Character {character_id} has no room for {}",
+                dropped.item_id
+            );
+            loot.put_back(index, dropped);
+            return;
+        };
+        let direct = messages.direct.entry(client_id).or_default();
+        direct.extend(inventory_updates(&character.inventory, &changes));
+        let move_speed = character.stats.move_speed;
+
+        if !loot.is_empty() {
+            direct.push(ServerUpdate::new(s::Message::LootItemlist(
+                loot_list(entity_id, loot),
+            )));
+            return;
+        }
+        if let Some(entity) = self.entities.get_mut(&entity_id) {
+            entity.loot = None;
+        }
+        direct.extend(
+            [
+                s::Message::Action(s::Action::new(
+                    character_id,
+                    ActionType::EndLoot,
+                    move_speed,
+                    entity_id,
+                )),
+                s::Message::Loot(s::Loot::new(entity_id, 3)),
+            ]
+            .map(ServerUpdate::new),
+        );
+    }
+
+    /// The corpse's loot if the character is allowed to take it and is
+    /// standing next to it
+    fn lootable(&self, character_id: u32, entity_id: u32) -> Option<&Loot> {
+        let location = self.characters.get(&character_id)?.location();
+        self.entities
+            .get(&entity_id)
+            .filter(|entity| entity.is_dead())
+            .filter(|entity| location.distance(entity.location()) <= LOOT_RANGE)
+            .and_then(|entity| entity.loot.as_ref())
+            .filter(|loot| loot.can_loot(character_id))
+    }
+}
+
+fn loot_list(entity_id: u32, loot: &Loot) -> s::LootItemlist {
+    s::LootItemlist::new(
+        entity_id,
+        loot.items()
+            .map(|(index, item)| {
+                (index, LootItem::new(item.item_id, item.count))
+            })
+            .collect(),
+    )
+}
@@ -0,0 +1,378 @@
+//! Mail, letters go to characters by name whether they are in the world or
+//! not. Letters live in the repository rather than on the characters, and
+//! anything attached is saved together with the inventory it moved out of
+//! or into
+
+use super::super::engine::mail::{self, Letter, EXPIRY, MAILBOX_SIZE};
+use super::super::id::ObjectKind;
+use super::super::message::{client as c, server as s};
+use super::super::{Messages, ServerUpdate, TICK_RATE};
+use super::inventory::inventory_updates;
+use super::{unix_time, State};
+
+/// Longest title and message the client lets players type, in characters
+const MAX_TITLE_LEN: usize = 20;
+const MAX_MESSAGE_LEN: usize = 1000;
+/// Expired letters are returned or thrown away this often
+pub(super) const EXPIRY_INTERVAL: f32 = 60. * 60. * TICK_RATE;
+
+impl State {
+    /// Take the attachments out of the sender's inventory and put the letter
+    /// in the recipient's mailbox
+    pub(super) fn send_mail(
+        &mut self,
+        character_id: u32,
+        msg: c::MailWrite,
+        messages: &mut Messages,
+    ) {
+        let recipient = msg.recipient.clone();
+        let result = match self.post(character_id, msg, messages) {
+            Ok(recipient_id) => {
+                self.notify_unread(recipient_id, messages);
+                s::MailResult::Sent
+            }
+            Err((result, err)) => {
+                println!(
+                    "WARNING: Character {character_id} can't mail \
+                     {recipient}: {err}"
+                );
+                result
+            }
+        };
+        let Some(character) = self.characters.get(&character_id) else {
+            return;
+        };
+        messages
+            .direct
+            .entry(character.client_id())
+            .or_default()
+            .push(ServerUpdate::new(s::Message::Mail(s::Mail::result(result))));
+    }
+
+    /// Store the letter and the sender's emptier inventory, returning who it
+    /// went to
+    fn post(
+        &mut self,
+        character_id: u32,
+        msg: c::MailWrite,
+        messages: &mut Messages,
+    ) -> Result<u32, (s::MailResult, &'static str)> {
+        let failed = |err| (s::MailResult::Failed, err);
+        let (recipient_id, recipient_name) = match self
+            .repository
+            .find_name(&msg.recipient)
+        {
+            Ok(Some(recipient)) => recipient,
+            Ok(None) => {
+                return Err((s::MailResult::NoRecipient, "no such name"))
+            }
+            Err(err) => {
+                println!("ERROR: Failed to find {}: {err:?}", msg.recipient);
+                return Err(failed("repository error"));
+            }
+        };
+        if recipient_id == character_id {
+            return Err((s::MailResult::NoRecipient, "sent to self"));
+        }
+        if msg.title.chars().count() > MAX_TITLE_LEN
+            || msg.message.chars().count() > MAX_MESSAGE_LEN
+        {
+            return Err(failed("too long"));
+        }
+        match self.repository.mailbox(recipient_id) {
+            Ok(letters) if letters.len() >= MAILBOX_SIZE => {
+                return Err((s::MailResult::MailboxFull, "mailbox full"));
+            }
+            Ok(_) => (),
+            Err(err) => {
+                println!(
+                    "ERROR: Failed to load mailbox {recipient_id}: {err:?}"
+                );
+                return Err(failed("repository error"));
+            }
+        }
+        let character = self
+            .characters
+            .get_mut(&character_id)
+            .ok_or(failed("not in the world"))?;
+
+        let (ids, repository) = (&mut self.ids, &mut self.repository);
+        let mut new_id = |kind| match ids.allocate(kind, repository.as_mut()) {
+            Ok(id) => Some(id),
+            Err(err) => {
+                println!("ERROR: Failed to allocate {kind:?} id: {err:?}");
+                None
+            }
+        };
+        let id = new_id(ObjectKind::Mail).ok_or(failed("no id"))?;
+        let inventory = character.inventory.clone();
+        let item = (msg.item_id != 0).then_some((msg.item_id, msg.count));
+        let (item, changes) =
+            mail::attach(&mut character.inventory, item, msg.kinah, || {
+                new_id(ObjectKind::InventoryItem)
+            })
+            .map_err(failed)?;
+
+        let letter = Letter {
+            id,
+            sender_id: character_id,
+            sender_name: character.name().clone(),
+            recipient_id,
+            recipient_name,
+            title: msg.title,
+            message: msg.message,
+            item,
+            kinah: msg.kinah,
+            unread: true,
+            sent_at: unix_time(),
+            returned: false,
+        };
+        if let Err(err) = repository.save_letter(&letter, Some(character)) {
+            println!("ERROR: Failed to send letter {id}: {err:?}");
+            character.inventory = inventory;
+            return Err(failed("repository error"));
+        }
+
+        messages
+            .direct
+            .entry(character.client_id())
+            .or_default()
+            .extend(inventory_updates(&character.inventory, &changes));
+        Ok(recipient_id)
+    }
+
+    pub(super) fn list_mail(&self, character_id: u32, messages: &mut Messages) {
+        let Some(character) = self.characters.get(&character_id) else {
+            return;
+        };
+        let letters = match self.repository.mailbox(character_id) {
+            Ok(letters) => letters,
+            Err(err) => {
+                println!(
+                    "ERROR: Failed to load mailbox {character_id}: {err:?}"
+                );
+                return;
+            }
+        };
+        messages
+            .direct
+            .entry(character.client_id())
+            .or_default()
+            .push(ServerUpdate::new(s::Message::Mail(s::Mail::list(
+                character_id,
+                letters,
+            ))));
+    }
+
+    pub(super) fn read_mail(
+        &mut self,
+        character_id: u32,
+        msg: c::MailRead,
+        messages: &mut Messages,
+    ) {
+        let Some(mut letter) = self.own_letter(character_id, msg.letter_id)
+        else {
+            return;
+        };
+        if letter.unread {
+            letter.unread = false;
+            if let Err(err) = self.repository.save_letter(&letter, None) {
+                println!("ERROR: Failed to save letter {}: {err:?}", letter.id);
+            }
+        }
+        let Some(character) = self.characters.get(&character_id) else {
+            return;
+        };
+        messages
+            .direct
+            .entry(character.client_id())
+            .or_default()
+            .push(ServerUpdate::new(s::Message::Mail(s::Mail::read(letter))));
+    }
+
+    /// Move the item or kinah on a letter into the recipient's inventory
+    pub(super) fn take_attachment(
+        &mut self,
+        character_id: u32,
+        msg: c::MailGetItem,
+        messages: &mut Messages,
+    ) {
+        let Some(mut letter) = self.own_letter(character_id, msg.letter_id)
+        else {
+            return;
+        };
+        let Some(character) = self.characters.get_mut(&character_id) else {
+            return;
+        };
+
+        let (ids, repository) = (&mut self.ids, &mut self.repository);
+        let new_id = || match ids
+            .allocate(ObjectKind::InventoryItem, repository.as_mut())
+        {
+            Ok(id) => Some(id),
+            Err(err) => {
+                println!("ERROR: Failed to allocate item id: {err:?}");
+                None
+            }
+        };
+        let inventory = character.inventory.clone();
+        let changes = match msg.attachment {
+            c::Attachment::Item => letter
+                .take_item(&mut character.inventory)
+                .map(|change| vec![change]),
+            c::Attachment::Kinah => {
+                letter.take_kinah(&mut character.inventory, new_id)
+            }
+            attachment => {
+                println!("WARNING: Mail attachment {attachment:?} not handled");
+                return;
+            }
+        };
+        let changes = match changes {
+            Ok(changes) => changes,
+            Err(err) => {
+                println!(
+                    "WARNING: Character {character_id} can't take {:?} from \
+                     letter {}: {err}",
+                    msg.attachment, letter.id
+                );
+                return;
+            }
+        };
+        if let Err(err) = repository.save_letter(&letter, Some(character)) {
+            println!("ERROR: Failed to save letter {}: {err:?}", letter.id);
+            character.inventory = inventory;
+            return;
+        }
+
+        let direct = messages.direct.entry(character.client_id()).or_default();
+        direct.extend(inventory_updates(&character.inventory, &changes));
+        direct.push(ServerUpdate::new(s::Message::Mail(s::Mail::taken(
+            letter.id,
+            msg.attachment,
+        ))));
+    }
+
+    /// Letters have to be emptied before they can be thrown away
+    pub(super) fn delete_mail(
+        &mut self,
+        character_id: u32,
+        msg: c::MailDelete,
+        messages: &mut Messages,
+    ) {
+        let Some(letter) = self.own_letter(character_id, msg.letter_id) else {
+            return;
+        };
+        if letter.has_attachments() {
+            println!(
+                "WARNING: Character {character_id} can't delete letter {}: \
+                 attachments not taken",
+                letter.id
+            );
+            return;
+        }
+        if let Err(err) = self.repository.delete_letter(letter.id) {
+            println!("ERROR: Failed to delete letter {}: {err:?}", letter.id);
+            return;
+        }
+        let Some(character) = self.characters.get(&character_id) else {
+            return;
+        };
+        messages
+            .direct
+            .entry(character.client_id())
+            .or_default()
+            .push(ServerUpdate::new(s::Message::Mail(s::Mail::deleted(
+                letter.id,
+            ))));
+    }
+
+    /// Let a character in the world know how many letters they haven't read
+    pub(super) fn notify_unread(
+        &self,
+        character_id: u32,
+        messages: &mut Messages,
+    ) {
+        let Some(character) = self.characters.get(&character_id) else {
+            return;
+        };
+        let unread = match self.repository.mailbox(character_id) {
+            Ok(letters) => {
+                letters.iter().filter(|letter| letter.unread).count()
+            }
+            Err(err) => {
+                println!(
+                    "ERROR: Failed to load mailbox {character_id}: {err:?}"
+                );
+                return;
+            }
+        };
+        if unread == 0 {
+            return;
+        }
+        messages
+            .direct
+            .entry(character.client_id())
+            .or_default()
+            .push(ServerUpdate::new(s::Message::Mail(s::Mail::unread(
+                unread as u16,
+            ))));
+    }
+
+    /// Send letters that have sat too long back with their attachments, or
+    /// throw them away if there is nothing on them or they already came back
+    pub(super) fn update_mail(&mut self, messages: &mut Messages) {
+        self.ticks_since_mail_expiry += 1;
+        if (self.ticks_since_mail_expiry as f32) < EXPIRY_INTERVAL {
+            return;
+        }
+        self.ticks_since_mail_expiry = 0;
+
+        let now = unix_time();
+        let letters =
+            match self.repository.letters_before(now.saturating_sub(EXPIRY)) {
+                Ok(letters) => letters,
+                Err(err) => {
+                    println!("ERROR: Failed to load expired mail: {err:?}");
+                    return;
+                }
+            };
+        for letter in letters {
+            let id = letter.id;
+            let result = match letter.return_to_sender(now) {
+                Some(returned) => {
+                    let sender_id = returned.recipient_id;
+                    let result = self.repository.save_letter(&returned, None);
+                    if result.is_ok() {
+                        self.notify_unread(sender_id, messages);
+                    }
+                    result
+                }
+                None => self.repository.delete_letter(id),
+            };
+            if let Err(err) = result {
+                println!("ERROR: Failed to expire letter {id}: {err:?}");
+            }
+        }
+    }
+
+    /// A letter from a character's own mailbox
+    fn own_letter(&self, character_id: u32, letter_id: u32) -> Option<Letter> {
+        match self.repository.letter(letter_id) {
+            Ok(Some(letter)) if letter.recipient_id == character_id => {
+                Some(letter)
+            }
+            Ok(_) => {
+                println!(
+                    "WARNING: Character {character_id} has no letter \
+                     {letter_id}"
+                );
+                None
+            }
+            Err(err) => {
+                println!("ERROR: Failed to load letter {letter_id}: {err:?}");
+                None
+            }
+        }
+    }
+}
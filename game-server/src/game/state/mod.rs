@@ -0,0 +1,1277 @@
+mod chat;
+mod death;
+mod effect;
+mod exchange;
+mod inventory;
+mod level;
+mod loot;
+mod mail;
+mod party;
+mod regen;
+mod shop;
+mod skill;
+
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crossbeam_channel::Sender;
+
+use super::character::{Appearance, Character, APPEARANCE_LEN};
+use super::data::drop::Tables as DropTables;
+use super::data::goods::Lists as GoodsLists;
+use super::data::item::Templates as ItemTemplates;
+use super::data::skill::Templates as SkillTemplates;
+use super::data::ActionType;
+use super::data::GameData;
+use super::engine::exchange::Exchanges;
+use super::engine::party::Parties;
+use super::engine::skill::{Cast, Cooldowns};
+use super::engine::Coord;
+use super::entity::Entity;
+use super::id::{IdAllocator, ObjectKind};
+use super::message::{client as c, server as s};
+use super::repository::CharacterRepository;
+use super::spawn::Spawner;
+use super::world::{Visibility, World};
+use super::{ClientUpdate, Event, Messages, ServerUpdate, TICK_RATE};
+
+/// How often characters in the world are flushed to the repository
+const SAVE_INTERVAL: f32 = 60. * TICK_RATE;
+
+/// Slots on the character select screen
+const MAX_CHARACTERS: usize = 8;
+/// Where newly created characters start
+const START_LOCATION: Coord = Coord::new(1816., 589., 256.);
+/// Characters below this level only wait [SHORT_DELETION_DELAY]
+const DELETION_LEVEL: u16 = 20;
+/// Seconds before a deleted character is gone for good
+const SHORT_DELETION_DELAY: u32 = 5 * 60;
+const LONG_DELETION_DELAY: u32 = 7 * 24 * 60 * 60;
+// TODO: Keep track of the time in game
+const GAME_TIME: u32 = 148641933;
+
+pub struct State {
+    /// Characters currently in the world
+    characters: HashMap<u32, Character>,
+    /// NPCs currently in the world, including corpses
+    entities: HashMap<u32, Entity>,
+    spawner: Spawner,
+    repository: Box<dyn CharacterRepository + Send>,
+    ids: IdAllocator,
+    world: World,
+    /// Ticks since the last flush to the repository
+    ticks_since_save: u32,
+    ticks_since_regen: u32,
+    skills: SkillTemplates,
+    drops: DropTables,
+    items: ItemTemplates,
+    /// Skills characters are partway through casting
+    casts: HashMap<u32, Cast>,
+    /// Keyed by character id and kept after they leave the world so
+    /// relogging doesn't reset them
+    cooldowns: HashMap<u32, Cooldowns>,
+    parties: Parties,
+    /// HP, MP and location of each party member as their party last saw them
+    party_synced: HashMap<u32, (i32, u32, Coord)>,
+    ticks_since_party_sync: u32,
+    exchanges: Exchanges,
+    goods: GoodsLists,
+    /// The NPC each character has a dialog open with
+    dialogs: HashMap<u32, u32>,
+    ticks_since_mail_expiry: u32,
+}
+
+impl State {
+    pub fn new(
+        repository: Box<dyn CharacterRepository + Send>,
+        spawner: Spawner,
+        data: GameData,
+    ) -> Self {
+        let ids = IdAllocator::load(repository.as_ref()).unwrap();
+
+        Self {
+            characters: HashMap::with_capacity(1000),
+            entities: HashMap::with_capacity(spawner.len()),
+            spawner,
+            repository,
+            ids,
+            world: World::new(),
+            ticks_since_save: 0,
+            ticks_since_regen: 0,
+            skills: data.skills,
+            drops: data.drops,
+            items: data.items,
+            casts: HashMap::new(),
+            cooldowns: HashMap::new(),
+            parties: Parties::default(),
+            party_synced: HashMap::new(),
+            ticks_since_party_sync: 0,
+            exchanges: Exchanges::default(),
+            goods: data.goods,
+            dialogs: HashMap::new(),
+            // Straight away, the server may have been down while letters
+            // expired
+            ticks_since_mail_expiry: mail::EXPIRY_INTERVAL as u32,
+        }
+    }
+
+    /// Fill empty spawn points and clear away old corpses
+    fn spawn(&mut self, messages: &mut Messages) {
+        let ids = &mut self.ids;
+        let repository = &mut self.repository;
+        let changes = self.spawner.update(&mut self.entities, || {
+            match ids.allocate(ObjectKind::Npc, repository.as_mut()) {
+                Ok(id) => Some(id),
+                Err(err) => {
+                    println!("ERROR: Failed to allocate NPC id: {err:?}");
+                    None
+                }
+            }
+        });
+
+        for id in changes.despawned {
+            let visibility = self.world.remove(id);
+            self.show(visibility, messages);
+            self.ids.release(ObjectKind::Npc, id);
+        }
+        for id in changes.spawned {
+            let location = *self.entities[&id].location();
+            let visibility = self.world.insert(id, location, false);
+            self.show(visibility, messages);
+        }
+    }
+
+    /// Characters on the select screen, any whose deletion countdown has
+    /// finished are removed first
+    fn account_characters(
+        &mut self,
+        update: &ClientUpdate,
+    ) -> Option<Vec<Character>> {
+        let mut characters =
+            match self.repository.characters(update.account_id()) {
+                Ok(characters) => characters,
+                Err(err) => {
+                    println!(
+                        "ERROR: Failed to load characters for {}: {err:?}",
+                        update.account_id()
+                    );
+                    return None;
+                }
+            };
+
+        let now = unix_time();
+        characters.retain(|character| match character.deletion_time() {
+            Some(deletion_time) if deletion_time <= now => {
+                println!("INFO: Deleting character {}", character.id());
+                if let Err(err) = self.repository.delete(character.id()) {
+                    println!(
+                        "ERROR: Failed to delete character {}: {err:?}",
+                        character.id()
+                    );
+                }
+                false
+            }
+            _ => true,
+        });
+
+        Some(characters)
+    }
+
+    /// Load a character from the repository, making sure the requesting
+    /// account owns it
+    fn owned_character(
+        &self,
+        update: &ClientUpdate,
+        character_id: u32,
+    ) -> Option<Character> {
+        let character = match self.repository.load(character_id) {
+            Ok(Some(character)) => character,
+            Ok(None) => {
+                println!("WARNING: Character {character_id} does not exist");
+                return None;
+            }
+            Err(err) => {
+                println!(
+                    "ERROR: Failed to load character {character_id}: {err:?}"
+                );
+                return None;
+            }
+        };
+
+        if character.account_id() != update.account_id() {
+            println!(
+                "WARNING: Account {} does not own character {character_id}",
+                update.account_id(),
+            );
+            return None;
+        }
+
+        Some(character)
+    }
+
+    fn create_character(
+        &mut self,
+        update: &ClientUpdate,
+        create: &c::CreateCharacter,
+    ) -> Result<Character, s::CreateCharacterResult> {
+        let name_len = create.name.chars().count();
+        if !(2..=16).contains(&name_len)
+            || !create.name.chars().all(|c| c.is_ascii_alphabetic())
+        {
+            return Err(s::CreateCharacterResult::InvalidName);
+        }
+
+        let appearance = Appearance {
+            gender: create
+                .gender
+                .try_into()
+                .map_err(|_| s::CreateCharacterResult::Failed)?,
+            race: create
+                .race
+                .try_into()
+                .map_err(|_| s::CreateCharacterResult::Failed)?,
+            class: create
+                .class
+                .try_into()
+                .map_err(|_| s::CreateCharacterResult::Failed)?,
+            voice: create.voice,
+            raw: create
+                .appearance
+                .get(..APPEARANCE_LEN)
+                .and_then(|raw| raw.try_into().ok())
+                .ok_or(s::CreateCharacterResult::Failed)?,
+        };
+
+        let characters = self
+            .account_characters(update)
+            .ok_or(s::CreateCharacterResult::DatabaseError)?;
+        if characters.len() >= MAX_CHARACTERS {
+            return Err(s::CreateCharacterResult::ServerLimitExceeded);
+        }
+
+        match self.repository.name_taken(&create.name) {
+            Ok(false) => (),
+            Ok(true) => return Err(s::CreateCharacterResult::NameAlreadyUsed),
+            Err(err) => {
+                println!(
+                    "ERROR: Failed to check name {}: {err:?}",
+                    create.name
+                );
+                return Err(s::CreateCharacterResult::DatabaseError);
+            }
+        }
+
+        let mut character = Character::new(
+            self.allocate_id(ObjectKind::Character)
+                .ok_or(s::CreateCharacterResult::DatabaseError)?,
+            update.account_id(),
+            create.name.clone(),
+            appearance,
+            START_LOCATION,
+        );
+        // The starter inventory needs ids of its own
+        let items: Vec<_> = character.inventory.items().cloned().collect();
+        character.inventory.clear();
+        for mut item in items {
+            item.id = self
+                .allocate_id(ObjectKind::InventoryItem)
+                .ok_or(s::CreateCharacterResult::DatabaseError)?;
+            character.inventory.insert(item);
+        }
+
+        if let Err(err) = self.repository.create(&character) {
+            println!("ERROR: Failed to create character: {err:?}");
+            return Err(s::CreateCharacterResult::DatabaseError);
+        }
+        println!(
+            "INFO: Account {} created character {}",
+            update.account_id(),
+            character.id()
+        );
+
+        Ok(character)
+    }
+
+    fn allocate_id(&mut self, kind: ObjectKind) -> Option<u32> {
+        match self.ids.allocate(kind, self.repository.as_mut()) {
+            Ok(id) => Some(id),
+            Err(err) => {
+                println!("ERROR: Failed to allocate {kind:?} id: {err:?}");
+                None
+            }
+        }
+    }
+
+    /// Messages that load an object onto a client
+    fn put_object(&self, id: u32) -> Vec<s::Message> {
+        let (mut messages, effects, entity_type) = if let Some(character) =
+            self.characters.get(&id)
+        {
+            (
+                vec![
+                    s::Message::PutUser(s::PutUser::new(character)),
+                    s::Message::InvisibleLevel(s::InvisibleLevel::finish(id)),
+                ],
+                &character.effects,
+                s::EntityType::Character,
+            )
+        } else if let Some(entity) = self.entities.get(&id) {
+            (
+                vec![s::Message::PutNpc(s::PutNpc::new(entity))],
+                &entity.effects,
+                s::EntityType::Npc,
+            )
+        } else {
+            return vec![];
+        };
+
+        let effects = effect::status_bar(effects);
+        if !effects.is_empty() {
+            messages.push(s::Message::AbnormalStatusOther(
+                s::AbnormalStatusOther::new(id, entity_type, effects),
+            ));
+        }
+        messages
+    }
+
+    /// Put objects onto the clients that can now see them and remove them
+    /// from the clients that no longer can
+    fn show(&self, visibility: Visibility, messages: &mut Messages) {
+        for (observer, object) in visibility.appeared {
+            let Some(observer) = self.characters.get(&observer) else {
+                continue;
+            };
+            messages
+                .direct
+                .entry(observer.client_id())
+                .or_default()
+                .extend(
+                    self.put_object(object).into_iter().map(ServerUpdate::new),
+                );
+        }
+        for (observer, object) in visibility.disappeared {
+            let Some(observer) = self.characters.get(&observer) else {
+                continue;
+            };
+            messages
+                .direct
+                .entry(observer.client_id())
+                .or_default()
+                .push(ServerUpdate::new(s::Message::RemoveObject(
+                    s::RemoveObject::new(object),
+                )));
+        }
+    }
+
+    /// Turn updates about an object into direct messages for the characters
+    /// that can see it
+    pub fn route(&self, messages: &mut Messages) {
+        for (id, updates) in messages.observers.drain() {
+            let observers = self.world.observers(id).chain(Some(id));
+            for observer in observers {
+                let Some(observer) = self.characters.get(&observer) else {
+                    continue;
+                };
+                messages
+                    .direct
+                    .entry(observer.client_id())
+                    .or_default()
+                    .extend(updates.iter().cloned());
+            }
+        }
+        for (id, updates) in messages.others.drain() {
+            for observer in self.world.observers(id) {
+                let Some(observer) = self.characters.get(&observer) else {
+                    continue;
+                };
+                messages
+                    .direct
+                    .entry(observer.client_id())
+                    .or_default()
+                    .extend(updates.iter().cloned());
+            }
+        }
+        for (id, updates) in messages.party.drain() {
+            let Some(party) = self.parties.get(id) else {
+                continue;
+            };
+            for member in party.members() {
+                let Some(member) = self.characters.get(member) else {
+                    continue;
+                };
+                messages
+                    .direct
+                    .entry(member.client_id())
+                    .or_default()
+                    .extend(updates.iter().cloned());
+            }
+        }
+    }
+
+    /// Write every character in the world to the repository
+    fn flush(&mut self) {
+        for character in self.characters.values() {
+            save(self.repository.as_mut(), character);
+        }
+    }
+
+    /// Save a character and take it out of the world, anything fighting it
+    /// gives up and everyone who could see it is told it has gone
+    fn leave_world(&mut self, character_id: u32, messages: &mut Messages) {
+        self.leave_party(character_id, messages);
+        self.cancel_exchange(character_id, messages);
+        let Some(character) = self.characters.remove(&character_id) else {
+            return;
+        };
+        save(self.repository.as_mut(), &character);
+        self.casts.remove(&character_id);
+        self.dialogs.remove(&character_id);
+
+        for entity in self.entities.values_mut() {
+            entity.forget(character_id);
+        }
+
+        let visibility = self.world.remove(character_id);
+        self.show(visibility, messages);
+        println!("INFO: Character {character_id} left the world");
+    }
+
+    pub fn respond(&mut self, update: &ClientUpdate, messages: &mut Messages) {
+        let message = match update.event() {
+            Event::Message(message) => message,
+            Event::Disconnected => {
+                // Only the client that is playing a character can take it
+                // out of the world
+                let playing =
+                    self.characters.get(&update.character_id()).is_some_and(
+                        |character| character.client_id() == update.client_id(),
+                    );
+                if playing {
+                    self.leave_world(update.character_id(), messages);
+                }
+                return;
+            }
+        };
+
+        match message {
+            c::Message::MoveNew(move_new) => {
+                self.cancel_cast(update.character_id(), messages);
+                let Some(character) =
+                    self.characters.get_mut(&update.character_id)
+                else {
+                    return;
+                };
+
+                character.set_resting(false);
+                let s_move_new =
+                    s::Message::MoveNew(move_new.calculate(character.id()));
+                character.set_location(move_new.location());
+
+                let visibility =
+                    self.world.move_to(character.id(), move_new.location());
+                self.show(visibility, messages);
+
+                messages
+                    .others
+                    .entry(update.character_id())
+                    .or_default()
+                    .push(ServerUpdate::new(s_move_new));
+            }
+            c::Message::UseSkill(use_skill) => {
+                self.use_skill(update.character_id(), use_skill, messages);
+            }
+            c::Message::DeadRestart(msg) => {
+                self.restart(update.character_id(), msg.restart, messages);
+            }
+            c::Message::TurnOffAbnormalStatus(msg) => {
+                self.turn_off_effect(
+                    update.character_id(),
+                    msg.skill_id,
+                    messages,
+                );
+            }
+            c::Message::Attack(attack) => {
+                if let Some(entity) = self
+                    .entities
+                    .get_mut(&attack.target_id)
+                    .filter(|entity| entity.is_alive())
+                {
+                    // Calculate the client attack and send it to everyone nearby
+                    let Some(character) =
+                        self.characters.get_mut(&update.character_id())
+                    else {
+                        return;
+                    };
+                    if character.is_dead() || character.effects.stunned() {
+                        return;
+                    }
+                    if let Err(e) = character.start_swing(entity.location()) {
+                        println!(
+                            "WARNING: Character {} can't attack {}: {e}",
+                            character.id(),
+                            entity.id()
+                        );
+                        return;
+                    }
+                    character.enter_combat();
+                    let hp = entity.hp.current();
+                    let hits = character.attack(entity);
+                    entity.add_hate(character.id(), hp - entity.hp.current());
+                    let s_hit_point_other =
+                        s::HitPointOther::new(entity.id(), entity.hp.percent());
+
+                    messages
+                        .observers
+                        .entry(character.id())
+                        .or_default()
+                        .extend(
+                            [
+                                s::Message::Attack(s::Attack::new(
+                                    entity.id(),
+                                    character.id(),
+                                    0,
+                                    hits,
+                                )),
+                                s::Message::HitPointOther(s_hit_point_other),
+                            ]
+                            .map(ServerUpdate::new),
+                        );
+
+                    if entity.is_dead() {
+                        messages
+                            .observers
+                            .entry(entity.id())
+                            .or_default()
+                            .extend(
+                                death(
+                                    entity.id(),
+                                    character.id(),
+                                    character.stats.move_speed,
+                                )
+                                .map(ServerUpdate::new),
+                            );
+                        self.reward_kill(
+                            attack.target_id,
+                            update.character_id(),
+                            messages,
+                        );
+                    }
+                }
+            }
+            c::Message::ChangeTarget(change_target) => {
+                if let Some(entity) =
+                    self.entities.get(&change_target.target_id)
+                {
+                    messages
+                        .direct
+                        .entry(update.client_id())
+                        .or_default()
+                        .push(ServerUpdate::new(s::Message::TargetInfo(
+                            s::TargetInfo::new(
+                                change_target.target_id,
+                                entity.level(),
+                                entity.hp.current(),
+                                entity.hp.max(),
+                            ),
+                        )));
+                }
+            }
+            c::Message::Action(action) => {
+                let Some(character) =
+                    self.characters.get_mut(&update.character_id)
+                else {
+                    return;
+                };
+                match action.ty {
+                    ActionType::Rest => character.set_resting(true),
+                    ActionType::EndRest => character.set_resting(false),
+                    _ => (),
+                }
+
+                messages.observers.entry(character.id()).or_default().push(
+                    ServerUpdate::new(s::Message::Action(s::Action::new(
+                        character.id(),
+                        action.ty,
+                        character.stats.move_speed,
+                        0,
+                    ))),
+                );
+            }
+            c::Message::Loot(msg) => match msg.action {
+                c::LootAction::Start => self.start_loot(
+                    update.character_id,
+                    msg.entity_id,
+                    messages,
+                ),
+                c::LootAction::Stop => {}
+            },
+            c::Message::LootItem(msg) => self.loot_item(
+                update.character_id,
+                msg.entity_id,
+                msg.index,
+                messages,
+            ),
+            c::Message::UseEquipmentItem(msg) => self.use_equipment_item(
+                update.character_id,
+                msg.clone(),
+                messages,
+            ),
+            c::Message::MoveItemToAnotherSlot(msg) => {
+                self.move_item(update.character_id, msg.clone(), messages)
+            }
+            c::Message::Answer(msg) => match msg.question_id {
+                party::INVITE_QUESTION => self.answer_invite(
+                    update.character_id,
+                    msg.clone(),
+                    messages,
+                ),
+                exchange::EXCHANGE_QUESTION => self.answer_exchange(
+                    update.character_id,
+                    msg.clone(),
+                    messages,
+                ),
+                question_id => {
+                    println!("WARNING: Question {question_id} not handled")
+                }
+            },
+            c::Message::PartyByName(msg) => {
+                self.invite_to_party(update.character_id, msg.clone(), messages)
+            }
+            c::Message::Party(msg) => {
+                self.party_action(update.character_id, msg.clone(), messages)
+            }
+            c::Message::GroupChangeLootdist(msg) => self.change_loot_rule(
+                update.character_id,
+                msg.clone(),
+                messages,
+            ),
+            c::Message::AskXchg(msg) => {
+                self.ask_exchange(update.character_id, msg.clone(), messages)
+            }
+            c::Message::AddXchg(msg) => self.add_exchange_item(
+                update.character_id,
+                msg.clone(),
+                messages,
+            ),
+            c::Message::RemoveXchg(msg) => self.remove_exchange_item(
+                update.character_id,
+                msg.clone(),
+                messages,
+            ),
+            c::Message::XchgGold(msg) => self.add_exchange_kinah(
+                update.character_id,
+                msg.clone(),
+                messages,
+            ),
+            c::Message::CheckXchg(_) => {
+                self.lock_exchange(update.character_id, messages)
+            }
+            c::Message::AcceptXchg(_) => {
+                self.accept_exchange(update.character_id, messages)
+            }
+            c::Message::CancelXchg(_) => {
+                self.cancel_exchange(update.character_id, messages)
+            }
+            c::Message::StartDialog(msg) => {
+                self.start_dialog(update.character_id, msg.clone(), messages)
+            }
+            c::Message::EndDialog(msg) => {
+                self.end_dialog(update.character_id, msg.clone())
+            }
+            c::Message::BuySell(msg) => {
+                self.buy_sell(update.character_id, msg.clone(), messages)
+            }
+            c::Message::MailWrite(msg) => {
+                self.send_mail(update.character_id, msg.clone(), messages)
+            }
+            c::Message::MailList(_) => {
+                self.list_mail(update.character_id, messages)
+            }
+            c::Message::MailRead(msg) => {
+                self.read_mail(update.character_id, msg.clone(), messages)
+            }
+            c::Message::MailGetItem(msg) => {
+                self.take_attachment(update.character_id, msg.clone(), messages)
+            }
+            c::Message::MailDelete(msg) => {
+                self.delete_mail(update.character_id, msg.clone(), messages)
+            }
+            c::Message::Say(msg) => {
+                self.say(update.character_id, msg.clone(), messages)
+            }
+            c::Message::Whisper(msg) => {
+                self.whisper(update.character_id, msg.clone(), messages)
+            }
+            c::Message::MoveStackableItem(msg) => self.move_stackable_item(
+                update.character_id,
+                msg.clone(),
+                messages,
+            ),
+            c::Message::CharacterList(_) => {
+                let Some(characters) = self.account_characters(update) else {
+                    return;
+                };
+
+                messages.direct.entry(update.client_id()).or_default().push(
+                    ServerUpdate::new(s::Message::CharacterList(
+                        s::CharacterList::new([0u8; 4], characters),
+                    )),
+                );
+            }
+            c::Message::CreateCharacter(create) => {
+                let result = self.create_character(update, create);
+                messages.direct.entry(update.client_id()).or_default().push(
+                    ServerUpdate::new(s::Message::CreateCharacter(
+                        match &result {
+                            Ok(character) => s::CreateCharacter::ok(character),
+                            Err(result) => s::CreateCharacter::error(*result),
+                        },
+                    )),
+                );
+            }
+            c::Message::DeleteCharacter(delete) => {
+                let Some(mut character) =
+                    self.owned_character(update, delete.character_id)
+                else {
+                    return;
+                };
+
+                let delay = if character.stats.level() < DELETION_LEVEL {
+                    SHORT_DELETION_DELAY
+                } else {
+                    LONG_DELETION_DELAY
+                };
+                let deletion_time = unix_time() + delay;
+                character.set_deletion_time(Some(deletion_time));
+                save(self.repository.as_mut(), &character);
+
+                messages.direct.entry(update.client_id()).or_default().push(
+                    ServerUpdate::new(s::Message::DeleteCharacter(
+                        s::DeleteCharacter::new(character.id(), deletion_time),
+                    )),
+                );
+            }
+            c::Message::RestoreCharacter(restore) => {
+                let Some(mut character) =
+                    self.owned_character(update, restore.character_id)
+                else {
+                    return;
+                };
+
+                character.set_deletion_time(None);
+                save(self.repository.as_mut(), &character);
+
+                messages.direct.entry(update.client_id()).or_default().push(
+                    ServerUpdate::new(s::Message::RestoreCharacter(
+                        s::RestoreCharacter::new(character.id()),
+                    )),
+                );
+            }
+            c::Message::EnterWorld(msg) => {
+                if update.character_id() != 0 {
+                    println!(
+                        "WARNING: Client {:X} is already in the world as {}",
+                        update.client_id(),
+                        update.character_id()
+                    );
+                    return;
+                }
+                if self.characters.contains_key(&msg.character_id) {
+                    println!(
+                        "WARNING: Character {} is already in the world",
+                        msg.character_id
+                    );
+                    return;
+                }
+                let Some(mut character) =
+                    self.owned_character(update, msg.character_id)
+                else {
+                    return;
+                };
+
+                if character.deletion_time().is_some() {
+                    println!(
+                        "WARNING: Character {} is waiting to be deleted",
+                        character.id()
+                    );
+                    return;
+                }
+
+                character.set_client_id(update.client_id());
+                character.wear(&self.items);
+                messages.direct.entry(update.client_id()).or_default().push(
+                    ServerUpdate::enter_world(
+                        character.id(),
+                        s::Message::EnterWorldCheck(s::EnterWorldCheck::new()),
+                    ),
+                );
+                self.characters.insert(character.id(), character);
+            }
+            c::Message::ReadyToQuit(_) => {
+                self.leave_world(update.character_id(), messages);
+            }
+            c::Message::LevelReady(_) => {
+                let Some(character) =
+                    self.characters.get(&update.character_id())
+                else {
+                    return;
+                };
+
+                // The client needs to be told about itself
+                let direct =
+                    messages.direct.entry(update.client_id()).or_default();
+                direct.extend(
+                    self.put_object(character.id())
+                        .into_iter()
+                        .map(ServerUpdate::new),
+                );
+                direct.push(ServerUpdate::new(s::Message::InvincibleTime(
+                    s::InvincibleTime::new(character.invincible_remaining()),
+                )));
+                if character.is_dead() {
+                    direct.push(ServerUpdate::new(s::Message::ResurrectInfo(
+                        s::ResurrectInfo,
+                    )));
+                }
+
+                let visibility = self.world.insert(
+                    character.id(),
+                    *character.location(),
+                    true,
+                );
+                self.show(visibility, messages);
+                self.notify_unread(update.character_id(), messages);
+            }
+            c::Message::CurStatus(_) => {
+                let cooltimes = self.skill_cooltimes(update.character_id());
+                let resurrect_loc_info =
+                    self.resurrect_loc_info(update.character_id());
+                let Some(character) =
+                    self.characters.get_mut(&update.character_id())
+                else {
+                    return;
+                };
+
+                messages
+                    .direct
+                    .entry(update.client_id())
+                    .or_default()
+                    .extend(
+                        [
+                            // Most important packet
+                            s::Message::World(s::World::new(
+                                character.location(),
+                            )),
+                            // Required
+                            s::Message::Status(s::Status::new(
+                                character, GAME_TIME,
+                            )),
+                            s::Message::WorldInfo(s::WorldInfo::new()),
+                            s::Message::CurStatus(s::CurStatus::new()),
+                            s::Message::AddSkill(s::AddSkill::new(
+                                character.skills.clone(),
+                            )),
+                            s::Message::LoadSkillCooltime(
+                                s::LoadSkillCooltime::new(cooltimes),
+                            ),
+                            s::Message::LoadInventory(s::LoadInventory::start(
+                                character.inventory.items().cloned().collect(),
+                            )),
+                            s::Message::LoadInventory(s::LoadInventory::end()),
+                            resurrect_loc_info,
+                        ]
+                        .map(ServerUpdate::new),
+                    );
+            }
+            _ => (),
+        }
+    }
+
+    pub fn update(&mut self, messages: &mut Messages) {
+        self.ticks_since_save += 1;
+        if self.ticks_since_save as f32 >= SAVE_INTERVAL {
+            self.ticks_since_save = 0;
+            self.flush();
+        }
+
+        self.spawn(messages);
+        self.update_casts(messages);
+        self.update_effects(messages);
+        self.update_regen(messages);
+        self.update_parties(messages);
+        self.update_mail(messages);
+
+        let mut moved = Vec::new();
+        for entity in self.entities.values_mut() {
+            let nearby: Vec<u32> = self.world.observers(entity.id()).collect();
+            if entity.think(&mut self.characters, &nearby, messages) {
+                moved.push((entity.id(), *entity.location()));
+            }
+        }
+        for (id, location) in moved {
+            let visibility = self.world.move_to(id, location);
+            self.show(visibility, messages);
+        }
+        self.update_deaths(messages);
+    }
+}
+
+/// Tell everyone who can see an NPC that it died and can be looted
+fn death(entity_id: u32, killer_id: u32, move_speed: f32) -> [s::Message; 2] {
+    [
+        s::Message::Action(s::Action::new(
+            entity_id,
+            ActionType::Die,
+            move_speed,
+            killer_id,
+        )),
+        s::Message::Loot(s::Loot::new(entity_id, 0)),
+    ]
+}
+
+/// Seconds since the unix epoch, the client counts down deletions from this
+fn unix_time() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32
+}
+
+/// Saving is best effort, a failure is logged and retried on the next flush
+fn save(repository: &mut dyn CharacterRepository, character: &Character) {
+    if let Err(err) = repository.save(character) {
+        println!(
+            "ERROR: Failed to save character {}: {err:?}",
+            character.id()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::game::character::Appearance;
+    use crate::game::data::drop::Dropped;
+    use crate::game::data::item::KINAH;
+    use crate::game::data::npc::Templates as NpcTemplates;
+    use crate::game::engine::loot::Loot;
+    use crate::game::repository::MemoryRepository;
+    use crate::game::session::Account;
+    use crate::game::Deserialise;
+
+    const NPCS: &str = "<npc_clients>
+        <npc_client>
+            <id>210564</id>
+            <name>LF1_Mosbear_Starved</name>
+            <name_id>300703</name_id>
+            <level>13</level>
+            <max_hp>1817</max_hp>
+            <attack_delay>1750</attack_delay>
+            <npc_type>Aggressive</npc_type>
+        </npc_client>
+    </npc_clients>";
+    const SKILLS: &str = "<client_skills>
+        <client_skill>
+            <id>1</id>
+            <name>FI_FerociousStrike_G1</name>
+            <first_target>Target</first_target>
+            <effect1_type>PhysicalDamage</effect1_type>
+            <effect1_value>77</effect1_value>
+        </client_skill>
+    </client_skills>";
+    const ITEMS: &str = "<client_items>
+        <client_item>
+            <id>182400001</id>
+            <name>black_aion_toll_01</name>
+        </client_item>
+    </client_items>";
+
+    /// A world with no NPCs and the given characters saved but not in it
+    fn state(characters: &[Character]) -> State {
+        let mut repository = MemoryRepository::new();
+        for character in characters {
+            repository.create(character).unwrap();
+        }
+        let npcs = NpcTemplates::parse(NPCS).unwrap();
+        let spawner = Spawner::parse("<spawns></spawns>", &npcs, 1).unwrap();
+        let items = ItemTemplates::parse(ITEMS).unwrap();
+        let data = GameData {
+            skills: SkillTemplates::parse(SKILLS).unwrap(),
+            drops: DropTables::parse("<drops></drops>").unwrap(),
+            goods: GoodsLists::parse(
+                "<client_npc_goodslists></client_npc_goodslists>",
+                &items,
+            )
+            .unwrap(),
+            items,
+        };
+        State::new(Box::new(repository), spawner, data)
+    }
+
+    /// An NPC standing where the test characters start
+    fn spawn_npc(state: &mut State, id: u32) {
+        let npcs = NpcTemplates::parse(NPCS).unwrap();
+        let location = Coord::new(1., 2., 3.);
+        let entity =
+            Entity::new(id, location, 0, 0., npcs.get(210564).unwrap());
+        state.entities.insert(id, entity);
+        state.world.insert(id, location, false);
+    }
+
+    fn character(id: u32, account_id: u32) -> Character {
+        Character::new(
+            id,
+            account_id,
+            format!("Tester{id}"),
+            Appearance::starter(),
+            Coord::new(1., 2., 3.),
+        )
+    }
+
+    /// A logged in client, the way the network thread keeps one
+    fn session(client_id: u16, account_id: u32) -> Account {
+        let mut session = Account::new(client_id);
+        session.auth_server.id = account_id;
+        session
+    }
+
+    /// Ask to enter the world, tying the session to the character only if
+    /// the game loop lets it in
+    fn enter_world(
+        state: &mut State,
+        session: &mut Account,
+        character_id: u32,
+    ) -> bool {
+        let mut updates = Vec::new();
+        c::EnterWorld { character_id }.handle(&mut updates, session);
+        let mut messages = Messages::new();
+        state.respond(&updates[0], &mut messages);
+        let entered = messages
+            .direct
+            .values()
+            .flatten()
+            .find_map(ServerUpdate::entered_world);
+        if let Some(character_id) = entered {
+            session.set_character_id(character_id);
+        }
+        entered.is_some()
+    }
+
+    #[test]
+    fn enter_world_needs_the_owning_account() {
+        let mut state = state(&[character(1, 7)]);
+        let mut owner = session(1, 7);
+        let mut other = session(2, 8);
+
+        assert!(enter_world(&mut state, &mut owner, 1));
+        assert!(!enter_world(&mut state, &mut other, 1));
+
+        // The rejected session never got the character so dropping it
+        // leaves the owner playing
+        let mut messages = Messages::new();
+        state.respond(&other.disconnected(), &mut messages);
+        assert_eq!(state.characters[&1].client_id(), 1);
+
+        state.respond(&owner.disconnected(), &mut messages);
+        assert!(state.characters.is_empty());
+    }
+
+    #[test]
+    fn stored_hp_and_mp_are_held_to_max_once_gear_is_worn() {
+        let mut stored = character(1, 7);
+        stored.stats.hp.restore(i32::MAX);
+        stored.stats.mp.restore(u32::MAX);
+        let mut state = state(&[stored]);
+        let mut session = session(1, 7);
+        assert!(enter_world(&mut state, &mut session, 1));
+
+        let stats = &state.characters[&1].stats;
+        assert_eq!(stats.hp.current(), stats.hp.max());
+        assert_eq!(stats.mp.current(), stats.mp.max());
+    }
+
+    #[test]
+    fn ignore_messages_for_characters_not_in_the_world() {
+        let mut state = state(&[character(1, 7)]);
+        spawn_npc(&mut state, 0x8000_0001);
+        let mut session = session(1, 7);
+        assert!(enter_world(&mut state, &mut session, 1));
+        let update = |message| ClientUpdate::new(1, 7, 1, message);
+
+        let mut messages = Messages::new();
+        state.respond(
+            &update(c::Message::ReadyToQuit(c::ReadyToQuit)),
+            &mut messages,
+        );
+        assert!(state.characters.is_empty());
+
+        // Sent before the client heard it had left
+        let late = [
+            c::Message::MoveNew(c::MoveNew::deserialise(&[0; 14]).unwrap()),
+            c::Message::Action(c::Action::deserialise(&[0]).unwrap()),
+            c::Message::Attack(
+                c::Attack::deserialise(&[0x01, 0, 0, 0x80, 0]).unwrap(),
+            ),
+            c::Message::LevelReady(c::LevelReady),
+            c::Message::CurStatus(c::CurStatus),
+        ];
+        for message in late {
+            state.respond(&update(message), &mut messages);
+        }
+        assert!(state.characters.is_empty());
+    }
+
+    #[test]
+    fn corpses_reward_the_kill_once() {
+        let mut state = state(&[character(1, 7)]);
+        spawn_npc(&mut state, 0x8000_0001);
+        let mut session = session(1, 7);
+        assert!(enter_world(&mut state, &mut session, 1));
+        let entity = state.entities.get_mut(&0x8000_0001).unwrap();
+        entity.hp.update(1 - entity.hp.max());
+        // Swing as fast as the test likes
+        state.characters.get_mut(&1).unwrap().stats.attack_speed = 0;
+
+        let attack = |state: &mut State| {
+            let attack =
+                c::Attack::deserialise(&[0x01, 0, 0, 0x80, 0]).unwrap();
+            let mut messages = Messages::new();
+            state.respond(
+                &ClientUpdate::new(1, 7, 1, c::Message::Attack(attack)),
+                &mut messages,
+            );
+            messages
+        };
+        // Swings can miss, keep going until the last hit point is gone
+        assert!((0..100).any(|_| {
+            attack(&mut state);
+            state.entities[&0x8000_0001].is_dead()
+        }));
+        let exp = state.characters[&1].stats.exp;
+        assert!(exp > 0);
+
+        // A killing blow that lands exactly on the last hit point leaves the
+        // corpse on 0
+        let entity = state.entities.get_mut(&0x8000_0001).unwrap();
+        entity.hp.update(-entity.hp.current());
+        assert_eq!(entity.hp.current(), 0);
+
+        for _ in 0..2 {
+            let messages = attack(&mut state);
+            assert!(messages.observers.is_empty());
+            assert!(messages.direct.is_empty());
+            assert_eq!(state.characters[&1].stats.exp, exp);
+        }
+    }
+
+    #[test]
+    fn attacks_need_reach_and_swing_time() {
+        let mut state = state(&[character(1, 7)]);
+        spawn_npc(&mut state, 0x8000_0001);
+        let mut session = session(1, 7);
+        assert!(enter_world(&mut state, &mut session, 1));
+
+        let attack = |state: &mut State| {
+            let attack =
+                c::Attack::deserialise(&[0x01, 0, 0, 0x80, 0]).unwrap();
+            let mut messages = Messages::new();
+            state.respond(
+                &ClientUpdate::new(1, 7, 1, c::Message::Attack(attack)),
+                &mut messages,
+            );
+            !messages.observers.is_empty()
+        };
+        let character = state.characters.get_mut(&1).unwrap();
+        character.set_location(Coord::new(50., 2., 3.));
+        assert!(!attack(&mut state));
+
+        let character = state.characters.get_mut(&1).unwrap();
+        character.set_location(Coord::new(1., 2., 3.));
+        assert!(attack(&mut state));
+        assert!(!attack(&mut state));
+    }
+
+    #[test]
+    fn loot_needs_the_corpse_in_reach() {
+        let mut state = state(&[character(1, 7)]);
+        spawn_npc(&mut state, 0x8000_0001);
+        let mut session = session(1, 7);
+        assert!(enter_world(&mut state, &mut session, 1));
+        let entity = state.entities.get_mut(&0x8000_0001).unwrap();
+        entity.hp.update(-entity.hp.current());
+        let kinah = Dropped {
+            item_id: KINAH,
+            name_id: 0,
+            count: 100,
+        };
+        entity.loot = Some(Loot::new(vec![1], vec![kinah]));
+        let before = state.characters[&1].inventory.kinah();
+
+        let loot = |state: &mut State| {
+            let loot = c::LootItem {
+                entity_id: 0x8000_0001,
+                index: 0,
+            };
+            let mut messages = Messages::new();
+            state.respond(
+                &ClientUpdate::new(1, 7, 1, c::Message::LootItem(loot)),
+                &mut messages,
+            );
+            state.characters[&1].inventory.kinah()
+        };
+        let character = state.characters.get_mut(&1).unwrap();
+        character.set_location(Coord::new(50., 2., 3.));
+        assert_eq!(loot(&mut state), before);
+
+        let character = state.characters.get_mut(&1).unwrap();
+        character.set_location(Coord::new(1., 2., 3.));
+        assert_eq!(loot(&mut state), before + 100);
+    }
+
+    #[test]
+    fn shouts_are_throttled() {
+        let mut state = state(&[character(1, 7)]);
+        let mut session = session(1, 7);
+        assert!(enter_world(&mut state, &mut session, 1));
+
+        let shout = |state: &mut State| {
+            let say = c::Say {
+                ty: c::ChatType::Shout,
+                message: "WTS".to_string(),
+            };
+            let mut messages = Messages::new();
+            state.respond(
+                &ClientUpdate::new(1, 7, 1, c::Message::Say(say)),
+                &mut messages,
+            );
+            !messages.direct.is_empty()
+        };
+        assert!(shout(&mut state));
+        assert!(!shout(&mut state));
+    }
+
+    #[test]
+    fn resurrecting_keeps_cooldowns() {
+        let mut state = state(&[character(1, 7)]);
+        let mut session = session(1, 7);
+        assert!(enter_world(&mut state, &mut session, 1));
+        state
+            .cooldowns
+            .entry(1)
+            .or_default()
+            .start(1, Duration::from_secs(60));
+        let character = state.characters.get_mut(&1).unwrap();
+        character.stats.hp.update(-character.stats.hp.max());
+
+        let mut messages = Messages::new();
+        let restart = c::DeadRestart::deserialise(&[0]).unwrap();
+        state.respond(
+            &ClientUpdate::new(1, 7, 1, c::Message::DeadRestart(restart)),
+            &mut messages,
+        );
+        assert!(!state.characters[&1].is_dead());
+        assert!(state.cooldowns[&1].remaining(1).is_some());
+    }
+}
@@ -0,0 +1,337 @@
+//! Inviting characters into parties, managing them from the party window and
+//! keeping every member's view of the others up to date
+
+use super::super::engine::party::{Departure, LootRule};
+use super::super::engine::Coord;
+use super::super::message::{client as c, server as s};
+use super::super::world::VISIBILITY_RANGE;
+use super::super::{Messages, ServerUpdate, TICK_RATE};
+use super::State;
+
+/// "%0 has invited you to join their party"
+pub(super) const INVITE_QUESTION: u32 = 60000;
+// TODO: Verify this, it is from older versions of the game
+/// "%0 declined your invitation", takes the name
+const INVITE_DECLINED: u32 = 1300122;
+/// Members' HP, MP and locations are sent out this often when they change
+const SYNC_INTERVAL: f32 = TICK_RATE;
+
+impl State {
+    /// Ask a player to join the party, starting one if there isn't one yet
+    pub(super) fn invite_to_party(
+        &mut self,
+        character_id: u32,
+        msg: c::PartyByName,
+        messages: &mut Messages,
+    ) {
+        let Some(character) = self.characters.get(&character_id) else {
+            return;
+        };
+        let Some((target_id, target_client_id)) = self
+            .online(&msg.name)
+            .map(|target| (target.id(), target.client_id()))
+        else {
+            let update = self.not_online(msg.name);
+            messages
+                .direct
+                .entry(character.client_id())
+                .or_default()
+                .push(update);
+            return;
+        };
+        if let Err(err) = self.parties.invite(character_id, target_id) {
+            println!(
+                "WARNING: Character {character_id} can't invite {target_id}: \
+                 {err}"
+            );
+            return;
+        }
+
+        messages.direct.entry(target_client_id).or_default().push(
+            ServerUpdate::new(s::Message::Ask(s::Ask::new(
+                INVITE_QUESTION,
+                character_id,
+                vec![character.name().clone()],
+            ))),
+        );
+    }
+
+    /// Join the inviter's party or tell them the invite was turned down
+    pub(super) fn answer_invite(
+        &mut self,
+        character_id: u32,
+        msg: c::Answer,
+        messages: &mut Messages,
+    ) {
+        let Some(inviter_id) = self.parties.take_invite(character_id) else {
+            println!("WARNING: Character {character_id} has no invite");
+            return;
+        };
+        if msg.sender_id != inviter_id {
+            println!(
+                "WARNING: Character {character_id} answered {} but was \
+                 invited by {inviter_id}",
+                msg.sender_id
+            );
+            return;
+        }
+        let (Some(character), Some(inviter)) = (
+            self.characters.get(&character_id),
+            self.characters.get(&inviter_id),
+        ) else {
+            return;
+        };
+        if !msg.accepted {
+            messages
+                .direct
+                .entry(inviter.client_id())
+                .or_default()
+                .push(ServerUpdate::new(s::Message::MessageCode(
+                    s::MessageCode::with_params(
+                        INVITE_DECLINED,
+                        vec![character.name().clone()],
+                    ),
+                )));
+            return;
+        }
+
+        let party = match self.parties.join(inviter_id, character_id) {
+            Ok(party) => party,
+            Err(err) => {
+                println!(
+                    "WARNING: Character {character_id} can't join \
+                     {inviter_id}'s party: {err}"
+                );
+                return;
+            }
+        };
+        println!("INFO: Character {character_id} joined party {}", party.id());
+
+        // The newcomer needs to hear about everyone already there, everyone
+        // else only about the newcomer
+        let direct = messages.direct.entry(character.client_id()).or_default();
+        for member in party.members() {
+            let Some(member) = self.characters.get(member) else {
+                continue;
+            };
+            if member.id() != character_id {
+                direct.push(ServerUpdate::new(s::Message::PartyMemberInfo(
+                    s::PartyMemberInfo::new(
+                        party.id(),
+                        member,
+                        s::PartyEvent::Join,
+                    ),
+                )));
+            }
+        }
+        messages.party.entry(character_id).or_default().extend(
+            [
+                s::Message::PartyInfo(s::PartyInfo::new(party)),
+                s::Message::PartyMemberInfo(s::PartyMemberInfo::new(
+                    party.id(),
+                    character,
+                    s::PartyEvent::Join,
+                )),
+            ]
+            .map(ServerUpdate::new),
+        );
+    }
+
+    /// Leaving, kicking and handing over the lead from the party window
+    pub(super) fn party_action(
+        &mut self,
+        character_id: u32,
+        msg: c::Party,
+        messages: &mut Messages,
+    ) {
+        let result = match msg.action {
+            c::PartyAction::Leave => {
+                self.leave_party(character_id, messages);
+                return;
+            }
+            c::PartyAction::Kick => self
+                .parties
+                .kick(character_id, msg.character_id)
+                .map(|departure| {
+                    self.depart(
+                        msg.character_id,
+                        departure,
+                        s::PartyEvent::Kicked,
+                        messages,
+                    )
+                }),
+            c::PartyAction::ChangeLeader => self
+                .parties
+                .change_leader(character_id, msg.character_id)
+                .map(|party| {
+                    messages.party.entry(character_id).or_default().push(
+                        ServerUpdate::new(s::Message::PartyInfo(
+                            s::PartyInfo::new(party),
+                        )),
+                    )
+                }),
+            action => {
+                println!("WARNING: Party action {action:?} not handled");
+                return;
+            }
+        };
+        if let Err(err) = result {
+            println!(
+                "WARNING: Character {character_id} can't {:?} {}: {err}",
+                msg.action, msg.character_id
+            );
+        }
+    }
+
+    pub(super) fn change_loot_rule(
+        &mut self,
+        character_id: u32,
+        msg: c::GroupChangeLootdist,
+        messages: &mut Messages,
+    ) {
+        let result = LootRule::try_from(msg.loot_rule)
+            .map_err(|_| "unknown loot rule")
+            .and_then(|loot_rule| {
+                self.parties.set_loot_rule(character_id, loot_rule)
+            });
+        match result {
+            Ok(party) => messages.party.entry(character_id).or_default().push(
+                ServerUpdate::new(s::Message::PartyInfo(s::PartyInfo::new(
+                    party,
+                ))),
+            ),
+            Err(err) => println!(
+                "WARNING: Character {character_id} can't change the loot \
+                 rule to {}: {err}",
+                msg.loot_rule
+            ),
+        }
+    }
+
+    /// Take a character out of their party, also used when they leave the
+    /// world so it has to happen while they are still in it
+    pub(super) fn leave_party(
+        &mut self,
+        character_id: u32,
+        messages: &mut Messages,
+    ) {
+        if let Some(departure) = self.parties.leave(character_id) {
+            self.depart(
+                character_id,
+                departure,
+                s::PartyEvent::Leave,
+                messages,
+            );
+        }
+    }
+
+    /// Tell whoever left and whoever is left. The rest of the party might
+    /// have a new leader
+    fn depart(
+        &mut self,
+        character_id: u32,
+        departure: Departure,
+        event: s::PartyEvent,
+        messages: &mut Messages,
+    ) {
+        let party = departure.party;
+        self.party_synced.remove(&character_id);
+        println!("INFO: Character {character_id} left party {}", party.id());
+
+        let Some(character) = self.characters.get(&character_id) else {
+            return;
+        };
+        let info = ServerUpdate::new(s::Message::PartyMemberInfo(
+            s::PartyMemberInfo::new(party.id(), character, event),
+        ));
+        messages
+            .direct
+            .entry(character.client_id())
+            .or_default()
+            .push(info.clone());
+
+        if departure.disbanded {
+            for member in party.members() {
+                self.party_synced.remove(member);
+                let Some(member) = self.characters.get(member) else {
+                    continue;
+                };
+                messages.direct.entry(member.client_id()).or_default().push(
+                    ServerUpdate::new(s::Message::PartyMemberInfo(
+                        s::PartyMemberInfo::new(
+                            party.id(),
+                            member,
+                            s::PartyEvent::Disband,
+                        ),
+                    )),
+                );
+            }
+            return;
+        }
+        messages.party.entry(party.leader()).or_default().extend([
+            info,
+            ServerUpdate::new(s::Message::PartyInfo(s::PartyInfo::new(&party))),
+        ]);
+    }
+
+    /// Members the killer shares a kill with, everyone in their party alive
+    /// and close enough to it. Just the killer when they are on their own
+    pub(super) fn kill_party(
+        &self,
+        killer_id: u32,
+        location: &Coord,
+    ) -> Vec<u32> {
+        let Some(party) = self.parties.get(killer_id) else {
+            return vec![killer_id];
+        };
+        party
+            .members()
+            .iter()
+            .copied()
+            .filter(|member| {
+                self.characters.get(member).is_some_and(|member| {
+                    !member.is_dead()
+                        && member.location().distance(location)
+                            <= VISIBILITY_RANGE
+                })
+            })
+            .collect()
+    }
+
+    /// Send out the HP, MP and location of every party member that changed
+    /// since the last time
+    pub(super) fn update_parties(&mut self, messages: &mut Messages) {
+        self.ticks_since_party_sync += 1;
+        if (self.ticks_since_party_sync as f32) < SYNC_INTERVAL {
+            return;
+        }
+        self.ticks_since_party_sync = 0;
+
+        for party in self.parties.iter() {
+            for member in party.members() {
+                let Some(character) = self.characters.get(member) else {
+                    continue;
+                };
+                let stats = &character.stats;
+                let synced = (
+                    stats.hp.current(),
+                    stats.mp.current(),
+                    *character.location(),
+                );
+                if self.party_synced.get(member) == Some(&synced) {
+                    continue;
+                }
+                self.party_synced.insert(*member, synced);
+                messages.party.entry(*member).or_default().push(
+                    ServerUpdate::new(s::Message::PartyMemberInfo(
+                        s::PartyMemberInfo::new(
+                            party.id(),
+                            character,
+                            s::PartyEvent::Update,
+                        ),
+                    )),
+                );
+            }
+        }
+    }
+}
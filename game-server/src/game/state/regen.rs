@@ -0,0 +1,52 @@
+//! HP, MP and DP recovery for every character in the world, run every
+//! [REGEN_INTERVAL] ticks
+
+use super::super::message::server as s;
+use super::super::{Messages, ServerUpdate, TICK_RATE};
+use super::State;
+
+const REGEN_INTERVAL: f32 = 3. * TICK_RATE;
+
+impl State {
+    pub(super) fn update_regen(&mut self, messages: &mut Messages) {
+        self.ticks_since_regen += 1;
+        if (self.ticks_since_regen as f32) < REGEN_INTERVAL {
+            return;
+        }
+        self.ticks_since_regen = 0;
+
+        for character in self.characters.values_mut() {
+            if character.is_dead() {
+                continue;
+            }
+            let regenerated = character.regenerate();
+            let stats = &character.stats;
+            let direct =
+                messages.direct.entry(character.client_id()).or_default();
+
+            if regenerated.hp {
+                direct.push(ServerUpdate::new(s::Message::HitPoint(
+                    s::HitPoint::new(stats.hp.current(), stats.hp.max()),
+                )));
+                messages.others.entry(character.id()).or_default().push(
+                    ServerUpdate::new(s::Message::HitPointOther(
+                        s::HitPointOther::new(
+                            character.id(),
+                            stats.hp.percent(),
+                        ),
+                    )),
+                );
+            }
+            if regenerated.mp {
+                direct.push(ServerUpdate::new(s::Message::ManaPoint(
+                    s::ManaPoint::new(stats.mp.current(), stats.mp.max()),
+                )));
+            }
+            if regenerated.dp {
+                direct.push(ServerUpdate::new(s::Message::Dp(s::Dp::new(
+                    stats.dp.current(),
+                ))));
+            }
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Merchant NPCs, talking to one opens its shop and everything in the shop
+//! window's basket is bought or sold in one go
+
+use super::super::engine::shop;
+use super::super::id::ObjectKind;
+use super::super::message::{client as c, server as s};
+use super::super::{Messages, ServerUpdate};
+use super::inventory::inventory_updates;
+use super::State;
+
+/// How close a character has to stand to talk to an NPC
+const DIALOG_RANGE: f32 = 10.;
+
+impl State {
+    /// Open a merchant's shop, other dialogs aren't supported yet
+    pub(super) fn start_dialog(
+        &mut self,
+        character_id: u32,
+        msg: c::StartDialog,
+        messages: &mut Messages,
+    ) {
+        let Some(character) = self.characters.get(&character_id) else {
+            return;
+        };
+        let goods = match self.merchant_goods(character_id, msg.target_id) {
+            Ok(goods) => goods,
+            Err(err) => {
+                println!(
+                    "WARNING: Character {character_id} can't talk to {}: {err}",
+                    msg.target_id
+                );
+                return;
+            }
+        };
+        self.dialogs.insert(character_id, msg.target_id);
+
+        messages
+            .direct
+            .entry(character.client_id())
+            .or_default()
+            .push(ServerUpdate::new(s::Message::ShopSellList(
+                s::ShopSellList::new(msg.target_id, goods),
+            )));
+    }
+
+    pub(super) fn end_dialog(&mut self, character_id: u32, msg: c::EndDialog) {
+        if self.dialogs.get(&character_id) == Some(&msg.target_id) {
+            self.dialogs.remove(&character_id);
+        }
+    }
+
+    /// Buy or sell everything in the basket, nothing changes hands unless
+    /// all of it can
+    pub(super) fn buy_sell(
+        &mut self,
+        character_id: u32,
+        msg: c::BuySell,
+        messages: &mut Messages,
+    ) {
+        let goods = if self.dialogs.get(&character_id) == Some(&msg.npc_id) {
+            self.merchant_goods(character_id, msg.npc_id)
+        } else {
+            Err("shop not open")
+        };
+        let goods = match goods {
+            Ok(goods) => goods,
+            Err(err) => {
+                println!(
+                    "WARNING: Character {character_id} can't trade with {}: \
+                     {err}",
+                    msg.npc_id
+                );
+                return;
+            }
+        };
+        let Some(character) = self.characters.get_mut(&character_id) else {
+            return;
+        };
+
+        let (ids, repository) = (&mut self.ids, &mut self.repository);
+        let new_id = || match ids
+            .allocate(ObjectKind::InventoryItem, repository.as_mut())
+        {
+            Ok(id) => Some(id),
+            Err(err) => {
+                println!("ERROR: Failed to allocate item id: {err:?}");
+                None
+            }
+        };
+        let inventory = &mut character.inventory;
+        let result = match msg.action {
+            c::TradeAction::Buy => {
+                shop::buy(inventory, &goods, &self.items, &msg.items, new_id)
+            }
+            c::TradeAction::Sell => {
+                shop::sell(inventory, &self.items, &msg.items, new_id)
+            }
+            action => {
+                println!("WARNING: Trade action {action:?} not handled");
+                return;
+            }
+        };
+        match result {
+            Ok(changes) => messages
+                .direct
+                .entry(character.client_id())
+                .or_default()
+                .extend(inventory_updates(&character.inventory, &changes)),
+            Err(err) => println!(
+                "WARNING: Character {character_id} can't {:?} {:?}: {err}",
+                msg.action, msg.items
+            ),
+        }
+    }
+
+    /// Everything an NPC sells, as long as it is a merchant and the
+    /// character is close enough to it
+    fn merchant_goods(
+        &self,
+        character_id: u32,
+        npc_id: u32,
+    ) -> Result<Vec<u32>, &'static str> {
+        let character = self
+            .characters
+            .get(&character_id)
+            .ok_or("not in the world")?;
+        let entity = self.entities.get(&npc_id).ok_or("no NPC")?;
+        if entity.is_dead() {
+            return Err("dead");
+        }
+        if character.location().distance(entity.location()) > DIALOG_RANGE {
+            return Err("too far away");
+        }
+        let goods = self.goods.goods(entity.template());
+        if goods.is_empty() {
+            return Err("not a merchant");
+        }
+        Ok(goods)
+    }
+}
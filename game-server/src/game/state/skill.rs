@@ -0,0 +1,348 @@
+//! Using skills. Every use is checked against the skill's template and the
+//! character using it, skills with a cast time go off in [State::update]
+//! unless the caster moves first
+
+use super::super::character::Character;
+use super::super::data::skill::{CostParameter, Effect, Target, Template};
+use super::super::engine::combat::{self, Hand};
+use super::super::engine::skill::Cast;
+use super::super::message::{client as c, server as s};
+use super::super::{Messages, ServerUpdate};
+use super::{death, State};
+
+/// Positions lag behind the client a little so targets can be slightly
+/// further away than the skill's range
+const RANGE_LEEWAY: f32 = 2.;
+
+impl State {
+    /// Start using a skill, a refused skill cancels the client's cast bar
+    pub(super) fn use_skill(
+        &mut self,
+        character_id: u32,
+        use_skill: &c::UseSkill,
+        messages: &mut Messages,
+    ) {
+        let Some(character) = self.characters.get(&character_id) else {
+            return;
+        };
+
+        let cast = match self.start_skill(character, use_skill) {
+            Ok(cast) => cast,
+            Err(reason) => {
+                println!(
+                    "WARNING: Character {character_id} can't use skill {}: \
+                     {reason}",
+                    use_skill.skill_id
+                );
+                messages
+                    .direct
+                    .entry(character.client_id())
+                    .or_default()
+                    .push(ServerUpdate::new(s::Message::SkillCanceled(
+                        s::SkillCanceled::new(character_id, use_skill.skill_id),
+                    )));
+                return;
+            }
+        };
+
+        messages.observers.entry(character_id).or_default().push(
+            ServerUpdate::new(s::Message::UseSkill(s::UseSkill::new(
+                character_id,
+                cast.template.id,
+                cast.level,
+                cast.target_id,
+            ))),
+        );
+        if cast.finished() {
+            self.finish_cast(character_id, cast, messages);
+        } else {
+            self.casts.insert(character_id, cast);
+        }
+    }
+
+    fn start_skill(
+        &self,
+        character: &Character,
+        use_skill: &c::UseSkill,
+    ) -> Result<Cast, &'static str> {
+        if self.casts.contains_key(&character.id()) {
+            return Err("already casting");
+        }
+        if character.is_dead() {
+            return Err("dead");
+        }
+        if character.effects.stunned() {
+            return Err("stunned");
+        }
+        let Some(skill) = character
+            .skills
+            .iter()
+            .find(|skill| skill.id() == use_skill.skill_id)
+        else {
+            return Err("not learnt");
+        };
+        if u32::from(use_skill.skill_level) > skill.level() {
+            return Err("level not learnt");
+        }
+        let Some(template) = self.skills.get(use_skill.skill_id) else {
+            return Err("no template");
+        };
+        let on_cooldown = self
+            .cooldowns
+            .get(&character.id())
+            .and_then(|cooldowns| cooldowns.remaining(template.id))
+            .is_some();
+        if on_cooldown {
+            return Err("on cooldown");
+        }
+
+        let target_id = match template.target {
+            Target::Me => character.id(),
+            Target::Target => use_skill.target_id,
+        };
+        self.check_target(character, &template, target_id)?;
+        if !can_afford(character, &template) {
+            return Err("not enough MP or DP");
+        }
+
+        Ok(Cast::new(template, skill.level() as u16, target_id))
+    }
+
+    /// NPCs can be hit and characters can be healed, either way they have to
+    /// be alive and in range
+    fn check_target(
+        &self,
+        character: &Character,
+        template: &Template,
+        target_id: u32,
+    ) -> Result<(), &'static str> {
+        if target_id == character.id() {
+            return Ok(());
+        }
+        let location = if let Some(entity) = self.entities.get(&target_id) {
+            entity.is_alive().then_some(entity.location())
+        } else if let Some(target) = self.characters.get(&target_id) {
+            (target.stats.hp.current() > 0).then_some(target.location())
+        } else {
+            None
+        };
+        let Some(location) = location else {
+            return Err("no target");
+        };
+        if character.location().distance(location)
+            > template.range + RANGE_LEEWAY
+        {
+            return Err("out of range");
+        }
+        Ok(())
+    }
+
+    /// Casts whose time is up go off
+    pub(super) fn update_casts(&mut self, messages: &mut Messages) {
+        let finished: Vec<u32> = self
+            .casts
+            .iter()
+            .filter(|(_, cast)| cast.finished())
+            .map(|(character_id, _)| *character_id)
+            .collect();
+        for character_id in finished {
+            if let Some(cast) = self.casts.remove(&character_id) {
+                self.finish_cast(character_id, cast, messages);
+            }
+        }
+    }
+
+    /// Interrupt a cast, used when the caster moves
+    pub(super) fn cancel_cast(
+        &mut self,
+        character_id: u32,
+        messages: &mut Messages,
+    ) {
+        if let Some(cast) = self.casts.remove(&character_id) {
+            messages.observers.entry(character_id).or_default().push(
+                ServerUpdate::new(s::Message::SkillCanceled(
+                    s::SkillCanceled::new(character_id, cast.template.id),
+                )),
+            );
+        }
+    }
+
+    /// Things may have changed during the cast so the target and cost are
+    /// checked again before the skill goes off
+    fn finish_cast(
+        &mut self,
+        character_id: u32,
+        cast: Cast,
+        messages: &mut Messages,
+    ) {
+        let Some(character) = self.characters.get(&character_id) else {
+            return;
+        };
+        let template = &cast.template;
+        let checked = self
+            .check_target(character, template, cast.target_id)
+            .and_then(|()| {
+                can_afford(character, template)
+                    .then_some(())
+                    .ok_or("not enough MP or DP")
+            });
+        if let Err(reason) = checked {
+            println!(
+                "WARNING: Character {character_id} skill {} failed: {reason}",
+                template.id
+            );
+            messages.observers.entry(character_id).or_default().push(
+                ServerUpdate::new(s::Message::SkillCanceled(
+                    s::SkillCanceled::new(character_id, template.id),
+                )),
+            );
+            return;
+        }
+
+        let Some(character) = self.characters.get_mut(&character_id) else {
+            return;
+        };
+        let stats = &mut character.stats;
+        let spent = match template.cost {
+            Some((CostParameter::Mp, mp)) => {
+                stats.mp.spend(mp);
+                Some(s::Message::ManaPoint(s::ManaPoint::new(
+                    stats.mp.current(),
+                    stats.mp.max(),
+                )))
+            }
+            Some((CostParameter::Dp, dp)) => {
+                stats.dp.spend(dp as u16);
+                Some(s::Message::Dp(s::Dp::new(stats.dp.current())))
+            }
+            None => None,
+        };
+        if let Some(spent) = spent {
+            messages
+                .direct
+                .entry(character.client_id())
+                .or_default()
+                .push(ServerUpdate::new(spent));
+        }
+        if template.is_harmful() {
+            character.enter_combat();
+        }
+        let attacker = character.fighter(Hand::Main);
+        let move_speed = character.stats.move_speed;
+        self.cooldowns
+            .entry(character_id)
+            .or_default()
+            .start(template.id, template.cooldown);
+
+        let mut hits = Vec::new();
+        let mut target_hp = None;
+        let mut killed = false;
+        for effect in &template.effects {
+            match *effect {
+                Effect::Damage(damage) => {
+                    let Some(entity) = self
+                        .entities
+                        .get_mut(&cast.target_id)
+                        .filter(|entity| entity.is_alive())
+                    else {
+                        continue;
+                    };
+                    let mut attacker = attacker.clone();
+                    attacker.attack += damage;
+                    let hit = combat::swing(
+                        &attacker,
+                        &entity.fighter(),
+                        Hand::Main,
+                        &mut rand::thread_rng(),
+                    );
+                    entity.hp.update(-hit.damage);
+                    entity.add_hate(character_id, hit.damage);
+                    target_hp = Some(entity.hp.percent());
+                    hits.push(hit);
+
+                    if entity.is_dead() {
+                        killed = true;
+                        messages
+                            .observers
+                            .entry(entity.id())
+                            .or_default()
+                            .extend(
+                                death(entity.id(), character_id, move_speed)
+                                    .map(ServerUpdate::new),
+                            );
+                    }
+                }
+                Effect::Heal(heal) => {
+                    let Some(target) = self.characters.get_mut(&cast.target_id)
+                    else {
+                        continue;
+                    };
+                    let hp = &mut target.stats.hp;
+                    hp.set_current(hp.current() + heal);
+                    target_hp = Some(hp.percent());
+                    let hit_point = s::HitPoint::new(hp.current(), hp.max());
+                    messages
+                        .direct
+                        .entry(target.client_id())
+                        .or_default()
+                        .push(ServerUpdate::new(s::Message::HitPoint(
+                            hit_point,
+                        )));
+                }
+                _ => (),
+            }
+        }
+        if killed {
+            self.reward_kill(cast.target_id, character_id, messages);
+        } else if template.is_timed() {
+            self.apply_effects(character_id, &cast, messages);
+        }
+
+        let cooldown = (template.cooldown.as_millis() / 100) as u32;
+        messages.observers.entry(character_id).or_default().push(
+            ServerUpdate::new(s::Message::SkillSucceded(
+                s::SkillSucceded::new(
+                    character_id,
+                    cast.target_id,
+                    template.id,
+                    cast.level,
+                    cooldown,
+                    hits,
+                ),
+            )),
+        );
+        if let Some(hp) = target_hp {
+            messages.observers.entry(character_id).or_default().push(
+                ServerUpdate::new(s::Message::HitPointOther(
+                    s::HitPointOther::new(cast.target_id, hp),
+                )),
+            );
+        }
+    }
+
+    /// Skills still cooling down, in the deciseconds the client wants
+    pub(super) fn skill_cooltimes(
+        &mut self,
+        character_id: u32,
+    ) -> Vec<(u16, u32)> {
+        self.cooldowns
+            .get_mut(&character_id)
+            .map(|cooldowns| cooldowns.active())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(skill_id, remaining)| {
+                (skill_id, (remaining.as_millis() / 100) as u32)
+            })
+            .collect()
+    }
+}
+
+fn can_afford(character: &Character, template: &Template) -> bool {
+    match template.cost {
+        Some((CostParameter::Mp, mp)) => character.stats.mp.current() >= mp,
+        Some((CostParameter::Dp, dp)) => {
+            u32::from(character.stats.dp.current()) >= dp
+        }
+        None => true,
+    }
+}
@@ -0,0 +1,228 @@
+//! Interest management, objects are bucketed into a grid of cells so we only
+//! have to look at neighbouring cells to find out who can see who. Updates
+//! are then routed to the characters that can see the object instead of
+//! every connected client
+
+use std::collections::{HashMap, HashSet};
+
+use super::engine::Coord;
+
+/// How far away a character can see other objects
+pub const VISIBILITY_RANGE: f32 = 100.;
+
+/// Cells are as wide as the visibility range so everything in range is in
+/// the 3x3 block of cells around an object
+const CELL_SIZE: f32 = VISIBILITY_RANGE;
+
+type Cell = (i32, i32);
+
+fn cell(location: &Coord) -> Cell {
+    (
+        (location.x() / CELL_SIZE).floor() as i32,
+        (location.y() / CELL_SIZE).floor() as i32,
+    )
+}
+
+struct Object {
+    location: Coord,
+    /// Characters observe, NPCs only get observed
+    observer: bool,
+}
+
+/// Changes in what a character can see
+#[derive(Debug, Default, PartialEq)]
+pub struct Visibility {
+    /// (observer, object) pairs that need the object put into the world
+    pub appeared: Vec<(u32, u32)>,
+    /// (observer, object) pairs that need the object removed
+    pub disappeared: Vec<(u32, u32)>,
+}
+
+#[derive(Default)]
+pub struct World {
+    objects: HashMap<u32, Object>,
+    cells: HashMap<Cell, HashSet<u32>>,
+    /// Objects an observer currently has loaded on their client
+    known: HashMap<u32, HashSet<u32>>,
+    /// The reverse of known, the observers that have an object loaded
+    seen_by: HashMap<u32, HashSet<u32>>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an object to the world and work out who can now see it
+    pub fn insert(
+        &mut self,
+        id: u32,
+        location: Coord,
+        observer: bool,
+    ) -> Visibility {
+        self.cells.entry(cell(&location)).or_default().insert(id);
+        self.objects.insert(id, Object { location, observer });
+        self.refresh(id)
+    }
+
+    /// Remove an object, every observer that knew about it is returned in
+    /// [Visibility::disappeared]
+    pub fn remove(&mut self, id: u32) -> Visibility {
+        let mut visibility = Visibility::default();
+        let Some(object) = self.objects.remove(&id) else {
+            return visibility;
+        };
+
+        let cell = cell(&object.location);
+        if let Some(objects) = self.cells.get_mut(&cell) {
+            objects.remove(&id);
+            if objects.is_empty() {
+                self.cells.remove(&cell);
+            }
+        }
+
+        for observer in self.seen_by.remove(&id).unwrap_or_default() {
+            if let Some(known) = self.known.get_mut(&observer) {
+                known.remove(&id);
+            }
+            visibility.disappeared.push((observer, id));
+        }
+        for object in self.known.remove(&id).unwrap_or_default() {
+            if let Some(seen_by) = self.seen_by.get_mut(&object) {
+                seen_by.remove(&id);
+            }
+        }
+
+        visibility
+    }
+
+    /// Move an object and work out who gained or lost sight of it
+    pub fn move_to(&mut self, id: u32, location: Coord) -> Visibility {
+        let Some(object) = self.objects.get_mut(&id) else {
+            return Visibility::default();
+        };
+
+        let (from, to) = (cell(&object.location), cell(&location));
+        object.location = location;
+        if from != to {
+            if let Some(objects) = self.cells.get_mut(&from) {
+                objects.remove(&id);
+                if objects.is_empty() {
+                    self.cells.remove(&from);
+                }
+            }
+            self.cells.entry(to).or_default().insert(id);
+        }
+
+        self.refresh(id)
+    }
+
+    /// Characters that have this object loaded
+    pub fn observers(&self, id: u32) -> impl Iterator<Item = u32> + '_ {
+        self.seen_by.get(&id).into_iter().flatten().copied()
+    }
+
+    /// Objects in range of a location
+    fn nearby(&self, location: &Coord) -> impl Iterator<Item = u32> + '_ {
+        let (x, y) = cell(location);
+        let location = *location;
+        (x - 1..=x + 1)
+            .flat_map(move |x| (y - 1..=y + 1).map(move |y| (x, y)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .filter(move |id| {
+                self.objects[id].location.distance(&location)
+                    <= VISIBILITY_RANGE
+            })
+    }
+
+    /// Recalculate visibility in both directions for a single object, what it
+    /// can see if it is an observer and which observers can see it
+    fn refresh(&mut self, id: u32) -> Visibility {
+        let mut visibility = Visibility::default();
+        let object = &self.objects[&id];
+        let observer = object.observer;
+
+        let in_range: HashSet<u32> = self
+            .nearby(&object.location)
+            .filter(|other| *other != id)
+            .collect();
+
+        // What this object can see
+        if observer {
+            let known = self.known.entry(id).or_default();
+            let appeared: Vec<u32> =
+                in_range.difference(known).copied().collect();
+            let disappeared: Vec<u32> =
+                known.difference(&in_range).copied().collect();
+
+            for other in appeared {
+                self.known.entry(id).or_default().insert(other);
+                self.seen_by.entry(other).or_default().insert(id);
+                visibility.appeared.push((id, other));
+            }
+            for other in disappeared {
+                self.known.entry(id).or_default().remove(&other);
+                if let Some(seen_by) = self.seen_by.get_mut(&other) {
+                    seen_by.remove(&id);
+                }
+                visibility.disappeared.push((id, other));
+            }
+        }
+
+        // Who can see this object
+        let seen_by = self.seen_by.entry(id).or_default();
+        let appeared: Vec<u32> = in_range
+            .iter()
+            .filter(|other| self.objects[other].observer)
+            .filter(|other| !seen_by.contains(other))
+            .copied()
+            .collect();
+        let disappeared: Vec<u32> =
+            seen_by.difference(&in_range).copied().collect();
+
+        for other in appeared {
+            self.seen_by.entry(id).or_default().insert(other);
+            self.known.entry(other).or_default().insert(id);
+            visibility.appeared.push((other, id));
+        }
+        for other in disappeared {
+            self.seen_by.entry(id).or_default().remove(&other);
+            if let Some(known) = self.known.get_mut(&other) {
+                known.remove(&id);
+            }
+            visibility.disappeared.push((other, id));
+        }
+
+        visibility
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enter_and_leave_range() {
+        let mut world = World::new();
+        world.insert(1, Coord::new(0., 0., 0.), false);
+
+        let visibility = world.insert(2, Coord::new(50., 0., 0.), true);
+        assert_eq!(visibility.appeared, vec![(2, 1)]);
+        assert_eq!(world.observers(1).collect::<Vec<_>>(), vec![2]);
+
+        let visibility = world.insert(3, Coord::new(1000., 0., 0.), true);
+        assert!(visibility.appeared.is_empty());
+
+        let visibility = world.move_to(2, Coord::new(950., 0., 0.));
+        assert_eq!(visibility.disappeared, vec![(2, 1)]);
+        assert_eq!(visibility.appeared.len(), 2);
+        assert!(visibility.appeared.contains(&(2, 3)));
+        assert!(visibility.appeared.contains(&(3, 2)));
+
+        let visibility = world.remove(3);
+        assert_eq!(visibility.disappeared, vec![(2, 3)]);
+        assert_eq!(world.observers(1).count(), 0);
+    }
+}
@@ -18,57 +18,90 @@
 
 mod error;
 mod game;
+mod network;
 
-use crossbeam_channel::Sender;
-use game::{ClientUpdate, Connection, ServerUpdate};
+use game::{
+    DropTables, GameData, GoodsLists, ItemTemplates, NpcTemplates,
+    SkillTemplates, Spawner, SqliteRepository, WORLD_ID,
+};
+use network::Network;
 use std::collections::HashMap;
-use std::net::{TcpListener, TcpStream};
 use std::sync::{Arc, Mutex};
 use std::thread::spawn;
-use std::time::Duration;
 
-use error::Error;
+const LISTEN_ADDRESS: &str = "0.0.0.0:7777";
+const DATABASE_PATH: &str = "game.db";
+/// Extracted from the client with the pak and bxml tools
+const NPC_TEMPLATES_PATH: &str = "data/client_npcs.xml";
+/// Also extracted from the client
+const SKILL_TEMPLATES_PATH: &str = "data/client_skills.xml";
+/// Also extracted from the client
+const GOODS_LISTS_PATH: &str = "data/client_npc_goodslist.xml";
+/// Compiled by the build script from the client's items, see build.rs
+const ITEM_TEMPLATES: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/client_items.bin"));
+const SPAWNS_PATH: &str = "data/spawns.xml";
+const DROPS_PATH: &str = "data/drops.xml";
 
 fn main() {
     println!("INFO: Starting Game Server");
-    let listener = TcpListener::bind("0.0.0.0:7777").unwrap();
-    println!("INFO: Listening on {}", listener.local_addr().unwrap());
-
     let clients = Arc::new(Mutex::new(HashMap::new()));
     let (server_tx, server_rx) = crossbeam_channel::bounded(1000);
 
-    let connections = clients.clone();
-    spawn(move || game::game_update(connections, server_rx));
+    let network = Network::bind(
+        LISTEN_ADDRESS.parse().unwrap(),
+        clients.clone(),
+        server_tx,
+    )
+    .unwrap();
+    println!("INFO: Listening on {}", network.local_addr().unwrap());
 
-    while let Ok((stream, _)) = listener.accept() {
-        let connections = clients.clone();
-        let server_tx = server_tx.clone();
-        spawn(|| handle(stream, connections, server_tx).unwrap());
-    }
-}
+    let repository = SqliteRepository::open(DATABASE_PATH).unwrap();
+    println!("INFO: Opened database {DATABASE_PATH}");
+
+    let templates = NpcTemplates::load(NPC_TEMPLATES_PATH).unwrap();
+    println!(
+        "INFO: Loaded {} NPC templates from {NPC_TEMPLATES_PATH}",
+        templates.len()
+    );
+
+    let skills = SkillTemplates::load(SKILL_TEMPLATES_PATH).unwrap();
+    println!(
+        "INFO: Loaded {} skill templates from {SKILL_TEMPLATES_PATH}",
+        skills.len()
+    );
+
+    let items = ItemTemplates::from_bytes(ITEM_TEMPLATES).unwrap();
+    println!("INFO: Loaded {} item templates", items.len());
 
-fn handle(
-    stream: TcpStream,
-    clients: Arc<Mutex<HashMap<u16, Sender<ServerUpdate>>>>,
-    server_tx: Sender<ClientUpdate>,
-) -> error::Result<()> {
-    let peer_addr = stream.peer_addr().map_err(Error::PeerAddr)?;
-    let client_id = peer_addr.port();
-    println!("INFO: new session {peer_addr}");
+    let goods = GoodsLists::load(GOODS_LISTS_PATH, &items).unwrap();
+    println!(
+        "INFO: Loaded {} goods lists from {GOODS_LISTS_PATH}",
+        goods.len()
+    );
 
-    stream
-        .set_read_timeout(Some(Duration::from_millis(1)))
-        .map_err(Error::SetStreamTimeout)?;
-    //    stream
-    //        .set_write_timeout(Some(Duration::from_secs(60)))
-    //        .map_err(Error::SetStreamTimeout)?;
+    let spawner = Spawner::load(SPAWNS_PATH, &templates, WORLD_ID).unwrap();
+    println!("INFO: Loaded {} spawns from {SPAWNS_PATH}", spawner.len());
 
-    let (client_tx, client_rx) = crossbeam_channel::bounded(100);
-    clients.lock().unwrap().insert(client_id, client_tx);
-    Connection::new(stream, client_id, client_rx, server_tx).handle();
+    let drops = DropTables::load(DROPS_PATH).unwrap();
+    println!("INFO: Loaded {} drop tables from {DROPS_PATH}", drops.len());
 
-    clients.lock().unwrap().remove(&peer_addr.port());
-    println!("INFO: Connection closed from {peer_addr}");
+    let waker = network.waker();
+    spawn(move || {
+        game::game_update(
+            clients,
+            waker,
+            server_rx,
+            Box::new(repository),
+            spawner,
+            GameData {
+                skills,
+                drops,
+                items,
+                goods,
+            },
+        )
+    });
 
-    Ok(())
+    network.run().unwrap();
 }
@@ -0,0 +1,227 @@
+//! Every socket is non-blocking and serviced from a single event loop, the
+//! game loop hands updates over through a bounded queue per client then wakes
+//! this thread up to write them out
+
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::Sender;
+use mio::net::TcpListener;
+use mio::{Events, Interest, Poll, Token, Waker};
+
+use crate::error::{Error, Result};
+use crate::game::{ClientUpdate, Connection, ServerUpdate};
+
+const LISTENER: Token = Token(usize::MAX);
+const WAKER: Token = Token(usize::MAX - 1);
+
+/// Updates the game loop can queue for a client before it is considered too
+/// slow and disconnected
+const OUTBOUND_QUEUE_LEN: usize = 100;
+
+/// How often to look for connections that have gone quiet
+const REAP_INTERVAL: Duration = Duration::from_secs(5);
+
+pub struct Network {
+    poll: Poll,
+    listener: TcpListener,
+    waker: Arc<Waker>,
+    /// Keyed by client id, which is also the token of the socket
+    connections: HashMap<u16, Connection>,
+    /// Outbound queues shared with the game loop
+    clients: Arc<Mutex<HashMap<u16, Sender<ServerUpdate>>>>,
+    server_tx: Sender<ClientUpdate>,
+    last_client_id: u16,
+}
+
+impl Network {
+    pub fn bind(
+        addr: SocketAddr,
+        clients: Arc<Mutex<HashMap<u16, Sender<ServerUpdate>>>>,
+        server_tx: Sender<ClientUpdate>,
+    ) -> Result<Self> {
+        let poll = Poll::new().map_err(Error::Network)?;
+        let mut listener = TcpListener::bind(addr).map_err(Error::Network)?;
+        poll.registry()
+            .register(&mut listener, LISTENER, Interest::READABLE)
+            .map_err(Error::Network)?;
+        let waker =
+            Waker::new(poll.registry(), WAKER).map_err(Error::Network)?;
+
+        Ok(Self {
+            poll,
+            listener,
+            waker: Arc::new(waker),
+            connections: HashMap::new(),
+            clients,
+            server_tx,
+            last_client_id: 0,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.listener.local_addr().map_err(Error::Network)
+    }
+
+    /// Wakes the event loop up to flush whatever the game loop has queued
+    pub fn waker(&self) -> Arc<Waker> {
+        self.waker.clone()
+    }
+
+    pub fn run(mut self) -> Result<()> {
+        let mut events = Events::with_capacity(1024);
+        let mut last_reap = Instant::now();
+        loop {
+            // Don't wait on the socket for clients that still have something
+            // to read
+            let timeout = if self.connections.values().any(Connection::unread) {
+                Duration::ZERO
+            } else {
+                REAP_INTERVAL
+            };
+            match self.poll.poll(&mut events, Some(timeout)) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(Error::Network(e)),
+            }
+
+            for event in events.iter() {
+                match event.token() {
+                    LISTENER => self.accept(),
+                    WAKER => {
+                        let client_ids: Vec<u16> =
+                            self.connections.keys().copied().collect();
+                        for client_id in client_ids {
+                            self.ready(client_id, false);
+                        }
+                    }
+                    Token(client_id) => {
+                        self.ready(client_id as u16, event.is_readable())
+                    }
+                }
+            }
+
+            // Every other client has had its turn, carry on reading those
+            // that stopped at a whole message
+            let unread: Vec<u16> = self
+                .connections
+                .iter()
+                .filter(|(_, connection)| connection.unread())
+                .map(|(client_id, _)| *client_id)
+                .collect();
+            for client_id in unread {
+                self.ready(client_id, true);
+            }
+
+            if last_reap.elapsed() >= REAP_INTERVAL {
+                last_reap = Instant::now();
+                self.reap();
+            }
+        }
+    }
+
+    fn accept(&mut self) {
+        loop {
+            let (mut stream, peer_addr) = match self.listener.accept() {
+                Ok(connection) => connection,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    println!("WARNING: Failed to accept connection: {e}");
+                    return;
+                }
+            };
+
+            let Some(client_id) = self.next_client_id() else {
+                println!("WARNING: No free client ids, dropping {peer_addr}");
+                continue;
+            };
+            if let Err(e) = self.poll.registry().register(
+                &mut stream,
+                Token(client_id as usize),
+                Interest::READABLE | Interest::WRITABLE,
+            ) {
+                println!("WARNING: Failed to register {peer_addr}: {e}");
+                continue;
+            }
+            println!("INFO: new session {peer_addr}");
+
+            let (client_tx, client_rx) =
+                crossbeam_channel::bounded(OUTBOUND_QUEUE_LEN);
+            self.clients.lock().unwrap().insert(client_id, client_tx);
+            let connection = Connection::new(
+                stream,
+                peer_addr,
+                client_id,
+                client_rx,
+                self.server_tx.clone(),
+            );
+            self.connections.insert(client_id, connection);
+        }
+    }
+
+    /// Drop connections that have stopped sending heartbeats
+    fn reap(&mut self) {
+        let timed_out: Vec<u16> = self
+            .connections
+            .iter()
+            .filter(|(_, connection)| connection.timed_out())
+            .map(|(client_id, _)| *client_id)
+            .collect();
+        for client_id in timed_out {
+            println!(
+                "WARNING: Disconnecting {}: heartbeat timed out",
+                self.connections[&client_id].peer_addr()
+            );
+            self.close(client_id);
+        }
+    }
+
+    /// Client ids start at 1 as 0 marks an offline character
+    fn next_client_id(&mut self) -> Option<u16> {
+        for _ in 0..u16::MAX {
+            self.last_client_id = self.last_client_id.wrapping_add(1).max(1);
+            if !self.connections.contains_key(&self.last_client_id) {
+                return Some(self.last_client_id);
+            }
+        }
+        None
+    }
+
+    fn ready(&mut self, client_id: u16, readable: bool) {
+        let Some(connection) = self.connections.get_mut(&client_id) else {
+            return;
+        };
+        // A panic handling one client shouldn't take every other client down
+        // with it
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            connection.ready(readable)
+        }));
+        match result {
+            Ok(Ok(true)) => return,
+            Ok(Ok(false)) => {}
+            Ok(Err(e)) => println!(
+                "WARNING: Disconnecting {}: {e}",
+                connection.peer_addr()
+            ),
+            Err(_) => println!(
+                "ERROR: Panicked handling {}, disconnecting",
+                connection.peer_addr()
+            ),
+        }
+
+        self.close(client_id);
+    }
+
+    fn close(&mut self, client_id: u16) {
+        self.clients.lock().unwrap().remove(&client_id);
+        // Dropping the stream deregisters it
+        if let Some(connection) = self.connections.remove(&client_id) {
+            connection.disconnect();
+            println!("INFO: Connection closed from {}", connection.peer_addr());
+        }
+    }
+}